@@ -1,5 +1,13 @@
+use std::{net::IpAddr, path::PathBuf};
+
 use redis::{
-    manager::RedisManager, rdb::RDBConfig, replication::RedisReplicationMode, store::RedisStore,
+    log::{self, LogLevel},
+    manager::RedisManager,
+    rdb::{RDBConfig, SavePoint},
+    replication::RedisReplicationMode,
+    server::{self, OutputBufferLimits, ServerConfig},
+    slowlog::SlowLogConfig,
+    store::{EncodingConfig, RedisStore},
 };
 
 mod redis;
@@ -12,7 +20,32 @@ fn parse_option<T>(option_name: &str, option_parser: impl Fn(std::env::Args) ->
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let host = [127, 0, 0, 1];
+    let bind_addresses = parse_option("--bind", |args| {
+        let bind_addresses = args
+            .take_while(|arg| !arg.starts_with("--"))
+            .flat_map(|arg| {
+                arg.split_ascii_whitespace()
+                    .map(|address| {
+                        // like Redis, a leading '-' marks an address that is allowed to be
+                        // unavailable, which is treated the same as any other address here
+                        let address = address.strip_prefix('-').unwrap_or(address);
+                        address.parse::<IpAddr>().unwrap_or_else(|_| {
+                            panic!("[redis - error] invalid bind address '{address}'")
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        assert!(
+            !bind_addresses.is_empty(),
+            "[redis - error] at least one value expected for bind addresses"
+        );
+
+        bind_addresses
+    })
+    .unwrap_or_else(|| vec![IpAddr::from([127, 0, 0, 1])]);
+
     let port = parse_option("--port", |mut args| {
         args.next()
             .expect("[redis - error] value expected for port")
@@ -21,6 +54,117 @@ async fn main() -> anyhow::Result<()> {
     })
     .unwrap_or(6379);
 
+    let unix_socket = parse_option("--unixsocket", |mut args| {
+        args.next()
+            .map(PathBuf::from)
+            .expect("[redis - error] value expected for unix socket path")
+    });
+
+    let tcp_backlog = parse_option("--tcp-backlog", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for TCP backlog")
+            .parse::<u32>()
+            .expect("[redis - error] expected TCP backlog to be a positive number")
+    })
+    .unwrap_or(511);
+
+    // only turning keepalive on or off is supported, since the probe interval that Redis
+    // takes in seconds cannot be set on the socket
+    let tcp_keepalive = parse_option("--tcp-keepalive", |mut args| {
+        match args
+            .next()
+            .expect("[redis - error] value expected for TCP keepalive")
+            .as_str()
+        {
+            "0" => false,
+            "1" => true,
+            _ => panic!(
+                "[redis - error] expected TCP keepalive to be 1 to enable it or 0 to disable it, \
+                 since setting its interval is not supported"
+            ),
+        }
+    })
+    .unwrap_or(true);
+
+    let timeout = parse_option("--timeout", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for timeout")
+            .parse::<u64>()
+            .expect("[redis - error] expected timeout to be a positive number of seconds")
+    })
+    .unwrap_or(0);
+
+    let output_buffer_limits = parse_option("--client-output-buffer-limit", |mut args| {
+        let limits = args
+            .next()
+            .expect("[redis - error] value expected for client output buffer limits");
+        OutputBufferLimits::parse(&limits).unwrap_or_else(|| {
+            panic!(
+                "[redis - error] expected client output buffer limits to be pairs of a class (normal, replica or pubsub) and a size"
+            )
+        })
+    })
+    .unwrap_or_default();
+
+    let proto_max_bulk_len = parse_option("--proto-max-bulk-len", |mut args| {
+        let limit = args
+            .next()
+            .expect("[redis - error] value expected for proto max bulk len");
+        server::parse_size(&limit)
+            .filter(|&limit| limit > 0)
+            .expect("[redis - error] expected proto max bulk len to be a positive size")
+    });
+
+    let default_slowlog_config = SlowLogConfig::default();
+    let slowlog_log_slower_than = parse_option("--slowlog-log-slower-than", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for slowlog log slower than")
+            .parse::<i64>()
+            .expect(
+                "[redis - error] expected slowlog log slower than to be a number of microseconds",
+            )
+    })
+    .unwrap_or(default_slowlog_config.log_slower_than);
+
+    let slowlog_max_len = parse_option("--slowlog-max-len", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for slowlog max len")
+            .parse::<usize>()
+            .expect("[redis - error] expected slowlog max len to be a positive number")
+    })
+    .unwrap_or(default_slowlog_config.max_len);
+
+    let latency_monitor_threshold = parse_option("--latency-monitor-threshold", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for latency monitor threshold")
+            .parse::<u64>()
+            .expect("[redis - error] expected latency monitor threshold to be a positive number of milliseconds")
+    })
+    .unwrap_or(0);
+
+    let io_threads = parse_option("--io-threads", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for number of I/O threads")
+            .parse::<usize>()
+            .ok()
+            .filter(|&io_threads| io_threads > 0)
+            .expect("[redis - error] expected number of I/O threads to be a positive number")
+    })
+    .unwrap_or(1);
+
+    let log_level = parse_option("--loglevel", |mut args| {
+        let log_level = args
+            .next()
+            .expect("[redis - error] value expected for log level");
+        LogLevel::parse(&log_level).unwrap_or_else(|| {
+            panic!(
+                "[redis - error] expected log level to be one of debug, verbose, notice or warning"
+            )
+        })
+    })
+    .unwrap_or(LogLevel::Notice);
+    log::set_level(log_level);
+
     let replication_mode = parse_option("--replicaof", |mut args| {
         (
             args.next()
@@ -42,19 +186,151 @@ async fn main() -> anyhow::Result<()> {
     })
     .unwrap_or_else(|| "dump.rdb".to_string());
 
+    // unlike Redis, snapshots are only taken automatically when save points are given
+    let save_points = parse_option("--save", |mut args| {
+        let save_points = args
+            .next()
+            .expect("[redis - error] value expected for save points")
+            .split_ascii_whitespace()
+            .map(|value| {
+                value
+                    .parse::<u64>()
+                    .expect("[redis - error] expected save points to be positive numbers")
+            })
+            .collect::<Vec<_>>();
+
+        assert!(
+            save_points.len() % 2 == 0,
+            "[redis - error] expected save points to be pairs of seconds and changes"
+        );
+
+        save_points
+            .chunks_exact(2)
+            .map(|save_point| SavePoint {
+                seconds: save_point[0],
+                changes: save_point[1] as usize,
+            })
+            .collect::<Vec<_>>()
+    })
+    .unwrap_or_default();
+
+    let databases = parse_option("--databases", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for number of databases")
+            .parse::<usize>()
+            .ok()
+            .filter(|&databases| databases > 0)
+            .expect("[redis - error] expected number of databases to be a positive number")
+    })
+    .unwrap_or(16);
+
+    // TLS requires a TLS implementation such as rustls, which is not a dependency of this
+    // build, so refuse to start rather than silently serving plaintext where TLS was asked for
+    for tls_option in [
+        "--tls-port",
+        "--tls-cert-file",
+        "--tls-key-file",
+        "--tls-ca-cert-file",
+        "--tls-replication",
+    ] {
+        anyhow::ensure!(
+            parse_option(tls_option, |_| ()).is_none(),
+            "[redis - error] option '{tls_option}' is not supported since this server is built without TLS"
+        );
+    }
+
     let mode = if let Some((primary_host, primary_port)) = replication_mode {
         let primary_port = primary_port.parse()?;
         RedisReplicationMode::replica(primary_host, primary_port)
     } else {
-        RedisReplicationMode::primary("8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb".to_string())
+        RedisReplicationMode::primary()
     };
 
-    let store = RedisStore::new();
+    let default_encoding_config = EncodingConfig::default();
+    let list_max_listpack_size = parse_option("--list-max-listpack-size", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for list max listpack size")
+            .parse::<i64>()
+            .ok()
+            .filter(|&size| size > 0 || (-5..=-1).contains(&size))
+            .expect(
+                "[redis - error] expected list max listpack size to be positive or from -5 to -1",
+            )
+    })
+    .unwrap_or(default_encoding_config.list_max_listpack_size);
+
+    let set_max_intset_entries = parse_option("--set-max-intset-entries", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for set max intset entries")
+            .parse::<usize>()
+            .expect("[redis - error] expected set max intset entries to be a positive number")
+    })
+    .unwrap_or(default_encoding_config.set_max_intset_entries);
+
+    let set_max_listpack_entries = parse_option("--set-max-listpack-entries", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for set max listpack entries")
+            .parse::<usize>()
+            .expect("[redis - error] expected set max listpack entries to be a positive number")
+    })
+    .unwrap_or(default_encoding_config.set_max_listpack_entries);
+
+    let set_max_listpack_value = parse_option("--set-max-listpack-value", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for set max listpack value")
+            .parse::<usize>()
+            .expect("[redis - error] expected set max listpack value to be a positive number")
+    })
+    .unwrap_or(default_encoding_config.set_max_listpack_value);
+
+    let hash_max_listpack_entries = parse_option("--hash-max-listpack-entries", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for hash max listpack entries")
+            .parse::<usize>()
+            .expect("[redis - error] expected hash max listpack entries to be a positive number")
+    })
+    .unwrap_or(default_encoding_config.hash_max_listpack_entries);
+
+    let hash_max_listpack_value = parse_option("--hash-max-listpack-value", |mut args| {
+        args.next()
+            .expect("[redis - error] value expected for hash max listpack value")
+            .parse::<usize>()
+            .expect("[redis - error] expected hash max listpack value to be a positive number")
+    })
+    .unwrap_or(default_encoding_config.hash_max_listpack_value);
+
+    let mut store = RedisStore::new(databases);
+    store.encoding_config = EncodingConfig {
+        list_max_listpack_size,
+        set_max_intset_entries,
+        set_max_listpack_entries,
+        set_max_listpack_value,
+        hash_max_listpack_entries,
+        hash_max_listpack_value,
+    };
+    let mut server_config = ServerConfig::new(
+        bind_addresses,
+        port,
+        unix_socket,
+        tcp_backlog,
+        tcp_keepalive,
+        timeout,
+        output_buffer_limits,
+    );
+    server_config.slowlog = SlowLogConfig {
+        log_slower_than: slowlog_log_slower_than,
+        max_len: slowlog_max_len,
+    };
+    server_config.latency_monitor_threshold = latency_monitor_threshold;
+    server_config.io_threads = io_threads;
+    if let Some(proto_max_bulk_len) = proto_max_bulk_len {
+        server_config.proto_max_bulk_len = proto_max_bulk_len;
+    }
     RedisManager::new(
-        (host, port).into(),
+        server_config,
         store,
         mode,
-        RDBConfig::new(rdb_dir, rdb_file_name),
+        RDBConfig::new(rdb_dir, rdb_file_name, save_points),
     )
     .start()
     .await
@@ -0,0 +1,409 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::Bytes;
+
+use self::sha256::sha256_hex;
+
+use super::{
+    resp::{
+        command::{AclSection, RedisCommand},
+        encoding, RESPValue,
+    },
+    server::{ClientConnectionInfo, ClientId, RedisWriteStream},
+};
+
+mod sha256;
+
+const DEFAULT_USER: &[u8] = b"default";
+
+const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "bitmap",
+    "hyperloglog",
+    "geo",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+];
+
+#[derive(Debug, Clone)]
+pub struct AclUser {
+    name: Bytes,
+    is_enabled: bool,
+    is_nopass: bool,
+    password_hashes: BTreeSet<String>,
+    allows_all_commands: bool,
+    /// Commands that are denied when all commands are allowed and allowed otherwise.
+    command_exceptions: BTreeSet<String>,
+}
+
+impl AclUser {
+    fn new(name: Bytes) -> Self {
+        Self {
+            name,
+            is_enabled: false,
+            is_nopass: false,
+            password_hashes: BTreeSet::new(),
+            allows_all_commands: false,
+            command_exceptions: BTreeSet::new(),
+        }
+    }
+
+    fn default_user() -> Self {
+        Self {
+            is_enabled: true,
+            is_nopass: true,
+            allows_all_commands: true,
+            ..Self::new(Bytes::from_static(DEFAULT_USER))
+        }
+    }
+
+    fn can_run(&self, command_name: &str) -> bool {
+        self.allows_all_commands != self.command_exceptions.contains(command_name)
+    }
+
+    fn check_password(&self, password: &[u8]) -> bool {
+        self.is_enabled && (self.is_nopass || self.password_hashes.contains(&sha256_hex(password)))
+    }
+
+    fn apply_rule(&mut self, rule: &[u8]) -> Result<(), &'static str> {
+        match &*rule.to_ascii_lowercase() {
+            b"on" => self.is_enabled = true,
+            b"off" => self.is_enabled = false,
+            b"nopass" => {
+                self.is_nopass = true;
+                self.password_hashes.clear();
+            }
+            b"resetpass" => {
+                self.is_nopass = false;
+                self.password_hashes.clear();
+            }
+            b"+@all" | b"allcommands" => {
+                self.allows_all_commands = true;
+                self.command_exceptions.clear();
+            }
+            b"-@all" | b"nocommands" => {
+                self.allows_all_commands = false;
+                self.command_exceptions.clear();
+            }
+            // key and channel patterns are not restricted, so only the permissive forms are accepted
+            b"~*" | b"allkeys" | b"&*" | b"allchannels" => {}
+            b"reset" => {
+                self.is_enabled = false;
+                self.apply_rule(b"resetpass")?;
+                self.apply_rule(b"-@all")?;
+            }
+            _ => match rule.split_first() {
+                Some((b'>', password)) => {
+                    self.is_nopass = false;
+                    self.password_hashes.insert(sha256_hex(password));
+                }
+                Some((b'<', password)) => {
+                    if !self.password_hashes.remove(&sha256_hex(password)) {
+                        return Err("no such password");
+                    }
+                }
+                Some((b'#', hash)) => {
+                    let hash = Self::parse_password_hash(hash)?;
+                    self.is_nopass = false;
+                    self.password_hashes.insert(hash);
+                }
+                Some((b'!', hash)) => {
+                    let hash = Self::parse_password_hash(hash)?;
+                    if !self.password_hashes.remove(&hash) {
+                        return Err("no such password");
+                    }
+                }
+                Some((b'+' | b'-', command_name)) if !command_name.starts_with(b"@") => {
+                    let command_name = std::str::from_utf8(command_name)
+                        .map_err(|_| "Syntax error")?
+                        .to_ascii_lowercase();
+                    let is_allowed = rule[0] == b'+';
+                    if is_allowed == self.allows_all_commands {
+                        self.command_exceptions.remove(&command_name);
+                    } else {
+                        self.command_exceptions.insert(command_name);
+                    }
+                }
+                Some((b'+' | b'-', _)) => return Err("Unknown command or category name in ACL"),
+                _ => return Err("Syntax error"),
+            },
+        }
+
+        Ok(())
+    }
+
+    fn parse_password_hash(hash: &[u8]) -> Result<String, &'static str> {
+        let is_valid = hash.len() == 64 && hash.iter().all(u8::is_ascii_hexdigit);
+        if !is_valid {
+            return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters");
+        }
+
+        Ok(String::from_utf8_lossy(hash).to_ascii_lowercase())
+    }
+
+    fn describe_commands(&self) -> String {
+        let (all, exception) = if self.allows_all_commands {
+            ("+@all", '-')
+        } else {
+            ("-@all", '+')
+        };
+
+        let mut description = all.to_string();
+        for command_name in &self.command_exceptions {
+            description.push_str(&format!(" {exception}{command_name}"));
+        }
+
+        description
+    }
+
+    fn describe(&self) -> String {
+        let mut description = format!(
+            "user {} {}",
+            String::from_utf8_lossy(&self.name),
+            if self.is_enabled { "on" } else { "off" }
+        );
+
+        if self.is_nopass {
+            description.push_str(" nopass");
+        }
+
+        for hash in &self.password_hashes {
+            description.push_str(&format!(" #{hash}"));
+        }
+
+        description.push_str(&format!(" ~* &* {}", self.describe_commands()));
+        description
+    }
+}
+
+/// The user store backing `AUTH` and `ACL`. Only command permissions are enforced;
+/// every user can access all keys and channels.
+pub struct Acl {
+    users: BTreeMap<Bytes, AclUser>,
+}
+
+impl Acl {
+    pub fn new() -> Self {
+        let default_user = AclUser::default_user();
+        Self {
+            users: BTreeMap::from([(default_user.name.clone(), default_user)]),
+        }
+    }
+
+    /// Returns the user a connection acts as, or `None` when the connection has not
+    /// authenticated and the default user requires a password.
+    fn current_user(&self, client_info: &ClientConnectionInfo) -> Option<&AclUser> {
+        match client_info.user() {
+            Some(name) => self.users.get(&name),
+            None => self
+                .users
+                .get(DEFAULT_USER)
+                .filter(|user| user.is_enabled && user.is_nopass),
+        }
+    }
+
+    /// Checks whether a connection may run a command, returning the error reply when it
+    /// may not. The link to the primary is always trusted.
+    pub fn check_permission(
+        &self,
+        client_info: &ClientConnectionInfo,
+        command: &RedisCommand,
+    ) -> Option<RESPValue> {
+        if client_info.id == ClientId::primary() || !command.requires_auth() {
+            return None;
+        }
+
+        let Some(user) = self.current_user(client_info) else {
+            return Some(encoding::simple_error("NOAUTH Authentication required."));
+        };
+
+        let command_name = command.name();
+        (!user.can_run(command_name)).then(|| {
+            encoding::simple_error(format!(
+                "NOPERM User {} has no permissions to run the '{command_name}' command",
+                String::from_utf8_lossy(&user.name)
+            ))
+        })
+    }
+
+    pub async fn auth(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        username: Option<&Bytes>,
+        password: &Bytes,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let default_user = Bytes::from_static(DEFAULT_USER);
+        let user = self.users.get(username.unwrap_or(&default_user));
+        if username.is_none() && user.is_some_and(|user| user.is_nopass) {
+            return write_stream
+                .write(encoding::simple_error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?"))
+                .await;
+        }
+
+        match user {
+            Some(user) if user.check_password(password) => {
+                client_info.set_user(user.name.clone());
+                write_stream.write(encoding::simple_string("OK")).await
+            }
+            _ => {
+                write_stream
+                    .write(encoding::simple_error(
+                        "WRONGPASS invalid username-password pair or user is disabled.",
+                    ))
+                    .await
+            }
+        }
+    }
+
+    pub async fn handle_command(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        section: &AclSection,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match section {
+            AclSection::WhoAmI => match self.current_user(client_info) {
+                Some(user) => encoding::bulk_string(&user.name),
+                None => encoding::null_bulk_string(),
+            },
+            AclSection::List => encoding::array(
+                self.users
+                    .values()
+                    .map(|user| encoding::bulk_string(user.describe()))
+                    .collect(),
+            ),
+            AclSection::Cat => {
+                encoding::array(CATEGORIES.iter().map(encoding::bulk_string).collect())
+            }
+            AclSection::SetUser { username, rules } => {
+                let mut user = self
+                    .users
+                    .get(username)
+                    .cloned()
+                    .unwrap_or_else(|| AclUser::new(username.clone()));
+
+                // rules are applied to a copy so that a failing rule leaves the user untouched
+                for rule in rules {
+                    if let Err(err) = user.apply_rule(rule) {
+                        return write_stream
+                            .write(encoding::simple_error(format!(
+                                "ERR Error in ACL SETUSER modifier '{}': {err}",
+                                String::from_utf8_lossy(rule)
+                            )))
+                            .await;
+                    }
+                }
+
+                self.users.insert(username.clone(), user);
+                encoding::simple_string("OK")
+            }
+            AclSection::Help => encoding::help(
+                "acl",
+                &[
+                    "CAT",
+                    "    List all command categories.",
+                    "GETUSER <username>",
+                    "    Get the user's details.",
+                    "LIST",
+                    "    Show users details in config file format.",
+                    "SETUSER <username> <attribute> [<attribute> ...]",
+                    "    Create or modify a user with the specified attributes.",
+                    "WHOAMI",
+                    "    Return the current connection username.",
+                ],
+            ),
+            AclSection::GetUser { username } => match self.users.get(username) {
+                Some(user) => {
+                    let mut flags = vec![encoding::bulk_string(if user.is_enabled {
+                        "on"
+                    } else {
+                        "off"
+                    })];
+                    if user.is_nopass {
+                        flags.push(encoding::bulk_string("nopass"));
+                    }
+
+                    encoding::array(vec![
+                        encoding::bulk_string("flags"),
+                        encoding::array(flags),
+                        encoding::bulk_string("passwords"),
+                        encoding::array(
+                            user.password_hashes
+                                .iter()
+                                .map(encoding::bulk_string)
+                                .collect(),
+                        ),
+                        encoding::bulk_string("commands"),
+                        encoding::bulk_string(user.describe_commands()),
+                        encoding::bulk_string("keys"),
+                        encoding::bulk_string("~*"),
+                        encoding::bulk_string("channels"),
+                        encoding::bulk_string("&*"),
+                    ])
+                }
+                None => encoding::null_bulk_string(),
+            },
+        };
+
+        write_stream.write(reply).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::AclUser;
+
+    fn user_with_rules(rules: &[&[u8]]) -> AclUser {
+        let mut user = AclUser::new(Bytes::from_static(b"alice"));
+        for rule in rules {
+            user.apply_rule(rule).unwrap();
+        }
+
+        user
+    }
+
+    #[test]
+    fn applies_command_rules_in_order() {
+        let user = user_with_rules(&[b"on", b"+@all", b"-set", b"+set", b"-keys"]);
+        assert!(user.can_run("get"));
+        assert!(user.can_run("set"));
+        assert!(!user.can_run("keys"));
+        assert_eq!(user.describe_commands(), "+@all -keys");
+
+        let user = user_with_rules(&[b"on", b"-@all", b"+get"]);
+        assert!(user.can_run("get"));
+        assert!(!user.can_run("set"));
+        assert_eq!(user.describe_commands(), "-@all +get");
+    }
+
+    #[test]
+    fn checks_passwords() {
+        let user = user_with_rules(&[b"on", b">secret"]);
+        assert!(user.check_password(b"secret"));
+        assert!(!user.check_password(b"wrong"));
+
+        let user = user_with_rules(&[b">secret"]);
+        assert!(!user.check_password(b"secret"));
+
+        let user = user_with_rules(&[b"on", b">secret", b"nopass"]);
+        assert!(user.check_password(b"anything"));
+    }
+}
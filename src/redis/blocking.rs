@@ -0,0 +1,159 @@
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use super::{
+    resp::command::{ListEnd, MinOrMax},
+    server::{ClientConnectionInfo, ClientId, RedisWriteStream},
+};
+
+/// What a blocked client pops once one of its keys has something to pop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockedPop {
+    SortedSet(MinOrMax),
+    List(ListEnd),
+}
+
+/// A client blocked by `BZPOPMIN`, `BZPOPMAX`, `BZMPOP` or `BLMPOP` until one of its keys
+/// holds members or elements.
+pub struct BlockedClient {
+    pub client_info: ClientConnectionInfo,
+    pub write_stream: RedisWriteStream,
+    pub db: usize,
+    pub keys: Vec<Bytes>,
+    pub pop: BlockedPop,
+    /// How many members `BZMPOP` or elements `BLMPOP` pops, or `None` for `BZPOPMIN` and
+    /// `BZPOPMAX`, which pop one and reply with it on its own.
+    pub count: Option<usize>,
+    /// When to give up and reply with a null array, or `None` to wait forever.
+    pub deadline: Option<Instant>,
+}
+
+/// The blocked clients, indexed by the keys they wait on so that a write only wakes up
+/// the clients that wait on the key it wrote to.
+#[derive(Default)]
+pub struct BlockedClients {
+    clients: HashMap<ClientId, BlockedClient>,
+    /// The clients waiting on each key of each database, in the order they blocked.
+    waiting: HashMap<(usize, Bytes), VecDeque<ClientId>>,
+}
+
+impl BlockedClients {
+    pub fn block(&mut self, client: BlockedClient) {
+        for key in &client.keys {
+            self.waiting
+                .entry((client.db, key.clone()))
+                .or_default()
+                .push_back(client.client_info.id);
+        }
+
+        self.clients.insert(client.client_info.id, client);
+    }
+
+    /// Unblocks the clients waiting on the key, in the order they blocked.
+    pub fn take_waiting_on(&mut self, db: usize, key: &Bytes) -> Vec<BlockedClient> {
+        let Some(ids) = self.waiting.remove(&(db, key.clone())) else {
+            return vec![];
+        };
+
+        ids.into_iter().filter_map(|id| self.remove(id)).collect()
+    }
+
+    /// Unblocks the clients whose deadline has passed.
+    pub fn take_timed_out(&mut self, now: Instant) -> Vec<BlockedClient> {
+        let ids = self
+            .clients
+            .values()
+            .filter(|client| client.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|client| client.client_info.id)
+            .collect::<Vec<_>>();
+
+        ids.into_iter().filter_map(|id| self.remove(id)).collect()
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.clients
+            .values()
+            .filter_map(|client| client.deadline)
+            .min()
+    }
+
+    fn remove(&mut self, id: ClientId) -> Option<BlockedClient> {
+        let client = self.clients.remove(&id)?;
+        for key in &client.keys {
+            if let Entry::Occupied(mut waiting) = self.waiting.entry((client.db, key.clone())) {
+                waiting.get_mut().retain(|waiting_id| *waiting_id != id);
+                if waiting.get().is_empty() {
+                    waiting.remove();
+                }
+            }
+        }
+
+        Some(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use bytes::Bytes;
+    use tokio::time::Instant;
+
+    use crate::redis::{
+        resp::command::MinOrMax,
+        server::{ClientAddress, ClientConnectionInfo, ClientId, RedisWriteStream},
+    };
+
+    use super::{BlockedClient, BlockedClients, BlockedPop};
+
+    fn blocked_client(
+        id: ClientId,
+        keys: &[&'static str],
+        deadline: Option<Instant>,
+    ) -> BlockedClient {
+        BlockedClient {
+            client_info: ClientConnectionInfo::new(id, ClientAddress::Unix(PathBuf::new())),
+            write_stream: RedisWriteStream::sink(),
+            db: 0,
+            keys: keys
+                .iter()
+                .map(|key| Bytes::from_static(key.as_bytes()))
+                .collect(),
+            pop: BlockedPop::SortedSet(MinOrMax::Min),
+            count: None,
+            deadline,
+        }
+    }
+
+    #[tokio::test]
+    async fn wakes_clients_waiting_on_a_key_in_order() {
+        let mut blocked_clients = BlockedClients::default();
+        let now = Instant::now();
+        let ids = (0..3).map(ClientId::new).collect::<Vec<_>>();
+        blocked_clients.block(blocked_client(ids[0], &["a", "b"], None));
+        blocked_clients.block(blocked_client(ids[1], &["b"], Some(now)));
+        blocked_clients.block(blocked_client(
+            ids[2],
+            &["a"],
+            Some(now + Duration::from_secs(60)),
+        ));
+        assert_eq!(blocked_clients.next_deadline(), Some(now));
+
+        let woken = blocked_clients.take_waiting_on(0, &Bytes::from_static(b"a"));
+        let woken = woken
+            .iter()
+            .map(|client| client.client_info.id)
+            .collect::<Vec<_>>();
+        assert_eq!(woken, [ids[0], ids[2]]);
+
+        let timed_out = blocked_clients.take_timed_out(now);
+        assert_eq!(timed_out.len(), 1);
+        assert_eq!(timed_out[0].client_info.id, ids[1]);
+        assert!(blocked_clients
+            .take_waiting_on(0, &Bytes::from_static(b"b"))
+            .is_empty());
+        assert_eq!(blocked_clients.next_deadline(), None);
+    }
+}
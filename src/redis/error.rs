@@ -0,0 +1,78 @@
+use super::resp::{encoding, resp_reader::ProtocolError, RESPValue};
+
+/// What went wrong while reading or running a command, which decides whether the client
+/// is told about it and whether its connection survives.
+#[derive(Debug, thiserror::Error)]
+pub enum RedisError {
+    /// The client broke the protocol, so nothing it sends next can be trusted. It is
+    /// replied to before the connection is closed.
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+    /// A command that cannot run as sent, e.g. against a key of the wrong type. It is
+    /// replied to with this error, and the connection stays open.
+    #[error("{0}")]
+    Command(String),
+    /// The connection cannot be used anymore, so it is closed without a reply.
+    #[error(transparent)]
+    Transport(anyhow::Error),
+}
+
+impl RedisError {
+    /// The error reply the client gets, if any.
+    pub fn reply(&self) -> Option<RESPValue> {
+        match self {
+            Self::Protocol(err) => Some(encoding::simple_error(format!("ERR {err}"))),
+            Self::Command(message) => Some(encoding::simple_error(message)),
+            Self::Transport(_) => None,
+        }
+    }
+
+    pub fn closes_connection(&self) -> bool {
+        !matches!(self, Self::Command(_))
+    }
+}
+
+/// Recovers the kind of an error that was passed along as an [`anyhow::Error`], so any
+/// error that is not known to be the client's fault closes its connection.
+impl From<anyhow::Error> for RedisError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<Self>() {
+            Ok(err) => err,
+            Err(err) => match err.downcast::<ProtocolError>() {
+                Ok(err) => Self::Protocol(err),
+                Err(err) => Self::Transport(err),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redis::resp::{encoding, resp_reader::ProtocolError};
+
+    use super::RedisError;
+
+    #[test]
+    fn keeps_the_connection_open_only_for_command_errors() {
+        let command = RedisError::Command("WRONGTYPE wrong kind of value".to_string());
+        assert_eq!(
+            command.reply(),
+            Some(encoding::simple_error("WRONGTYPE wrong kind of value"))
+        );
+        assert!(!command.closes_connection());
+
+        let protocol = RedisError::from(anyhow::Error::from(ProtocolError::InvalidBulkLength));
+        assert_eq!(
+            protocol.reply(),
+            Some(encoding::simple_error(
+                "ERR Protocol error: invalid bulk length"
+            ))
+        );
+        assert!(protocol.closes_connection());
+
+        let transport = RedisError::from(anyhow::anyhow!("connection reset"));
+        assert!(matches!(transport, RedisError::Transport(_)));
+        assert!(transport.reply().is_none());
+        assert!(transport.closes_connection());
+    }
+}
@@ -0,0 +1,184 @@
+/// How deeply `*` may recurse before a pattern is considered not to match, which keeps
+/// patterns like `a*a*a*...` from using up the stack.
+const MAX_NESTING: usize = 1000;
+
+/// Whether `string` matches the glob-style `pattern`, following the rules of Redis:
+/// `*` matches any sequence, `?` any single byte, `[...]` a class of bytes that can
+/// be negated with `^` and contain ranges, and `\` escapes the next byte.
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let mut skip_longer_matches = false;
+    matches_from(pattern, string, &mut skip_longer_matches, 0)
+}
+
+fn matches_from(
+    pattern: &[u8],
+    string: &[u8],
+    skip_longer_matches: &mut bool,
+    nesting: usize,
+) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    let (mut p, mut s) = (0, 0);
+    while p < pattern.len() && s < string.len() {
+        match pattern[p] {
+            b'*' => {
+                while pattern.get(p + 1) == Some(&b'*') {
+                    p += 1;
+                }
+
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+
+                while s < string.len() {
+                    if matches_from(
+                        &pattern[p + 1..],
+                        &string[s..],
+                        skip_longer_matches,
+                        nesting + 1,
+                    ) {
+                        return true;
+                    }
+
+                    if *skip_longer_matches {
+                        return false;
+                    }
+
+                    s += 1;
+                }
+
+                // the rest of the pattern matches nowhere in the rest of the string, so
+                // earlier stars cannot match either by consuming more of it
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => s += 1,
+            b'[' => {
+                p += 1;
+                let is_negated = pattern.get(p) == Some(&b'^');
+                if is_negated {
+                    p += 1;
+                }
+
+                let mut is_match = false;
+                loop {
+                    match pattern.get(p) {
+                        Some(b'\\') if pattern.len() - p >= 2 => {
+                            p += 1;
+                            is_match |= pattern[p] == string[s];
+                        }
+                        Some(b']') => break,
+                        // an unterminated class ends with the pattern
+                        None => {
+                            p -= 1;
+                            break;
+                        }
+                        Some(&start) if pattern.len() - p >= 3 && pattern[p + 1] == b'-' => {
+                            let end = pattern[p + 2];
+                            let (start, end) = (start.min(end), start.max(end));
+                            is_match |= (start..=end).contains(&string[s]);
+                            p += 2;
+                        }
+                        Some(&byte) => is_match |= byte == string[s],
+                    }
+
+                    p += 1;
+                }
+
+                if is_match == is_negated {
+                    return false;
+                }
+
+                s += 1;
+            }
+            byte => {
+                let byte = if byte == b'\\' && pattern.len() - p >= 2 {
+                    p += 1;
+                    pattern[p]
+                } else {
+                    byte
+                };
+
+                if byte != string[s] {
+                    return false;
+                }
+
+                s += 1;
+            }
+        }
+
+        p += 1;
+        if s == string.len() {
+            while pattern.get(p) == Some(&b'*') {
+                p += 1;
+            }
+
+            break;
+        }
+    }
+
+    p == pattern.len() && s == string.len()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn matches_like_redis() {
+        let cases: &[(&[u8], &[u8], bool)] = &[
+            (b"", b"", true),
+            (b"", b"a", false),
+            // like in Redis, a pattern is only tried against a non-empty string
+            (b"*", b"", false),
+            (b"*", b"anything", true),
+            (b"h?llo", b"hello", true),
+            (b"h?llo", b"hllo", false),
+            (b"h*llo", b"heeeello", true),
+            (b"h*llo*", b"hello", true),
+            (b"h[ae]llo", b"hallo", true),
+            (b"h[ae]llo", b"hillo", false),
+            (b"h[^e]llo", b"hallo", true),
+            (b"h[^e]llo", b"hello", false),
+            (b"h[a-b]llo", b"hbllo", true),
+            (b"h[b-a]llo", b"hallo", true),
+            (b"h[a-b]llo", b"hcllo", false),
+            (b"[\\]]", b"]", true),
+            (b"[a-", b"a", true),
+            (b"[a-", b"-", true),
+            (b"[a-", b"b", false),
+            (b"[", b"[", false),
+            (b"\\*", b"*", true),
+            (b"\\*", b"a", false),
+            (b"\\?x", b"?x", true),
+            (b"a\\", b"a\\", true),
+            (b"*\\", b"a\\", true),
+        ];
+
+        for (pattern, string, expected) in cases {
+            assert_eq!(
+                super::matches(pattern, string),
+                *expected,
+                "{} against {}",
+                String::from_utf8_lossy(pattern),
+                String::from_utf8_lossy(string)
+            );
+        }
+    }
+
+    #[test]
+    fn gives_up_on_abusive_patterns_quickly() {
+        let pattern = format!("{}b", "a*".repeat(50));
+        let string = "a".repeat(1_000_000);
+        assert!(!super::matches(pattern.as_bytes(), string.as_bytes()));
+
+        // repeated stars collapse into one instead of nesting
+        let pattern = "*".repeat(2000) + "a";
+        assert!(super::matches(pattern.as_bytes(), b"ba"));
+
+        // too many nested stars never match
+        let pattern = "a*".repeat(2000);
+        let string = "a".repeat(2000);
+        assert!(!super::matches(pattern.as_bytes(), string.as_bytes()));
+    }
+}
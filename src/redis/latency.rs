@@ -0,0 +1,218 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+use super::resp::{encoding, RESPValue};
+
+/// Like in Redis, only this many of the latest spikes are kept for each event.
+const MAX_SAMPLES: usize = 160;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LatencySample {
+    /// When the spike happened, in seconds since the epoch.
+    timestamp: u64,
+    /// How long the spike took, in milliseconds.
+    latency: u64,
+}
+
+#[derive(Debug, Default)]
+struct LatencyEvent {
+    samples: VecDeque<LatencySample>,
+    max: u64,
+}
+
+/// The latency spikes of events such as executing a command, recorded when they take at
+/// least `threshold` milliseconds. A threshold of 0 turns the monitor off.
+#[derive(Debug)]
+pub struct LatencyMonitor {
+    pub threshold: u64,
+    events: BTreeMap<Bytes, LatencyEvent>,
+}
+
+impl LatencyMonitor {
+    pub fn new(threshold: u64) -> Self {
+        Self {
+            threshold,
+            events: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_spike(&self, duration: Duration) -> bool {
+        self.threshold > 0 && duration.as_millis() >= u128::from(self.threshold)
+    }
+
+    pub fn record(&mut self, event: &'static str, duration: Duration) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let latency = duration.as_millis() as u64;
+        self.push(event, LatencySample { timestamp, latency });
+    }
+
+    fn push(&mut self, event: &'static str, sample: LatencySample) {
+        let event = self
+            .events
+            .entry(Bytes::from_static(event.as_bytes()))
+            .or_default();
+        event.max = event.max.max(sample.latency);
+        // spikes within the same second are merged into the worst of them
+        match event.samples.back_mut() {
+            Some(latest) if latest.timestamp == sample.timestamp => {
+                latest.latency = latest.latency.max(sample.latency);
+            }
+            _ => {
+                if event.samples.len() == MAX_SAMPLES {
+                    event.samples.pop_front();
+                }
+
+                event.samples.push_back(sample);
+            }
+        }
+    }
+
+    /// Every spike of an event, oldest first, as pairs of timestamp and latency.
+    pub fn history(&self, event: &[u8]) -> RESPValue {
+        let samples = self.events.get(event).map_or_else(Vec::new, |event| {
+            event
+                .samples
+                .iter()
+                .map(|sample| {
+                    encoding::array(vec![
+                        encoding::integer(sample.timestamp as i64),
+                        encoding::integer(sample.latency as i64),
+                    ])
+                })
+                .collect()
+        });
+
+        encoding::array(samples)
+    }
+
+    /// The latest spike of each event, along with the worst spike it ever had.
+    pub fn latest(&self) -> RESPValue {
+        let events = self
+            .events
+            .iter()
+            .filter_map(|(name, event)| {
+                let latest = event.samples.back()?;
+                Some(encoding::array(vec![
+                    encoding::bulk_string(name),
+                    encoding::integer(latest.timestamp as i64),
+                    encoding::integer(latest.latency as i64),
+                    encoding::integer(event.max as i64),
+                ]))
+            })
+            .collect();
+
+        encoding::array(events)
+    }
+
+    /// Forgets the spikes of the given events, or of every event when none are given,
+    /// returning how many events had spikes.
+    pub fn reset(&mut self, events: &[Bytes]) -> usize {
+        if events.is_empty() {
+            let count = self.events.len();
+            self.events.clear();
+            return count;
+        }
+
+        events
+            .iter()
+            .filter(|event| self.events.remove(*event).is_some())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use crate::redis::resp::encoding;
+
+    use super::{LatencyMonitor, LatencySample, MAX_SAMPLES};
+
+    #[test]
+    fn records_spikes_over_the_threshold() {
+        let mut monitor = LatencyMonitor::new(10);
+        assert!(!monitor.is_spike(Duration::from_millis(9)));
+        assert!(monitor.is_spike(Duration::from_millis(10)));
+        assert!(!LatencyMonitor::new(0).is_spike(Duration::from_secs(1)));
+
+        monitor.push(
+            "command",
+            LatencySample {
+                timestamp: 100,
+                latency: 20,
+            },
+        );
+        // merged with the previous spike, which was worse
+        monitor.push(
+            "command",
+            LatencySample {
+                timestamp: 100,
+                latency: 15,
+            },
+        );
+        monitor.push(
+            "command",
+            LatencySample {
+                timestamp: 101,
+                latency: 12,
+            },
+        );
+
+        assert_eq!(
+            monitor.history(b"command"),
+            encoding::array(vec![
+                encoding::array(vec![encoding::integer(100), encoding::integer(20)]),
+                encoding::array(vec![encoding::integer(101), encoding::integer(12)]),
+            ])
+        );
+        assert_eq!(
+            monitor.latest(),
+            encoding::array(vec![encoding::array(vec![
+                encoding::bulk_string("command"),
+                encoding::integer(101),
+                encoding::integer(12),
+                encoding::integer(20),
+            ])])
+        );
+        assert_eq!(monitor.history(b"expire-cycle"), encoding::array(vec![]));
+    }
+
+    #[test]
+    fn keeps_the_latest_samples_until_reset() {
+        let mut monitor = LatencyMonitor::new(1);
+        for timestamp in 0..MAX_SAMPLES as u64 + 10 {
+            monitor.push(
+                "command",
+                LatencySample {
+                    timestamp,
+                    latency: 5,
+                },
+            );
+        }
+
+        monitor.record("fast-command", Duration::from_millis(1));
+        assert_eq!(monitor.latest().into_array().unwrap().len(), 2);
+        let history = monitor.history(b"command").into_array().unwrap();
+        assert_eq!(history.len(), MAX_SAMPLES);
+        assert_eq!(
+            history[0],
+            encoding::array(vec![encoding::integer(10), encoding::integer(5)])
+        );
+
+        assert_eq!(
+            monitor.reset(&[Bytes::from("command"), Bytes::from("unknown")]),
+            1
+        );
+        assert_eq!(monitor.reset(&[]), 1);
+        assert_eq!(monitor.history(b"fast-command"), encoding::array(vec![]));
+        assert_eq!(monitor.latest(), encoding::array(vec![]));
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How important a log line is. Lines below the level given with `--loglevel` are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    Notice,
+    Warning,
+}
+
+impl LogLevel {
+    pub fn parse(name: &str) -> Option<Self> {
+        match &*name.to_ascii_lowercase() {
+            "debug" => Some(Self::Debug),
+            "verbose" => Some(Self::Verbose),
+            "notice" => Some(Self::Notice),
+            "warning" => Some(Self::Warning),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Verbose => "verbose",
+            Self::Notice => "notice",
+            Self::Warning => "warning",
+        }
+    }
+
+    fn marker(self) -> char {
+        match self {
+            Self::Debug => '.',
+            Self::Verbose => '-',
+            Self::Notice => '*',
+            Self::Warning => '#',
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Notice as u8);
+static IS_REPLICA: AtomicBool = AtomicBool::new(false);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Debug,
+        1 => LogLevel::Verbose,
+        2 => LogLevel::Notice,
+        _ => LogLevel::Warning,
+    }
+}
+
+/// Sets the role shown in log lines, `M` for a primary and `S` for a replica.
+pub fn set_role(is_replica: bool) {
+    IS_REPLICA.store(is_replica, Ordering::Relaxed);
+}
+
+/// Writes a line to stderr in the same shape as Redis, e.g.
+/// `1234:M 17 Oct 2026 19:18:01.123 * Ready to accept connections`.
+pub fn write(level: LogLevel, message: fmt::Arguments) {
+    if (level as u8) < LEVEL.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let role = if IS_REPLICA.load(Ordering::Relaxed) {
+        'S'
+    } else {
+        'M'
+    };
+
+    eprintln!(
+        "{}:{} {} {} {}",
+        std::process::id(),
+        role,
+        format_timestamp(SystemTime::now()),
+        level.marker(),
+        message
+    );
+}
+
+/// Formats a UTC timestamp as `17 Oct 2026 19:18:01.123`.
+fn format_timestamp(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = (seconds / 86400, seconds % 86400);
+
+    // converts days since the epoch to a civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{} {} {} {:02}:{:02}:{:02}.{:03}",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Logs a line at the given level, e.g. `log!(Notice, "server started at {}", address)`.
+macro_rules! log {
+    ($level:ident, $($arg:tt)*) => {
+        $crate::redis::log::write($crate::redis::log::LogLevel::$level, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use log;
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::format_timestamp;
+
+    #[test]
+    fn formats_timestamps_like_redis() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1 Jan 1970 00:00:00.000");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_millis(951_782_400_123)),
+            "29 Feb 2000 00:00:00.123"
+        );
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(1_792_264_708)),
+            "17 Oct 2026 19:18:28.000"
+        );
+    }
+}
@@ -1,16 +1,48 @@
-use std::net::SocketAddr;
+use std::{sync::atomic::Ordering, time::UNIX_EPOCH};
 
 use bytes::Bytes;
-use tokio::sync::mpsc;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+    task::JoinHandle,
+    time::{interval, sleep_until, Duration, Instant},
+};
 
-use crate::redis::resp::command::{RedisCommand, RedisServerCommand};
+use crate::redis::resp::command::{RedisCommand, RedisServerCommand, RedisStoreCommand};
 
 use super::{
-    rdb::{RDBConfig, RDBPesistence},
+    acl::Acl,
+    blocking::{BlockedClient, BlockedClients, BlockedPop},
+    error::RedisError,
+    glob,
+    latency::LatencyMonitor,
+    log::{self, log},
+    monitor::Monitors,
+    pubsub::PubSub,
+    random,
+    rdb::{self, RDBConfig, RDBPesistence},
     replication::{RedisReplication, RedisReplicationMode},
-    resp::{command::ConfigSection, encoding},
-    server::{ClientConnectionInfo, RedisReadStream, RedisServer, RedisWriteStream},
-    store::RedisStore,
+    resp::{
+        command::{
+            ClientSection, ClusterSection, CommandSection, ConfigSection, DebugSection,
+            FunctionSection, InfoSection, LatencySection, ScriptSection, SlowLogSection,
+            XInfoSection,
+        },
+        command_docs,
+        command_table::{self, CommandSpec},
+        encoding, RESPValue,
+    },
+    server::{
+        ClientConnectionInfo, ConnectionGuard, ConnectionStats, RedisReadStream, RedisServer,
+        RedisWriteStream, ServerConfig,
+    },
+    slowlog::SlowLog,
+    store::{
+        sort::Sort,
+        stream::{StreamEntryId, XAddEntryId},
+        EncodingConfig, RedisStore, StoreValue,
+    },
+    REDIS_VERSION,
 };
 
 pub struct RedisCommandPacket {
@@ -34,77 +66,1350 @@ impl RedisCommandPacket {
 }
 
 pub struct RedisManager {
-    address: SocketAddr,
+    server_config: ServerConfig,
+    connection_stats: ConnectionStats,
+    acl: Acl,
+    pubsub: PubSub,
+    monitors: Monitors,
+    paused_writes: Vec<RedisCommandPacket>,
+    blocked_clients: BlockedClients,
     store: RedisStore,
     replication: RedisReplication,
     rdb_persistence: RDBPesistence,
+    /// The save started once a save point was met, with the number of changes its
+    /// snapshot covers, until its result is back.
+    background_save: Option<(usize, JoinHandle<anyhow::Result<()>>)>,
+    slowlog: SlowLog,
+    latency_monitor: LatencyMonitor,
+    /// A random ID for this run of the server, reported by `INFO`.
+    run_id: String,
+    /// Whether the RDB file is still being loaded. Data commands are rejected until it is.
+    is_loading: bool,
+    /// Whether expired keys are removed in the background. When turned off with `DEBUG
+    /// SET-ACTIVE-EXPIRE 0`, a key outlives its TTL until a command touches it, which
+    /// keeps expiry deterministic for tests.
+    is_active_expire_enabled: bool,
 }
 
 impl RedisManager {
     pub fn new(
-        address: SocketAddr,
+        server_config: ServerConfig,
         store: RedisStore,
         replication_mode: RedisReplicationMode,
         rdb_config: RDBConfig,
     ) -> Self {
         Self {
-            address,
+            replication: RedisReplication::new(server_config.port, replication_mode),
+            slowlog: SlowLog::new(server_config.slowlog),
+            latency_monitor: LatencyMonitor::new(server_config.latency_monitor_threshold),
+            server_config,
+            connection_stats: ConnectionStats::default(),
+            acl: Acl::new(),
+            pubsub: PubSub::new(),
+            monitors: Monitors::default(),
+            paused_writes: vec![],
+            blocked_clients: BlockedClients::default(),
             store,
-            replication: RedisReplication::new(address, replication_mode),
             rdb_persistence: RDBPesistence::new(rdb_config),
+            background_save: None,
+            run_id: random::hex_id(),
+            is_loading: false,
+            is_active_expire_enabled: true,
         }
     }
 
     pub async fn start(&mut self) -> anyhow::Result<()> {
         let (command_tx, mut command_rx) = mpsc::channel(32);
-        let server = RedisServer::start(self.address).await?;
-        eprintln!("[redis] server started at {}", self.address);
-
-        let rdb_store = self.rdb_persistence.setup().await?;
-        self.store.merge(rdb_store);
-        self.replication.setup(command_tx.clone()).await?;
-        self.setup_client_connection_handling(server, command_tx);
-        while let Some(RedisCommandPacket {
+        let server = RedisServer::start(&self.server_config).await?;
+        for address in self.server_config.addresses() {
+            log!(Notice, "server started at {}", address);
+        }
+
+        if let Some(path) = &self.server_config.unix_socket {
+            log!(Notice, "server started at {}", path.display());
+        }
+
+        // replication starts once the dataset is loaded so that commands streamed by a
+        // primary are applied on top of it
+        let rdb_load = self.rdb_persistence.setup(self.store.database_count());
+        tokio::pin!(rdb_load);
+        self.is_loading = true;
+        self.setup_client_connection_handling(server, command_tx.clone());
+        let shutdown_signal = Self::shutdown_signal();
+        tokio::pin!(shutdown_signal);
+        let mut save_point_check = interval(Duration::from_secs(1));
+        let mut expire_cycle = interval(Duration::from_millis(100));
+        loop {
+            let failover_deadline = self.replication.failover_deadline();
+            let blocked_deadline = self.blocked_clients.next_deadline();
+            let packet = tokio::select! {
+                packet = command_rx.recv() => packet,
+                result = &mut rdb_load, if self.is_loading => {
+                    self.store.merge(result??);
+                    self.is_loading = false;
+                    log!(Notice, "DB loaded from disk");
+                    self.replication.setup(command_tx.clone()).await?;
+                    continue;
+                }
+                _ = sleep_until(failover_deadline.unwrap_or_else(Instant::now)), if failover_deadline.is_some() => {
+                    log!(Warning, "failover timed out, aborting");
+                    self.replication.abort_failover();
+                    self.resume_paused_writes().await?;
+                    continue;
+                }
+                _ = sleep_until(blocked_deadline.unwrap_or_else(Instant::now)), if blocked_deadline.is_some() => {
+                    self.time_out_blocked_clients().await;
+                    continue;
+                }
+                _ = save_point_check.tick(), if !self.is_loading => {
+                    self.save_if_needed();
+                    continue;
+                }
+                result = Self::background_save_result(&mut self.background_save), if self.background_save.is_some() => {
+                    self.finish_background_save(result);
+                    continue;
+                }
+                _ = expire_cycle.tick(), if !self.is_loading && self.is_active_expire_enabled => {
+                    self.remove_expired_keys();
+                    continue;
+                }
+                result = &mut shutdown_signal => {
+                    result?;
+                    log!(Notice, "received shutdown signal");
+                    None
+                }
+            };
+
+            let Some(packet) = packet else {
+                break;
+            };
+
+            self.process_packet(packet).await;
+            if self.replication.is_failover_in_progress() {
+                self.replication.try_complete_failover().await?;
+            }
+
+            if !self.replication.is_failover_in_progress() {
+                self.resume_paused_writes().await?;
+            }
+        }
+
+        self.shutdown()
+    }
+
+    /// Handles a packet without letting its errors stop the server. The client is replied
+    /// to with command errors, and its connection is closed on any other error.
+    async fn process_packet(&mut self, packet: RedisCommandPacket) {
+        let write_stream = packet.write_stream.clone();
+        let Err(err) = self.handle_packet(packet).await else {
+            return;
+        };
+
+        if let Some(reply) = err.reply() {
+            // the client may be gone already, in which case there is no one to tell
+            let _ = write_stream.write(reply).await;
+        }
+
+        if err.closes_connection() {
+            log!(Warning, "closing client connection: {err}");
+            write_stream.disconnect();
+        }
+    }
+
+    async fn handle_packet(&mut self, packet: RedisCommandPacket) -> Result<(), RedisError> {
+        let replicated_len = self
+            .replication
+            .replicated_len(packet.client_info.id, &packet.command);
+        self.execute_packet(packet).await?;
+        self.replication.post_command_hook(replicated_len);
+        Ok(())
+    }
+
+    async fn execute_packet(&mut self, packet: RedisCommandPacket) -> anyhow::Result<()> {
+        let RedisCommandPacket {
             client_info,
             command,
             write_stream,
-        }) = command_rx.recv().await
+        } = packet;
+
+        // like in Redis, the arguments are checked before permissions and the state of
+        // the server
+        if let RedisCommand::Invalid { error, .. } = &command {
+            return write_stream
+                .write(encoding::simple_error(error.to_string()))
+                .await;
+        }
+
+        if let Some(error) = self.acl.check_permission(&client_info, &command) {
+            return write_stream.write(error).await;
+        }
+
+        // RESP3 connections can tell pushed messages from replies, so they can keep
+        // running any command while subscribed
+        if client_info.is_subscribed()
+            && !client_info.is_resp3()
+            && !command.is_allowed_when_subscribed()
         {
-            match &command {
-                RedisCommand::Store(command) => {
-                    self.store.handle(command, write_stream).await?;
-                    if command.is_write() {
-                        self.replication.try_replicate(command.into()).await?;
-                    }
-                }
-                RedisCommand::Server(RedisServerCommand::Ping) => self.ping(write_stream).await?,
-                RedisCommand::Server(RedisServerCommand::Echo { message }) => {
-                    self.echo(message.clone(), write_stream).await?
-                }
-                RedisCommand::Server(RedisServerCommand::Config { section }) => {
-                    self.config(section, write_stream).await?
+            return write_stream
+                .write(encoding::simple_error(format!(
+                    "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                    command.name()
+                )))
+                .await;
+        }
+
+        // writes are paused during a failover so that the target can catch up, and the
+        // client is blocked to keep the order of its commands
+        if let RedisCommand::Store(store_command) = &command {
+            if store_command.is_write() && self.replication.is_failover_in_progress() {
+                client_info.is_read_blocked.store(true, Ordering::SeqCst);
+                self.paused_writes.push(RedisCommandPacket {
+                    client_info,
+                    command,
+                    write_stream,
+                });
+
+                return Ok(());
+            }
+        }
+
+        if self.is_loading && !command.is_allowed_while_loading() {
+            return write_stream
+                .write(encoding::simple_error(
+                    "LOADING Redis is loading the dataset in memory",
+                ))
+                .await;
+        }
+
+        self.monitors.feed(&client_info, &command).await?;
+        let started_at = Instant::now();
+        match &command {
+            RedisCommand::Store(RedisStoreCommand::BZPop { keys, timeout, end }) => {
+                let blocked_client = BlockedClient {
+                    db: client_info.selected_db(),
+                    client_info: client_info.clone(),
+                    write_stream,
+                    keys: keys.clone(),
+                    pop: BlockedPop::SortedSet(*end),
+                    count: None,
+                    // a deadline too far to represent is never reached anyway
+                    deadline: timeout.and_then(|timeout| Instant::now().checked_add(timeout)),
+                };
+
+                self.pop_or_block(blocked_client).await?
+            }
+            RedisCommand::Store(RedisStoreCommand::BZMPop {
+                keys,
+                timeout,
+                end,
+                count,
+            }) => {
+                let blocked_client = BlockedClient {
+                    db: client_info.selected_db(),
+                    client_info: client_info.clone(),
+                    write_stream,
+                    keys: keys.clone(),
+                    pop: BlockedPop::SortedSet(*end),
+                    count: Some(count.unwrap_or(1)),
+                    // a deadline too far to represent is never reached anyway
+                    deadline: timeout.and_then(|timeout| Instant::now().checked_add(timeout)),
+                };
+
+                self.pop_or_block(blocked_client).await?
+            }
+            RedisCommand::Store(RedisStoreCommand::BLMPop {
+                keys,
+                timeout,
+                end,
+                count,
+            }) => {
+                let blocked_client = BlockedClient {
+                    db: client_info.selected_db(),
+                    client_info: client_info.clone(),
+                    write_stream,
+                    keys: keys.clone(),
+                    pop: BlockedPop::List(*end),
+                    count: Some(count.unwrap_or(1)),
+                    // a deadline too far to represent is never reached anyway
+                    deadline: timeout.and_then(|timeout| Instant::now().checked_add(timeout)),
+                };
+
+                self.pop_or_block(blocked_client).await?
+            }
+            // the store replies the same way to every client, so the map RESP3 clients
+            // get is built here
+            RedisCommand::Store(RedisStoreCommand::XInfo {
+                section: XInfoSection::Stream { key },
+            }) if client_info.is_resp3() => {
+                let value = self.store.get(client_info.selected_db(), key);
+                write_stream
+                    .write(RedisStore::stream_info(value, true))
+                    .await?
+            }
+            RedisCommand::Store(command) => {
+                let db = client_info.selected_db();
+                let stream_top = self.stream_top(db, command);
+                let is_getdel_hit = self.is_getdel_hit(db, command);
+                self.store.handle(db, command, write_stream).await?;
+                if let Some(write) = self.replicated_write(db, command, stream_top, is_getdel_hit) {
+                    self.replication.try_replicate(db, write).await?;
                 }
-                RedisCommand::Replication(command) => {
-                    self.replication
-                        .handle_command(client_info, command, write_stream)
-                        .await?
+
+                if let RedisStoreCommand::ZAdd { key, .. }
+                | RedisStoreCommand::GeoAdd { key, .. }
+                | RedisStoreCommand::ListPush { key, .. }
+                | RedisStoreCommand::Sort {
+                    sort: Sort {
+                        store: Some(key), ..
+                    },
+                    ..
+                } = command
+                {
+                    for blocked_client in self.blocked_clients.take_waiting_on(db, key) {
+                        self.pop_or_block(blocked_client).await?;
+                    }
                 }
             }
+            RedisCommand::Server(RedisServerCommand::Ping { message }) => {
+                self.ping(&client_info, message.as_ref(), write_stream)
+                    .await?
+            }
+            RedisCommand::Server(RedisServerCommand::Echo { message }) => {
+                self.echo(message.clone(), write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Config { section }) => {
+                self.config(section, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Select { index }) => {
+                self.select(&client_info, *index, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Lolwut { .. }) => {
+                self.lolwut(write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Quit) => Self::quit(write_stream).await?,
+            RedisCommand::Server(RedisServerCommand::Monitor) => {
+                // the commands of every other client are fed to a monitor, so one that
+                // stops reading is dropped rather than only having its own reads paused
+                self.monitors.add(
+                    write_stream
+                        .clone()
+                        .with_output_buffer_limit(self.server_config.output_buffer_limits.normal),
+                );
+                write_stream.write(encoding::simple_string("OK")).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Hello { protocol_version }) => {
+                self.hello(&client_info, *protocol_version, write_stream)
+                    .await?
+            }
+            RedisCommand::Server(RedisServerCommand::Auth { username, password }) => {
+                self.acl
+                    .auth(&client_info, username.as_ref(), password, write_stream)
+                    .await?
+            }
+            RedisCommand::Server(RedisServerCommand::Acl { section }) => {
+                self.acl
+                    .handle_command(&client_info, section, write_stream)
+                    .await?
+            }
+            RedisCommand::Server(RedisServerCommand::Info { section }) => {
+                self.info(*section, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::LastSave) => {
+                self.lastsave(write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Debug { section }) => {
+                self.debug(&client_info, section, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Command { section }) => {
+                Self::command(section, client_info.is_resp3(), write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::SlowLog { section }) => {
+                self.slowlog(section, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Latency { section }) => {
+                self.latency(section, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Cluster { section }) => {
+                self.cluster(*section, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Client { section }) => {
+                Self::client(&client_info, *section, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Function { section }) => {
+                Self::function(*section, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::Eval { .. }) => {
+                write_stream
+                    .write(encoding::simple_error(
+                        "ERR EVAL is not supported since this server does not run scripts",
+                    ))
+                    .await?
+            }
+            // like in Redis, clients that find no cached script fall back to `EVAL`
+            RedisCommand::Server(RedisServerCommand::EvalSha { .. }) => {
+                write_stream
+                    .write(encoding::simple_error(
+                        "NOSCRIPT No matching script. Please use EVAL.",
+                    ))
+                    .await?
+            }
+            RedisCommand::Server(RedisServerCommand::Script { section }) => {
+                Self::script(section, write_stream).await?
+            }
+            RedisCommand::Server(RedisServerCommand::FCall { is_read_only, .. }) => {
+                let name = if *is_read_only { "FCALL_RO" } else { "FCALL" };
+                write_stream
+                    .write(encoding::simple_error(format!(
+                        "ERR {name} is not supported since this server does not run scripts"
+                    )))
+                    .await?
+            }
+            RedisCommand::PubSub(command) => {
+                let write_stream = write_stream
+                    .with_output_buffer_limit(self.server_config.output_buffer_limits.pubsub);
+                self.pubsub
+                    .handle_command(&client_info, command, write_stream)
+                    .await?
+            }
+            RedisCommand::Replication(command) => {
+                let write_stream = write_stream
+                    .with_output_buffer_limit(self.server_config.output_buffer_limits.replica);
+                self.replication
+                    .handle_command(client_info.clone(), command, write_stream)
+                    .await?
+            }
+            RedisCommand::Invalid { .. } => unreachable!("invalid commands are rejected above"),
+        }
+
+        let duration = started_at.elapsed();
+        if self.latency_monitor.is_spike(duration) {
+            let is_fast = command_table::lookup(command.name().as_bytes())
+                .is_some_and(|spec| spec.flags.contains(&"fast"));
+            let event = if is_fast { "fast-command" } else { "command" };
+            self.latency_monitor.record(event, duration);
+        }
+
+        if self.slowlog.is_slow(duration) {
+            self.slowlog
+                .push(&command, duration, client_info.address)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// The ID at the top of the stream an `XADD` adds to, before it runs.
+    fn stream_top(&self, db: usize, command: &RedisStoreCommand) -> Option<StreamEntryId> {
+        let RedisStoreCommand::XAdd { key, .. } = command else {
+            return None;
+        };
+
+        match self.store.get(db, key) {
+            Some(StoreValue::Stream(stream)) => Some(stream.last_id()),
+            _ => None,
+        }
+    }
+
+    /// Whether the command is a `GETDEL` whose key holds a string, which it deletes.
+    fn is_getdel_hit(&self, db: usize, command: &RedisStoreCommand) -> bool {
+        let RedisStoreCommand::GetDel { key } = command else {
+            return false;
+        };
+
+        matches!(self.store.get(db, key), Some(StoreValue::String { .. }))
+    }
+
+    /// What is sent to replicas for a command, if anything. An `XADD` is only sent when
+    /// it added an entry, and with the ID picked here, since a replica would pick its own.
+    /// A `GETDEL` is sent as the `DEL` it amounts to, and only when it deleted the key.
+    fn replicated_write(
+        &self,
+        db: usize,
+        command: &RedisStoreCommand,
+        stream_top: Option<StreamEntryId>,
+        is_getdel_hit: bool,
+    ) -> Option<Bytes> {
+        match command {
+            RedisStoreCommand::XAdd {
+                key,
+                no_mkstream,
+                trim,
+                fields,
+                ..
+            } => {
+                let Some(StoreValue::Stream(stream)) = self.store.get(db, key) else {
+                    return None;
+                };
+
+                let entry_id = stream.last_id();
+                (Some(entry_id) != stream_top).then(|| {
+                    encoding::xadd(
+                        key,
+                        &XAddEntryId::Explicit(entry_id),
+                        *no_mkstream,
+                        trim.as_ref(),
+                        fields,
+                    )
+                })
+            }
+            RedisStoreCommand::GetDel { key } => {
+                is_getdel_hit.then(|| encoding::del(std::slice::from_ref(key)))
+            }
+            command if command.is_write() => Some(command.into()),
+            _ => None,
+        }
+    }
+
+    /// Processes the writes that were paused by a failover. When the failover completed,
+    /// this node is now a replica and rejects them.
+    async fn resume_paused_writes(&mut self) -> anyhow::Result<()> {
+        for packet in std::mem::take(&mut self.paused_writes) {
+            packet
+                .client_info
+                .is_read_blocked
+                .store(false, Ordering::SeqCst);
+            if self.replication.is_replica() {
+                packet
+                    .write_stream
+                    .write(encoding::simple_error(
+                        "READONLY You can't write against a read only replica.",
+                    ))
+                    .await?;
+            } else {
+                self.process_packet(packet).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pops from the first non-empty sorted set or list of a blocking pop, or blocks the
+    /// client until one of its keys gets something to pop or its deadline passes. The pop
+    /// is replicated as a plain `ZPOPMIN`, `ZPOPMAX`, `LPOP` or `RPOP` so that replicas
+    /// never block.
+    async fn pop_or_block(&mut self, blocked_client: BlockedClient) -> anyhow::Result<()> {
+        let (db, keys, count) = (
+            blocked_client.db,
+            &blocked_client.keys,
+            blocked_client.count,
+        );
+        let popped = match blocked_client.pop {
+            BlockedPop::SortedSet(end) => {
+                self.store
+                    .pop_any(db, keys, end, count.unwrap_or(1))
+                    .map(|popped| {
+                        popped.map(|(key, mut popped)| {
+                            // the replicas pop what was popped here, whichever command
+                            // popped it
+                            let write = encoding::zpop(&key, count, end);
+                            let reply = match count {
+                                Some(_) => RedisStore::multi_pop_reply(key, popped),
+                                None => {
+                                    let (member, score) = popped.swap_remove(0);
+                                    encoding::array(vec![
+                                        encoding::bulk_string(key),
+                                        encoding::bulk_string(member),
+                                        encoding::bulk_string(score.to_string()),
+                                    ])
+                                }
+                            };
+                            (write, reply)
+                        })
+                    })
+            }
+            BlockedPop::List(end) => self
+                .store
+                .pop_any_list(db, keys, end, count.unwrap_or(1))
+                .map(|popped| {
+                    popped.map(|(key, popped)| {
+                        let write = encoding::list_pop(&key, end, Some(popped.len()));
+                        (write, RedisStore::list_multi_pop_reply(key, popped))
+                    })
+                }),
+        };
+        let reply = match popped {
+            Ok(Some((write, reply))) => {
+                self.replication.try_replicate(db, write).await?;
+                reply
+            }
+            Ok(None) => {
+                blocked_client
+                    .client_info
+                    .is_read_blocked
+                    .store(true, Ordering::SeqCst);
+                self.blocked_clients.block(blocked_client);
+                return Ok(());
+            }
+            Err(error) => error,
+        };
+
+        blocked_client
+            .client_info
+            .is_read_blocked
+            .store(false, Ordering::SeqCst);
+        // a client that disconnected while blocked must not stop the server
+        let _ = blocked_client.write_stream.write(reply).await;
+        Ok(())
+    }
+
+    async fn time_out_blocked_clients(&mut self) {
+        for blocked_client in self.blocked_clients.take_timed_out(Instant::now()) {
+            blocked_client
+                .client_info
+                .is_read_blocked
+                .store(false, Ordering::SeqCst);
+            let _ = blocked_client
+                .write_stream
+                .write(encoding::null_array())
+                .await;
+        }
+    }
+
+    /// Removes keys whose TTL has passed, so that keys no command touches again do not
+    /// linger in memory.
+    fn remove_expired_keys(&mut self) {
+        let started_at = Instant::now();
+        self.store.remove_expired();
+        let duration = started_at.elapsed();
+        if self.latency_monitor.is_spike(duration) {
+            self.latency_monitor.record("expire-cycle", duration);
+        }
+    }
+
+    /// Snapshots the store once one of the configured save points is met, unless a save
+    /// is still running. The snapshot is written in the background, see
+    /// [`Self::finish_background_save`].
+    fn save_if_needed(&mut self) {
+        let changes = self.store.dirty();
+        if self.background_save.is_some()
+            || changes == 0
+            || !self.rdb_persistence.should_save(changes)
+        {
+            return;
+        }
+
+        log!(
+            Notice,
+            "{changes} changes since the last save, saving in the background"
+        );
+        let save = self.rdb_persistence.save_in_background(&self.store);
+        self.background_save = Some((changes, save));
+    }
+
+    /// Resolves once the background save is written, and never when there is none. It
+    /// is kept until then, so that waiting for it can be given up.
+    async fn background_save_result(
+        background_save: &mut Option<(usize, JoinHandle<anyhow::Result<()>>)>,
+    ) -> anyhow::Result<()> {
+        let Some((_, save)) = background_save else {
+            return std::future::pending().await;
+        };
+
+        save.await.unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Records the result of the background save. Writes made while it was written stay
+    /// dirty, and a failed save is logged and retried on the next check rather than
+    /// stopping the server.
+    fn finish_background_save(&mut self, result: anyhow::Result<()>) {
+        let Some((changes, _)) = self.background_save.take() else {
+            return;
+        };
+
+        self.rdb_persistence.finish_save(&result);
+        match result {
+            Ok(()) => {
+                self.store.clear_dirty_before(changes);
+                log!(Notice, "background saving terminated with success");
+            }
+            Err(err) => log!(Warning, "unable to save the RDB file: {err}"),
+        }
+    }
 
-            self.replication.post_command_hook(&command);
+    async fn shutdown_signal() -> anyhow::Result<()> {
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
         }
 
         Ok(())
     }
 
-    async fn ping(&mut self, write_stream: RedisWriteStream) -> anyhow::Result<()> {
-        write_stream.write(encoding::bulk_string("PONG")).await
+    fn shutdown(&mut self) -> anyhow::Result<()> {
+        if let Some(path) = &self.server_config.unix_socket {
+            match std::fs::remove_file(path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn ping(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        message: Option<&Bytes>,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        write_stream
+            .write(Self::pong(
+                message,
+                client_info.is_subscribed() && !client_info.is_resp3(),
+            ))
+            .await
+    }
+
+    /// Builds the reply to `PING`. Subscribed clients can only receive arrays,
+    /// so the reply is wrapped as `["pong", message]` in that mode.
+    fn pong(message: Option<&Bytes>, is_subscribed: bool) -> RESPValue {
+        match (message, is_subscribed) {
+            (message, true) => encoding::array(vec![
+                encoding::bulk_string("pong"),
+                encoding::bulk_string(message.map(|message| &message[..]).unwrap_or_default()),
+            ]),
+            (Some(message), false) => encoding::bulk_string(message),
+            (None, false) => encoding::simple_string("PONG"),
+        }
     }
 
     async fn echo(&mut self, message: Bytes, write_stream: RedisWriteStream) -> anyhow::Result<()> {
         write_stream.write(encoding::bulk_string(message)).await
     }
 
+    async fn lolwut(&mut self, write_stream: RedisWriteStream) -> anyhow::Result<()> {
+        let banner = format!(
+            concat!(
+                " ____          _ _     \n",
+                "|  _ \\ ___  __| (_)___ \n",
+                "| |_) / _ \\/ _` | / __|\n",
+                "|  _ <  __/ (_| | \\__ \\\n",
+                "|_| \\_\\___|\\__,_|_|___/\n",
+                "\n",
+                "Redis ver. {}\n"
+            ),
+            REDIS_VERSION
+        );
+
+        write_stream.write(encoding::bulk_string(banner)).await
+    }
+
+    /// Replies to `QUIT`. The connection itself is closed by `process_stream`, which
+    /// stops after forwarding `QUIT`, so the writer task exits once this reply is flushed.
+    async fn quit(write_stream: RedisWriteStream) -> anyhow::Result<()> {
+        write_stream.write(encoding::simple_string("OK")).await
+    }
+
+    /// Switches the connection to another RESP version and replies with details about the
+    /// server, as a map under RESP3. Only pub-sub messages are sent as RESP3 types so far.
+    async fn hello(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        protocol_version: Option<i64>,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        if let Some(protocol_version) = protocol_version {
+            let Ok(protocol_version @ 2..=3) = u8::try_from(protocol_version) else {
+                return write_stream
+                    .write(encoding::simple_error(
+                        "NOPROTO unsupported protocol version",
+                    ))
+                    .await;
+            };
+
+            client_info
+                .protocol_version
+                .store(protocol_version, Ordering::SeqCst);
+        }
+
+        let role = if self.replication.is_replica() {
+            "replica"
+        } else {
+            "master"
+        };
+        let fields = vec![
+            ("server", encoding::bulk_string("redis")),
+            ("version", encoding::bulk_string(REDIS_VERSION)),
+            (
+                "proto",
+                encoding::integer(client_info.protocol_version() as i64),
+            ),
+            ("id", encoding::integer(client_info.id.value() as i64)),
+            ("mode", encoding::bulk_string("standalone")),
+            ("role", encoding::bulk_string(role)),
+            ("modules", encoding::array(vec![])),
+        ];
+
+        let reply = encoding::map_or_array(
+            client_info.is_resp3(),
+            fields
+                .into_iter()
+                .map(|(name, value)| (encoding::bulk_string(name), value))
+                .collect(),
+        );
+
+        write_stream.write(reply).await
+    }
+
+    async fn info(
+        &mut self,
+        section: InfoSection,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let server = format!(
+            "# Server\nredis_version:{}\nrun_id:{}\ntcp_port:{}",
+            REDIS_VERSION, self.run_id, self.server_config.port
+        );
+        let clients = format!(
+            "# Clients\nconnected_clients:{}",
+            self.connection_stats.connected_clients()
+        );
+        let persistence = format!(
+            "# Persistence\nloading:{}\nrdb_changes_since_last_save:{}\nrdb_last_save_time:{}\nrdb_last_bgsave_status:{}\naof_enabled:0",
+            u8::from(self.is_loading),
+            self.store.dirty(),
+            self.last_save_timestamp(),
+            if self.rdb_persistence.is_last_save_ok {
+                "ok"
+            } else {
+                "err"
+            }
+        );
+        let keyspace_stats = self.store.keyspace_stats();
+        let stats = format!(
+            "# Stats\ntotal_connections_received:{}\nexpired_keys:{}\nevicted_keys:{}\nkeyspace_hits:{}\nkeyspace_misses:{}",
+            self.connection_stats.total_connections_received(),
+            keyspace_stats.expired_keys,
+            keyspace_stats.evicted_keys,
+            keyspace_stats.keyspace_hits,
+            keyspace_stats.keyspace_misses
+        );
+        let replication = format!("# Replication\n{}", self.replication.info());
+        let info = match section {
+            InfoSection::Server => server,
+            InfoSection::Clients => clients,
+            InfoSection::Persistence => persistence,
+            InfoSection::Stats => stats,
+            InfoSection::Replication => replication,
+            InfoSection::Default => [server, clients, persistence, stats, replication].join("\n\n"),
+        };
+
+        write_stream.write(encoding::bulk_string(info)).await
+    }
+
+    async fn lastsave(&mut self, write_stream: RedisWriteStream) -> anyhow::Result<()> {
+        let last_save_time: i64 = self.last_save_timestamp().try_into()?;
+        write_stream.write(encoding::integer(last_save_time)).await
+    }
+
+    async fn debug(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        section: &DebugSection,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match section {
+            DebugSection::Object { key } => {
+                let encoding_config = &self.store.encoding_config;
+                match self.store.get(client_info.selected_db(), key) {
+                    Some(value) => {
+                        let encoding = value.encoding(encoding_config);
+                        let mut object = format!(
+                            "Value at:{:p} refcount:1 encoding:{} serializedlength:{} lru:0 lru_seconds_idle:0",
+                            value,
+                            encoding,
+                            rdb::serialized_length(value)
+                        );
+                        if let (StoreValue::List(list), "quicklist") = (value, encoding) {
+                            let nodes =
+                                list.quicklist_nodes(encoding_config.list_max_listpack_size);
+                            object.push_str(&format!(
+                                " ql_nodes:{} ql_avg_node:{:.2} ql_listpack_max:{} ql_compressed:0 ql_uncompressed_size:{}",
+                                nodes,
+                                list.len() as f64 / nodes as f64,
+                                encoding_config.list_max_listpack_size,
+                                list.size()
+                            ));
+                        }
+
+                        encoding::bulk_string(object)
+                    }
+                    None => encoding::simple_error("ERR no such key"),
+                }
+            }
+            DebugSection::ChangeReplId => {
+                self.replication.change_replication_id();
+                encoding::simple_string("OK")
+            }
+            DebugSection::ReplicateOffset => {
+                let (offset, replicas) = self.replication.offsets();
+                let replicas = replicas
+                    .into_iter()
+                    .map(|(address, acked)| {
+                        Ok(encoding::array(vec![
+                            encoding::bulk_string(address.to_string()),
+                            encoding::integer(i64::try_from(acked)?),
+                        ]))
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+
+                encoding::array(vec![
+                    encoding::integer(i64::try_from(offset)?),
+                    encoding::array(replicas),
+                ])
+            }
+            DebugSection::StringMatchLen { pattern, string } => {
+                encoding::integer(glob::matches(pattern, string) as i64)
+            }
+            DebugSection::SetActiveExpire { is_enabled } => {
+                self.is_active_expire_enabled = *is_enabled;
+                encoding::simple_string("OK")
+            }
+            DebugSection::Jmap | DebugSection::Unsupported { .. } => encoding::simple_string("OK"),
+            DebugSection::Sleep { duration } => {
+                // commands are executed one at a time, so sleeping here holds up every
+                // other client just like Redis does, while their connections keep
+                // reading the commands they send in the meantime
+                tokio::time::sleep(*duration).await;
+                encoding::simple_string("OK")
+            }
+            DebugSection::Reload => {
+                // an older snapshot must not replace the one saved here once it is written
+                if self.background_save.is_some() {
+                    let result = Self::background_save_result(&mut self.background_save).await;
+                    self.finish_background_save(result);
+                }
+
+                if let Err(err) = self.rdb_persistence.save(&self.store).await {
+                    log!(Warning, "unable to save the RDB file: {err}");
+                    encoding::simple_error("ERR Error trying to save the DB")
+                } else {
+                    self.store.clear_dirty();
+                    match self.reload().await {
+                        Ok(()) => encoding::simple_string("OK"),
+                        Err(err) => {
+                            log!(Warning, "unable to load the RDB file: {err}");
+                            encoding::simple_error("ERR Error trying to load the RDB dump")
+                        }
+                    }
+                }
+            }
+        };
+
+        write_stream.write(reply).await
+    }
+
+    /// Replaces the dataset with the contents of the RDB file.
+    async fn reload(&mut self) -> anyhow::Result<()> {
+        let store = self
+            .rdb_persistence
+            .setup(self.store.database_count())
+            .await??;
+        self.store.replace_dataset(store);
+        Ok(())
+    }
+
+    /// Replies to `COMMAND INFO` with one entry per name, or for every command when no
+    /// names are given. Unknown commands are a null entry. `COMMAND COUNT` counts the
+    /// same table, so it always matches what `COMMAND INFO` lists. `COMMAND DOCS` is
+    /// keyed by name instead and leaves unknown commands out, like in Redis.
+    async fn command(
+        section: &CommandSection,
+        is_resp3: bool,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match section {
+            CommandSection::Info { names } if names.is_empty() => encoding::array(
+                command_table::COMMANDS
+                    .iter()
+                    .map(CommandSpec::info)
+                    .collect(),
+            ),
+            CommandSection::Info { names } => encoding::array(
+                names
+                    .iter()
+                    .map(|name| {
+                        command_table::lookup(name)
+                            .map_or_else(encoding::null_array, CommandSpec::info)
+                    })
+                    .collect(),
+            ),
+            CommandSection::Count => encoding::integer(command_table::COMMANDS.len() as i64),
+            CommandSection::Docs { names } => {
+                let docs = if names.is_empty() {
+                    command_docs::DOCS.iter().collect::<Vec<_>>()
+                } else {
+                    names
+                        .iter()
+                        .filter_map(|name| command_docs::lookup(name))
+                        .collect()
+                };
+
+                encoding::map_or_array(
+                    is_resp3,
+                    docs.into_iter()
+                        .map(|docs| (encoding::bulk_string(docs.name), docs.docs(is_resp3)))
+                        .collect(),
+                )
+            }
+        };
+
+        write_stream.write(reply).await
+    }
+
+    fn last_save_timestamp(&self) -> u64 {
+        self.rdb_persistence
+            .last_save_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    async fn select(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        index: usize,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        if index >= self.store.database_count() {
+            return write_stream
+                .write(encoding::simple_error("ERR DB index is out of range"))
+                .await;
+        }
+
+        client_info.selected_db.store(index, Ordering::SeqCst);
+        write_stream.write(encoding::simple_string("OK")).await
+    }
+
+    async fn slowlog(
+        &mut self,
+        section: &SlowLogSection,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match section {
+            SlowLogSection::Get { count } => match count {
+                None => self.slowlog.get(Some(10)),
+                Some(-1) => self.slowlog.get(None),
+                Some(count) => match usize::try_from(*count) {
+                    Ok(count) => self.slowlog.get(Some(count)),
+                    Err(_) => {
+                        encoding::simple_error("ERR count should be greater than or equal to -1")
+                    }
+                },
+            },
+            SlowLogSection::Len => encoding::integer(self.slowlog.len() as i64),
+            SlowLogSection::Reset => {
+                self.slowlog.reset();
+                encoding::simple_string("OK")
+            }
+            SlowLogSection::Help => encoding::help(
+                "slowlog",
+                &[
+                    "GET [<count>]",
+                    "    Return top <count> entries from the slowlog (default: 10, -1 mean all).",
+                    "    Entries are made of:",
+                    "    id, timestamp, time in microseconds, arguments array, client IP and port,",
+                    "    client name",
+                    "LEN",
+                    "    Return the length of the slowlog.",
+                    "RESET",
+                    "    Reset the slowlog.",
+                ],
+            ),
+        };
+
+        write_stream.write(reply).await
+    }
+
+    /// Answers the cluster commands clients send to find out whether they talk to a
+    /// cluster, as a server with cluster support disabled. The run ID doubles as the ID
+    /// of this node.
+    async fn cluster(
+        &mut self,
+        section: ClusterSection,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match section {
+            ClusterSection::Info => encoding::bulk_string(
+                "cluster_enabled:0\r\n\
+                cluster_state:ok\r\n\
+                cluster_slots_assigned:0\r\n\
+                cluster_slots_ok:0\r\n\
+                cluster_slots_pfail:0\r\n\
+                cluster_slots_fail:0\r\n\
+                cluster_known_nodes:1\r\n\
+                cluster_size:0\r\n\
+                cluster_current_epoch:0\r\n\
+                cluster_my_epoch:0\r\n",
+            ),
+            ClusterSection::MyId => encoding::bulk_string(&self.run_id),
+            ClusterSection::Slots | ClusterSection::Shards => encoding::array(vec![]),
+            // a list of nodes, one per line, which clients parse as text
+            ClusterSection::Nodes => encoding::bulk_string(""),
+            ClusterSection::Help => encoding::help(
+                "cluster",
+                &[
+                    "INFO",
+                    "    Return information about the cluster.",
+                    "MYID",
+                    "    Return the node id.",
+                    "NODES",
+                    "    Return cluster configuration seen by node.",
+                    "SHARDS",
+                    "    Return information about slot range mappings and the nodes associated with them.",
+                    "SLOTS",
+                    "    Return information about slots range mappings.",
+                ],
+            ),
+        };
+
+        write_stream.write(reply).await
+    }
+
+    /// Answers `FUNCTION` as a server without scripting, which has no functions, so that
+    /// clients probing for them carry on without. Loading a function is refused.
+    async fn function(
+        section: FunctionSection,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match section {
+            FunctionSection::List | FunctionSection::Dump => encoding::array(vec![]),
+            FunctionSection::Load => encoding::simple_error(
+                "ERR FUNCTION LOAD is not supported since this server does not run scripts",
+            ),
+            FunctionSection::Help => encoding::help(
+                "function",
+                &[
+                    "LIST",
+                    "    Return an empty list, since there are no functions.",
+                    "DUMP",
+                    "    Return an empty list, since there are no functions.",
+                    "LOAD <FUNCTION CODE>",
+                    "    Not supported, since scripts are not run.",
+                ],
+            ),
+        };
+
+        write_stream.write(reply).await
+    }
+
+    /// Answers `SCRIPT` as a server without a Lua interpreter, whose script cache is
+    /// always empty. Loading a script is refused.
+    async fn script(section: &ScriptSection, write_stream: RedisWriteStream) -> anyhow::Result<()> {
+        let reply = match section {
+            ScriptSection::Load => encoding::simple_error(
+                "ERR SCRIPT LOAD is not supported since this server does not run scripts",
+            ),
+            ScriptSection::Exists { sha1s } => {
+                encoding::array(sha1s.iter().map(|_| encoding::integer(0)).collect())
+            }
+            ScriptSection::Flush => encoding::simple_string("OK"),
+            ScriptSection::Help => encoding::help(
+                "script",
+                &[
+                    "EXISTS <sha1> [<sha1> ...]",
+                    "    Return 0 for each SHA1, since no scripts are cached.",
+                    "FLUSH [ASYNC|SYNC]",
+                    "    Do nothing, since no scripts are cached.",
+                    "LOAD <script>",
+                    "    Not supported, since scripts are not run.",
+                ],
+            ),
+        };
+
+        write_stream.write(reply).await
+    }
+
+    async fn latency(
+        &mut self,
+        section: &LatencySection,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match section {
+            LatencySection::History { event } => self.latency_monitor.history(event),
+            LatencySection::Latest => self.latency_monitor.latest(),
+            LatencySection::Reset { events } => {
+                encoding::integer(self.latency_monitor.reset(events) as i64)
+            }
+            LatencySection::Help => encoding::help(
+                "latency",
+                &[
+                    "HISTORY <event>",
+                    "    Return time-latency samples for the <event> class.",
+                    "LATEST",
+                    "    Return the latest latency samples for all events.",
+                    "RESET [<event> ...]",
+                    "    Reset latency data of one or more <event> classes.",
+                    "    (default: reset all data for all event classes)",
+                ],
+            ),
+        };
+
+        write_stream.write(reply).await
+    }
+
+    async fn client(
+        client_info: &ClientConnectionInfo,
+        section: ClientSection,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match section {
+            ClientSection::NoEvict { is_enabled } => {
+                client_info.set_no_evict(is_enabled);
+                encoding::simple_string("OK")
+            }
+            ClientSection::NoTouch { is_enabled } => {
+                client_info.set_no_touch(is_enabled);
+                encoding::simple_string("OK")
+            }
+            ClientSection::Help => encoding::help(
+                "client",
+                &[
+                    "NO-EVICT (ON|OFF)",
+                    "    Protect current client connection from eviction.",
+                    "NO-TOUCH (ON|OFF)",
+                    "    Will not touch LRU/LFU stats when this mode is on.",
+                ],
+            ),
+        };
+
+        write_stream.write(reply).await
+    }
+
+    /// The parameters `CONFIG GET` reports, with their current values.
+    fn config_parameters(&self) -> Vec<(&'static str, String)> {
+        let encoding_config = &self.store.encoding_config;
+        let bind = self
+            .server_config
+            .bind_addresses
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let save = self
+            .rdb_persistence
+            .config
+            .save_points
+            .iter()
+            .map(|save_point| format!("{} {}", save_point.seconds, save_point.changes))
+            .collect::<Vec<_>>();
+        let unix_socket = self
+            .server_config
+            .unix_socket
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        vec![
+            ("bind", bind.join(" ")),
+            ("port", self.server_config.port.to_string()),
+            ("unixsocket", unix_socket),
+            ("tcp-backlog", self.server_config.tcp_backlog.to_string()),
+            (
+                "tcp-keepalive",
+                u8::from(self.server_config.tcp_keepalive).to_string(),
+            ),
+            ("timeout", self.server_config.timeout.to_string()),
+            ("loglevel", log::level().name().to_string()),
+            ("save", save.join(" ")),
+            ("dir", self.rdb_persistence.config.dir.clone()),
+            ("dbfilename", self.rdb_persistence.config.file_name.clone()),
+            ("databases", self.store.database_count().to_string()),
+            (
+                "list-max-listpack-size",
+                encoding_config.list_max_listpack_size.to_string(),
+            ),
+            (
+                "latency-monitor-threshold",
+                self.latency_monitor.threshold.to_string(),
+            ),
+            (
+                "slowlog-log-slower-than",
+                self.slowlog.config.log_slower_than.to_string(),
+            ),
+            ("slowlog-max-len", self.slowlog.config.max_len.to_string()),
+            (
+                "proto-max-bulk-len",
+                self.server_config.proto_max_bulk_len.to_string(),
+            ),
+            ("io-threads", self.server_config.io_threads.to_string()),
+            (
+                "client-output-buffer-limit",
+                self.server_config.output_buffer_limits.to_string(),
+            ),
+            (
+                "set-max-intset-entries",
+                encoding_config.set_max_intset_entries.to_string(),
+            ),
+            (
+                "set-max-listpack-entries",
+                encoding_config.set_max_listpack_entries.to_string(),
+            ),
+            (
+                "set-max-listpack-value",
+                encoding_config.set_max_listpack_value.to_string(),
+            ),
+            (
+                "hash-max-listpack-entries",
+                encoding_config.hash_max_listpack_entries.to_string(),
+            ),
+            (
+                "hash-max-listpack-value",
+                encoding_config.hash_max_listpack_value.to_string(),
+            ),
+        ]
+    }
+
+    /// Sets a parameter of `CONFIG SET` in `encoding_config`, the only parameters that
+    /// can be changed while the server runs. Lists and sets already stored keep their
+    /// encoding until they are written to again, while hashes report theirs against the
+    /// new thresholds right away.
+    fn set_config_parameter(
+        &self,
+        encoding_config: &mut EncodingConfig,
+        name: &[u8],
+        value: &[u8],
+    ) -> Result<(), RESPValue> {
+        let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+        let failed = |reason: &str| {
+            encoding::simple_error(format!(
+                "ERR CONFIG SET failed (possibly related to argument '{name}') - {reason}"
+            ))
+        };
+        let not_integer = || failed("argument couldn't be parsed into an integer");
+        let integer = std::str::from_utf8(value).ok();
+        let threshold = match name.as_str() {
+            // the only threshold that can be negative, for limiting nodes by size
+            "list-max-listpack-size" => {
+                encoding_config.list_max_listpack_size = integer
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(not_integer)?;
+                return Ok(());
+            }
+            "set-max-intset-entries" => &mut encoding_config.set_max_intset_entries,
+            "set-max-listpack-entries" => &mut encoding_config.set_max_listpack_entries,
+            "set-max-listpack-value" => &mut encoding_config.set_max_listpack_value,
+            "hash-max-listpack-entries" => &mut encoding_config.hash_max_listpack_entries,
+            "hash-max-listpack-value" => &mut encoding_config.hash_max_listpack_value,
+            _ if self
+                .config_parameters()
+                .iter()
+                .any(|(parameter, _)| *parameter == name) =>
+            {
+                return Err(failed("can't set immutable config"))
+            }
+            _ => {
+                return Err(encoding::simple_error(format!(
+                    "ERR Unknown option or number of arguments for CONFIG SET - '{name}'"
+                )))
+            }
+        };
+
+        *threshold = integer
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(not_integer)?;
+        Ok(())
+    }
+
     async fn config(
         &mut self,
         section: &ConfigSection,
@@ -112,23 +1417,52 @@ impl RedisManager {
     ) -> anyhow::Result<()> {
         match section {
             ConfigSection::Get { keys } => {
-                let mut values = vec![];
-                for key in keys {
-                    values.push(encoding::bulk_string(key));
-                    if &**key == b"dir" {
-                        values.push(encoding::bulk_string(&self.rdb_persistence.config.dir));
-                    } else if &**key == b"dbfilename" {
-                        values.push(encoding::bulk_string(
-                            &self.rdb_persistence.config.file_name,
-                        ));
-                    } else {
-                        return Err(anyhow::anyhow!(
-                            "[redis - error] unexpected configuration key found"
-                        ));
+                // like in Redis, parameter names are matched as case-insensitive globs,
+                // and a parameter matched by several of them is listed once
+                let patterns = keys
+                    .iter()
+                    .map(|key| key.to_ascii_lowercase())
+                    .collect::<Vec<_>>();
+                let values = self
+                    .config_parameters()
+                    .into_iter()
+                    .filter(|(name, _)| {
+                        patterns
+                            .iter()
+                            .any(|pattern| glob::matches(pattern, name.as_bytes()))
+                    })
+                    .flat_map(|(name, value)| {
+                        [encoding::bulk_string(name), encoding::bulk_string(value)]
+                    })
+                    .collect();
+
+                write_stream.write(encoding::array(values)).await
+            }
+            ConfigSection::Set { parameters } => {
+                // like in Redis, either every parameter is set or none of them is
+                let mut encoding_config = self.store.encoding_config;
+                for (name, value) in parameters {
+                    if let Err(error) = self.set_config_parameter(&mut encoding_config, name, value)
+                    {
+                        return write_stream.write(error).await;
                     }
                 }
 
-                write_stream.write(encoding::array(values)).await
+                self.store.encoding_config = encoding_config;
+                write_stream.write(encoding::simple_string("OK")).await
+            }
+            ConfigSection::Help => {
+                write_stream
+                    .write(encoding::help(
+                        "config",
+                        &[
+                            "GET <parameter> [<parameter> ...]",
+                            "    Return the values of the given configuration parameters.",
+                            "SET <directive> <value> [<directive> <value> ...]",
+                            "    Set the configuration <directive> to <value>.",
+                        ],
+                    ))
+                    .await
             }
         }
     }
@@ -140,24 +1474,31 @@ impl RedisManager {
         mut server: RedisServer,
         command_tx: mpsc::Sender<RedisCommandPacket>,
     ) {
+        let connection_stats = self.connection_stats.clone();
         tokio::spawn(async move {
             loop {
                 let (read_stream, write_stream, client_info) = server.accept().await?;
-                let address = client_info.address;
-                eprintln!("[redis] client at {} connected", address);
+                let connection_guard = connection_stats.connect();
+                let address = client_info.address.clone();
+                log!(Verbose, "client at {} connected", address);
                 let command_tx = command_tx.clone();
                 tokio::spawn(async move {
-                    if let Err(err) =
-                        Self::process_stream(client_info, read_stream, write_stream, command_tx)
-                            .await
+                    if let Err(err) = Self::process_stream(
+                        client_info,
+                        read_stream,
+                        write_stream,
+                        command_tx,
+                        connection_guard,
+                    )
+                    .await
                     {
-                        eprintln!("{err}");
-                        eprintln!(
-                            "[redis - error] an unknown error occurred when processing client stream"
+                        log!(
+                            Warning,
+                            "error while processing the stream of client at {address}: {err}"
                         )
                     }
 
-                    eprintln!("[redis] client at {} disconnected", address);
+                    log!(Verbose, "client at {} disconnected", address);
                 });
             }
 
@@ -168,6 +1509,22 @@ impl RedisManager {
 
     async fn process_stream(
         client_info: ClientConnectionInfo,
+        read_stream: RedisReadStream,
+        write_stream: RedisWriteStream,
+        command_tx: mpsc::Sender<RedisCommandPacket>,
+        // dropped on every exit path, including errors, to mark the client as disconnected
+        _connection_guard: ConnectionGuard,
+    ) -> anyhow::Result<()> {
+        let result =
+            Self::forward_commands(&client_info, read_stream, write_stream, command_tx).await;
+        // the commands already sent are still executed, but whatever holds on to the
+        // connection for later, such as its subscriptions, can let go of it
+        client_info.close();
+        result
+    }
+
+    async fn forward_commands(
+        client_info: &ClientConnectionInfo,
         mut read_stream: RedisReadStream,
         write_stream: RedisWriteStream,
         command_tx: mpsc::Sender<RedisCommandPacket>,
@@ -175,6 +1532,7 @@ impl RedisManager {
         loop {
             match read_stream.read().await {
                 Ok(Some(command)) => {
+                    let is_quit = matches!(command, RedisCommand::Server(RedisServerCommand::Quit));
                     command_tx
                         .send(RedisCommandPacket {
                             client_info: client_info.clone(),
@@ -182,10 +1540,1910 @@ impl RedisManager {
                             write_stream: write_stream.clone(),
                         })
                         .await?;
+
+                    if is_quit {
+                        return Ok(());
+                    }
                 }
                 Ok(None) => return Ok(()),
-                Err(err) => return Err(err),
+                Err(err) => {
+                    if let Some(reply) = err.reply() {
+                        write_stream.write(reply).await?;
+                    }
+
+                    return Err(err.into());
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::IpAddr,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use bytes::Bytes;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+    };
+
+    use crate::redis::{
+        pubsub::command::RedisPubSubCommand,
+        rdb::{RDBConfig, SavePoint},
+        replication::{
+            command::{RedisReplicationCommand, ReplConfSection},
+            handshake::{self, PrimaryLink},
+            RedisReplicationMode,
+        },
+        resp::{
+            command::{
+                CommandSection, DebugSection, ExpiryOption, ListEnd, MinOrMax, RedisCommand,
+                RedisServerCommand, RedisStoreCommand, SetOperation,
+            },
+            command_table, encoding,
+            resp_reader::{RESPReader, DEFAULT_MAX_BULK_LEN},
+            RESPValue,
+        },
+        server::{
+            ClientAddress, ClientConnectionInfo, ClientId, ConnectionStats, OutputBufferLimits,
+            RedisServer, RedisWriteStream, ServerConfig,
+        },
+        store::{
+            geo::Coordinates,
+            sorted_set::Score,
+            stream::{StreamEntryId, XAddEntryId},
+            RedisStore, StoreValue,
+        },
+    };
+
+    use super::{RedisCommandPacket, RedisManager};
+
+    fn test_manager() -> RedisManager {
+        RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        )
+    }
+
+    fn test_client(id: usize) -> ClientConnectionInfo {
+        ClientConnectionInfo::new(ClientId::new(id), ClientAddress::Unix(PathBuf::new()))
+    }
+
+    /// Runs a command the way a client sends it, returning the reply it gets.
+    async fn reply(
+        manager: &mut RedisManager,
+        client_info: &ClientConnectionInfo,
+        args: &[&str],
+    ) -> Bytes {
+        let command = encoding::array(args.iter().map(encoding::bulk_string).collect());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        manager
+            .process_packet(RedisCommandPacket::new(
+                client_info.clone(),
+                RedisCommand::from_client(command).unwrap(),
+                RedisWriteStream::new(tx),
+            ))
+            .await;
+        rx.try_recv().unwrap()
+    }
+
+    #[tokio::test]
+    async fn replies_to_xinfo_stream_with_a_map_for_resp3_clients() {
+        let mut manager = test_manager();
+        let client_info = test_client(1);
+        reply(&mut manager, &client_info, &["XADD", "s", "1-1", "f", "v"]).await;
+
+        let fields = "$6\r\nlength\r\n:1\r\n\
+            $17\r\nlast-generated-id\r\n$3\r\n1-1\r\n\
+            $11\r\nfirst-entry\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n\
+            $10\r\nlast-entry\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n";
+        assert_eq!(
+            reply(&mut manager, &client_info, &["XINFO", "STREAM", "s"]).await,
+            format!("*8\r\n{fields}")
+        );
+
+        reply(&mut manager, &client_info, &["HELLO", "3"]).await;
+        assert_eq!(
+            reply(&mut manager, &client_info, &["XINFO", "STREAM", "s"]).await,
+            format!("%4\r\n{fields}")
+        );
+        assert_eq!(
+            reply(&mut manager, &client_info, &["XINFO", "STREAM", "missing"]).await,
+            "-ERR no such key\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn replies_to_help_with_the_subcommands_of_container_commands() {
+        let mut manager = test_manager();
+        let client_info = test_client(1);
+        for command in [
+            "CONFIG", "ACL", "XINFO", "XGROUP", "SLOWLOG", "CLIENT", "LATENCY", "PUBSUB",
+        ] {
+            let reply = reply(&mut manager, &client_info, &[command, "help"]).await;
+            let header =
+                format!("{command} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:");
+            let header = format!("${}\r\n{header}\r\n", header.len());
+            // the header is the first element, right after the length of the array
+            let elements = reply.iter().position(|&byte| byte == b'\n').unwrap() + 1;
+            assert!(
+                reply[elements..].starts_with(header.as_bytes()),
+                "{command}: {reply:?}"
+            );
+            assert!(
+                reply.ends_with(b"$4\r\nHELP\r\n$20\r\n    Print this help.\r\n"),
+                "{command}: {reply:?}"
+            );
+        }
+
+        let reply = reply(&mut manager, &client_info, &["config", "help"]).await;
+        assert_eq!(
+            reply,
+            Bytes::from(encoding::help(
+                "config",
+                &[
+                    "GET <parameter> [<parameter> ...]",
+                    "    Return the values of the given configuration parameters.",
+                    "SET <directive> <value> [<directive> <value> ...]",
+                    "    Set the configuration <directive> to <value>.",
+                ],
+            ))
+        );
+    }
+
+    #[test]
+    fn replies_to_ping_with_simple_string() {
+        assert_eq!(
+            RedisManager::pong(None, false),
+            RESPValue::SimpleString(Bytes::from_static(b"PONG"))
+        );
+    }
+
+    #[test]
+    fn echoes_ping_message_as_bulk_string() {
+        let message = Bytes::from_static(b"hello");
+        assert_eq!(
+            RedisManager::pong(Some(&message), false),
+            RESPValue::BulkString(message)
+        );
+    }
+
+    #[test]
+    fn replies_to_ping_with_array_when_subscribed() {
+        let message = Bytes::from_static(b"hello");
+        assert_eq!(
+            RedisManager::pong(Some(&message), true),
+            RESPValue::Array(vec![
+                RESPValue::BulkString(Bytes::from_static(b"pong")),
+                RESPValue::BulkString(message),
+            ])
+        );
+        assert_eq!(
+            RedisManager::pong(None, true),
+            RESPValue::Array(vec![
+                RESPValue::BulkString(Bytes::from_static(b"pong")),
+                RESPValue::BulkString(Bytes::new()),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_every_command_in_the_table() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        RedisManager::command(
+            &CommandSection::Count,
+            false,
+            RedisWriteStream::new(tx.clone()),
+        )
+        .await
+        .unwrap();
+        RedisManager::command(
+            &CommandSection::Info { names: vec![] },
+            false,
+            RedisWriteStream::new(tx),
+        )
+        .await
+        .unwrap();
+
+        let count = RESPReader::new(&rx.recv().await.unwrap()[..])
+            .read_value()
+            .await
+            .unwrap();
+        let info = RESPReader::new(&rx.recv().await.unwrap()[..])
+            .read_value()
+            .await
+            .unwrap();
+        assert_eq!(
+            count,
+            RESPValue::Integer(command_table::COMMANDS.len() as i64)
+        );
+        assert_eq!(
+            info.into_array().unwrap().len(),
+            command_table::COMMANDS.len()
+        );
+    }
+
+    #[tokio::test]
+    async fn documents_requested_commands_by_name() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let names = vec![Bytes::from("GET"), Bytes::from("nosuchcommand")];
+        for is_resp3 in [false, true] {
+            RedisManager::command(
+                &CommandSection::Docs {
+                    names: names.clone(),
+                },
+                is_resp3,
+                RedisWriteStream::new(tx.clone()),
+            )
+            .await
+            .unwrap();
+        }
+
+        let field = |name: &str, value| (encoding::bulk_string(name), value);
+        let docs = |is_resp3| {
+            let key = encoding::map_or_array(
+                is_resp3,
+                vec![
+                    field("name", encoding::bulk_string("key")),
+                    field("type", encoding::bulk_string("key")),
+                ],
+            );
+            encoding::map_or_array(
+                is_resp3,
+                vec![(
+                    encoding::bulk_string("get"),
+                    encoding::map_or_array(
+                        is_resp3,
+                        vec![
+                            field(
+                                "summary",
+                                encoding::bulk_string("Returns the string value of a key."),
+                            ),
+                            field("since", encoding::bulk_string("1.0.0")),
+                            field("group", encoding::bulk_string("string")),
+                            field("arguments", encoding::array(vec![key])),
+                        ],
+                    ),
+                )],
+            )
+        };
+
+        assert_eq!(rx.recv().await.unwrap(), Bytes::from(docs(false)));
+        assert_eq!(rx.recv().await.unwrap(), Bytes::from(docs(true)));
+    }
+
+    #[tokio::test]
+    async fn quit_replies_ok_and_closes_connection() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (read_half, write_half) = tokio::io::split(server);
+        let (read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            None,
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        let (command_tx, mut command_rx) = mpsc::channel(1);
+        let connection_stats = ConnectionStats::default();
+        let client_info =
+            ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+        let processing = tokio::spawn(RedisManager::process_stream(
+            client_info,
+            read_stream,
+            write_stream,
+            command_tx,
+            connection_stats.connect(),
+        ));
+
+        client.write_all(b"*1\r\n$4\r\nQUIT\r\n").await.unwrap();
+        let packet = command_rx.recv().await.unwrap();
+        assert_eq!(
+            packet.command,
+            RedisCommand::Server(RedisServerCommand::Quit)
+        );
+
+        RedisManager::quit(packet.write_stream).await.unwrap();
+        processing.await.unwrap().unwrap();
+        assert!(packet.client_info.is_closed());
+        assert_eq!(connection_stats.connected_clients(), 0);
+        assert_eq!(connection_stats.total_connections_received(), 1);
+
+        let mut reply = vec![];
+        client.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, b"+OK\r\n");
+    }
+
+    #[tokio::test]
+    async fn protocol_errors_close_the_connection_and_disconnect_the_client() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (read_half, write_half) = tokio::io::split(server);
+        let (read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            None,
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        let (command_tx, _command_rx) = mpsc::channel(1);
+        let connection_stats = ConnectionStats::default();
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+        let processing = tokio::spawn(RedisManager::process_stream(
+            client_info.clone(),
+            read_stream,
+            write_stream,
+            command_tx,
+            connection_stats.connect(),
+        ));
+        assert_eq!(connection_stats.connected_clients(), 1);
+
+        client.write_all(b"*1\r\n$1000000000\r\n").await.unwrap();
+        assert!(processing.await.unwrap().is_err());
+        assert!(client_info.is_closed());
+        assert_eq!(connection_stats.connected_clients(), 0);
+        assert_eq!(connection_stats.total_connections_received(), 1);
+
+        let mut reply = vec![];
+        client.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, b"-ERR Protocol error: invalid bulk length\r\n");
+    }
+
+    #[tokio::test]
+    async fn rejects_xadd_arity_errors_without_closing_connection() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (read_half, write_half) = tokio::io::split(server);
+        let (read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            None,
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        let (command_tx, mut command_rx) = mpsc::channel(4);
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+        tokio::spawn(RedisManager::process_stream(
+            client_info,
+            read_stream,
+            write_stream,
+            command_tx,
+            ConnectionStats::default().connect(),
+        ));
+
+        client
+            .write_all(b"*3\r\n$4\r\nXADD\r\n$1\r\ns\r\n$1\r\n*\r\n")
+            .await
+            .unwrap();
+        client
+            .write_all(b"*4\r\n$4\r\nXADD\r\n$1\r\ns\r\n$1\r\n*\r\n$1\r\nf\r\n")
+            .await
+            .unwrap();
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        for _ in 0..3 {
+            let packet = command_rx.recv().await.unwrap();
+            manager.handle_packet(packet).await.unwrap();
+        }
+
+        let expected = b"-ERR wrong number of arguments for 'xadd' command\r\n\
+            -ERR wrong number of arguments for 'xadd' command\r\n\
+            +PONG\r\n";
+        let mut reply = vec![0; expected.len()];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, expected);
+    }
+
+    #[tokio::test]
+    async fn saves_in_the_background_once_a_save_point_is_met() {
+        let dir = std::env::temp_dir().join(format!("bgsave-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let save_points = vec![SavePoint {
+            seconds: 0,
+            changes: 1,
+        }];
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new(
+                dir.display().to_string(),
+                "dump.rdb".to_string(),
+                save_points,
+            ),
+        );
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+        let set = |key: &'static str| {
+            RedisCommandPacket::new(
+                client_info.clone(),
+                RedisCommand::Store(RedisStoreCommand::Set {
+                    key: Bytes::from(key),
+                    value: Bytes::from("value"),
+                    px: None,
+                }),
+                RedisWriteStream::sink(),
+            )
+        };
+
+        manager.handle_packet(set("saved")).await.unwrap();
+        manager.save_if_needed();
+        assert!(manager.background_save.is_some());
+
+        // written while the snapshot is saved, so it is not covered by it
+        manager.handle_packet(set("unsaved")).await.unwrap();
+        let result = RedisManager::background_save_result(&mut manager.background_save).await;
+        manager.finish_background_save(result);
+
+        assert!(manager.background_save.is_none());
+        assert!(manager.rdb_persistence.is_last_save_ok);
+        assert_eq!(manager.store.dirty(), 1);
+        let loaded = manager.rdb_persistence.setup(16).await.unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(loaded.get(0, b"saved").is_some());
+        assert!(loaded.get(0, b"unsaved").is_none());
+    }
+
+    #[tokio::test]
+    async fn drops_monitors_over_their_output_buffer_limit() {
+        let output_buffer_limits = OutputBufferLimits {
+            normal: 256,
+            ..OutputBufferLimits::default()
+        };
+        let mut manager = RedisManager::new(
+            ServerConfig::new(vec![], 6379, None, 511, false, 0, output_buffer_limits),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+
+        // nothing drains the monitor's channel, as if it stopped reading
+        let (monitor_tx, _monitor_rx) = mpsc::unbounded_channel();
+        let monitor_stream = RedisWriteStream::new(monitor_tx);
+        manager
+            .handle_packet(RedisCommandPacket::new(
+                ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new())),
+                RedisCommand::Server(RedisServerCommand::Monitor),
+                monitor_stream.clone(),
+            ))
+            .await
+            .unwrap();
+
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(2), ClientAddress::Unix(PathBuf::new()));
+        assert!(!monitor_stream.is_disconnected());
+        for _ in 0..64 {
+            manager
+                .handle_packet(RedisCommandPacket::new(
+                    client_info.clone(),
+                    RedisCommand::Server(RedisServerCommand::Ping { message: None }),
+                    RedisWriteStream::sink(),
+                ))
+                .await
+                .unwrap();
+        }
+
+        assert!(monitor_stream.is_disconnected());
+    }
+
+    #[tokio::test]
+    async fn selects_databases_per_client_within_range() {
+        let mut manager = test_manager();
+        let (client_info, other_client) = (test_client(1), test_client(2));
+        reply(&mut manager, &client_info, &["SET", "key", "0"]).await;
+
+        for index in ["16", "-1"] {
+            let select = reply(&mut manager, &client_info, &["SELECT", index]).await;
+            assert!(select.starts_with(b"-ERR"), "{index}");
+        }
+        assert_eq!(client_info.selected_db(), 0);
+
+        assert_eq!(
+            reply(&mut manager, &client_info, &["SELECT", "15"]).await,
+            "+OK\r\n"
+        );
+        assert_eq!(
+            reply(&mut manager, &client_info, &["GET", "key"]).await,
+            "$-1\r\n"
+        );
+        reply(&mut manager, &client_info, &["SET", "key", "15"]).await;
+        assert_eq!(
+            reply(&mut manager, &other_client, &["GET", "key"]).await,
+            "$1\r\n0\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn swaps_databases_and_moves_keys_between_them() {
+        let mut manager = test_manager();
+        let (client_info, other_client) = (test_client(1), test_client(2));
+        reply(&mut manager, &client_info, &["SET", "key", "0"]).await;
+        reply(&mut manager, &other_client, &["SELECT", "1"]).await;
+        reply(&mut manager, &other_client, &["SET", "key", "1"]).await;
+
+        // clients keep their index and see the contents of the other database
+        assert_eq!(
+            reply(&mut manager, &client_info, &["SWAPDB", "0", "1"]).await,
+            "+OK\r\n"
+        );
+        assert_eq!(
+            reply(&mut manager, &client_info, &["GET", "key"]).await,
+            "$1\r\n1\r\n"
+        );
+        assert_eq!(
+            reply(&mut manager, &other_client, &["GET", "key"]).await,
+            "$1\r\n0\r\n"
+        );
+        let swap = reply(&mut manager, &client_info, &["SWAPDB", "0", "16"]).await;
+        assert!(swap.starts_with(b"-ERR"));
+
+        // a key is only moved to a database that does not have it yet
+        for (args, expected) in [
+            (&["MOVE", "key", "1"][..], ":0\r\n"),
+            (&["MOVE", "missing", "1"], ":0\r\n"),
+            (&["MOVE", "key", "2"], ":1\r\n"),
+            (&["GET", "key"], "$-1\r\n"),
+            (
+                &["MOVE", "key", "0"],
+                "-ERR source and destination objects are the same\r\n",
+            ),
+        ] {
+            assert_eq!(
+                reply(&mut manager, &client_info, args).await,
+                expected,
+                "{args:?}"
+            );
+        }
+        reply(&mut manager, &client_info, &["SELECT", "2"]).await;
+        assert_eq!(
+            reply(&mut manager, &client_info, &["GET", "key"]).await,
+            "$1\r\n1\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn config_get_matches_parameter_names_as_globs() {
+        let mut manager = test_manager();
+        let client_info = test_client(1);
+
+        assert_eq!(
+            reply(&mut manager, &client_info, &["CONFIG", "GET", "appendonly"]).await,
+            Bytes::from("*0\r\n")
+        );
+        assert_eq!(
+            reply(&mut manager, &client_info, &["CONFIG", "GET", "DIR"]).await,
+            Bytes::from("*2\r\n$3\r\ndir\r\n$12\r\n/nonexistent\r\n")
+        );
+        assert_eq!(
+            reply(
+                &mut manager,
+                &client_info,
+                &["CONFIG", "GET", "*-max-listpack-value", "set-*-value"]
+            )
+            .await,
+            Bytes::from(encoding::array(
+                [
+                    "set-max-listpack-value",
+                    "64",
+                    "hash-max-listpack-value",
+                    "64"
+                ]
+                .iter()
+                .map(encoding::bulk_string)
+                .collect()
+            ))
+        );
+
+        let mut config = ServerConfig::new(
+            vec![IpAddr::from([127, 0, 0, 1]), IpAddr::from([0, 0, 0, 0])],
+            7000,
+            None,
+            511,
+            true,
+            60,
+            OutputBufferLimits::default(),
+        );
+        config.io_threads = 4;
+        let save_points = vec![
+            SavePoint {
+                seconds: 3600,
+                changes: 1,
+            },
+            SavePoint {
+                seconds: 60,
+                changes: 10000,
+            },
+        ];
+        manager = RedisManager::new(
+            config,
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new(
+                "/nonexistent".to_string(),
+                "dump.rdb".to_string(),
+                save_points,
+            ),
+        );
+        for (name, value) in [
+            ("bind", "127.0.0.1 0.0.0.0"),
+            ("port", "7000"),
+            ("unixsocket", ""),
+            ("tcp-backlog", "511"),
+            ("tcp-keepalive", "1"),
+            ("timeout", "60"),
+            ("io-threads", "4"),
+            ("save", "3600 1 60 10000"),
+        ] {
+            assert_eq!(
+                reply(&mut manager, &client_info, &["CONFIG", "GET", name]).await,
+                Bytes::from(encoding::array(vec![
+                    encoding::bulk_string(name),
+                    encoding::bulk_string(value)
+                ])),
+                "{name}"
+            );
+        }
+
+        let all = reply(&mut manager, &client_info, &["CONFIG", "GET", "*"]).await;
+        let count = manager.config_parameters().len() * 2;
+        assert!(all.starts_with(format!("*{count}\r\n").as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn config_set_changes_the_set_encoding_thresholds() {
+        let mut manager = test_manager();
+        let client_info = test_client(1);
+        let encoding = |key: &'static str| ["OBJECT", "ENCODING", key];
+
+        reply(
+            &mut manager,
+            &client_info,
+            &["SADD", "before", "1", "2", "3"],
+        )
+        .await;
+        let set = ["CONFIG", "SET", "set-max-intset-entries", "2"];
+        assert_eq!(reply(&mut manager, &client_info, &set).await, "+OK\r\n");
+        assert_eq!(
+            reply(
+                &mut manager,
+                &client_info,
+                &["CONFIG", "GET", "set-max-intset-entries"]
+            )
+            .await,
+            "*2\r\n$22\r\nset-max-intset-entries\r\n$1\r\n2\r\n"
+        );
+
+        // sets only move on to another encoding when they are written to
+        reply(
+            &mut manager,
+            &client_info,
+            &["SADD", "after", "1", "2", "3"],
+        )
+        .await;
+        assert_eq!(
+            reply(&mut manager, &client_info, &encoding("before")).await,
+            "$6\r\nintset\r\n"
+        );
+        assert_eq!(
+            reply(&mut manager, &client_info, &encoding("after")).await,
+            "$8\r\nlistpack\r\n"
+        );
+        reply(&mut manager, &client_info, &["SADD", "before", "4"]).await;
+        assert_eq!(
+            reply(&mut manager, &client_info, &encoding("before")).await,
+            "$8\r\nlistpack\r\n"
+        );
+
+        // a parameter that cannot be set leaves the ones before it unchanged too
+        let set = [
+            "CONFIG",
+            "SET",
+            "set-max-listpack-entries",
+            "1",
+            "port",
+            "7000",
+        ];
+        assert_eq!(
+            reply(&mut manager, &client_info, &set).await,
+            "-ERR CONFIG SET failed (possibly related to argument 'port') - can't set \
+             immutable config\r\n"
+        );
+        assert_eq!(manager.store.encoding_config.set_max_listpack_entries, 128);
+        let set = ["CONFIG", "SET", "set-max-listpack-value", "big"];
+        assert_eq!(
+            reply(&mut manager, &client_info, &set).await,
+            "-ERR CONFIG SET failed (possibly related to argument 'set-max-listpack-value') - \
+             argument couldn't be parsed into an integer\r\n"
+        );
+        assert_eq!(
+            reply(
+                &mut manager,
+                &client_info,
+                &["CONFIG", "SET", "appendonly", "yes"]
+            )
+            .await,
+            "-ERR Unknown option or number of arguments for CONFIG SET - 'appendonly'\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn config_set_changes_the_hash_encoding_thresholds() {
+        let mut manager = test_manager();
+        let client_info = test_client(1);
+        let set = [
+            "CONFIG",
+            "SET",
+            "hash-max-listpack-entries",
+            "1",
+            "hash-max-listpack-value",
+            "3",
+        ];
+        assert_eq!(reply(&mut manager, &client_info, &set).await, "+OK\r\n");
+
+        for (fields, encoding) in [
+            (&["small", "a", "1"][..], "listpack"),
+            (&["long", "a", "1234"], "hashtable"),
+            (&["many", "a", "1", "b", "2"], "hashtable"),
+        ] {
+            let mut hset = vec!["HSET"];
+            hset.extend(fields);
+            reply(&mut manager, &client_info, &hset).await;
+            assert_eq!(
+                reply(
+                    &mut manager,
+                    &client_info,
+                    &["OBJECT", "ENCODING", fields[0]]
+                )
+                .await,
+                format!("${}\r\n{encoding}\r\n", encoding.len())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn config_set_changes_the_list_node_limit() {
+        let mut manager = test_manager();
+        let client_info = test_client(1);
+        let set = ["CONFIG", "SET", "list-max-listpack-size", "2"];
+        assert_eq!(reply(&mut manager, &client_info, &set).await, "+OK\r\n");
+        assert_eq!(manager.store.encoding_config.list_max_listpack_size, 2);
+
+        reply(
+            &mut manager,
+            &client_info,
+            &["RPUSH", "list", "a", "b", "c"],
+        )
+        .await;
+        assert_eq!(
+            reply(&mut manager, &client_info, &["OBJECT", "ENCODING", "list"]).await,
+            "$9\r\nquicklist\r\n"
+        );
+
+        let set = ["CONFIG", "SET", "list-max-listpack-size", "-3"];
+        assert_eq!(reply(&mut manager, &client_info, &set).await, "+OK\r\n");
+        assert_eq!(
+            reply(&mut manager, &client_info, &["CONFIG", "GET", "list-max-*"]).await,
+            "*2\r\n$22\r\nlist-max-listpack-size\r\n$2\r\n-3\r\n"
+        );
+        // popping checks the list against the new limit, which it now fits in
+        reply(&mut manager, &client_info, &["RPOP", "list"]).await;
+        assert_eq!(
+            reply(&mut manager, &client_info, &["OBJECT", "ENCODING", "list"]).await,
+            "$8\r\nlistpack\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_quicklist_nodes_in_debug_object() {
+        let mut manager = test_manager();
+        manager.store.encoding_config.list_max_listpack_size = 2;
+        let client_info = test_client(1);
+        reply(&mut manager, &client_info, &["RPUSH", "list", "a", "b"]).await;
+        let listpack = reply(&mut manager, &client_info, &["DEBUG", "OBJECT", "list"]).await;
+        let listpack = String::from_utf8_lossy(&listpack);
+        assert!(listpack.contains("encoding:listpack"), "{listpack}");
+        assert!(!listpack.contains("ql_nodes"), "{listpack}");
+
+        reply(&mut manager, &client_info, &["RPUSH", "list", "c"]).await;
+        let quicklist = reply(&mut manager, &client_info, &["DEBUG", "OBJECT", "list"]).await;
+        let quicklist = String::from_utf8_lossy(&quicklist);
+        assert!(quicklist.contains("encoding:quicklist"), "{quicklist}");
+        assert!(quicklist.contains(" ql_nodes:2 "), "{quicklist}");
+    }
+
+    #[tokio::test]
+    async fn sorts_lists_and_stores_the_result_as_a_list() {
+        let mut manager = test_manager();
+        let client_info = test_client(1);
+        reply(
+            &mut manager,
+            &client_info,
+            &["RPUSH", "list", "3", "10", "2"],
+        )
+        .await;
+        assert_eq!(
+            reply(&mut manager, &client_info, &["SORT", "list", "DESC"]).await,
+            "*3\r\n$2\r\n10\r\n$1\r\n3\r\n$1\r\n2\r\n"
+        );
+
+        reply(&mut manager, &client_info, &["SET", "sorted", "value"]).await;
+        let dirty = manager.store.dirty();
+        let store = ["SORT", "list", "ALPHA", "STORE", "sorted"];
+        assert_eq!(reply(&mut manager, &client_info, &store).await, ":3\r\n");
+        assert_eq!(manager.store.dirty(), dirty + 1);
+        assert_eq!(
+            reply(&mut manager, &client_info, &["LRANGE", "sorted", "0", "-1"]).await,
+            "*3\r\n$2\r\n10\r\n$1\r\n2\r\n$1\r\n3\r\n"
+        );
+
+        // an empty result deletes the destination instead of storing an empty list
+        let store = ["SORT", "missing", "STORE", "sorted"];
+        assert_eq!(reply(&mut manager, &client_info, &store).await, ":0\r\n");
+        assert_eq!(
+            reply(&mut manager, &client_info, &["TYPE", "sorted"]).await,
+            "+none\r\n"
+        );
+        assert_eq!(manager.store.dirty(), dirty + 2);
+    }
+
+    #[tokio::test]
+    async fn keeps_keyspace_stats_across_debug_reload() {
+        let dir = std::env::temp_dir().join(format!("reload-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new(dir.display().to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let client_info = test_client(1);
+        reply(&mut manager, &client_info, &["SET", "key", "value"]).await;
+        reply(&mut manager, &client_info, &["GET", "key"]).await;
+        reply(&mut manager, &client_info, &["GET", "missing"]).await;
+
+        let reloaded = reply(&mut manager, &client_info, &["DEBUG", "RELOAD"]).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(reloaded, Bytes::from("+OK\r\n"));
+        let stats = manager.store.keyspace_stats();
+        assert_eq!((stats.keyspace_hits, stats.keyspace_misses), (1, 1));
+        assert!(manager.store.get(0, b"key").is_some());
+    }
+
+    #[tokio::test]
+    async fn replies_loading_until_the_rdb_file_is_loaded() {
+        async fn get(client: &mut TcpStream) -> Vec<u8> {
+            client
+                .write_all(b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n")
+                .await
+                .unwrap();
+            let mut reply = vec![0; 64];
+            let len = client.read(&mut reply).await.unwrap();
+            reply.truncate(len);
+            reply
+        }
+
+        let dir = std::env::temp_dir().join(format!("loading-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rdb_config =
+            || RDBConfig::new(dir.display().to_string(), "dump.rdb".to_string(), vec![]);
+
+        // DEBUG RELOAD saves the dump that is fed to the loading server below
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            rdb_config(),
+        );
+        let client_info = test_client(1);
+        reply(&mut manager, &client_info, &["SET", "key", "value"]).await;
+        reply(&mut manager, &client_info, &["DEBUG", "RELOAD"]).await;
+        let dump_path = dir.join("dump.rdb");
+        let dump = std::fs::read(&dump_path).unwrap();
+
+        // reading from a named pipe blocks the loading thread until the dump is written
+        std::fs::remove_file(&dump_path).unwrap();
+        let status = std::process::Command::new("mkfifo")
+            .arg(&dump_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![IpAddr::from([127, 0, 0, 1])],
+                port,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            rdb_config(),
+        );
+        let client = async {
+            let mut client = loop {
+                match TcpStream::connect(("127.0.0.1", port)).await {
+                    Ok(client) => break client,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            };
+            assert_eq!(
+                get(&mut client).await,
+                b"-LOADING Redis is loading the dataset in memory\r\n"
+            );
+
+            let dump_path = dump_path.clone();
+            tokio::task::spawn_blocking(move || std::fs::write(dump_path, dump))
+                .await
+                .unwrap()
+                .unwrap();
+            loop {
+                let reply = get(&mut client).await;
+                if !reply.starts_with(b"-LOADING") {
+                    assert_eq!(reply, b"$5\r\nvalue\r\n");
+                    break;
+                }
+            }
+        };
+
+        tokio::select! {
+            result = manager.start() => panic!("the server stopped: {result:?}"),
+            result = tokio::time::timeout(Duration::from_secs(5), client) => result.unwrap(),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_debug_reload_while_loading() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        manager.is_loading = true;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        manager
+            .handle_packet(RedisCommandPacket::new(
+                ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new())),
+                RedisCommand::Server(RedisServerCommand::Debug {
+                    section: DebugSection::Reload,
+                }),
+                RedisWriteStream::new(tx),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Bytes::from("-LOADING Redis is loading the dataset in memory\r\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_the_connection_open_after_a_command_error() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (read_half, write_half) = tokio::io::split(server);
+        let (read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            None,
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        let (command_tx, mut command_rx) = mpsc::channel(4);
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+        tokio::spawn(RedisManager::process_stream(
+            client_info,
+            read_stream,
+            write_stream,
+            command_tx,
+            ConnectionStats::default().connect(),
+        ));
+
+        client.write_all(b"SET s v\r\n").await.unwrap();
+        client.write_all(b"XADD s * f v\r\n").await.unwrap();
+        client.write_all(b"FOO a b\r\n").await.unwrap();
+        client.write_all(b"PING\r\n").await.unwrap();
+        for _ in 0..4 {
+            let packet = command_rx.recv().await.unwrap();
+            manager.process_packet(packet).await;
+        }
+
+        let expected = b"+OK\r\n\
+            -WRONGTYPE Operation against a key holding the wrong kind of value\r\n\
+            -ERR unknown command 'FOO', with args beginning with: 'a' 'b' \r\n\
+            +PONG\r\n";
+        let mut reply = vec![0; expected.len()];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, expected);
+    }
+
+    #[tokio::test]
+    async fn closes_only_the_connection_of_a_transport_error() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let ping = RedisCommand::Server(RedisServerCommand::Ping { message: None });
+
+        // the connection is gone, so the reply cannot be written
+        let (tx, rx) = mpsc::unbounded_channel();
+        drop(rx);
+        let broken_stream = RedisWriteStream::new(tx);
+        manager
+            .process_packet(RedisCommandPacket::new(
+                ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new())),
+                ping.clone(),
+                broken_stream.clone(),
+            ))
+            .await;
+        assert!(broken_stream.is_disconnected());
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let write_stream = RedisWriteStream::new(tx);
+        manager
+            .process_packet(RedisCommandPacket::new(
+                ClientConnectionInfo::new(ClientId::new(2), ClientAddress::Unix(PathBuf::new())),
+                ping,
+                write_stream.clone(),
+            ))
+            .await;
+        assert!(!write_stream.is_disconnected());
+        assert_eq!(rx.try_recv().unwrap(), Bytes::from("+PONG\r\n"));
+    }
+
+    #[tokio::test]
+    async fn restricts_commands_of_shard_subscribers() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+        let channels = vec![Bytes::from("channel")];
+        let get = RedisCommand::Store(RedisStoreCommand::Get {
+            key: Bytes::from("key"),
+        });
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for command in [
+            RedisCommand::PubSub(RedisPubSubCommand::SSubscribe {
+                channels: channels.clone(),
+            }),
+            get.clone(),
+            RedisCommand::PubSub(RedisPubSubCommand::SPublish {
+                channel: channels[0].clone(),
+                message: Bytes::from("message"),
+            }),
+            RedisCommand::PubSub(RedisPubSubCommand::SUnsubscribe { channels }),
+            get,
+        ] {
+            manager
+                .handle_packet(RedisCommandPacket::new(
+                    client_info.clone(),
+                    command,
+                    RedisWriteStream::new(tx.clone()),
+                ))
+                .await
+                .unwrap();
+        }
+
+        let mut replies = vec![];
+        while let Ok(reply) = rx.try_recv() {
+            replies.push(reply);
+        }
+
+        let rejection = |name: &str| {
+            Bytes::from(format!(
+                "-ERR Can't execute '{name}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context\r\n"
+            ))
+        };
+        assert_eq!(
+            replies,
+            [
+                Bytes::from("*3\r\n$10\r\nssubscribe\r\n$7\r\nchannel\r\n:1\r\n"),
+                rejection("get"),
+                rejection("spublish"),
+                Bytes::from("*3\r\n$12\r\nsunsubscribe\r\n$7\r\nchannel\r\n:0\r\n"),
+                Bytes::from("$-1\r\n"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn debug_sleep_stalls_every_client() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let (command_tx, mut command_rx) = mpsc::channel(4);
+        let mut clients = vec![];
+        for id in 1..=2 {
+            let (client, server) = tokio::io::duplex(1024);
+            let (read_half, write_half) = tokio::io::split(server);
+            let (read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+                read_half,
+                write_half,
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(AtomicUsize::new(0)),
+                None,
+                0,
+                DEFAULT_MAX_BULK_LEN,
+            );
+            let client_info =
+                ClientConnectionInfo::new(ClientId::new(id), ClientAddress::Unix(PathBuf::new()));
+            tokio::spawn(RedisManager::process_stream(
+                client_info,
+                read_stream,
+                write_stream,
+                command_tx.clone(),
+                ConnectionStats::default().connect(),
+            ));
+            clients.push(client);
+        }
+
+        // commands are executed one at a time, like in `start`
+        tokio::spawn(async move {
+            while let Some(packet) = command_rx.recv().await {
+                manager.handle_packet(packet).await.unwrap();
+            }
+        });
+
+        let started_at = Instant::now();
+        clients[0]
+            .write_all(b"*3\r\n$5\r\nDEBUG\r\n$5\r\nSLEEP\r\n$3\r\n0.3\r\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        clients[1].write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+
+        let mut pong = [0; 7];
+        clients[1].read_exact(&mut pong).await.unwrap();
+        assert_eq!(&pong, b"+PONG\r\n");
+        assert!(started_at.elapsed() >= Duration::from_millis(300));
+        let mut ok = [0; 5];
+        clients[0].read_exact(&mut ok).await.unwrap();
+        assert_eq!(&ok, b"+OK\r\n");
+    }
+
+    #[tokio::test]
+    async fn applies_writes_to_the_database_the_primary_selected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let link = Arc::new(PrimaryLink::default());
+        let (command_tx, mut command_rx) = mpsc::channel(4);
+        let handshake = tokio::spawn(handshake::complete_handshake(
+            6380,
+            ("127.0.0.1", port),
+            link.clone(),
+            false,
+            command_tx,
+        ));
+
+        // the primary's part of the handshake, followed by what it propagates
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut read_half = RESPReader::new(read_half);
+        for reply in [
+            &b"+PONG\r\n"[..],
+            b"+OK\r\n",
+            b"+OK\r\n",
+            b"+FULLRESYNC abc 0\r\n$3\r\nrdb",
+        ] {
+            read_half.read_value().await.unwrap();
+            write_half.write_all(reply).await.unwrap();
+        }
+        handshake.await.unwrap().unwrap();
+        write_half.write_all(&encoding::select(1)).await.unwrap();
+        write_half
+            .write_all(&encoding::set("key", "value", None))
+            .await
+            .unwrap();
+
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6380,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::replica("127.0.0.1".to_string(), port),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        for _ in 0..2 {
+            let packet = command_rx.recv().await.unwrap();
+            manager.handle_packet(packet).await.unwrap();
+        }
+
+        assert!(manager.store.get(0, b"key").is_none());
+        assert!(manager.store.get(1, b"key").is_some());
+        link.detach();
+    }
+
+    #[tokio::test]
+    async fn acks_the_offset_from_before_each_getack() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6380,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::replica("localhost".to_string(), 6379),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let set = RedisCommand::Store(RedisStoreCommand::Set {
+            key: Bytes::from("key"),
+            value: Bytes::from("value"),
+            px: None,
+        });
+        let getack = RedisCommand::Replication(RedisReplicationCommand::ReplConf {
+            section: ReplConfSection::GetAck,
+        });
+        let primary_info =
+            ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for (command, write_stream) in [
+            (set.clone(), RedisWriteStream::sink()),
+            (getack.clone(), RedisWriteStream::new(tx.clone())),
+            (getack, RedisWriteStream::new(tx)),
+        ] {
+            manager
+                .handle_packet(RedisCommandPacket::new(
+                    primary_info.clone(),
+                    command,
+                    write_stream,
+                ))
+                .await
+                .unwrap();
+        }
+
+        // like in Redis, a `GETACK` only counts towards the offsets reported after it
+        let set_len = Bytes::from(&set).len();
+        let getack_len = encoding::replconf_get_ack().len();
+        assert_eq!(rx.recv().await.unwrap(), encoding::replconf_ack(set_len));
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            encoding::replconf_ack(set_len + getack_len)
+        );
+    }
+
+    #[tokio::test]
+    async fn replicates_every_write_command() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let (replica_tx, mut replica_rx) = mpsc::unbounded_channel();
+        let psync = RedisCommand::Replication(RedisReplicationCommand::PSync {
+            replication_id: "?".to_string(),
+            replication_offset: -1,
+            failover: false,
+        });
+        let replica_info =
+            ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+        manager
+            .handle_packet(RedisCommandPacket::new(
+                replica_info,
+                psync,
+                RedisWriteStream::new(replica_tx),
+            ))
+            .await
+            .unwrap();
+
+        let bytes = |values: &[&'static str]| -> Vec<Bytes> {
+            values.iter().map(|value| Bytes::from(*value)).collect()
+        };
+        let score = |score| Score::new(score).unwrap();
+        let writes = [
+            (
+                0,
+                RedisStoreCommand::Set {
+                    key: Bytes::from("string"),
+                    value: Bytes::from("value"),
+                    px: None,
+                },
+            ),
+            (
+                0,
+                RedisStoreCommand::Set {
+                    key: Bytes::from("expiring"),
+                    value: Bytes::from("value"),
+                    px: Some(std::time::SystemTime::now() + std::time::Duration::from_secs(60)),
+                },
+            ),
+            (
+                0,
+                RedisStoreCommand::GetEx {
+                    key: Bytes::from("expiring"),
+                    expiry_option: Some(ExpiryOption::Persist),
+                },
+            ),
+            (
+                0,
+                RedisStoreCommand::XAdd {
+                    key: Bytes::from("stream"),
+                    entry_id: XAddEntryId::Auto,
+                    no_mkstream: false,
+                    trim: None,
+                    fields: vec![(Bytes::from("field"), Bytes::from("value"))],
+                },
+            ),
+            (
+                0,
+                RedisStoreCommand::XAdd {
+                    key: Bytes::from("stream"),
+                    entry_id: XAddEntryId::Explicit(StreamEntryId::new(1, 1)),
+                    no_mkstream: false,
+                    trim: None,
+                    fields: vec![(Bytes::from("rejected"), Bytes::from("value"))],
+                },
+            ),
+            (
+                0,
+                RedisStoreCommand::SetBit {
+                    key: Bytes::from("bits"),
+                    offset: 7,
+                    bit: true,
+                },
+            ),
+            (
+                1,
+                RedisStoreCommand::SAdd {
+                    key: Bytes::from("set"),
+                    members: bytes(&["a", "b"]),
+                },
+            ),
+            (
+                1,
+                RedisStoreCommand::SAdd {
+                    key: Bytes::from("other-set"),
+                    members: bytes(&["c"]),
+                },
+            ),
+            (
+                1,
+                RedisStoreCommand::SetOperationStore {
+                    operation: SetOperation::Union,
+                    destination: Bytes::from("union"),
+                    keys: bytes(&["set", "other-set"]),
+                },
+            ),
+            (
+                1,
+                RedisStoreCommand::SMove {
+                    source: Bytes::from("set"),
+                    destination: Bytes::from("other-set"),
+                    member: Bytes::from("a"),
+                },
+            ),
+            (
+                1,
+                RedisStoreCommand::HSet {
+                    key: Bytes::from("hash"),
+                    fields: vec![(Bytes::from("field"), Bytes::from("value"))],
+                },
+            ),
+            (
+                1,
+                RedisStoreCommand::ZAdd {
+                    key: Bytes::from("zset"),
+                    members: vec![
+                        (score(1.0), Bytes::from("a")),
+                        (score(2.0), Bytes::from("b")),
+                    ],
+                },
+            ),
+            (
+                1,
+                RedisStoreCommand::ZPop {
+                    key: Bytes::from("zset"),
+                    count: None,
+                    end: MinOrMax::Min,
+                },
+            ),
+            (
+                1,
+                RedisStoreCommand::ZMPop {
+                    keys: bytes(&["missing", "zset"]),
+                    end: MinOrMax::Max,
+                    count: Some(5),
+                },
+            ),
+            (
+                2,
+                RedisStoreCommand::PfAdd {
+                    key: Bytes::from("hll"),
+                    elements: bytes(&["a", "b"]),
+                },
+            ),
+            (
+                2,
+                RedisStoreCommand::PfMerge {
+                    destination: Bytes::from("merged"),
+                    keys: bytes(&["hll"]),
+                },
+            ),
+            (
+                2,
+                RedisStoreCommand::GeoAdd {
+                    key: Bytes::from("geo"),
+                    points: vec![(
+                        Coordinates::parse(b"13.361389", b"38.115556").unwrap(),
+                        Bytes::from("Palermo"),
+                    )],
+                },
+            ),
+            (
+                2,
+                RedisStoreCommand::Move {
+                    key: Bytes::from("hll"),
+                    db: 3,
+                },
+            ),
+            (
+                0,
+                RedisStoreCommand::SwapDb {
+                    index: 1,
+                    other_index: 4,
+                },
+            ),
+        ];
+
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(2), ClientAddress::Unix(PathBuf::new()));
+        for (db, write) in writes {
+            assert!(write.is_write(), "{} is not a write", write.name());
+            assert!(command_table::lookup(write.name().as_bytes())
+                .is_some_and(|spec| spec.flags.contains(&"write")));
+            client_info
+                .selected_db
+                .store(db, std::sync::atomic::Ordering::SeqCst);
+            manager
+                .handle_packet(RedisCommandPacket::new(
+                    client_info.clone(),
+                    RedisCommand::Store(write),
+                    RedisWriteStream::sink(),
+                ))
+                .await
+                .unwrap();
+        }
+
+        let mut replicated = vec![];
+        while let Ok(bytes) = replica_rx.try_recv() {
+            replicated.extend_from_slice(&bytes);
+        }
+
+        // a replica picking its own stream IDs would most likely pick later ones by now
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        let mut reader = RESPReader::new(&replicated[..]);
+        reader.read_value().await.unwrap();
+        reader.read_rdb_file().await.unwrap();
+        let mut replica = RedisStore::new(16);
+        let mut db = 0;
+        while let Ok(value) = reader.read_value().await {
+            match RedisCommand::try_from(value).unwrap() {
+                RedisCommand::Server(RedisServerCommand::Select { index }) => db = index,
+                RedisCommand::Store(command) => replica
+                    .handle(db, &command, RedisWriteStream::sink())
+                    .await
+                    .unwrap(),
+                command => panic!("unexpected replicated command {command:?}"),
+            }
+        }
+
+        for (index, (primary_db, replica_db)) in manager
+            .store
+            .databases()
+            .zip(replica.databases())
+            .enumerate()
+        {
+            let primary_db = primary_db.collect::<std::collections::HashMap<_, _>>();
+            let replica_db = replica_db.collect::<std::collections::HashMap<_, _>>();
+            assert_eq!(primary_db, replica_db, "database {index} differs");
+        }
+    }
+
+    #[tokio::test]
+    async fn bzmpop_waits_for_members_and_replicates_the_pop() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let (replica_tx, mut replica_rx) = mpsc::unbounded_channel();
+        let psync = RedisCommand::Replication(RedisReplicationCommand::PSync {
+            replication_id: "?".to_string(),
+            replication_offset: -1,
+            failover: false,
+        });
+        manager
+            .handle_packet(RedisCommandPacket::new(
+                ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new())),
+                psync,
+                RedisWriteStream::new(replica_tx),
+            ))
+            .await
+            .unwrap();
+        while replica_rx.try_recv().is_ok() {}
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let bzmpop = RedisCommand::Store(RedisStoreCommand::BZMPop {
+            keys: vec![Bytes::from("other"), Bytes::from("zset")],
+            timeout: None,
+            end: MinOrMax::Min,
+            count: Some(2),
+        });
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(2), ClientAddress::Unix(PathBuf::new()));
+        manager
+            .handle_packet(RedisCommandPacket::new(
+                client_info.clone(),
+                bzmpop,
+                RedisWriteStream::new(tx),
+            ))
+            .await
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+        assert!(client_info
+            .is_read_blocked
+            .load(std::sync::atomic::Ordering::SeqCst));
+
+        let zadd = RedisCommand::Store(RedisStoreCommand::ZAdd {
+            key: Bytes::from("zset"),
+            members: [(1.0, "a"), (2.0, "b"), (3.0, "c")]
+                .into_iter()
+                .map(|(score, member)| (Score::new(score).unwrap(), Bytes::from(member)))
+                .collect(),
+        });
+        manager
+            .handle_packet(RedisCommandPacket::new(
+                ClientConnectionInfo::new(ClientId::new(3), ClientAddress::Unix(PathBuf::new())),
+                zadd,
+                RedisWriteStream::sink(),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            Bytes::from(RedisStore::multi_pop_reply(
+                Bytes::from("zset"),
+                vec![
+                    (Bytes::from("a"), Score::new(1.0).unwrap()),
+                    (Bytes::from("b"), Score::new(2.0).unwrap()),
+                ],
+            ))
+        );
+        assert!(!client_info
+            .is_read_blocked
+            .load(std::sync::atomic::Ordering::SeqCst));
+
+        let mut replicated = vec![];
+        while let Ok(bytes) = replica_rx.try_recv() {
+            replicated.extend_from_slice(&bytes);
+        }
+        assert!(replicated.ends_with(&encoding::zpop("zset", Some(2), MinOrMax::Min)));
+    }
+
+    #[tokio::test]
+    async fn blmpop_waits_for_elements_and_replicates_the_pop() {
+        let mut manager = test_manager();
+        let (replica_tx, mut replica_rx) = mpsc::unbounded_channel();
+        let psync = RedisCommand::Replication(RedisReplicationCommand::PSync {
+            replication_id: "?".to_string(),
+            replication_offset: -1,
+            failover: false,
+        });
+        manager
+            .handle_packet(RedisCommandPacket::new(
+                test_client(1),
+                psync,
+                RedisWriteStream::new(replica_tx),
+            ))
+            .await
+            .unwrap();
+        while replica_rx.try_recv().is_ok() {}
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let blmpop = RedisCommand::Store(RedisStoreCommand::BLMPop {
+            keys: vec![Bytes::from("other"), Bytes::from("list")],
+            timeout: None,
+            end: ListEnd::Right,
+            count: Some(2),
+        });
+        let client_info = test_client(2);
+        manager
+            .handle_packet(RedisCommandPacket::new(
+                client_info.clone(),
+                blmpop,
+                RedisWriteStream::new(tx),
+            ))
+            .await
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+        assert!(client_info
+            .is_read_blocked
+            .load(std::sync::atomic::Ordering::SeqCst));
+
+        assert_eq!(
+            reply(
+                &mut manager,
+                &test_client(3),
+                &["RPUSH", "list", "a", "b", "c"]
+            )
+            .await,
+            ":3\r\n"
+        );
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            "*2\r\n$4\r\nlist\r\n*2\r\n$1\r\nc\r\n$1\r\nb\r\n"
+        );
+        assert!(!client_info
+            .is_read_blocked
+            .load(std::sync::atomic::Ordering::SeqCst));
+
+        let mut replicated = vec![];
+        while let Ok(bytes) = replica_rx.try_recv() {
+            replicated.extend_from_slice(&bytes);
+        }
+        assert!(replicated.ends_with(&encoding::list_pop("list", ListEnd::Right, Some(2))));
+        assert_eq!(
+            reply(
+                &mut manager,
+                &test_client(3),
+                &["LRANGE", "list", "0", "-1"]
+            )
+            .await,
+            "*1\r\n$1\r\na\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn replicates_getdel_as_del_and_getex_with_its_expiry() {
+        let mut manager = RedisManager::new(
+            ServerConfig::new(
+                vec![],
+                6379,
+                None,
+                511,
+                false,
+                0,
+                OutputBufferLimits::default(),
+            ),
+            RedisStore::new(16),
+            RedisReplicationMode::primary(),
+            RDBConfig::new("/nonexistent".to_string(), "dump.rdb".to_string(), vec![]),
+        );
+        let (replica_tx, mut replica_rx) = mpsc::unbounded_channel();
+        let psync = RedisCommand::Replication(RedisReplicationCommand::PSync {
+            replication_id: "?".to_string(),
+            replication_offset: -1,
+            failover: false,
+        });
+        manager
+            .handle_packet(RedisCommandPacket::new(
+                ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new())),
+                psync,
+                RedisWriteStream::new(replica_tx),
+            ))
+            .await
+            .unwrap();
+
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(2), ClientAddress::Unix(PathBuf::new()));
+        for command in [
+            &["SET", "deleted", "value"][..],
+            &["SET", "expiring", "value"],
+            &["SADD", "set", "member"],
+            &["GETDEL", "deleted"],
+            &["GETDEL", "missing"],
+            &["GETDEL", "set"],
+            &["GETEX", "expiring", "EX", "100"],
+        ] {
+            let command = encoding::array(command.iter().map(encoding::bulk_string).collect());
+            manager
+                .handle_packet(RedisCommandPacket::new(
+                    client_info.clone(),
+                    RedisCommand::try_from(command).unwrap(),
+                    RedisWriteStream::sink(),
+                ))
+                .await
+                .unwrap();
+        }
+
+        let mut replicated = vec![];
+        while let Ok(bytes) = replica_rx.try_recv() {
+            replicated.extend_from_slice(&bytes);
+        }
+
+        let mut reader = RESPReader::new(&replicated[..]);
+        reader.read_value().await.unwrap();
+        reader.read_rdb_file().await.unwrap();
+        let mut replica = RedisStore::new(16);
+        let mut names = vec![];
+        while let Ok(value) = reader.read_value().await {
+            match RedisCommand::try_from(value).unwrap() {
+                RedisCommand::Store(command) => {
+                    names.push(command.name());
+                    replica
+                        .handle(0, &command, RedisWriteStream::sink())
+                        .await
+                        .unwrap();
+                }
+                RedisCommand::Server(RedisServerCommand::Select { index: 0 }) => {}
+                command => panic!("unexpected replicated command {command:?}"),
+            }
+        }
+
+        // only the GETDEL that deleted a key is sent, as a DEL
+        assert_eq!(names, ["set", "set", "sadd", "del", "getex"]);
+        assert!(replica.get(0, b"deleted").is_none());
+        assert!(matches!(
+            replica.get(0, b"expiring"),
+            Some(StoreValue::String {
+                expiration: Some(_),
+                ..
+            })
+        ));
+        assert_eq!(
+            replica.get(0, b"expiring"),
+            manager.store.get(0, b"expiring")
+        );
+    }
+}
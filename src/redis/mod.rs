@@ -1,6 +1,19 @@
+pub mod acl;
+mod blocking;
+pub mod error;
+mod glob;
+mod latency;
+pub mod log;
 pub mod manager;
+mod monitor;
+pub mod pubsub;
+mod random;
 pub mod rdb;
 pub mod replication;
 mod resp;
-mod server;
+pub mod server;
+pub mod slowlog;
 pub mod store;
+
+/// The Redis version this server reports to clients.
+pub const REDIS_VERSION: &str = "7.2.0";
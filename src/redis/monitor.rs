@@ -0,0 +1,154 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use super::{
+    resp::{command::RedisCommand, encoding, RESPValue},
+    server::{ClientConnectionInfo, RedisWriteStream},
+};
+
+/// The connections that ran `MONITOR`, which are sent a line for every command the server
+/// executes until they disconnect.
+#[derive(Default)]
+pub struct Monitors {
+    write_streams: Vec<RedisWriteStream>,
+}
+
+impl Monitors {
+    pub fn add(&mut self, write_stream: RedisWriteStream) {
+        self.write_streams.push(write_stream);
+    }
+
+    /// Sends a command to every monitor. Like in Redis, administrative commands are left
+    /// out and passwords are redacted.
+    pub async fn feed(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        command: &RedisCommand,
+    ) -> anyhow::Result<()> {
+        self.write_streams
+            .retain(|write_stream| !write_stream.is_disconnected());
+        if self.write_streams.is_empty() || command.is_admin() {
+            return Ok(());
+        }
+
+        let line = Bytes::from(Self::line(SystemTime::now(), client_info, command).await?);
+        for write_stream in &self.write_streams {
+            // a monitor that went away must not stop the line reaching the others
+            let _ = write_stream.write(line.clone()).await;
+        }
+
+        Ok(())
+    }
+
+    /// Formats a command the way Redis does, such as
+    /// `+1700000000.123456 [0 127.0.0.1:5000] "SET" "key" "value"`.
+    async fn line(
+        now: SystemTime,
+        client_info: &ClientConnectionInfo,
+        command: &RedisCommand,
+    ) -> anyhow::Result<RESPValue> {
+        let now = now.duration_since(UNIX_EPOCH)?;
+        let args = command
+            .redacted_args()
+            .await?
+            .iter()
+            .map(|arg| quote(arg))
+            .collect::<Vec<_>>();
+
+        Ok(encoding::simple_string(format!(
+            "{}.{:06} [{} {}] {}",
+            now.as_secs(),
+            now.subsec_micros(),
+            client_info.selected_db(),
+            client_info.address,
+            args.join(" ")
+        )))
+    }
+}
+
+/// Quotes an argument like Redis does, escaping quotes, backslashes and unprintable bytes.
+fn quote(arg: &[u8]) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for &byte in arg {
+        match byte {
+            b'\\' => quoted.push_str("\\\\"),
+            b'"' => quoted.push_str("\\\""),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            0x07 => quoted.push_str("\\a"),
+            0x08 => quoted.push_str("\\b"),
+            byte if byte.is_ascii_graphic() || byte == b' ' => quoted.push(byte as char),
+            byte => quoted.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use bytes::Bytes;
+    use tokio::sync::mpsc;
+
+    use crate::redis::{
+        resp::command::{DebugSection, RedisCommand, RedisServerCommand, RedisStoreCommand},
+        server::{ClientAddress, ClientConnectionInfo, ClientId, RedisWriteStream},
+    };
+
+    use super::Monitors;
+
+    #[tokio::test]
+    async fn feeds_commands_to_connected_monitors() {
+        let mut monitors = Monitors::default();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let disconnected = RedisWriteStream::sink();
+        disconnected.disconnect();
+        monitors.add(disconnected);
+        monitors.add(RedisWriteStream::new(tx));
+
+        let client_info = ClientConnectionInfo::new(
+            ClientId::new(1),
+            ClientAddress::Tcp(SocketAddr::from(([127, 0, 0, 1], 5000))),
+        );
+        for command in [
+            RedisCommand::Store(RedisStoreCommand::Set {
+                key: Bytes::from("key"),
+                value: Bytes::from("say \"hi\"\r\n\x00"),
+                px: None,
+            }),
+            RedisCommand::Server(RedisServerCommand::Debug {
+                section: DebugSection::Sleep {
+                    duration: Duration::ZERO,
+                },
+            }),
+            RedisCommand::Server(RedisServerCommand::Auth {
+                username: None,
+                password: Bytes::from("secret"),
+            }),
+        ] {
+            monitors.feed(&client_info, &command).await.unwrap();
+        }
+
+        assert_eq!(monitors.write_streams.len(), 1);
+        // the timestamp is left out, since it changes with every run
+        let mut lines = vec![];
+        while let Ok(line) = rx.try_recv() {
+            let line = String::from_utf8(line.to_vec()).unwrap();
+            lines.push(line[line.find(' ').unwrap()..].to_string());
+        }
+
+        assert_eq!(
+            lines,
+            [
+                " [0 127.0.0.1:5000] \"SET\" \"key\" \"say \\\"hi\\\"\\r\\n\\x00\"\r\n",
+                " [0 127.0.0.1:5000] \"AUTH\" \"(redacted)\"\r\n",
+            ]
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use bytes::Bytes;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PubSubSection {
+    Channels { pattern: Option<Bytes> },
+    NumSub { channels: Vec<Bytes> },
+    NumPat,
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RedisPubSubCommand {
+    Subscribe { channels: Vec<Bytes> },
+    Unsubscribe { channels: Vec<Bytes> },
+    Publish { channel: Bytes, message: Bytes },
+    SSubscribe { channels: Vec<Bytes> },
+    SUnsubscribe { channels: Vec<Bytes> },
+    SPublish { channel: Bytes, message: Bytes },
+    PubSub { section: PubSubSection },
+}
+
+impl RedisPubSubCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Subscribe { .. } => "subscribe",
+            Self::Unsubscribe { .. } => "unsubscribe",
+            Self::Publish { .. } => "publish",
+            Self::SSubscribe { .. } => "ssubscribe",
+            Self::SUnsubscribe { .. } => "sunsubscribe",
+            Self::SPublish { .. } => "spublish",
+            Self::PubSub { .. } => "pubsub",
+        }
+    }
+}
@@ -0,0 +1,773 @@
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+use bytes::Bytes;
+
+use self::command::{PubSubSection, RedisPubSubCommand};
+
+use super::{
+    glob,
+    resp::{encoding, RESPValue},
+    server::{ClientConnectionInfo, ClientId, RedisWriteStream},
+};
+
+pub mod command;
+
+/// A client subscribed to a channel, along with the write stream it subscribed with.
+struct Subscriber {
+    client_info: ClientConnectionInfo,
+    write_stream: RedisWriteStream,
+}
+
+/// Which namespace a channel lives in. Shard channels, used through `SSUBSCRIBE` and
+/// `SPUBLISH`, are kept apart from regular channels even though there are no shards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChannelKind {
+    Global,
+    Shard,
+}
+
+impl ChannelKind {
+    /// The prefix of the kinds of messages sent to subscribers, such as `smessage`.
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Global => "",
+            Self::Shard => "s",
+        }
+    }
+}
+
+/// The channels of one namespace and the clients subscribed to them.
+#[derive(Default)]
+struct Channels {
+    subscribers: HashMap<Bytes, HashMap<ClientId, Subscriber>>,
+    /// The channels of each client, in the order they were subscribed to.
+    subscriptions: HashMap<ClientId, Vec<Bytes>>,
+}
+
+impl Channels {
+    /// Adds a single subscription, returning how many subscriptions the client has.
+    fn add_subscription(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        channel: &Bytes,
+        write_stream: &RedisWriteStream,
+    ) -> usize {
+        let subscriptions = self.subscriptions.entry(client_info.id).or_default();
+        if !subscriptions.contains(channel) {
+            subscriptions.push(channel.clone());
+            self.subscribers.entry(channel.clone()).or_default().insert(
+                client_info.id,
+                Subscriber {
+                    client_info: client_info.clone(),
+                    write_stream: write_stream.clone(),
+                },
+            );
+        }
+
+        subscriptions.len()
+    }
+
+    /// Removes a single subscription, returning how many subscriptions the client has left.
+    fn remove_subscription(&mut self, id: ClientId, channel: &Bytes) -> usize {
+        if let Some(subscribers) = self.subscribers.get_mut(channel) {
+            subscribers.remove(&id);
+            if subscribers.is_empty() {
+                self.subscribers.remove(channel);
+            }
+        }
+
+        let Some(subscriptions) = self.subscriptions.get_mut(&id) else {
+            return 0;
+        };
+
+        subscriptions.retain(|subscribed| subscribed != channel);
+        let count = subscriptions.len();
+        if count == 0 {
+            self.subscriptions.remove(&id);
+        }
+
+        count
+    }
+
+    fn subscriptions(&self, id: ClientId) -> &[Bytes] {
+        self.subscriptions.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Drops every subscription of the clients whose connection closed, which would
+    /// otherwise be counted as receiving messages and keep their connection open.
+    fn remove_closed_clients(&mut self) {
+        let mut closed = vec![];
+        self.subscribers.retain(|_, subscribers| {
+            subscribers.retain(|id, subscriber| {
+                let is_closed = subscriber.client_info.is_closed();
+                if is_closed {
+                    closed.push(*id);
+                }
+
+                !is_closed
+            });
+            !subscribers.is_empty()
+        });
+
+        for id in closed {
+            self.subscriptions.remove(&id);
+        }
+    }
+}
+
+/// The channels clients are subscribed to, regular and shard channels alike. Messages
+/// are written to the write stream a client subscribed with.
+#[derive(Default)]
+pub struct PubSub {
+    channels: Channels,
+    shard_channels: Channels,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn handle_command(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        command: &RedisPubSubCommand,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        self.channels.remove_closed_clients();
+        self.shard_channels.remove_closed_clients();
+        match command {
+            RedisPubSubCommand::Subscribe { channels } => {
+                self.subscribe(client_info, ChannelKind::Global, channels, write_stream)
+                    .await
+            }
+            RedisPubSubCommand::Unsubscribe { channels } => {
+                self.unsubscribe(client_info, ChannelKind::Global, channels, write_stream)
+                    .await
+            }
+            RedisPubSubCommand::Publish { channel, message } => {
+                let receivers = self.publish(ChannelKind::Global, channel, message).await;
+                write_stream
+                    .write(encoding::integer(receivers as i64))
+                    .await
+            }
+            RedisPubSubCommand::SSubscribe { channels } => {
+                self.subscribe(client_info, ChannelKind::Shard, channels, write_stream)
+                    .await
+            }
+            RedisPubSubCommand::SUnsubscribe { channels } => {
+                self.unsubscribe(client_info, ChannelKind::Shard, channels, write_stream)
+                    .await
+            }
+            RedisPubSubCommand::SPublish { channel, message } => {
+                let receivers = self.publish(ChannelKind::Shard, channel, message).await;
+                write_stream
+                    .write(encoding::integer(receivers as i64))
+                    .await
+            }
+            RedisPubSubCommand::PubSub { section } => {
+                write_stream.write(self.introspect(section)).await
+            }
+        }
+    }
+
+    /// Replies to `PUBSUB`, which only looks at regular channels.
+    fn introspect(&self, section: &PubSubSection) -> RESPValue {
+        match section {
+            PubSubSection::Channels { pattern } => encoding::array(
+                self.channels
+                    .subscribers
+                    .keys()
+                    .filter(|channel| {
+                        pattern
+                            .as_ref()
+                            .is_none_or(|pattern| glob::matches(pattern, channel))
+                    })
+                    .map(encoding::bulk_string)
+                    .collect(),
+            ),
+            PubSubSection::NumSub { channels } => encoding::array(
+                channels
+                    .iter()
+                    .flat_map(|channel| {
+                        let count = self
+                            .channels
+                            .subscribers
+                            .get(channel)
+                            .map_or(0, HashMap::len);
+                        [
+                            encoding::bulk_string(channel),
+                            encoding::integer(count as i64),
+                        ]
+                    })
+                    .collect(),
+            ),
+            // there are no pattern subscriptions without `PSUBSCRIBE`
+            PubSubSection::NumPat => encoding::integer(0),
+            PubSubSection::Help => encoding::help(
+                "pubsub",
+                &[
+                    "CHANNELS [<pattern>]",
+                    "    Return the currently active channels matching a <pattern> (default: '*').",
+                    "NUMPAT",
+                    "    Return number of subscriptions to patterns.",
+                    "NUMSUB [<channel> ...]",
+                    "    Return the number of subscribers for the specified channels, excluding",
+                    "    pattern subscriptions(default: no channels).",
+                ],
+            ),
+        }
+    }
+
+    fn namespace(&self, kind: ChannelKind) -> &Channels {
+        match kind {
+            ChannelKind::Global => &self.channels,
+            ChannelKind::Shard => &self.shard_channels,
+        }
+    }
+
+    fn namespace_mut(&mut self, kind: ChannelKind) -> &mut Channels {
+        match kind {
+            ChannelKind::Global => &mut self.channels,
+            ChannelKind::Shard => &mut self.shard_channels,
+        }
+    }
+
+    /// Keeps the subscriptions of a client in both namespaces counted on its connection,
+    /// which stays in subscribe mode as long as it has any.
+    fn update_subscription_count(&self, client_info: &ClientConnectionInfo) {
+        let count = self.channels.subscriptions(client_info.id).len()
+            + self.shard_channels.subscriptions(client_info.id).len();
+        client_info
+            .subscription_count
+            .store(count, Ordering::SeqCst);
+    }
+
+    /// Subscribes to each channel in turn, confirming every channel separately with the
+    /// number of subscriptions the client has at that point in the namespace.
+    async fn subscribe(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        kind: ChannelKind,
+        channels: &[Bytes],
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let confirmation_kind = format!("{}subscribe", kind.prefix());
+        for channel in channels {
+            let count =
+                self.namespace_mut(kind)
+                    .add_subscription(client_info, channel, &write_stream);
+            self.update_subscription_count(client_info);
+            write_stream
+                .write(Self::confirmation(
+                    client_info,
+                    &confirmation_kind,
+                    Some(channel),
+                    count,
+                ))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Unsubscribes from each channel in turn, or from every subscribed channel when none
+    /// are given, confirming every channel separately with the remaining subscriptions.
+    async fn unsubscribe(
+        &mut self,
+        client_info: &ClientConnectionInfo,
+        kind: ChannelKind,
+        channels: &[Bytes],
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let confirmation_kind = format!("{}unsubscribe", kind.prefix());
+        let channels = if channels.is_empty() {
+            self.namespace(kind).subscriptions(client_info.id).to_vec()
+        } else {
+            channels.to_vec()
+        };
+
+        if channels.is_empty() {
+            return write_stream
+                .write(Self::confirmation(client_info, &confirmation_kind, None, 0))
+                .await;
+        }
+
+        for channel in &channels {
+            let count = self
+                .namespace_mut(kind)
+                .remove_subscription(client_info.id, channel);
+            self.update_subscription_count(client_info);
+            write_stream
+                .write(Self::confirmation(
+                    client_info,
+                    &confirmation_kind,
+                    Some(channel),
+                    count,
+                ))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a message to every subscriber of the channel, returning how many received it.
+    /// Commands are handled one at a time and each subscriber is written to through its
+    /// connection's ordered write stream, so messages arrive in the order they were published.
+    async fn publish(&self, kind: ChannelKind, channel: &Bytes, message: &Bytes) -> usize {
+        let Some(subscribers) = self.namespace(kind).subscribers.get(channel) else {
+            return 0;
+        };
+
+        // RESP3 subscribers get pushes, which they can tell apart from replies
+        let values = || {
+            vec![
+                encoding::bulk_string(format!("{}message", kind.prefix())),
+                encoding::bulk_string(channel),
+                encoding::bulk_string(message),
+            ]
+        };
+        let message = Bytes::from(encoding::array(values()));
+        let pushed_message = Bytes::from(encoding::push(values()));
+
+        let mut receivers = 0;
+        for subscriber in subscribers.values() {
+            let message = if subscriber.client_info.is_resp3() {
+                pushed_message.clone()
+            } else {
+                message.clone()
+            };
+
+            // a subscriber that went away must not stop the message reaching the others
+            if subscriber.write_stream.write(message).await.is_ok() {
+                receivers += 1;
+            }
+        }
+
+        receivers
+    }
+
+    /// Confirms a change to the subscriptions, as a push under RESP3 like every message a
+    /// subscriber receives.
+    fn confirmation(
+        client_info: &ClientConnectionInfo,
+        kind: &str,
+        channel: Option<&Bytes>,
+        count: usize,
+    ) -> RESPValue {
+        let values = vec![
+            encoding::bulk_string(kind),
+            channel.map_or_else(encoding::null_bulk_string, encoding::bulk_string),
+            encoding::integer(count as i64),
+        ];
+
+        if client_info.is_resp3() {
+            encoding::push(values)
+        } else {
+            encoding::array(values)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use bytes::Bytes;
+    use tokio::{io::AsyncReadExt, sync::mpsc};
+
+    use crate::redis::{
+        resp::{encoding, resp_reader::DEFAULT_MAX_BULK_LEN},
+        server::{ClientAddress, ClientConnectionInfo, ClientId, RedisServer, RedisWriteStream},
+    };
+
+    use super::{
+        command::{PubSubSection, RedisPubSubCommand},
+        ChannelKind, PubSub,
+    };
+
+    fn channels(names: &[&'static str]) -> Vec<Bytes> {
+        names
+            .iter()
+            .map(|name| Bytes::from_static(name.as_bytes()))
+            .collect()
+    }
+
+    fn drain(rx: &mut mpsc::UnboundedReceiver<Bytes>) -> Vec<Bytes> {
+        let mut replies = vec![];
+        while let Ok(reply) = rx.try_recv() {
+            replies.push(reply);
+        }
+
+        replies
+    }
+
+    #[tokio::test]
+    async fn confirms_each_channel_with_running_count() {
+        let mut pubsub = PubSub::new();
+        let client_info =
+            ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let write_stream = RedisWriteStream::new(tx);
+
+        pubsub
+            .handle_command(
+                &client_info,
+                &RedisPubSubCommand::Subscribe {
+                    channels: channels(&["a", "b", "c"]),
+                },
+                write_stream.clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&mut rx),
+            [
+                &b"*3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n"[..],
+                &b"*3\r\n$9\r\nsubscribe\r\n$1\r\nb\r\n:2\r\n"[..],
+                &b"*3\r\n$9\r\nsubscribe\r\n$1\r\nc\r\n:3\r\n"[..],
+            ]
+        );
+        assert!(client_info.is_subscribed());
+
+        pubsub
+            .handle_command(
+                &client_info,
+                &RedisPubSubCommand::Unsubscribe { channels: vec![] },
+                write_stream.clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&mut rx),
+            [
+                &b"*3\r\n$11\r\nunsubscribe\r\n$1\r\na\r\n:2\r\n"[..],
+                &b"*3\r\n$11\r\nunsubscribe\r\n$1\r\nb\r\n:1\r\n"[..],
+                &b"*3\r\n$11\r\nunsubscribe\r\n$1\r\nc\r\n:0\r\n"[..],
+            ]
+        );
+        assert!(!client_info.is_subscribed());
+
+        pubsub
+            .handle_command(
+                &client_info,
+                &RedisPubSubCommand::Unsubscribe { channels: vec![] },
+                write_stream,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            drain(&mut rx),
+            [&b"*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n"[..]]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_shard_channels_apart_from_regular_channels() {
+        let mut pubsub = PubSub::new();
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let write_stream = RedisWriteStream::new(tx);
+        for command in [
+            RedisPubSubCommand::Subscribe {
+                channels: channels(&["a"]),
+            },
+            RedisPubSubCommand::SSubscribe {
+                channels: channels(&["a", "b"]),
+            },
+        ] {
+            pubsub
+                .handle_command(&client_info, &command, write_stream.clone())
+                .await
+                .unwrap();
+        }
+
+        // each namespace counts its own subscriptions
+        assert_eq!(
+            drain(&mut rx),
+            [
+                &b"*3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n"[..],
+                &b"*3\r\n$10\r\nssubscribe\r\n$1\r\na\r\n:1\r\n"[..],
+                &b"*3\r\n$10\r\nssubscribe\r\n$1\r\nb\r\n:2\r\n"[..],
+            ]
+        );
+
+        let message = Bytes::from("hi");
+        let channel = Bytes::from("b");
+        assert_eq!(
+            pubsub
+                .publish(ChannelKind::Global, &channel, &message)
+                .await,
+            0
+        );
+        assert_eq!(
+            pubsub.publish(ChannelKind::Shard, &channel, &message).await,
+            1
+        );
+        assert_eq!(
+            drain(&mut rx),
+            [&b"*3\r\n$8\r\nsmessage\r\n$1\r\nb\r\n$2\r\nhi\r\n"[..]]
+        );
+
+        pubsub
+            .handle_command(
+                &client_info,
+                &RedisPubSubCommand::Unsubscribe { channels: vec![] },
+                write_stream.clone(),
+            )
+            .await
+            .unwrap();
+        assert!(client_info.is_subscribed());
+        pubsub
+            .handle_command(
+                &client_info,
+                &RedisPubSubCommand::SUnsubscribe { channels: vec![] },
+                write_stream,
+            )
+            .await
+            .unwrap();
+        assert!(!client_info.is_subscribed());
+        assert_eq!(
+            drain(&mut rx),
+            [
+                &b"*3\r\n$11\r\nunsubscribe\r\n$1\r\na\r\n:0\r\n"[..],
+                &b"*3\r\n$12\r\nsunsubscribe\r\n$1\r\na\r\n:1\r\n"[..],
+                &b"*3\r\n$12\r\nsunsubscribe\r\n$1\r\nb\r\n:0\r\n"[..],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn forgets_subscribers_once_they_disconnect() {
+        let mut pubsub = PubSub::new();
+        let subscriber =
+            ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+        for command in [
+            RedisPubSubCommand::Subscribe {
+                channels: channels(&["news"]),
+            },
+            RedisPubSubCommand::SSubscribe {
+                channels: channels(&["news"]),
+            },
+        ] {
+            pubsub
+                .handle_command(&subscriber, &command, RedisWriteStream::sink())
+                .await
+                .unwrap();
+        }
+
+        subscriber.close();
+        let publisher =
+            ClientConnectionInfo::new(ClientId::new(2), ClientAddress::Unix(PathBuf::new()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for command in [
+            RedisPubSubCommand::Publish {
+                channel: Bytes::from("news"),
+                message: Bytes::from("hello"),
+            },
+            RedisPubSubCommand::SPublish {
+                channel: Bytes::from("news"),
+                message: Bytes::from("hello"),
+            },
+            RedisPubSubCommand::PubSub {
+                section: PubSubSection::Channels { pattern: None },
+            },
+        ] {
+            pubsub
+                .handle_command(&publisher, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(drain(&mut rx), [&b":0\r\n"[..], b":0\r\n", b"*0\r\n"]);
+        assert!(pubsub.channels.subscriptions.is_empty());
+        assert!(pubsub.shard_channels.subscriptions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_active_channels_and_subscribers() {
+        let mut pubsub = PubSub::new();
+        for (id, command) in [
+            (
+                1,
+                RedisPubSubCommand::Subscribe {
+                    channels: channels(&["news.tech", "news.art", "sport"]),
+                },
+            ),
+            (
+                2,
+                RedisPubSubCommand::Subscribe {
+                    channels: channels(&["news.tech"]),
+                },
+            ),
+            (
+                2,
+                RedisPubSubCommand::SSubscribe {
+                    channels: channels(&["news.shard"]),
+                },
+            ),
+            (
+                1,
+                RedisPubSubCommand::Unsubscribe {
+                    channels: channels(&["sport"]),
+                },
+            ),
+        ] {
+            let client_info =
+                ClientConnectionInfo::new(ClientId::new(id), ClientAddress::Unix(PathBuf::new()));
+            pubsub
+                .handle_command(&client_info, &command, RedisWriteStream::sink())
+                .await
+                .unwrap();
+        }
+
+        let active_channels = |pattern: Option<&'static str>| {
+            let mut channels = pubsub
+                .introspect(&PubSubSection::Channels {
+                    pattern: pattern.map(Bytes::from),
+                })
+                .into_array()
+                .unwrap()
+                .into_iter()
+                .map(|channel| channel.into_bulk_string().unwrap())
+                .collect::<Vec<_>>();
+            channels.sort();
+            channels
+        };
+        // channels without subscribers and shard channels are left out
+        assert_eq!(active_channels(None), channels(&["news.art", "news.tech"]));
+        assert_eq!(active_channels(Some("*.t*")), channels(&["news.tech"]));
+        assert_eq!(
+            pubsub.introspect(&PubSubSection::NumSub {
+                channels: channels(&["news.tech", "sport", "news.shard"]),
+            }),
+            encoding::array(vec![
+                encoding::bulk_string("news.tech"),
+                encoding::integer(2),
+                encoding::bulk_string("sport"),
+                encoding::integer(0),
+                encoding::bulk_string("news.shard"),
+                encoding::integer(0),
+            ])
+        );
+        assert_eq!(
+            pubsub.introspect(&PubSubSection::NumPat),
+            encoding::integer(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn pushes_messages_to_resp3_subscribers() {
+        let mut pubsub = PubSub::new();
+        let channel = Bytes::from_static(b"news");
+        let mut receivers = vec![];
+        for (id, protocol_version) in [(1, 2), (2, 3)] {
+            let client_info =
+                ClientConnectionInfo::new(ClientId::new(id), ClientAddress::Unix(PathBuf::new()));
+            client_info
+                .protocol_version
+                .store(protocol_version, Ordering::SeqCst);
+            let (tx, rx) = mpsc::unbounded_channel();
+            pubsub
+                .handle_command(
+                    &client_info,
+                    &RedisPubSubCommand::Subscribe {
+                        channels: vec![channel.clone()],
+                    },
+                    RedisWriteStream::new(tx),
+                )
+                .await
+                .unwrap();
+            receivers.push(rx);
+        }
+
+        assert_eq!(
+            pubsub
+                .publish(ChannelKind::Global, &channel, &Bytes::from("hi"))
+                .await,
+            2
+        );
+        assert_eq!(
+            drain(&mut receivers[0]),
+            [
+                &b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n"[..],
+                &b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n"[..],
+            ]
+        );
+        assert_eq!(
+            drain(&mut receivers[1]),
+            [
+                &b">3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n"[..],
+                &b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n"[..],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn delivers_messages_in_publish_order() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let (read_half, write_half) = tokio::io::split(server);
+        let (_read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            None,
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        let mut pubsub = PubSub::new();
+        let client_info =
+            ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+        let channel = Bytes::from_static(b"news");
+        pubsub
+            .handle_command(
+                &client_info,
+                &RedisPubSubCommand::Subscribe {
+                    channels: vec![channel.clone()],
+                },
+                write_stream.clone(),
+            )
+            .await
+            .unwrap();
+
+        let mut expected = b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n".to_vec();
+        for index in 0..20 {
+            // replies to the subscriber's own commands share the stream with its messages
+            if index == 10 {
+                write_stream
+                    .write(encoding::simple_string("PONG"))
+                    .await
+                    .unwrap();
+                expected.extend_from_slice(b"+PONG\r\n");
+            }
+
+            let message = Bytes::from(index.to_string());
+            assert_eq!(
+                pubsub
+                    .publish(ChannelKind::Global, &channel, &message)
+                    .await,
+                1
+            );
+            expected.extend_from_slice(
+                format!(
+                    "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n${}\r\n{index}\r\n",
+                    message.len()
+                )
+                .as_bytes(),
+            );
+        }
+
+        let mut received = vec![0; expected.len()];
+        client.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Write,
+    hash::{BuildHasher, Hasher},
+    io::Read,
+};
+
+/// Returns a random index below `len`, which must not be zero. Every `RandomState` is
+/// keyed randomly by the standard library, which is enough for sampling values.
+pub fn index(len: usize) -> usize {
+    let random = RandomState::new().build_hasher().finish();
+    (random % len as u64) as usize
+}
+
+/// Generates a random 40 character hex ID, like the replication ID and run ID, from
+/// `/dev/urandom` the way Redis does. The randomly keyed hashers of the standard
+/// library are used where it is not available.
+pub fn hex_id() -> String {
+    let mut random = [0; 20];
+    let is_read = std::fs::File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(&mut random))
+        .is_ok();
+    if !is_read {
+        for chunk in random.chunks_mut(8) {
+            let bytes = RandomState::new().build_hasher().finish().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    random.iter().fold(String::new(), |mut id, byte| {
+        let _ = write!(id, "{byte:02x}");
+        id
+    })
+}
+
+/// The most items a negative count picks. Redis streams such replies, while this server
+/// builds them in memory first, so larger counts are refused instead of exhausting it.
+pub const MAX_REPEATED_PICKS: u64 = 1 << 24;
+
+/// Picks items at random the way the `*RANDFIELD` and `*RANDMEMBER` commands do: a
+/// positive `count` picks distinct items, all of them at most, and a negative `count`
+/// picks exactly `-count` items that may repeat.
+pub fn sample<T>(items: &[T], count: i64) -> Vec<&T> {
+    if items.is_empty() {
+        return vec![];
+    }
+
+    if count < 0 {
+        return (0..count.unsigned_abs())
+            .map(|_| &items[index(items.len())])
+            .collect();
+    }
+
+    // a partial Fisher-Yates shuffle of the indices
+    let count = (count as u64).min(items.len() as u64) as usize;
+    let mut indices = (0..items.len()).collect::<Vec<_>>();
+    for position in 0..count {
+        let other = position + index(items.len() - position);
+        indices.swap(position, other);
+    }
+
+    indices[..count]
+        .iter()
+        .map(|&index| &items[index])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::sample;
+
+    #[test]
+    fn samples_distinct_or_repeated_items() {
+        let items = (0..10).collect::<Vec<_>>();
+        for count in [0, 1, 5, 10] {
+            let picked = sample(&items, count);
+            assert_eq!(picked.len(), count as usize);
+            assert_eq!(picked.iter().collect::<HashSet<_>>().len(), count as usize);
+        }
+
+        assert_eq!(sample(&items, 100).len(), 10);
+        assert_eq!(sample(&items, -100).len(), 100);
+        assert!(sample(&[1], -5).iter().all(|&&item| item == 1));
+        assert!(sample::<i32>(&[], -5).is_empty());
+    }
+}
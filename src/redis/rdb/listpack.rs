@@ -0,0 +1,222 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// A listpack, the compact list of strings and integers that RDB files use for streams.
+/// Entries are written as strings or integers but always read back as strings, with
+/// integers in their decimal form.
+#[derive(Debug, Default)]
+pub struct ListpackWriter {
+    entries: BytesMut,
+    len: usize,
+}
+
+impl ListpackWriter {
+    pub fn push_string(&mut self, value: &[u8]) {
+        let start = self.entries.len();
+        if value.len() < 1 << 6 {
+            self.entries.put_u8(0x80 | value.len() as u8);
+        } else if value.len() < 1 << 12 {
+            self.entries.put_u16(0xE000 | value.len() as u16);
+        } else {
+            self.entries.put_u8(0xF0);
+            self.entries.put_u32_le(value.len() as u32);
+        }
+
+        self.entries.put_slice(value);
+        self.finish_entry(start);
+    }
+
+    pub fn push_integer(&mut self, value: i64) {
+        let start = self.entries.len();
+        if (0..1 << 7).contains(&value) {
+            self.entries.put_u8(value as u8);
+        } else {
+            self.entries.put_u8(0xF4);
+            self.entries.put_i64_le(value);
+        }
+
+        self.finish_entry(start);
+    }
+
+    /// Writes the length of the entry starting at `start` after it, so that the
+    /// listpack can also be walked from the back.
+    fn finish_entry(&mut self, start: usize) {
+        // the low bits come last and every byte but the first flags that more follow
+        let length = self.entries.len() - start;
+        let size = backlen_size(length);
+        for index in (0..size).rev() {
+            let bits = (length >> (7 * index)) as u8 & 0x7F;
+            let more = if index + 1 < size { 0x80 } else { 0 };
+            self.entries.put_u8(bits | more);
+        }
+
+        self.len += 1;
+    }
+
+    pub fn finish(self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(self.entries.len() + 7);
+        buf.put_u32_le((self.entries.len() + 7) as u32);
+        buf.put_u16_le(self.len.min(u16::MAX as usize) as u16);
+        buf.put_slice(&self.entries);
+        buf.put_u8(0xFF);
+        buf.freeze()
+    }
+}
+
+/// Reads the entries of a listpack, failing on malformed input instead of panicking.
+pub fn parse(mut buf: Bytes) -> anyhow::Result<Vec<Bytes>> {
+    ensure_remaining(&buf, 6)?;
+    buf.advance(6);
+    let mut entries = Vec::new();
+    loop {
+        ensure_remaining(&buf, 1)?;
+        let encoding = buf[0];
+        if encoding == 0xFF {
+            return Ok(entries);
+        }
+
+        let (entry, length) = match encoding {
+            0x00..=0x7F => (Bytes::from((encoding as i64).to_string()), 1),
+            0x80..=0xBF => {
+                let length = (encoding & 0x3F) as usize;
+                ensure_remaining(&buf, 1 + length)?;
+                (buf.slice(1..1 + length), 1 + length)
+            }
+            0xC0..=0xDF => {
+                ensure_remaining(&buf, 2)?;
+                let value = ((encoding as i64 & 0x1F) << 8) | buf[1] as i64;
+                let value = if value >= 1 << 12 {
+                    value - (1 << 13)
+                } else {
+                    value
+                };
+                (Bytes::from(value.to_string()), 2)
+            }
+            0xE0..=0xEF => {
+                ensure_remaining(&buf, 2)?;
+                let length = ((encoding as usize & 0x0F) << 8) | buf[1] as usize;
+                ensure_remaining(&buf, 2 + length)?;
+                (buf.slice(2..2 + length), 2 + length)
+            }
+            0xF0 => {
+                ensure_remaining(&buf, 5)?;
+                let length = u32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+                ensure_remaining(&buf, 5 + length)?;
+                (buf.slice(5..5 + length), 5 + length)
+            }
+            0xF1..=0xF4 => {
+                let width = match encoding {
+                    0xF1 => 2,
+                    0xF2 => 3,
+                    0xF3 => 4,
+                    _ => 8,
+                };
+                ensure_remaining(&buf, 1 + width)?;
+                let mut bytes = [0; 8];
+                bytes[..width].copy_from_slice(&buf[1..1 + width]);
+                // sign-extend by shifting the value into the top bytes and back
+                let shift = 64 - 8 * width as u32;
+                let value = (i64::from_le_bytes(bytes) << shift) >> shift;
+                (Bytes::from(value.to_string()), 1 + width)
+            }
+            encoding => {
+                anyhow::bail!("[redis - error] unknown listpack entry encoding '{encoding}'")
+            }
+        };
+
+        let backlen = backlen_size(length);
+        ensure_remaining(&buf, length + backlen)?;
+        buf.advance(length + backlen);
+        entries.push(entry);
+    }
+}
+
+/// The number of bytes used to store the length of an entry, with the same
+/// thresholds as Redis so that entries line up when reading its listpacks.
+fn backlen_size(length: usize) -> usize {
+    match length {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+fn ensure_remaining(buf: &Bytes, length: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        buf.remaining() >= length,
+        "[redis - error] listpack ended unexpectedly, it may be truncated"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::ListpackWriter;
+
+    #[test]
+    fn reads_back_written_entries() {
+        let long = "x".repeat(5000);
+        let mut writer = ListpackWriter::default();
+        writer.push_integer(5);
+        writer.push_integer(-1);
+        writer.push_integer(1_700_000_000_000);
+        writer.push_string(b"");
+        writer.push_string(b"field");
+        writer.push_string("y".repeat(200).as_bytes());
+        writer.push_string(long.as_bytes());
+
+        let entries = super::parse(writer.finish()).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Bytes::from_static(b"5"),
+                Bytes::from_static(b"-1"),
+                Bytes::from_static(b"1700000000000"),
+                Bytes::new(),
+                Bytes::from_static(b"field"),
+                Bytes::from("y".repeat(200)),
+                Bytes::from(long),
+            ]
+        );
+    }
+
+    #[test]
+    fn fails_on_truncated_or_corrupt_headers() {
+        let mut writer = ListpackWriter::default();
+        writer.push_string(b"field");
+        writer.push_string("y".repeat(200).as_bytes());
+        writer.push_integer(-1);
+        let listpack = writer.finish();
+
+        for len in 0..listpack.len() {
+            assert!(super::parse(listpack.slice(..len)).is_err());
+        }
+
+        // entry headers claiming more bytes than are left
+        for header in [
+            &b"\xbf"[..],
+            b"\xef\xff",
+            b"\xf0\xff\xff\xff\xff",
+            b"\xf4\x01",
+            b"\xf5",
+        ] {
+            let mut corrupt = b"\x00\x00\x00\x00\x01\x00".to_vec();
+            corrupt.extend_from_slice(header);
+            corrupt.push(0xFF);
+            assert!(super::parse(Bytes::from(corrupt)).is_err());
+        }
+    }
+
+    #[test]
+    fn reads_compact_integers() {
+        // 13-bit -2, then int16 -300 and int24 70000, each followed by its backlen
+        let listpack =
+            b"\x00\x00\x00\x00\x03\x00\xdf\xfe\x02\xf1\xd4\xfe\x03\xf2\x70\x11\x01\x04\xff";
+        let entries = super::parse(Bytes::from_static(listpack)).unwrap();
+        assert_eq!(entries, vec!["-2", "-300", "70000"]);
+    }
+}
@@ -1,62 +1,177 @@
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     time::{Duration, SystemTime},
 };
 
-use bytes::{Buf, BytesMut};
+use std::collections::HashMap;
 
-use crate::redis::resp::command::RedisStoreCommand;
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::task::JoinHandle;
 
-use super::{resp::RESPValue, server::RedisWriteStream, store::RedisStore};
+use super::{
+    resp::{command::ListEnd, RESPValue},
+    store::{
+        list::List,
+        set::Set,
+        sorted_set::{Score, SortedSet},
+        stream::{Stream, StreamEntryId},
+        RedisStore, StoreValue,
+    },
+};
+
+mod listpack;
+mod writer;
+
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_SET: u8 = 2;
+const RDB_TYPE_HASH: u8 = 4;
+const RDB_TYPE_ZSET_2: u8 = 5;
+const RDB_TYPE_STREAM_LISTPACKS: u8 = 15;
+const RDB_TYPE_LIST_QUICKLIST_2: u8 = 18;
+
+const QUICKLIST_NODE_CONTAINER_PLAIN: usize = 1;
+const QUICKLIST_NODE_CONTAINER_PACKED: usize = 2;
+
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+pub use self::writer::serialized_length;
+
+/// A `save` rule: snapshot once at least `changes` writes happened within `seconds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavePoint {
+    pub seconds: u64,
+    pub changes: usize,
+}
 
 pub struct RDBConfig {
     pub dir: String,
     pub file_name: String,
+    pub save_points: Vec<SavePoint>,
 }
 
 impl RDBConfig {
-    pub fn new(dir: String, file_name: String) -> Self {
-        Self { dir, file_name }
+    pub fn new(dir: String, file_name: String, save_points: Vec<SavePoint>) -> Self {
+        Self {
+            dir,
+            file_name,
+            save_points,
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        Path::new(&self.dir).join(&self.file_name)
     }
 }
 
 pub struct RDBPesistence {
     pub config: RDBConfig,
+    /// When the dataset was last saved, which is the startup time until the first save.
+    pub last_save_time: SystemTime,
+    pub is_last_save_ok: bool,
 }
 
 impl RDBPesistence {
     pub fn new(config: RDBConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            last_save_time: SystemTime::now(),
+            is_last_save_ok: true,
+        }
+    }
+
+    /// Whether any save point is met by `changes` writes since the last save.
+    pub fn should_save(&self, changes: usize) -> bool {
+        let elapsed = self.last_save_time.elapsed().unwrap_or_default();
+        self.config.save_points.iter().any(|save_point| {
+            changes >= save_point.changes && elapsed.as_secs() >= save_point.seconds
+        })
+    }
+
+    /// Writes the store to the RDB file. The snapshot is written to a temporary file
+    /// first so that a failed save never leaves a partial dump behind.
+    pub async fn save(&mut self, store: &RedisStore) -> anyhow::Result<()> {
+        let snapshot = writer::encode_snapshot(store);
+        let result =
+            Self::write_snapshot(snapshot, self.config.path(), self.temp_path("temp")).await;
+        self.finish_save(&result);
+        result
     }
 
-    pub async fn setup(&mut self) -> anyhow::Result<RedisStore> {
-        let mut store = RedisStore::new();
-        let path = Path::new(&self.config.dir).join(&self.config.file_name);
-        if !path.try_exists()? {
-            return Ok(store);
+    /// Takes a snapshot of the store and writes it on its own task, so that clients are
+    /// served while the file is written. Its result is to be passed to
+    /// [`Self::finish_save`].
+    pub fn save_in_background(&self, store: &RedisStore) -> JoinHandle<anyhow::Result<()>> {
+        let snapshot = writer::encode_snapshot(store);
+        let path = self.config.path();
+        // a save in the foreground must not write to the same temporary file meanwhile
+        let temp_path = self.temp_path("temp-bg");
+        tokio::spawn(Self::write_snapshot(snapshot, path, temp_path))
+    }
+
+    /// Records how a save ended. A failed save keeps the last save time.
+    pub fn finish_save(&mut self, result: &anyhow::Result<()>) {
+        self.is_last_save_ok = result.is_ok();
+        if result.is_ok() {
+            self.last_save_time = SystemTime::now();
         }
+    }
+
+    async fn write_snapshot(
+        snapshot: BytesMut,
+        path: PathBuf,
+        temp_path: PathBuf,
+    ) -> anyhow::Result<()> {
+        tokio::fs::write(&temp_path, &snapshot).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+        Ok(())
+    }
+
+    fn temp_path(&self, prefix: &str) -> PathBuf {
+        Path::new(&self.config.dir).join(format!("{prefix}-{}.rdb", std::process::id()))
+    }
 
-        let mut buf = BytesMut::new();
-        let rdb_file = std::fs::read(path)?;
-        buf.extend_from_slice(&rdb_file);
-        let _ = self.parse_magic_header(&mut buf)?;
+    /// Starts loading the RDB file on a blocking thread, so that the server can accept
+    /// connections while a large dump is read and parsed.
+    pub fn setup(&self, database_count: usize) -> JoinHandle<anyhow::Result<RedisStore>> {
+        let path = self.config.path();
+        tokio::task::spawn_blocking(move || {
+            let mut store = RedisStore::new(database_count);
+            if !std::fs::exists(&path)? {
+                return Ok(store);
+            }
+
+            let rdb_file = std::fs::read(&path)?;
+            Self::load(BytesMut::from(&rdb_file[..]), &mut store)?;
+            Ok(store)
+        })
+    }
+
+    /// Loads the entries of an RDB file into the store. A file that ends early is an
+    /// error rather than a panic.
+    fn load(mut buf: BytesMut, store: &mut RedisStore) -> anyhow::Result<()> {
+        let _ = Self::parse_magic_header(&mut buf)?;
+        let mut db = 0;
         loop {
+            ensure_remaining(&buf, 1)?;
             let op_code = buf.get_u8();
             match op_code {
-                0xFA => self.parse_aux_fields(&mut buf),
-                0xFB => self.parse_resize_db(&mut buf),
-                0xFC => self.parse_expiry_milliseconds(&mut store, &mut buf).await?,
-                0xFD => self.parse_expiry_seconds(&mut store, &mut buf).await?,
-                0xFE => self.parse_database_selector(&mut buf)?,
+                0xFA => Self::parse_aux_fields(&mut buf)?,
+                0xFB => Self::parse_resize_db(&mut buf)?,
+                0xFC => Self::parse_expiry_milliseconds(store, db, &mut buf)?,
+                0xFD => Self::parse_expiry_seconds(store, db, &mut buf)?,
+                0xFE => db = Self::parse_database_selector(store, &mut buf)?,
                 0xFF => break,
-                value_encoding => self.parse_value(value_encoding, None, &mut store, &mut buf).await?,
+                value_type => Self::parse_value(value_type, None, store, db, &mut buf)?,
             }
         }
 
-        Ok(store)
+        Ok(())
     }
 
-    fn parse_magic_header(&mut self, buf: &mut BytesMut) -> anyhow::Result<usize> {
+    fn parse_magic_header(buf: &mut BytesMut) -> anyhow::Result<usize> {
+        ensure_remaining(buf, 9)?;
         anyhow::ensure!(
             &buf[..5] == b"REDIS",
             "[redis - error] expected magic string 'REDIS' at beginning of RDB file"
@@ -68,114 +183,629 @@ impl RDBPesistence {
         Ok(version)
     }
 
-    fn parse_aux_fields(&mut self, buf: &mut BytesMut) {
-        let _ = self.parse_string(buf);
-        let _ = self.parse_string(buf);
+    fn parse_aux_fields(buf: &mut BytesMut) -> anyhow::Result<()> {
+        let _ = Self::parse_string(buf)?;
+        let _ = Self::parse_string(buf)?;
+        Ok(())
     }
 
-    fn parse_resize_db(&mut self, buf: &mut BytesMut) {
-        let _ = self.parse_length(buf);
-        let _ = self.parse_length(buf);
+    fn parse_resize_db(buf: &mut BytesMut) -> anyhow::Result<()> {
+        let _ = Self::parse_length(buf)?;
+        let _ = Self::parse_length(buf)?;
+        Ok(())
     }
 
-    async fn parse_expiry_milliseconds(
-        &mut self,
+    fn parse_expiry_milliseconds(
         store: &mut RedisStore,
+        db: usize,
         buf: &mut BytesMut,
     ) -> anyhow::Result<()> {
+        ensure_remaining(buf, 9)?;
         let expiry_timestamp = buf.get_u64_le();
         let expiry_timestamp = SystemTime::UNIX_EPOCH + Duration::from_millis(expiry_timestamp);
-        let _ = self.parse_value(buf.get_u8(), Some(expiry_timestamp), store, buf).await?;
+        Self::parse_value(buf.get_u8(), Some(expiry_timestamp), store, db, buf)?;
         Ok(())
     }
 
-    async fn parse_expiry_seconds(
-        &mut self,
+    fn parse_expiry_seconds(
         store: &mut RedisStore,
+        db: usize,
         buf: &mut BytesMut,
     ) -> anyhow::Result<()> {
+        ensure_remaining(buf, 5)?;
         let expiry_timestamp = buf.get_u32_le() as u64;
         let expiry_timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(expiry_timestamp);
-        let _ = self.parse_value(buf.get_u8(), Some(expiry_timestamp), store, buf).await?;
+        Self::parse_value(buf.get_u8(), Some(expiry_timestamp), store, db, buf)?;
         Ok(())
     }
 
-    fn parse_database_selector(&mut self, buf: &mut BytesMut) -> anyhow::Result<()> {
-        let (_, is_encoded) = self.parse_length(buf);
+    fn parse_database_selector(store: &RedisStore, buf: &mut BytesMut) -> anyhow::Result<usize> {
+        let (db, is_encoded) = Self::parse_length(buf)?;
         anyhow::ensure!(
             !is_encoded,
             "[redis - error] expected database selector to not be an specially-encoded string"
         );
 
-        Ok(())
+        anyhow::ensure!(
+            db < store.database_count(),
+            "[redis - error] RDB file references database {db} but only {} databases are configured",
+            store.database_count()
+        );
+
+        Ok(db)
     }
 
-    async fn parse_value(
-        &mut self,
-        value_encoding: u8,
+    /// Reads a value of the given type. Only strings can expire, so the expiry of any
+    /// other type is dropped.
+    fn parse_value(
+        value_type: u8,
         px: Option<SystemTime>,
         store: &mut RedisStore,
+        db: usize,
         buf: &mut BytesMut,
     ) -> anyhow::Result<()> {
-        let key = self
-            .parse_string(buf)
-            .into_bulk_string()
-            .ok_or_else(|| anyhow::anyhow!("[redis - error] RDB key must be a bulk string"))?;
-
-        let value = match value_encoding {
-            0 => self.parse_string(buf),
-            encoding => todo!("[redis - todo] implement encoding for value type '{encoding}'"),
-        };
+        let key = Self::parse_bytes(buf)?;
+        let value = match value_type {
+            RDB_TYPE_STRING => StoreValue::String {
+                value: Self::parse_bytes(buf)?,
+                expiration: px,
+            },
+            RDB_TYPE_LIST => {
+                let (length, _) = Self::parse_length(buf)?;
+                let mut list = List::default();
+                for _ in 0..length {
+                    list.push(
+                        ListEnd::Right,
+                        Self::parse_bytes(buf)?,
+                        store.encoding_config.list_max_listpack_size,
+                    );
+                }
 
-        let value = value.into_bulk_string().ok_or_else(|| {
-            anyhow::anyhow!("[redis - error] only bulk strings are supported for RDB values")
-        })?;
+                StoreValue::List(list)
+            }
+            RDB_TYPE_LIST_QUICKLIST_2 => StoreValue::List(Self::parse_quicklist(
+                buf,
+                store.encoding_config.list_max_listpack_size,
+            )?),
+            RDB_TYPE_SET => {
+                let (length, _) = Self::parse_length(buf)?;
+                let mut set = Set::default();
+                for _ in 0..length {
+                    set.insert(Self::parse_bytes(buf)?, &store.encoding_config);
+                }
 
-        store.handle(
-            &RedisStoreCommand::Set { key, value, px },
-            RedisWriteStream::sink(),
-        ).await?;
+                StoreValue::Set(set)
+            }
+            RDB_TYPE_HASH => {
+                let (length, _) = Self::parse_length(buf)?;
+                let mut fields = HashMap::new();
+                for _ in 0..length {
+                    fields.insert(Self::parse_bytes(buf)?, Self::parse_bytes(buf)?);
+                }
+
+                StoreValue::Hash(fields)
+            }
+            RDB_TYPE_ZSET_2 => {
+                let (length, _) = Self::parse_length(buf)?;
+                let mut sorted_set = SortedSet::default();
+                for _ in 0..length {
+                    let member = Self::parse_bytes(buf)?;
+                    ensure_remaining(buf, 8)?;
+                    let score = Score::new(buf.get_f64_le()).ok_or_else(|| {
+                        anyhow::anyhow!("[redis - error] sorted set score in RDB file is NaN")
+                    })?;
+                    sorted_set.insert(member, score);
+                }
 
+                StoreValue::SortedSet(sorted_set)
+            }
+            RDB_TYPE_STREAM_LISTPACKS => StoreValue::Stream(Self::parse_stream(buf)?),
+            value_type => {
+                anyhow::bail!("[redis - error] unsupported value type '{value_type}' in RDB file")
+            }
+        };
+
+        store.insert(db, key, value);
         Ok(())
     }
 
-    fn parse_string(&mut self, buf: &mut BytesMut) -> RESPValue {
-        let (length, is_encoded) = self.parse_length(buf);
-        if is_encoded {
-            match length {
-                0 => RESPValue::Integer(buf.get_u8() as i64),
-                1 => RESPValue::Integer(buf.get_u16() as i64),
-                2 => RESPValue::Integer(buf.get_u32() as i64),
-                3 => todo!("[redis - todo] implement LZF compressed string"),
-                _ => unreachable!(),
+    /// Reads a list stored as quicklist nodes, each either a listpack of elements or a
+    /// single element too large for one. Compressed nodes are not supported.
+    fn parse_quicklist(buf: &mut BytesMut, list_max_listpack_size: i64) -> anyhow::Result<List> {
+        let mut list = List::default();
+        let (node_count, _) = Self::parse_length(buf)?;
+        for _ in 0..node_count {
+            let (container, _) = Self::parse_length(buf)?;
+            let node = Self::parse_bytes(buf)?;
+            let elements = match container {
+                QUICKLIST_NODE_CONTAINER_PLAIN => vec![node],
+                QUICKLIST_NODE_CONTAINER_PACKED => listpack::parse(node)?,
+                container => anyhow::bail!(
+                    "[redis - error] unsupported quicklist node container '{container}' in RDB file"
+                ),
+            };
+
+            for element in elements {
+                list.push(ListEnd::Right, element, list_max_listpack_size);
             }
+        }
+
+        Ok(list)
+    }
+
+    /// Reads a stream stored as listpacks of entries, each relative to the master entry
+    /// that starts its listpack. Consumer groups are not supported.
+    fn parse_stream(buf: &mut BytesMut) -> anyhow::Result<Stream> {
+        let mut stream = Stream::default();
+        let (listpack_count, _) = Self::parse_length(buf)?;
+        for _ in 0..listpack_count {
+            let node_key = Self::parse_bytes(buf)?;
+            anyhow::ensure!(
+                node_key.len() == 16,
+                "[redis - error] stream node key in RDB file must be 16 bytes"
+            );
+            let mut node_key = &node_key[..];
+            let master_id = StreamEntryId::new(node_key.get_u64(), node_key.get_u64());
+
+            let mut entries = listpack::parse(Self::parse_bytes(buf)?)?.into_iter();
+            let count = next_number(&mut entries)?
+                .checked_add(next_number(&mut entries)?)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("[redis - error] stream entry count in RDB file overflows")
+                })?;
+            let master_fields = (0..next_number(&mut entries)?)
+                .map(|_| next_entry(&mut entries))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            // the master entry ends with a zero
+            next_entry(&mut entries)?;
+
+            for _ in 0..count {
+                let flags = next_number(&mut entries)?;
+                let id = StreamEntryId::new(
+                    master_id.ms.wrapping_add(next_number(&mut entries)? as u64),
+                    master_id
+                        .seq
+                        .wrapping_add(next_number(&mut entries)? as u64),
+                );
+
+                let fields = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+                    master_fields
+                        .iter()
+                        .map(|field| Ok((field.clone(), next_entry(&mut entries)?)))
+                        .collect::<anyhow::Result<Vec<_>>>()?
+                } else {
+                    (0..next_number(&mut entries)?)
+                        .map(|_| Ok((next_entry(&mut entries)?, next_entry(&mut entries)?)))
+                        .collect::<anyhow::Result<Vec<_>>>()?
+                };
+
+                // the number of listpack entries in this entry, for walking backwards
+                next_entry(&mut entries)?;
+                if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+                    stream.add(id, fields);
+                }
+            }
+        }
+
+        let _length = Self::parse_length(buf)?;
+        let (last_ms, _) = Self::parse_length(buf)?;
+        let (last_seq, _) = Self::parse_length(buf)?;
+        stream.set_last_id(StreamEntryId::new(last_ms as u64, last_seq as u64));
+        let (group_count, _) = Self::parse_length(buf)?;
+        anyhow::ensure!(
+            group_count == 0,
+            "[redis - error] stream consumer groups in RDB files are not supported"
+        );
+
+        Ok(stream)
+    }
+
+    /// Reads a string, turning integer-encoded strings back into their decimal form.
+    fn parse_bytes(buf: &mut BytesMut) -> anyhow::Result<Bytes> {
+        match Self::parse_string(buf)? {
+            RESPValue::Integer(value) => Ok(Bytes::from(value.to_string())),
+            value => value
+                .into_bulk_string()
+                .ok_or_else(|| anyhow::anyhow!("[redis - error] expected a string in RDB file")),
+        }
+    }
+
+    fn parse_string(buf: &mut BytesMut) -> anyhow::Result<RESPValue> {
+        let (length, is_encoded) = Self::parse_length(buf)?;
+        if is_encoded {
+            let value = match length {
+                0 => {
+                    ensure_remaining(buf, 1)?;
+                    RESPValue::Integer(buf.get_u8() as i64)
+                }
+                1 => {
+                    ensure_remaining(buf, 2)?;
+                    RESPValue::Integer(buf.get_u16() as i64)
+                }
+                2 => {
+                    ensure_remaining(buf, 4)?;
+                    RESPValue::Integer(buf.get_u32() as i64)
+                }
+                3 => anyhow::bail!(
+                    "[redis - error] LZF compressed strings in RDB files are not supported"
+                ),
+                encoding => anyhow::bail!(
+                    "[redis - error] unknown string encoding '{encoding}' in RDB file"
+                ),
+            };
+
+            Ok(value)
         } else {
-            RESPValue::BulkString(buf.copy_to_bytes(length))
+            ensure_remaining(buf, length)?;
+            Ok(RESPValue::BulkString(buf.copy_to_bytes(length)))
         }
     }
 
-    fn parse_length(&mut self, buf: &mut BytesMut) -> (usize, bool) {
+    fn parse_length(buf: &mut BytesMut) -> anyhow::Result<(usize, bool)> {
+        ensure_remaining(buf, 1)?;
         let length_encoding = (buf[0] & 0b11000000) >> 6;
-        match length_encoding {
+        let length = match length_encoding {
             0b00 => {
                 let length = buf.get_u8() & 0b00111111;
                 (length as usize, false)
             }
             0b01 => {
+                ensure_remaining(buf, 2)?;
                 let length = (buf.get_u8() & 0b00111111) as usize;
                 let length = length << 8;
                 let length = length | (buf.get_u8() as usize);
-                (length as usize, false)
-            }
-            0b10 => {
-                buf.advance(1);
-                (buf.get_u32() as usize, false)
+                (length, false)
             }
+            0b10 => match buf[0] {
+                0x80 => {
+                    ensure_remaining(buf, 5)?;
+                    buf.advance(1);
+                    (buf.get_u32() as usize, false)
+                }
+                0x81 => {
+                    ensure_remaining(buf, 9)?;
+                    buf.advance(1);
+                    (buf.get_u64() as usize, false)
+                }
+                encoding => {
+                    anyhow::bail!(
+                        "[redis - error] unknown length encoding '{encoding}' in RDB file"
+                    )
+                }
+            },
             0b11 => {
                 let length = buf.get_u8() & 0b00111111;
                 (length as usize, true)
             }
             _ => unreachable!(),
+        };
+
+        Ok(length)
+    }
+}
+
+fn next_entry(entries: &mut impl Iterator<Item = Bytes>) -> anyhow::Result<Bytes> {
+    entries
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("[redis - error] stream listpack in RDB file ended early"))
+}
+
+fn next_number(entries: &mut impl Iterator<Item = Bytes>) -> anyhow::Result<i64> {
+    let entry = next_entry(entries)?;
+    std::str::from_utf8(&entry)
+        .ok()
+        .and_then(|entry| entry.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("[redis - error] expected a number in stream listpack"))
+}
+
+/// Fails when fewer than `length` bytes are left, since reading past the end of the
+/// buffer panics.
+fn ensure_remaining(buf: &BytesMut, length: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        buf.remaining() >= length,
+        "[redis - error] RDB file ended unexpectedly, it may be truncated"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use bytes::{BufMut, Bytes, BytesMut};
+    use tokio::sync::mpsc;
+
+    use crate::redis::{
+        resp::command::{ListEnd, RedisStoreCommand},
+        server::RedisWriteStream,
+        store::{
+            list::List,
+            set::Set,
+            sorted_set::{Score, SortedSet},
+            stream::{Stream, StreamEntryId},
+            EncodingConfig, RedisStore, StoreValue,
+        },
+    };
+
+    use super::{
+        listpack::ListpackWriter, RDBConfig, RDBPesistence, SavePoint,
+        QUICKLIST_NODE_CONTAINER_PACKED, QUICKLIST_NODE_CONTAINER_PLAIN, RDB_TYPE_LIST_QUICKLIST_2,
+    };
+
+    #[tokio::test]
+    async fn loads_saved_snapshots() {
+        let dir = std::env::temp_dir().join(format!("rdb-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = RDBConfig::new(dir.display().to_string(), "dump.rdb".to_string(), vec![]);
+        let mut persistence = RDBPesistence::new(config);
+
+        let mut store = RedisStore::new(2);
+        let expiration = SystemTime::now() + Duration::from_secs(3600);
+        for (db, key, value, px) in [
+            (0, "short", "value", None),
+            (1, "long", &*"x".repeat(20000), Some(expiration)),
+        ] {
+            let set = RedisStoreCommand::Set {
+                key: Bytes::from(key.to_string()),
+                value: Bytes::from(value.to_string()),
+                px,
+            };
+            store
+                .handle(db, &set, RedisWriteStream::sink())
+                .await
+                .unwrap();
+        }
+
+        persistence.save(&store).await.unwrap();
+        let mut loaded = persistence.setup(2).await.unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for (db, key) in [(0, "short"), (1, "long")] {
+            let get = RedisStoreCommand::Get {
+                key: Bytes::from_static(key.as_bytes()),
+            };
+            loaded
+                .handle(db, &get, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(rx.recv().await.unwrap(), &b"$5\r\nvalue\r\n"[..]);
+        assert!(rx.recv().await.unwrap().starts_with(b"$20000\r\nxxx"));
+    }
+
+    #[tokio::test]
+    async fn fails_to_load_truncated_files() {
+        let mut store = RedisStore::new(1);
+        let set = RedisStoreCommand::Set {
+            key: Bytes::from_static(b"key"),
+            value: Bytes::from_static(b"value"),
+            px: None,
+        };
+        store
+            .handle(0, &set, RedisWriteStream::sink())
+            .await
+            .unwrap();
+
+        let snapshot = super::writer::encode_snapshot(&store);
+        let entry_start = snapshot
+            .windows(4)
+            .position(|window| window == b"\x03key")
+            .unwrap();
+        for truncated in [
+            &[][..],
+            &b"REDIS0011"[..],
+            &snapshot[..entry_start + 2],
+            &snapshot[..entry_start + 5],
+        ] {
+            let mut loaded = RedisStore::new(1);
+            let result = RDBPesistence::load(BytesMut::from(truncated), &mut loaded);
+            assert!(result.is_err());
+        }
+
+        let mut loaded = RedisStore::new(1);
+        RDBPesistence::load(snapshot, &mut loaded).unwrap();
+        assert!(loaded.get(0, b"key").is_some());
+    }
+
+    #[test]
+    fn fails_to_load_corrupt_aggregates_without_panicking() {
+        let mut store = RedisStore::new(1);
+        let mut stream = Stream::default();
+        stream.add(
+            StreamEntryId::new(1, 0),
+            vec![(Bytes::from_static(b"field"), Bytes::from_static(b"value"))],
+        );
+        store.insert(0, Bytes::from_static(b"stream"), StoreValue::Stream(stream));
+        let snapshot = super::writer::encode_snapshot(&store);
+
+        // every truncation before the end of file marker and checksum fails, and every
+        // corrupted byte either fails or loads something, including the lengths inside
+        // the listpack
+        for len in 0..snapshot.len() - 8 {
+            let mut loaded = RedisStore::new(1);
+            let result = RDBPesistence::load(BytesMut::from(&snapshot[..len]), &mut loaded);
+            assert!(
+                result.is_err(),
+                "loaded a snapshot truncated to {len} bytes"
+            );
         }
+
+        for index in 9..snapshot.len() {
+            for byte in [0x00, 0x7F, 0x80, 0xBF, 0xC0, 0xE0, 0xF0, 0xF4, 0xFF] {
+                let mut corrupted = snapshot.clone();
+                corrupted[index] = byte;
+                let _ = RDBPesistence::load(corrupted, &mut RedisStore::new(1));
+            }
+        }
+
+        // a string compressed with LZF
+        let mut compressed = BytesMut::from(&b"REDIS0011\x00\x03key\xc3\x01\x01x\xff"[..]);
+        assert!(RDBPesistence::load(compressed.split(), &mut RedisStore::new(1)).is_err());
+    }
+
+    #[test]
+    fn loads_lists_saved_as_quicklists() {
+        let mut listpack = ListpackWriter::default();
+        listpack.push_string(b"a");
+        listpack.push_integer(12);
+        let listpack = listpack.finish();
+
+        let mut snapshot = BytesMut::from(&b"REDIS0011"[..]);
+        snapshot.put_u8(RDB_TYPE_LIST_QUICKLIST_2);
+        snapshot.put_slice(b"\x04list\x02");
+        snapshot.put_u8(QUICKLIST_NODE_CONTAINER_PACKED as u8);
+        snapshot.put_u8(listpack.len() as u8);
+        snapshot.put_slice(&listpack);
+        snapshot.put_u8(QUICKLIST_NODE_CONTAINER_PLAIN as u8);
+        snapshot.put_slice(b"\x05plain\xff");
+        snapshot.put_u64_le(0);
+
+        let mut loaded = RedisStore::new(1);
+        RDBPesistence::load(snapshot, &mut loaded).unwrap();
+        let Some(StoreValue::List(list)) = loaded.get(0, b"list") else {
+            panic!("the list was not loaded");
+        };
+        assert!(list.iter().eq(["a", "12", "plain"]));
+    }
+
+    #[test]
+    fn round_trips_every_value_type() {
+        let bytes = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| Bytes::from(value.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let expiration = SystemTime::UNIX_EPOCH
+            + Duration::from_millis(
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis() as u64
+                    + 3_600_000,
+            );
+        let members = Set::from_members(
+            bytes(&["a", "12", &"m".repeat(100)]),
+            &EncodingConfig::default(),
+        );
+        let fields = bytes(&["name", "redis", "", "empty"])
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        let mut sorted_set = SortedSet::default();
+        for (member, score) in [("low", -1.5), ("high", f64::INFINITY), ("zero", 0.0)] {
+            sorted_set.insert(Bytes::from(member), Score::new(score).unwrap());
+        }
+        let mut stream = Stream::default();
+        stream.add(
+            StreamEntryId::new(1, 5),
+            bytes(&["temperature", "20"])
+                .chunks(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect(),
+        );
+        stream.add(StreamEntryId::new(1_700_000_000_000, 0), vec![]);
+        stream.set_last_id(StreamEntryId::new(1_700_000_000_000, 3));
+        let mut list = List::default();
+        for element in bytes(&["first", "2", "", &"l".repeat(100)]) {
+            list.push(ListEnd::Right, element, -2);
+        }
+
+        let mut store = RedisStore::new(2);
+        store.insert(
+            0,
+            Bytes::from_static(b"string"),
+            StoreValue::String {
+                value: Bytes::from_static(b"\x00binary\xff"),
+                expiration: Some(expiration),
+            },
+        );
+        store.insert(0, Bytes::from_static(b"set"), StoreValue::Set(members));
+        store.insert(1, Bytes::from_static(b"hash"), StoreValue::Hash(fields));
+        store.insert(
+            1,
+            Bytes::from_static(b"zset"),
+            StoreValue::SortedSet(sorted_set),
+        );
+        store.insert(1, Bytes::from_static(b"stream"), StoreValue::Stream(stream));
+        store.insert(1, Bytes::from_static(b"list"), StoreValue::List(list));
+
+        let mut loaded = RedisStore::new(2);
+        RDBPesistence::load(super::writer::encode_snapshot(&store), &mut loaded).unwrap();
+        for (db, key) in [
+            (0, "string"),
+            (0, "set"),
+            (1, "hash"),
+            (1, "zset"),
+            (1, "stream"),
+            (1, "list"),
+        ] {
+            let original = store.get(db, key.as_bytes()).unwrap();
+            let loaded = loaded.get(db, key.as_bytes()).unwrap();
+            match (original, loaded) {
+                (
+                    StoreValue::String { value, expiration },
+                    StoreValue::String {
+                        value: loaded_value,
+                        expiration: loaded_expiration,
+                    },
+                ) => {
+                    assert_eq!(value, loaded_value);
+                    assert_eq!(expiration, loaded_expiration);
+                }
+                (StoreValue::Set(members), StoreValue::Set(loaded)) => assert_eq!(members, loaded),
+                (StoreValue::Hash(fields), StoreValue::Hash(loaded)) => assert_eq!(fields, loaded),
+                (StoreValue::SortedSet(sorted_set), StoreValue::SortedSet(loaded)) => {
+                    assert!(sorted_set.iter().eq(loaded.iter()));
+                }
+                (StoreValue::Stream(stream), StoreValue::Stream(loaded)) => {
+                    assert!(stream.entries().eq(loaded.entries()));
+                    assert_eq!(stream.last_id(), loaded.last_id());
+                }
+                (StoreValue::List(list), StoreValue::List(loaded)) => assert_eq!(list, loaded),
+                _ => panic!("'{key}' was loaded as a different type"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_last_save_time_when_a_save_fails() {
+        let config = RDBConfig::new(
+            "/nonexistent/rdb-dir".to_string(),
+            "dump.rdb".to_string(),
+            vec![],
+        );
+        let mut persistence = RDBPesistence::new(config);
+        let last_save_time = persistence.last_save_time;
+
+        assert!(persistence.save(&RedisStore::new(1)).await.is_err());
+        assert!(!persistence.is_last_save_ok);
+        assert_eq!(persistence.last_save_time, last_save_time);
+    }
+
+    #[test]
+    fn saves_once_a_save_point_is_met() {
+        let config = RDBConfig::new(
+            "./".to_string(),
+            "dump.rdb".to_string(),
+            vec![
+                SavePoint {
+                    seconds: 0,
+                    changes: 100,
+                },
+                SavePoint {
+                    seconds: 3600,
+                    changes: 1,
+                },
+            ],
+        );
+        let persistence = RDBPesistence::new(config);
+        assert!(!persistence.should_save(0));
+        assert!(!persistence.should_save(99));
+        assert!(persistence.should_save(100));
     }
 }
@@ -0,0 +1,198 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::{BufMut, BytesMut};
+
+use crate::redis::{
+    store::{stream::Stream, RedisStore, StoreValue},
+    REDIS_VERSION,
+};
+
+use super::{
+    listpack::ListpackWriter, RDB_TYPE_HASH, RDB_TYPE_LIST, RDB_TYPE_SET,
+    RDB_TYPE_STREAM_LISTPACKS, RDB_TYPE_STRING, RDB_TYPE_ZSET_2,
+};
+
+/// Encodes the store as an RDB file that `RDBPesistence::setup` can load back.
+pub fn encode_snapshot(store: &RedisStore) -> BytesMut {
+    let mut buf = BytesMut::new();
+    buf.put_slice(b"REDIS0011");
+    write_aux_field(&mut buf, b"redis-ver", REDIS_VERSION.as_bytes());
+    write_aux_field(&mut buf, b"redis-bits", b"64");
+    let ctime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    write_aux_field(&mut buf, b"ctime", ctime.to_string().as_bytes());
+
+    for (db, items) in store.databases().enumerate() {
+        let items = items
+            .filter(|(_, value)| !value_is_expired(value))
+            .collect::<Vec<_>>();
+
+        if items.is_empty() {
+            continue;
+        }
+
+        buf.put_u8(0xFE);
+        write_length(&mut buf, db);
+        buf.put_u8(0xFB);
+        write_length(&mut buf, items.len());
+        write_length(
+            &mut buf,
+            items
+                .iter()
+                .filter(|(_, value)| value_expiration(value).is_some())
+                .count(),
+        );
+
+        for (key, value) in items {
+            if let Some(expiration) = value_expiration(value) {
+                let expiration = expiration
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+                buf.put_u8(0xFC);
+                buf.put_u64_le(expiration);
+            }
+
+            buf.put_u8(value_type(value));
+            write_string(&mut buf, key);
+            write_value(&mut buf, value);
+        }
+    }
+
+    buf.put_u8(0xFF);
+    // a zero checksum tells readers that checksums are disabled
+    buf.put_u64_le(0);
+    buf
+}
+
+/// The number of bytes a value takes up in an RDB file.
+pub fn serialized_length(value: &StoreValue) -> usize {
+    let mut buf = BytesMut::new();
+    write_value(&mut buf, value);
+    buf.len()
+}
+
+fn value_type(value: &StoreValue) -> u8 {
+    match value {
+        StoreValue::String { .. } => RDB_TYPE_STRING,
+        StoreValue::Set(_) => RDB_TYPE_SET,
+        StoreValue::Hash(_) => RDB_TYPE_HASH,
+        StoreValue::SortedSet(_) => RDB_TYPE_ZSET_2,
+        StoreValue::Stream(_) => RDB_TYPE_STREAM_LISTPACKS,
+        StoreValue::List(_) => RDB_TYPE_LIST,
+    }
+}
+
+fn write_value(buf: &mut BytesMut, value: &StoreValue) {
+    match value {
+        StoreValue::String { value, .. } => write_string(buf, value),
+        StoreValue::Set(members) => {
+            write_length(buf, members.len());
+            for member in members.iter() {
+                write_string(buf, member);
+            }
+        }
+        StoreValue::Hash(fields) => {
+            write_length(buf, fields.len());
+            for (field, value) in fields {
+                write_string(buf, field);
+                write_string(buf, value);
+            }
+        }
+        StoreValue::SortedSet(sorted_set) => {
+            write_length(buf, sorted_set.len());
+            for (member, score) in sorted_set.iter() {
+                write_string(buf, member);
+                buf.put_f64_le(score.value());
+            }
+        }
+        StoreValue::Stream(stream) => write_stream(buf, stream),
+        StoreValue::List(list) => {
+            write_length(buf, list.len());
+            for element in list.iter() {
+                write_string(buf, element);
+            }
+        }
+    }
+}
+
+/// Writes a stream as a single listpack of entries. Each entry lists its own fields
+/// and its ID relative to the first entry, the master entry of the listpack.
+fn write_stream(buf: &mut BytesMut, stream: &Stream) {
+    match stream.first_entry() {
+        Some((&master_id, _)) => {
+            write_length(buf, 1);
+            let mut node_key = BytesMut::new();
+            node_key.put_u64(master_id.ms);
+            node_key.put_u64(master_id.seq);
+            write_string(buf, &node_key);
+
+            let mut listpack = ListpackWriter::default();
+            listpack.push_integer(stream.len() as i64);
+            // no deleted entries and no master fields
+            listpack.push_integer(0);
+            listpack.push_integer(0);
+            listpack.push_integer(0);
+            for (id, fields) in stream.entries() {
+                listpack.push_integer(0);
+                listpack.push_integer(id.ms.wrapping_sub(master_id.ms) as i64);
+                listpack.push_integer(id.seq.wrapping_sub(master_id.seq) as i64);
+                listpack.push_integer(fields.len() as i64);
+                for (field, value) in fields {
+                    listpack.push_string(field);
+                    listpack.push_string(value);
+                }
+
+                listpack.push_integer(fields.len() as i64 * 2 + 4);
+            }
+
+            write_string(buf, &listpack.finish());
+        }
+        None => write_length(buf, 0),
+    }
+
+    let last_id = stream.last_id();
+    write_length(buf, stream.len());
+    write_length(buf, last_id.ms as usize);
+    write_length(buf, last_id.seq as usize);
+    // consumer groups are not saved yet, so they do not survive a restart or a full resync
+    write_length(buf, 0);
+}
+
+fn value_expiration(value: &StoreValue) -> Option<SystemTime> {
+    match value {
+        StoreValue::String { expiration, .. } => *expiration,
+        _ => None,
+    }
+}
+
+fn value_is_expired(value: &StoreValue) -> bool {
+    value_expiration(value).is_some_and(|expiration| expiration <= SystemTime::now())
+}
+
+fn write_aux_field(buf: &mut BytesMut, key: &[u8], value: &[u8]) {
+    buf.put_u8(0xFA);
+    write_string(buf, key);
+    write_string(buf, value);
+}
+
+fn write_string(buf: &mut BytesMut, value: &[u8]) {
+    write_length(buf, value.len());
+    buf.put_slice(value);
+}
+
+fn write_length(buf: &mut BytesMut, length: usize) {
+    if length < 1 << 6 {
+        buf.put_u8(length as u8);
+    } else if length < 1 << 14 {
+        buf.put_u16(0b01 << 14 | length as u16);
+    } else if length <= u32::MAX as usize {
+        buf.put_u8(0x80);
+        buf.put_u32(length as u32);
+    } else {
+        buf.put_u8(0x81);
+        buf.put_u64(length as u64);
+    }
+}
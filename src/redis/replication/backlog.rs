@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+/// How many of the most recently replicated bytes are kept, the default
+/// `repl-backlog-size` of Redis.
+pub const BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// The most recently replicated bytes of a primary, kept so that a replica that lost its
+/// connection can continue from its offset instead of doing a full resync.
+#[derive(Debug)]
+pub struct Backlog {
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    /// The replication offset just after the last byte in the buffer.
+    end_offset: usize,
+}
+
+impl Backlog {
+    pub fn new(capacity: usize, offset: usize) -> Self {
+        Self {
+            buffer: VecDeque::new(),
+            capacity,
+            end_offset: offset,
+        }
+    }
+
+    /// Appends replicated bytes, dropping the oldest ones once the backlog is full.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.end_offset += bytes.len();
+        let bytes = &bytes[bytes.len().saturating_sub(self.capacity)..];
+        let overflow = (self.buffer.len() + bytes.len()).saturating_sub(self.capacity);
+        self.buffer.drain(..overflow);
+        self.buffer.extend(bytes);
+    }
+
+    /// The bytes replicated after `offset`, or `None` when they are no longer in the
+    /// backlog or the offset was never reached.
+    pub fn since(&self, offset: usize) -> Option<Bytes> {
+        let start_offset = self.end_offset - self.buffer.len();
+        if !(start_offset..=self.end_offset).contains(&offset) {
+            return None;
+        }
+
+        Some(
+            self.buffer
+                .range(offset - start_offset..)
+                .copied()
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Backlog;
+
+    #[test]
+    fn keeps_the_most_recent_bytes() {
+        let mut backlog = Backlog::new(8, 100);
+        assert_eq!(backlog.since(100).unwrap(), &b""[..]);
+        assert!(backlog.since(99).is_none());
+
+        backlog.push(b"abcdef");
+        assert_eq!(backlog.since(102).unwrap(), &b"cdef"[..]);
+
+        backlog.push(b"ghij");
+        assert_eq!(backlog.since(102).unwrap(), &b"cdefghij"[..]);
+        assert!(backlog.since(101).is_none());
+        assert!(backlog.since(111).is_none());
+
+        backlog.push(b"0123456789");
+        assert_eq!(backlog.since(112).unwrap(), &b"23456789"[..]);
+        assert!(backlog.since(111).is_none());
+    }
+}
@@ -1,38 +1,69 @@
 use bytes::Bytes;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub enum InfoSection {
-    Replication,
-    Default,
-}
-
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ReplConfSection {
-    Port { listening_port: u16 },
-    Capa { capabilities: Vec<Bytes> },
+    Port {
+        listening_port: u16,
+    },
+    Capa {
+        capabilities: Vec<Bytes>,
+    },
     GetAck,
-    Ack { processed_bytes: usize },
+    Ack {
+        processed_bytes: usize,
+    },
+    /// An `ACK` whose offset is missing or not a number, which is ignored rather than
+    /// treated as a protocol error so that it does not cost the link to the replica.
+    MalformedAck {
+        offset: Bytes,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum RedisReplicationCommand {
-    Info {
-        section: InfoSection,
+pub enum FailoverSection {
+    To {
+        host: String,
+        port: u16,
+        timeout: Option<u64>,
     },
+    Abort,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RedisReplicationCommand {
     ReplConf {
         section: ReplConfSection,
     },
     PSync {
         replication_id: String,
         replication_offset: i64,
+        failover: bool,
     },
     Wait {
         num_replicas: usize,
         timeout: usize,
     },
+    WaitAof {
+        num_local: usize,
+        num_replicas: usize,
+        timeout: usize,
+    },
+    Failover {
+        section: FailoverSection,
+    },
 }
 
 impl RedisReplicationCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ReplConf { .. } => "replconf",
+            Self::PSync { .. } => "psync",
+            Self::Wait { .. } => "wait",
+            Self::WaitAof { .. } => "waitaof",
+            Self::Failover { .. } => "failover",
+        }
+    }
+
     pub fn is_getack(&self) -> bool {
         matches!(
             self,
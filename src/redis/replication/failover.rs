@@ -0,0 +1,228 @@
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::redis::{
+    log::log,
+    resp::{encoding, RESPValue},
+    server::{ClientAddress, ClientId, RedisWriteStream},
+};
+
+use super::{
+    command::FailoverSection,
+    handler::{expected_acked_bytes, send_getack},
+    handshake::{self, PrimaryLink},
+    RedisReplication, RedisReplicationMode,
+};
+
+/// A coordinated failover to one of the replicas. Writes are paused while the target
+/// catches up to the offset that was replicated when the failover started.
+#[derive(Debug)]
+pub struct Failover {
+    target_id: ClientId,
+    host: String,
+    port: u16,
+    expected_acked_bytes: usize,
+    deadline: Option<Instant>,
+}
+
+impl RedisReplication {
+    pub(super) async fn failover(
+        &mut self,
+        section: &FailoverSection,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match section {
+            FailoverSection::To {
+                host,
+                port,
+                timeout,
+            } => self.start_failover(host, *port, *timeout).await?,
+            FailoverSection::Abort if self.abort_failover() => encoding::simple_string("OK"),
+            FailoverSection::Abort => encoding::simple_error("ERR No failover in progress."),
+        };
+
+        write_stream.write(reply).await
+    }
+
+    async fn start_failover(
+        &mut self,
+        host: &str,
+        port: u16,
+        timeout: Option<u64>,
+    ) -> anyhow::Result<RESPValue> {
+        let RedisReplicationMode::Primary {
+            replicas,
+            replicated_bytes,
+            backlog,
+            getack_offset,
+            listening_ports,
+            failover,
+            ..
+        } = &mut self.replication_mode
+        else {
+            return Ok(encoding::simple_error(
+                "ERR FAILOVER is not valid when server is a replica.",
+            ));
+        };
+
+        if failover.is_some() {
+            return Ok(encoding::simple_error("ERR FAILOVER already in progress."));
+        }
+
+        // replicas are identified by the address they connected from and the port they
+        // announced with `REPLCONF listening-port`
+        let target_addresses = (host, port)
+            .to_socket_addrs()
+            .map(|addresses| addresses.collect::<Vec<_>>())
+            .unwrap_or_default();
+        let target = replicas.values().find(|replica_info| {
+            match (&replica_info.address, listening_ports.get(&replica_info.id)) {
+                (ClientAddress::Tcp(address), Some(&listening_port)) => {
+                    target_addresses.contains(&SocketAddr::new(address.ip(), listening_port))
+                }
+                _ => false,
+            }
+        });
+
+        let Some(target) = target else {
+            return Ok(encoding::simple_error(
+                "ERR FAILOVER target HOST and PORT is not a replica.",
+            ));
+        };
+
+        let target_id = target.id;
+        let (expected_acked_bytes, is_getack_in_flight) =
+            expected_acked_bytes(*replicated_bytes, *getack_offset);
+        if target.acker.get_bytes() < expected_acked_bytes && !is_getack_in_flight {
+            send_getack(replicas, replicated_bytes, backlog, getack_offset).await?;
+        }
+
+        *failover = Some(Failover {
+            target_id,
+            host: host.to_string(),
+            port,
+            expected_acked_bytes,
+            deadline: timeout.map(|timeout| Instant::now() + Duration::from_millis(timeout)),
+        });
+
+        Ok(encoding::simple_string("OK"))
+    }
+
+    pub fn is_failover_in_progress(&self) -> bool {
+        matches!(
+            self.replication_mode,
+            RedisReplicationMode::Primary {
+                failover: Some(_),
+                ..
+            }
+        )
+    }
+
+    pub fn failover_deadline(&self) -> Option<Instant> {
+        match &self.replication_mode {
+            RedisReplicationMode::Primary {
+                failover: Some(failover),
+                ..
+            } => failover.deadline,
+            _ => None,
+        }
+    }
+
+    /// Cancels the failover in progress, returning whether there was one.
+    pub fn abort_failover(&mut self) -> bool {
+        match &mut self.replication_mode {
+            RedisReplicationMode::Primary { failover, .. } => failover.take().is_some(),
+            RedisReplicationMode::Replica { .. } => false,
+        }
+    }
+
+    /// Hands the primary role over to the failover target once it has caught up. This
+    /// node then replicates from the target, and its own replicas are disconnected.
+    pub async fn try_complete_failover(&mut self) -> anyhow::Result<()> {
+        let RedisReplicationMode::Primary {
+            replication_id,
+            replicas,
+            replicated_bytes,
+            failover,
+            ..
+        } = &mut self.replication_mode
+        else {
+            return Ok(());
+        };
+
+        let is_caught_up = failover.as_ref().is_some_and(|failover| {
+            replicas
+                .get(&failover.target_id)
+                .is_some_and(|target| target.acker.get_bytes() >= failover.expected_acked_bytes)
+        });
+
+        if !is_caught_up {
+            return Ok(());
+        }
+
+        let Failover { host, port, .. } = failover.take().unwrap();
+        let link = Arc::new(PrimaryLink::continuing(
+            replication_id.clone(),
+            *replicated_bytes,
+        ));
+        let command_tx = self.command_tx.clone().ok_or_else(|| {
+            anyhow::anyhow!("[redis - error] replication must be set up before a failover")
+        })?;
+
+        let handshake = handshake::complete_handshake(
+            self.listening_port,
+            (&host, port),
+            link.clone(),
+            true,
+            command_tx,
+        )
+        .await;
+
+        match handshake {
+            Ok(()) => {
+                log!(Notice, "failover to {host}:{port} completed");
+                self.switch_mode(RedisReplicationMode::replica_with_link(host, port, link));
+            }
+            Err(err) => {
+                log!(
+                    Warning,
+                    "failover to {host}:{port} failed, staying primary: {err}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Turns a replica into a primary when the node it replicates from fails over to it.
+    /// The replication ID and offset are taken over since both nodes share the same
+    /// history, which lets the old primary continue it as a replica. Like in Redis, the
+    /// replication ID must be the one this node is at, so that no other client can take
+    /// over a replica, and whether it matched is returned.
+    pub(super) fn promote(&mut self, replication_id: &str) -> bool {
+        match &self.replication_mode {
+            RedisReplicationMode::Primary {
+                replication_id: own_replication_id,
+                ..
+            } => own_replication_id == replication_id,
+            RedisReplicationMode::Replica { link, .. } => {
+                if link.replication_id().as_deref() != Some(replication_id) {
+                    return false;
+                }
+
+                log!(Notice, "promoted to primary by a failover");
+                let replication_offset = link.processed_bytes();
+                self.switch_mode(RedisReplicationMode::primary_continuing(
+                    replication_id.to_string(),
+                    replication_offset,
+                ));
+                true
+            }
+        }
+    }
+}
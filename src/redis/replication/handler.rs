@@ -1,16 +1,26 @@
-use std::{sync::atomic::Ordering, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use bytes::Bytes;
-use tokio::task::JoinSet;
+use tokio::{sync::broadcast, task::JoinSet};
 
 use crate::redis::{
-    resp::encoding,
-    server::{ClientConnectionInfo, ClientId, RedisWriteStream},
+    error::RedisError,
+    log::log,
+    resp::{encoding, RESPValue},
+    server::{ClientAddress, ClientConnectionInfo, ClientId, RedisWriteStream},
 };
 
 use super::{
     acker::Acker,
-    command::{InfoSection, RedisReplicationCommand, ReplConfSection},
+    backlog::Backlog,
+    command::{RedisReplicationCommand, ReplConfSection},
     RedisReplication, RedisReplicationMode, ReplicaInfo,
 };
 
@@ -23,20 +33,38 @@ impl RedisReplication {
         command: &RedisReplicationCommand,
         write_stream: RedisWriteStream,
     ) -> anyhow::Result<()> {
+        self.remove_disconnected_replicas();
         match command {
-            RedisReplicationCommand::Info { section } => self.info(*section, write_stream).await?,
             RedisReplicationCommand::ReplConf {
-                section: ReplConfSection::Port { .. },
-            } => self.repl_conf_port(write_stream).await?,
+                section: ReplConfSection::Port { listening_port },
+            } => {
+                self.repl_conf_port(client_info.id, *listening_port, write_stream)
+                    .await?
+            }
             RedisReplicationCommand::ReplConf {
                 section: ReplConfSection::Capa { .. },
             } => self.repl_conf_capa(write_stream).await?,
-            RedisReplicationCommand::PSync { .. } => {
-                self.psync(write_stream.clone()).await?;
+            RedisReplicationCommand::PSync {
+                replication_id,
+                replication_offset,
+                failover,
+            } => {
+                if *failover && !self.promote(replication_id) {
+                    return write_stream
+                        .write(encoding::simple_error(
+                            "ERR PSYNC FAILOVER replid must match my replid.",
+                        ))
+                        .await;
+                }
+
+                let replica_offset = self
+                    .psync(replication_id, *replication_offset, write_stream.clone())
+                    .await?;
                 self.add_replica(ReplicaInfo {
                     id: client_info.id,
+                    address: client_info.address,
                     write_stream,
-                    acker: Acker::new(0),
+                    acker: Acker::new(replica_offset),
                 });
             }
             RedisReplicationCommand::ReplConf {
@@ -44,7 +72,21 @@ impl RedisReplication {
             } => self.getack(write_stream).await?,
             RedisReplicationCommand::ReplConf {
                 section: ReplConfSection::Ack { processed_bytes },
-            } => self.ack(client_info.id, *processed_bytes).await?,
+            } => {
+                self.ack(client_info.id, *processed_bytes, write_stream)
+                    .await?
+            }
+            RedisReplicationCommand::ReplConf {
+                section: ReplConfSection::MalformedAck { offset },
+            } => {
+                // like in Redis, nothing is replied since replicas do not read replies
+                log!(
+                    Warning,
+                    "ignoring REPLCONF ACK with malformed offset '{}' from {}",
+                    String::from_utf8_lossy(offset),
+                    client_info.address
+                );
+            }
             RedisReplicationCommand::Wait {
                 num_replicas,
                 timeout,
@@ -52,36 +94,71 @@ impl RedisReplication {
                 self.wait(client_info, *num_replicas, *timeout, write_stream)
                     .await?;
             }
+            RedisReplicationCommand::WaitAof { num_local, .. } => {
+                self.wait_aof(*num_local, write_stream).await?
+            }
+            RedisReplicationCommand::Failover { section } => {
+                self.failover(section, write_stream).await?
+            }
         }
 
         Ok(())
     }
 
-    async fn info(
-        &mut self,
-        section: InfoSection,
-        write_stream: RedisWriteStream,
-    ) -> anyhow::Result<()> {
-        match section {
-            InfoSection::Default | InfoSection::Replication => {
-                let info = match &self.replication_mode {
-                    RedisReplicationMode::Primary {
-                        replication_id,
-                        replication_offset,
-                        ..
-                    } => format!(
-                        "role:master\nmaster_replid:{}\nmaster_repl_offset:{}",
-                        replication_id, replication_offset
-                    ),
-                    RedisReplicationMode::Replica { .. } => "role:slave".to_string(),
-                };
+    /// Describes the replication state for the `replication` section of `INFO`.
+    pub fn info(&self) -> String {
+        match &self.replication_mode {
+            RedisReplicationMode::Primary {
+                replication_id,
+                replicated_bytes,
+                ..
+            } => format!(
+                "role:master\nmaster_replid:{}\nmaster_repl_offset:{}",
+                replication_id, replicated_bytes
+            ),
+            RedisReplicationMode::Replica { .. } => "role:slave".to_string(),
+        }
+    }
 
-                write_stream.write(encoding::bulk_string(info)).await
+    /// The replication offset of this node, along with the offset each replica
+    /// acknowledged last, ordered by when the replicas connected. These are the offsets
+    /// `WAIT` compares, so tests can check that replicas caught up without waiting. A
+    /// replica reports the offset it processed up to, and has no replicas of its own.
+    pub fn offsets(&self) -> (usize, Vec<(ClientAddress, usize)>) {
+        match &self.replication_mode {
+            RedisReplicationMode::Primary {
+                replicas,
+                replicated_bytes,
+                ..
+            } => {
+                let mut replicas = replicas.values().collect::<Vec<_>>();
+                replicas.sort_by_key(|replica_info| replica_info.id.value());
+                let acked = replicas
+                    .into_iter()
+                    .map(|replica_info| {
+                        (replica_info.address.clone(), replica_info.acker.get_bytes())
+                    })
+                    .collect();
+
+                (*replicated_bytes, acked)
             }
+            RedisReplicationMode::Replica { link, .. } => (link.processed_bytes(), vec![]),
         }
     }
 
-    async fn repl_conf_port(&mut self, write_stream: RedisWriteStream) -> anyhow::Result<()> {
+    async fn repl_conf_port(
+        &mut self,
+        id: ClientId,
+        listening_port: u16,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        if let RedisReplicationMode::Primary {
+            listening_ports, ..
+        } = &mut self.replication_mode
+        {
+            listening_ports.insert(id, listening_port);
+        }
+
         write_stream.write(Bytes::from_static(b"+OK\r\n")).await
     }
 
@@ -89,16 +166,47 @@ impl RedisReplication {
         write_stream.write(Bytes::from_static(b"+OK\r\n")).await
     }
 
-    async fn psync(&mut self, write_stream: RedisWriteStream) -> anyhow::Result<()> {
+    /// Continues the replication stream from the offset of the replica when it is still
+    /// in the backlog, and starts a full resync otherwise. Returns the offset the replica
+    /// starts out at.
+    async fn psync(
+        &mut self,
+        replica_replication_id: &str,
+        replica_offset: i64,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<usize> {
         if let RedisReplicationMode::Primary {
             replication_id,
-            replication_offset,
+            replicated_bytes,
+            backlog,
             ..
         } = &self.replication_mode
         {
+            let missing_bytes = usize::try_from(replica_offset)
+                .ok()
+                .filter(|_| replica_replication_id == replication_id)
+                .and_then(|replica_offset| Some((replica_offset, backlog.since(replica_offset)?)));
+            if let Some((replica_offset, missing_bytes)) = missing_bytes {
+                log!(
+                    Notice,
+                    "partial resync accepted, sending {} bytes of backlog",
+                    missing_bytes.len()
+                );
+                write_stream
+                    .write(encoding::simple_string(format!(
+                        "CONTINUE {replication_id}"
+                    )))
+                    .await?;
+                if !missing_bytes.is_empty() {
+                    write_stream.write(missing_bytes).await?;
+                }
+
+                return Ok(replica_offset);
+            }
+
             let resync = encoding::simple_string(format!(
-                "+FULLRESYNC {} {}",
-                replication_id, *replication_offset
+                "FULLRESYNC {} {}",
+                replication_id, *replicated_bytes
             ));
 
             write_stream.write(resync).await?;
@@ -109,37 +217,55 @@ impl RedisReplication {
 
             let rdb_file: Bytes = encoding::bulk_string(rdb_file).into();
             let rdb_file = rdb_file.slice(0..rdb_file.len() - 2);
-            write_stream.write(rdb_file).await
+            write_stream.write(rdb_file).await?;
+            Ok(*replicated_bytes)
         } else {
-            Err(anyhow::anyhow!(
-                "[redis - error] Redis must be running in primary mode to respond to 'PSYNC' command"
-            ))
+            Err(RedisError::Command(
+                "ERR PSYNC is only served by a primary, replicas cannot be chained".to_string(),
+            )
+            .into())
         }
     }
 
     async fn getack(&mut self, write_stream: RedisWriteStream) -> anyhow::Result<()> {
-        if let RedisReplicationMode::Replica {
-            processed_bytes, ..
-        } = &self.replication_mode
-        {
-            write_stream
-                .write(encoding::replconf_ack(*processed_bytes))
-                .await
-        } else {
-            Err(anyhow::anyhow!("[redis - error] Redis must be running as a replica to respond to 'replconf getack' command"))
-        }
+        let reply = match &self.replication_mode {
+            RedisReplicationMode::Replica { link, .. } => {
+                encoding::replconf_ack(link.processed_bytes())
+            }
+            RedisReplicationMode::Primary { .. } => encoding::simple_error(
+                "ERR REPLCONF GETACK can only be sent to a replica by its primary",
+            )
+            .into(),
+        };
+
+        write_stream.write(reply).await
     }
 
-    async fn ack(&mut self, id: ClientId, processed_bytes: usize) -> anyhow::Result<()> {
-        if let RedisReplicationMode::Primary { replicas, .. } = &mut self.replication_mode {
-            let replica_info = replicas.get_mut(&id).ok_or_else(|| {
-                anyhow::anyhow!("[redis - error] reference to replica with unknown client id")
-            })?;
+    /// Records the offset a replica acknowledged. Acknowledgements get no reply, so only
+    /// one sent by a connection that is not a replica of this node is answered, with an error.
+    async fn ack(
+        &mut self,
+        id: ClientId,
+        processed_bytes: usize,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let replica_info = match &mut self.replication_mode {
+            RedisReplicationMode::Primary { replicas, .. } => replicas.get_mut(&id),
+            RedisReplicationMode::Replica { .. } => None,
+        };
 
-            replica_info.acker.ack(processed_bytes);
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("[redis - error] Redis must be running as a primary to handle 'replconf ack' response"))
+        match replica_info {
+            Some(replica_info) => {
+                replica_info.acker.ack(processed_bytes);
+                Ok(())
+            }
+            None => {
+                write_stream
+                    .write(encoding::simple_error(
+                        "ERR REPLCONF ACK can only be sent by a replica",
+                    ))
+                    .await
+            }
         }
     }
 
@@ -150,60 +276,992 @@ impl RedisReplication {
         timeout: usize,
         write_stream: RedisWriteStream,
     ) -> anyhow::Result<()> {
-        if let RedisReplicationMode::Primary {
+        let RedisReplicationMode::Primary {
             replicas,
             replicated_bytes,
+            backlog,
+            getack_offset,
             ..
         } = &mut self.replication_mode
-        {
-            let mut join_set = JoinSet::new();
-            let mut acked_replicas = replicas
-                .values()
-                .filter(|replica_info| replica_info.acker.get_bytes() == *replicated_bytes)
-                .count();
-
-            let replica_count = replicas.len();
-            if acked_replicas >= std::cmp::min(num_replicas, replica_count) {
-                let replica_count: i64 = acked_replicas.try_into()?;
-                return write_stream.write(encoding::integer(replica_count)).await;
-            }
-
-            client_info.is_read_blocked.store(true, Ordering::SeqCst);
-            let bytes = encoding::replconf_get_ack();
-            *replicated_bytes += bytes.len();
-            let expected_acked_bytes = *replicated_bytes - bytes.len();
-            for replica_info in replicas.values_mut() {
-                let mut rx = replica_info.acker.subscribe();
-                replica_info.write_stream.write(bytes.clone()).await?;
-                join_set.spawn(async move {
-                    rx.recv()
+        else {
+            // a replica has no replicas of its own since chained replication is not
+            // supported, so there is never anything to wait for
+            return write_stream.write(encoding::integer(0)).await;
+        };
+
+        // the common case of replicas that are caught up is answered without
+        // subscribing to their acks or spawning anything
+        let (expected_acked_bytes, is_getack_in_flight) =
+            expected_acked_bytes(*replicated_bytes, *getack_offset);
+        let acked = replicas
+            .values()
+            .filter(|replica_info| replica_info.acker.get_bytes() >= expected_acked_bytes)
+            .count();
+        let target = std::cmp::min(num_replicas, replicas.len());
+        if acked >= target {
+            return write_stream.write(wait_reply(acked)).await;
+        }
+
+        // from here on only the spawned task replies, and the client is unblocked on
+        // every path, including the errors before the task is spawned
+        let timeout = Duration::from_millis(timeout.try_into()?);
+        let read_block = ReadBlock::new(&client_info, &write_stream);
+        // replicas that are already up to date were counted above and must not be
+        // counted a second time when they answer the `GETACK`
+        let receivers = replicas
+            .values_mut()
+            .filter(|replica_info| replica_info.acker.get_bytes() < expected_acked_bytes)
+            .map(|replica_info| {
+                (
+                    replica_info.acker.subscribe(),
+                    replica_info.write_stream.clone(),
+                )
+            })
+            .collect();
+
+        if !is_getack_in_flight {
+            send_getack(replicas, replicated_bytes, backlog, getack_offset).await?;
+        }
+
+        let acked_replicas = Arc::new(AtomicUsize::new(acked));
+        tokio::spawn(async move {
+            let acked = count_acks(
+                acked_replicas,
+                receivers,
+                expected_acked_bytes,
+                target,
+                timeout,
+            )
+            .await;
+
+            // unblocking only after the reply keeps it ahead of replies to later commands
+            let result = write_stream.write(wait_reply(acked)).await;
+            drop(read_block);
+            result
+        });
+
+        Ok(())
+    }
+
+    /// Replies to `WAITAOF`. There is no AOF persistence, so nothing is ever fsynced
+    /// locally and replicas are reported as acknowledged as soon as they are connected.
+    async fn wait_aof(
+        &mut self,
+        num_local: usize,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        let reply = match &self.replication_mode {
+            RedisReplicationMode::Replica { .. } => encoding::simple_error("ERR WAITAOF cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated."),
+            RedisReplicationMode::Primary { .. } if num_local > 0 => encoding::simple_error(
+                "ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled.",
+            ),
+            RedisReplicationMode::Primary { replicas, .. } => encoding::array(vec![
+                encoding::integer(0),
+                encoding::integer(i64::try_from(replicas.len())?),
+            ]),
+        };
+
+        write_stream.write(reply).await
+    }
+}
+
+/// The single reply to a `WAIT`, whether it is answered right away or after waiting.
+fn wait_reply(acked: usize) -> RESPValue {
+    encoding::integer(i64::try_from(acked).unwrap_or(i64::MAX))
+}
+
+/// Stops reading the commands of a client while it waits, and resumes reading when
+/// dropped, however the wait ends.
+struct ReadBlock(Arc<AtomicBool>, RedisWriteStream);
+
+impl ReadBlock {
+    fn new(client_info: &ClientConnectionInfo, write_stream: &RedisWriteStream) -> Self {
+        client_info.is_read_blocked.store(true, Ordering::SeqCst);
+        Self(client_info.is_read_blocked.clone(), write_stream.clone())
+    }
+}
+
+impl Drop for ReadBlock {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+        self.1.wake_reader();
+    }
+}
+
+/// Returns the offset replicas have to acknowledge to be up to date and whether a
+/// `GETACK` round is in flight. When nothing was replicated since the last `GETACK`,
+/// that round is reused instead of growing the offset with another one. Replicas
+/// report their offset from before processing a `GETACK`, so it is not part of what
+/// they have to acknowledge.
+pub(super) fn expected_acked_bytes(
+    replicated_bytes: usize,
+    getack_offset: Option<usize>,
+) -> (usize, bool) {
+    if getack_offset == Some(replicated_bytes) {
+        let getack_len = encoding::replconf_get_ack().len();
+        (replicated_bytes - getack_len, true)
+    } else {
+        (replicated_bytes, false)
+    }
+}
+
+/// Asks every replica to acknowledge its offset, starting a new `GETACK` round.
+pub(super) async fn send_getack(
+    replicas: &HashMap<ClientId, ReplicaInfo>,
+    replicated_bytes: &mut usize,
+    backlog: &mut Backlog,
+    getack_offset: &mut Option<usize>,
+) -> anyhow::Result<()> {
+    let getack = encoding::replconf_get_ack();
+    *replicated_bytes += getack.len();
+    backlog.push(&getack);
+    *getack_offset = Some(*replicated_bytes);
+    for replica_info in replicas.values() {
+        replica_info.write_stream.write(getack.clone()).await?;
+    }
+
+    Ok(())
+}
+
+/// Waits until `target` replicas have acknowledged at least `expected_acked_bytes`
+/// or the timeout elapses, and returns the number of acknowledged replicas. Each
+/// receiver contributes at most once to the shared counter, and stops being waited for
+/// once the connection of its replica is dropped.
+async fn count_acks(
+    acked_replicas: Arc<AtomicUsize>,
+    receivers: Vec<(broadcast::Receiver<usize>, RedisWriteStream)>,
+    expected_acked_bytes: usize,
+    target: usize,
+    timeout: Duration,
+) -> usize {
+    let mut join_set = JoinSet::new();
+    for (mut rx, write_stream) in receivers {
+        let acked_replicas = acked_replicas.clone();
+        join_set.spawn(async move {
+            let count_ack = async {
+                while let Ok(acked_bytes) = rx.recv().await {
+                    if acked_bytes >= expected_acked_bytes {
+                        acked_replicas.fetch_add(1, Ordering::SeqCst);
+                        break;
+                    }
+                }
+            };
+
+            tokio::select! {
+                _ = count_ack => {}
+                _ = write_stream.disconnected() => {}
+            }
+        });
+    }
+
+    let _ = tokio::time::timeout(timeout, async {
+        while acked_replicas.load(Ordering::SeqCst) < target {
+            if join_set.join_next().await.is_none() {
+                break;
+            }
+        }
+    })
+    .await;
+
+    join_set.shutdown().await;
+    acked_replicas.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+    use tokio::sync::mpsc;
+
+    use crate::redis::{
+        replication::{
+            command::{RedisReplicationCommand, ReplConfSection},
+            PrimaryLink, RedisReplicationMode, ReplicaInfo,
+        },
+        resp::encoding,
+        server::{ClientAddress, ClientConnectionInfo, ClientId, RedisWriteStream},
+    };
+
+    use super::{count_acks, Acker, RedisReplication};
+
+    /// A replica that processes everything replicated to it and answers each `GETACK`
+    /// with its offset shifted by `skew`, acting as a replica that is ahead of or behind
+    /// the offset the primary expects.
+    struct MockReplica {
+        id: ClientId,
+        rx: mpsc::UnboundedReceiver<Bytes>,
+        processed_bytes: usize,
+        skew: isize,
+    }
+
+    impl MockReplica {
+        fn attach(replication: &mut RedisReplication, id: usize, skew: isize) -> Self {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let id = ClientId::new(id);
+            replication.add_replica(ReplicaInfo {
+                id,
+                address: ClientAddress::Unix(PathBuf::new()),
+                write_stream: RedisWriteStream::new(tx),
+                acker: Acker::new(0),
+            });
+
+            Self {
+                id,
+                rx,
+                processed_bytes: 0,
+                skew,
+            }
+        }
+
+        async fn answer_getacks(&mut self, replication: &mut RedisReplication) {
+            while let Ok(bytes) = self.rx.try_recv() {
+                if bytes == encoding::replconf_get_ack() {
+                    let processed_bytes = self.processed_bytes.checked_add_signed(self.skew);
+                    let client_info =
+                        ClientConnectionInfo::new(self.id, ClientAddress::Unix(PathBuf::new()));
+                    replication
+                        .handle_command(
+                            client_info,
+                            &RedisReplicationCommand::ReplConf {
+                                section: ReplConfSection::Ack {
+                                    processed_bytes: processed_bytes.unwrap(),
+                                },
+                            },
+                            RedisWriteStream::sink(),
+                        )
                         .await
-                        .map(|acked_bytes| acked_bytes == expected_acked_bytes)
-                });
+                        .unwrap();
+                }
+
+                self.processed_bytes += bytes.len();
             }
+        }
+    }
 
-            tokio::spawn(async move {
-                let timeout_millis = timeout.try_into()?;
-                let _ = tokio::time::timeout(Duration::from_millis(timeout_millis), async {
-                    while let Some(Ok(Ok(is_up_to_date))) = join_set.join_next().await {
-                        if is_up_to_date {
-                            acked_replicas += 1;
-                            if acked_replicas >= std::cmp::min(num_replicas, replica_count) {
-                                break;
-                            }
-                        }
+    fn replicated_bytes(replication: &RedisReplication) -> usize {
+        match replication.replication_mode {
+            RedisReplicationMode::Primary {
+                replicated_bytes, ..
+            } => replicated_bytes,
+            RedisReplicationMode::Replica { .. } => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn replies_zero_to_wait_on_replica() {
+        let mut replication = RedisReplication::new(
+            6380,
+            RedisReplicationMode::replica("localhost".to_string(), 6379),
+        );
+        let client_info =
+            ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let wait = RedisReplicationCommand::Wait {
+            num_replicas: 1,
+            timeout: 100,
+        };
+
+        replication
+            .handle_command(client_info, &wait, RedisWriteStream::new(tx))
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), &b":0\r\n"[..]);
+    }
+
+    #[tokio::test]
+    async fn rejects_waitaof_without_aof_persistence() {
+        async fn reply(replication: &mut RedisReplication, num_local: usize) -> Bytes {
+            let client_info =
+                ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+            let wait_aof = RedisReplicationCommand::WaitAof {
+                num_local,
+                num_replicas: 0,
+                timeout: 0,
+            };
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            replication
+                .handle_command(client_info, &wait_aof, RedisWriteStream::new(tx))
+                .await
+                .unwrap();
+            rx.recv().await.unwrap()
+        }
+
+        let mut primary = RedisReplication::new(6379, RedisReplicationMode::primary());
+        MockReplica::attach(&mut primary, 2, 0);
+        assert_eq!(
+            reply(&mut primary, 1).await,
+            &b"-ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled.\r\n"[..]
+        );
+        assert_eq!(reply(&mut primary, 0).await, &b"*2\r\n:0\r\n:1\r\n"[..]);
+
+        let mut replica = RedisReplication::new(
+            6380,
+            RedisReplicationMode::replica("localhost".to_string(), 6379),
+        );
+        assert_eq!(
+            reply(&mut replica, 0).await,
+            &b"-ERR WAITAOF cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated.\r\n"[..]
+        );
+    }
+
+    fn replication_id(replication: &RedisReplication) -> String {
+        let info = replication.info();
+        let line = info.lines().find(|line| line.starts_with("master_replid:"));
+        line.unwrap()["master_replid:".len()..].to_string()
+    }
+
+    #[test]
+    fn generates_distinct_replication_ids() {
+        let replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let other_replication = RedisReplication::new(6380, RedisReplicationMode::primary());
+        let id = replication_id(&replication);
+        assert_eq!(id.len(), 40);
+        assert!(id.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_ne!(id, replication_id(&other_replication));
+    }
+
+    #[test]
+    fn changes_replication_id_to_random_hex() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+
+        replication.change_replication_id();
+        let first_id = replication_id(&replication);
+        replication.change_replication_id();
+        let second_id = replication_id(&replication);
+
+        assert_eq!(first_id.len(), 40);
+        assert!(first_id.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_ne!(first_id, second_id);
+    }
+
+    #[tokio::test]
+    async fn continues_from_the_backlog_when_possible() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let first_write = encoding::ping(Some(b"first"));
+        let second_write = encoding::ping(Some(b"second"));
+        replication
+            .try_replicate(0, first_write.clone())
+            .await
+            .unwrap();
+        replication
+            .try_replicate(0, second_write.clone())
+            .await
+            .unwrap();
+
+        let id = replication_id(&replication);
+        let offset = (first_write.len() + second_write.len()) as i64;
+        let cases = [
+            (id.clone(), first_write.len() as i64, true),
+            (id.clone(), offset, true),
+            (id.clone(), offset + 1, false),
+            (id.clone(), -1, false),
+            ("?".to_string(), -1, false),
+        ];
+
+        for (index, (replication_id, replication_offset, is_continued)) in
+            cases.into_iter().enumerate()
+        {
+            let client_info = ClientConnectionInfo::new(
+                ClientId::new(index),
+                ClientAddress::Unix(PathBuf::new()),
+            );
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let psync = RedisReplicationCommand::PSync {
+                replication_id,
+                replication_offset,
+                failover: false,
+            };
+            replication
+                .handle_command(client_info, &psync, RedisWriteStream::new(tx))
+                .await
+                .unwrap();
+
+            let reply = rx.recv().await.unwrap();
+            if is_continued {
+                assert_eq!(reply, format!("+CONTINUE {id}\r\n").as_bytes());
+                let missing_bytes = rx.try_recv().unwrap_or_default();
+                assert_eq!(missing_bytes.len() as i64, offset - replication_offset);
+            } else {
+                assert_eq!(reply, format!("+FULLRESYNC {id} {offset}\r\n").as_bytes());
+            }
+        }
+
+        // resyncing replicas never adds to the replication offset
+        assert_eq!(replicated_bytes(&replication), offset as usize);
+    }
+
+    #[tokio::test]
+    async fn promotes_only_for_the_replication_id_it_follows() {
+        let link = Arc::new(PrimaryLink::continuing("followed".to_string(), 10));
+        let mut replication = RedisReplication::new(
+            6380,
+            RedisReplicationMode::Replica {
+                primary_host: "localhost".to_string(),
+                primary_port: 6379,
+                link,
+            },
+        );
+        let psync = |replication_id: &str| RedisReplicationCommand::PSync {
+            replication_id: replication_id.to_string(),
+            replication_offset: 10,
+            failover: true,
+        };
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new()));
+        replication
+            .handle_command(
+                client_info.clone(),
+                &psync("other"),
+                RedisWriteStream::new(tx.clone()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            "-ERR PSYNC FAILOVER replid must match my replid.\r\n"
+        );
+        assert!(replication.is_replica());
+
+        replication
+            .handle_command(client_info, &psync("followed"), RedisWriteStream::new(tx))
+            .await
+            .unwrap();
+        assert!(!replication.is_replica());
+        assert_eq!(replication_id(&replication), "followed");
+        assert_eq!(rx.recv().await.unwrap(), "+CONTINUE followed\r\n");
+    }
+
+    #[tokio::test]
+    async fn replies_with_errors_to_stray_acks() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for section in [
+            ReplConfSection::GetAck,
+            ReplConfSection::Ack { processed_bytes: 0 },
+        ] {
+            let client_info =
+                ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+            replication
+                .handle_command(
+                    client_info,
+                    &RedisReplicationCommand::ReplConf { section },
+                    RedisWriteStream::new(tx.clone()),
+                )
+                .await
+                .unwrap();
+            assert!(rx.recv().await.unwrap().starts_with(b"-ERR REPLCONF"));
+        }
+    }
+
+    #[tokio::test]
+    async fn ignores_malformed_acks_from_replicas() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let mut replica = MockReplica::attach(&mut replication, 1, 0);
+        let write = encoding::ping(Some(b"write"));
+        replication.try_replicate(0, write.clone()).await.unwrap();
+        replica.answer_getacks(&mut replication).await;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let replica_info =
+            ClientConnectionInfo::new(replica.id, ClientAddress::Unix(PathBuf::new()));
+        replication
+            .handle_command(
+                replica_info.clone(),
+                &RedisReplicationCommand::ReplConf {
+                    section: ReplConfSection::MalformedAck {
+                        offset: Bytes::from_static(b"garbage"),
+                    },
+                },
+                RedisWriteStream::new(tx.clone()),
+            )
+            .await
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+        match &replication.replication_mode {
+            RedisReplicationMode::Primary { replicas, .. } => {
+                assert!(replicas.contains_key(&replica.id))
+            }
+            RedisReplicationMode::Replica { .. } => unreachable!(),
+        }
+
+        // a valid ack that follows is still counted
+        replication
+            .handle_command(
+                replica_info,
+                &RedisReplicationCommand::ReplConf {
+                    section: ReplConfSection::Ack {
+                        processed_bytes: write.len(),
+                    },
+                },
+                RedisWriteStream::new(tx),
+            )
+            .await
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(2), ClientAddress::Unix(PathBuf::new()));
+        replication
+            .handle_command(
+                client_info,
+                &RedisReplicationCommand::Wait {
+                    num_replicas: 1,
+                    timeout: 500,
+                },
+                RedisWriteStream::new(tx),
+            )
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), &b":1\r\n"[..]);
+    }
+
+    #[tokio::test]
+    async fn answers_wait_before_any_write_without_getack() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let (replica_tx, mut replica_rx) = mpsc::unbounded_channel();
+        let psync = RedisReplicationCommand::PSync {
+            replication_id: "?".to_string(),
+            replication_offset: -1,
+            failover: false,
+        };
+        replication
+            .handle_command(
+                ClientConnectionInfo::new(ClientId::new(1), ClientAddress::Unix(PathBuf::new())),
+                &psync,
+                RedisWriteStream::new(replica_tx),
+            )
+            .await
+            .unwrap();
+        // the full resync and its snapshot
+        while replica_rx.try_recv().is_ok() {}
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        replication
+            .handle_command(
+                ClientConnectionInfo::new(ClientId::new(2), ClientAddress::Unix(PathBuf::new())),
+                &RedisReplicationCommand::Wait {
+                    num_replicas: 1,
+                    timeout: 100,
+                },
+                RedisWriteStream::new(tx),
+            )
+            .await
+            .unwrap();
+
+        // answered right away rather than after the timeout
+        assert_eq!(rx.try_recv().unwrap(), &b":1\r\n"[..]);
+        assert!(replica_rx.try_recv().is_err());
+        assert_eq!(replicated_bytes(&replication), 0);
+    }
+
+    #[tokio::test]
+    async fn reports_the_offset_each_replica_acked() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let mut replicas = [
+            MockReplica::attach(&mut replication, 2, 0),
+            MockReplica::attach(&mut replication, 1, 0),
+        ];
+        let write = encoding::ping(Some(b"write"));
+        replication.try_replicate(0, write.clone()).await.unwrap();
+
+        let acked = |replication: &RedisReplication| {
+            let (offset, replicas) = replication.offsets();
+            let acked = replicas
+                .into_iter()
+                .map(|(_, acked)| acked)
+                .collect::<Vec<_>>();
+            (offset, acked)
+        };
+        assert_eq!(acked(&replication), (write.len(), vec![0, 0]));
+
+        // only the replica that answers the `GETACK` of a `WAIT` catches up
+        replication
+            .handle_command(
+                ClientConnectionInfo::new(ClientId::new(3), ClientAddress::Unix(PathBuf::new())),
+                &RedisReplicationCommand::Wait {
+                    num_replicas: 2,
+                    timeout: 0,
+                },
+                RedisWriteStream::sink(),
+            )
+            .await
+            .unwrap();
+        replicas[1].answer_getacks(&mut replication).await;
+        let offset = write.len() + encoding::replconf_get_ack().len();
+        assert_eq!(acked(&replication), (offset, vec![write.len(), 0]));
+    }
+
+    #[tokio::test]
+    async fn replies_to_wait_exactly_once_and_unblocks_the_client() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let mut replica = MockReplica::attach(&mut replication, 1, 0);
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(2), ClientAddress::Unix(PathBuf::new()));
+        let wait = RedisReplicationCommand::Wait {
+            num_replicas: 1,
+            timeout: 50,
+        };
+
+        // answered right away, then after waiting for the ack, then after timing out
+        for (write, should_ack, expected) in [
+            (false, false, &b":1\r\n"[..]),
+            (true, true, b":1\r\n"),
+            (true, false, b":0\r\n"),
+        ] {
+            if write {
+                replication
+                    .try_replicate(0, encoding::ping(Some(b"write")))
+                    .await
+                    .unwrap();
+            }
+
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            replication
+                .handle_command(client_info.clone(), &wait, RedisWriteStream::new(tx))
+                .await
+                .unwrap();
+            if should_ack {
+                replica.answer_getacks(&mut replication).await;
+            } else {
+                // the replica falls behind, but keeps its offset for the next round
+                while let Ok(bytes) = replica.rx.try_recv() {
+                    replica.processed_bytes += bytes.len();
+                }
+            }
+
+            // the channel closes once every write stream of the wait is dropped
+            assert_eq!(rx.recv().await.unwrap(), expected);
+            assert!(rx.recv().await.is_none());
+            assert!(!client_info.is_read_blocked.load(Ordering::SeqCst));
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_waiting_for_replicas_that_disconnect() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let mut replica = MockReplica::attach(&mut replication, 1, 0);
+        let (gone_tx, _gone_rx) = mpsc::unbounded_channel();
+        let gone_write_stream = RedisWriteStream::new(gone_tx);
+        replication.add_replica(ReplicaInfo {
+            id: ClientId::new(2),
+            address: ClientAddress::Unix(PathBuf::new()),
+            write_stream: gone_write_stream.clone(),
+            acker: Acker::new(0),
+        });
+        replication
+            .try_replicate(0, encoding::ping(Some(b"write")))
+            .await
+            .unwrap();
+
+        let client_info =
+            ClientConnectionInfo::new(ClientId::new(3), ClientAddress::Unix(PathBuf::new()));
+        let wait = RedisReplicationCommand::Wait {
+            num_replicas: 2,
+            timeout: 60_000,
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        replication
+            .handle_command(
+                client_info.clone(),
+                &wait,
+                RedisWriteStream::new(tx.clone()),
+            )
+            .await
+            .unwrap();
+        replica.answer_getacks(&mut replication).await;
+        gone_write_stream.disconnect();
+
+        let reply = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await;
+        assert_eq!(reply.unwrap().unwrap(), &b":1\r\n"[..]);
+
+        // the next command forgets the replica, so it no longer counts towards the target
+        replication
+            .handle_command(client_info, &wait, RedisWriteStream::new(tx))
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), &b":1\r\n"[..]);
+        match &replication.replication_mode {
+            RedisReplicationMode::Primary { replicas, .. } => assert_eq!(replicas.len(), 1),
+            RedisReplicationMode::Replica { .. } => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_caught_up_waits_right_away_without_another_getack() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let mut replicas = (0..8)
+            .map(|id| MockReplica::attach(&mut replication, id, 0))
+            .collect::<Vec<_>>();
+        replication
+            .try_replicate(0, encoding::ping(Some(b"write")))
+            .await
+            .unwrap();
+
+        let client_info =
+            ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+        let wait = RedisReplicationCommand::Wait {
+            num_replicas: 8,
+            timeout: 100,
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        replication
+            .handle_command(client_info.clone(), &wait, RedisWriteStream::new(tx))
+            .await
+            .unwrap();
+        for replica in &mut replicas {
+            replica.answer_getacks(&mut replication).await;
+        }
+        assert_eq!(rx.recv().await.unwrap(), &b":8\r\n"[..]);
+        let caught_up_bytes = replicated_bytes(&replication);
+
+        // every replica is caught up, so each `WAIT` is answered before it returns, with
+        // nothing spawned, no client blocked and no `GETACK` built or sent
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for _ in 0..100 {
+            replication
+                .handle_command(
+                    client_info.clone(),
+                    &wait,
+                    RedisWriteStream::new(tx.clone()),
+                )
+                .await
+                .unwrap();
+            assert_eq!(rx.try_recv().unwrap(), &b":8\r\n"[..]);
+            assert!(!client_info.is_read_blocked.load(Ordering::SeqCst));
+        }
+
+        assert_eq!(replicated_bytes(&replication), caught_up_bytes);
+        for replica in &mut replicas {
+            assert!(replica.rx.try_recv().is_err());
+        }
+
+        // the `GETACK` a blocking `WAIT` sends is one static buffer, never rebuilt
+        assert_eq!(
+            encoding::replconf_get_ack().as_ptr(),
+            encoding::replconf_get_ack().as_ptr()
+        );
+    }
+
+    #[tokio::test]
+    async fn reuses_in_flight_getack_for_repeated_waits() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let (replica_tx, mut replica_rx) = mpsc::unbounded_channel();
+        replication.add_replica(ReplicaInfo {
+            id: ClientId::primary(),
+            address: ClientAddress::Unix(PathBuf::new()),
+            write_stream: RedisWriteStream::new(replica_tx),
+            acker: Acker::new(0),
+        });
+
+        let client_info =
+            ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+        let wait = RedisReplicationCommand::Wait {
+            num_replicas: 1,
+            timeout: 0,
+        };
+
+        let getack_len = encoding::replconf_get_ack().len();
+        let write = encoding::ping(Some(b"write"));
+        let mut expected_bytes = 0;
+        for _ in 0..2 {
+            replication.try_replicate(0, write.clone()).await.unwrap();
+            expected_bytes += write.len();
+            for _ in 0..100 {
+                replication
+                    .handle_command(client_info.clone(), &wait, RedisWriteStream::sink())
+                    .await
+                    .unwrap();
+            }
+
+            expected_bytes += getack_len;
+            assert_eq!(replicated_bytes(&replication), expected_bytes);
+        }
+
+        let mut getacks = 0;
+        while let Ok(bytes) = replica_rx.try_recv() {
+            if bytes == encoding::replconf_get_ack() {
+                getacks += 1;
+            }
+        }
+
+        assert_eq!(getacks, 2);
+    }
+
+    #[tokio::test]
+    async fn counts_replicas_that_acked_the_write_before_wait() {
+        let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+        let replica_id = ClientId::primary();
+        let (replica_tx, mut replica_rx) = mpsc::unbounded_channel();
+        replication.add_replica(ReplicaInfo {
+            id: replica_id,
+            address: ClientAddress::Unix(PathBuf::new()),
+            write_stream: RedisWriteStream::new(replica_tx),
+            acker: Acker::new(0),
+        });
+
+        let write = encoding::ping(Some(b"write"));
+        replication.try_replicate(0, write.clone()).await.unwrap();
+
+        let client_info =
+            ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        replication
+            .handle_command(
+                client_info,
+                &RedisReplicationCommand::Wait {
+                    num_replicas: 1,
+                    timeout: 500,
+                },
+                RedisWriteStream::new(tx),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(replica_rx.recv().await.unwrap(), write);
+        assert_eq!(
+            replica_rx.recv().await.unwrap(),
+            encoding::replconf_get_ack()
+        );
+
+        // the replica answers the `GETACK` with the offset from before processing it
+        let replica_info =
+            ClientConnectionInfo::new(replica_id, ClientAddress::Unix(PathBuf::new()));
+        replication
+            .handle_command(
+                replica_info,
+                &RedisReplicationCommand::ReplConf {
+                    section: ReplConfSection::Ack {
+                        processed_bytes: write.len(),
+                    },
+                },
+                RedisWriteStream::sink(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(rx.recv().await.unwrap(), &b":1\r\n"[..]);
+
+        // nothing was written since, so the same target is met without another round
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let client_info =
+            ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+        replication
+            .handle_command(
+                client_info,
+                &RedisReplicationCommand::Wait {
+                    num_replicas: 1,
+                    timeout: 500,
+                },
+                RedisWriteStream::new(tx),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(rx.recv().await.unwrap(), &b":1\r\n"[..]);
+        assert!(replica_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn counts_replicas_at_or_ahead_of_a_write_burst() {
+        // the skews of the replicas, how many to wait for and how many are counted
+        let cases: [(&[isize], usize, i64); 4] = [
+            (&[0, 0], 2, 2),
+            (&[7, 7], 2, 2),
+            (&[-1, -1], 2, 0),
+            (&[0, 7, -1], 3, 2),
+        ];
+
+        for (skews, num_replicas, expected) in cases {
+            let mut replication = RedisReplication::new(6379, RedisReplicationMode::primary());
+            let mut replicas = skews
+                .iter()
+                .enumerate()
+                .map(|(id, skew)| MockReplica::attach(&mut replication, id, *skew))
+                .collect::<Vec<_>>();
+
+            for index in 0..50 {
+                let write = encoding::ping(Some(format!("write {index}").as_bytes()));
+                replication.try_replicate(0, write).await.unwrap();
+            }
+
+            let client_info =
+                ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Unix(PathBuf::new()));
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            replication
+                .handle_command(
+                    client_info,
+                    &RedisReplicationCommand::Wait {
+                        num_replicas,
+                        timeout: 100,
+                    },
+                    RedisWriteStream::new(tx),
+                )
+                .await
+                .unwrap();
+
+            for replica in &mut replicas {
+                replica.answer_getacks(&mut replication).await;
+            }
+
+            let reply = rx.recv().await.unwrap();
+            assert_eq!(reply, format!(":{expected}\r\n").as_bytes(), "{skews:?}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn never_counts_more_acks_than_replicas() {
+        for round in 0..200 {
+            let replica_count = 4;
+            let expected_acked_bytes = 100;
+            let mut ackers = (0..replica_count)
+                .map(|_| Acker::new(0))
+                .collect::<Vec<_>>();
+            let receivers = ackers
+                .iter_mut()
+                .map(|acker| (acker.subscribe(), RedisWriteStream::sink()))
+                .collect();
+
+            let acks = tokio::spawn(async move {
+                for (i, acker) in ackers.iter_mut().enumerate() {
+                    // stale and repeated acks must not be counted more than once
+                    acker.ack(expected_acked_bytes - 1);
+                    if (i + round) % 3 != 0 {
+                        acker.ack(expected_acked_bytes);
+                        acker.ack(expected_acked_bytes);
                     }
-                })
-                .await;
 
-                client_info.is_read_blocked.store(false, Ordering::SeqCst);
-                let replica_count: i64 = acked_replicas.try_into()?;
-                write_stream.write(encoding::integer(replica_count)).await
+                    tokio::task::yield_now().await;
+                }
+
+                // dropping the ackers closes the channels so that the count finishes
+                // without waiting for the timeout
+                drop(ackers);
             });
 
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("[redis - error] Redis must be running in primary mode to respond to 'WAIT' command"))
+            let acked = count_acks(
+                Arc::new(AtomicUsize::new(0)),
+                receivers,
+                expected_acked_bytes,
+                replica_count,
+                Duration::from_secs(5),
+            )
+            .await;
+
+            let up_to_date = (0..replica_count).filter(|i| (i + round) % 3 != 0).count();
+            acks.await.unwrap();
+            assert!(acked <= replica_count);
+            assert_eq!(acked, up_to_date);
         }
     }
 }
@@ -1,9 +1,12 @@
 use std::{
-    net::ToSocketAddrs,
-    sync::{atomic::AtomicBool, Arc},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
-use anyhow::Context;
 use bytes::Bytes;
 use tokio::{
     io::AsyncWriteExt,
@@ -15,32 +18,242 @@ use tokio::{
 };
 
 use crate::redis::{
+    log::log,
     manager::RedisCommandPacket,
     resp::{command::RedisCommand, encoding, resp_reader::RESPReader, RESPValue},
-    server::{ClientConnectionInfo, ClientId, RedisWriteStream},
+    server::{ClientAddress, ClientConnectionInfo, ClientId, RedisWriteStream},
 };
 
+/// How long a replica waits before reconnecting to a primary it lost the connection to.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// What a replica knows about the replication stream of its primary. It outlives a
+/// single connection, so that the replica can ask to continue where it left off when it
+/// reconnects instead of doing a full resync.
+#[derive(Debug, Default)]
+pub struct PrimaryLink {
+    replication_id: Mutex<Option<String>>,
+    /// The offset up to which the commands of the primary were processed.
+    processed_bytes: AtomicUsize,
+    /// Set once the node stops replicating from the primary, which ends the link.
+    is_detached: AtomicBool,
+}
+
+impl PrimaryLink {
+    /// A link that continues an existing history, used when a primary fails over to one
+    /// of its replicas and starts replicating from it.
+    pub fn continuing(replication_id: String, replication_offset: usize) -> Self {
+        Self {
+            replication_id: Mutex::new(Some(replication_id)),
+            processed_bytes: AtomicUsize::new(replication_offset),
+            is_detached: AtomicBool::new(false),
+        }
+    }
+
+    /// The replication ID of the primary, once the first handshake learned it.
+    pub fn replication_id(&self) -> Option<String> {
+        self.replication_id.lock().unwrap().clone()
+    }
+
+    pub fn processed_bytes(&self) -> usize {
+        self.processed_bytes.load(Ordering::SeqCst)
+    }
+
+    pub fn add_processed_bytes(&self, bytes: usize) {
+        self.processed_bytes.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    pub fn detach(&self) {
+        self.is_detached.store(true, Ordering::SeqCst);
+    }
+
+    fn is_detached(&self) -> bool {
+        self.is_detached.load(Ordering::SeqCst)
+    }
+}
+
+/// Connects to the primary and starts processing its replication stream. Whenever the
+/// connection is lost, the replica reconnects and asks to continue from the offset it
+/// processed up to. When the first connection is part of a failover, the link holds the
+/// history of this node, which the new primary takes over.
 pub async fn complete_handshake(
     replica_port: u16,
     primary_address: (&str, u16),
+    link: Arc<PrimaryLink>,
+    is_failover: bool,
     command_tx: mpsc::Sender<RedisCommandPacket>,
 ) -> anyhow::Result<()> {
+    let (primary_host, primary_port) = primary_address;
+    let primary_address = resolve(primary_host, primary_port).await?;
+    let (read_half, write_half, _) = connect(replica_port, primary_address, &link, is_failover)
+        .await
+        .map_err(|err| {
+            anyhow::anyhow!(
+                "[redis - error] unable to connect to primary {primary_host}:{primary_port} at {primary_address}: {err}"
+            )
+        })?;
+    tokio::spawn(replicate(
+        replica_port,
+        (primary_host.to_string(), primary_port),
+        primary_address,
+        link,
+        read_half,
+        write_half,
+        command_tx,
+    ));
+
+    Ok(())
+}
+
+/// Resolves the host of the primary, which can be a hostname such as `localhost` or an
+/// IP address, with IPv6 addresses optionally in brackets.
+async fn resolve(host: &str, port: u16) -> anyhow::Result<SocketAddr> {
+    let unbracketed = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    tokio::net::lookup_host((unbracketed, port))
+        .await
+        .map_err(|err| {
+            anyhow::anyhow!("[redis - error] unable to resolve primary host '{host}': {err}")
+        })?
+        .next()
+        .ok_or_else(|| {
+            anyhow::anyhow!("[redis - error] primary host '{host}' resolved to no addresses")
+        })
+}
+
+/// Completes the handshake with the primary, returning whether it continued the
+/// replication stream rather than starting a full resync.
+async fn connect(
+    replica_port: u16,
+    primary_address: SocketAddr,
+    link: &PrimaryLink,
+    is_failover: bool,
+) -> anyhow::Result<(RESPReader<OwnedReadHalf>, OwnedWriteHalf, bool)> {
     let primary_stream = TcpStream::connect(primary_address).await?;
     let (read_stream, mut write_stream) = primary_stream.into_split();
     let mut read_stream = RESPReader::new(read_stream);
     send_ping(&mut read_stream, &mut write_stream).await?;
     send_replconf_port(&mut read_stream, &mut write_stream, replica_port).await?;
     send_replconf_capa(&mut read_stream, &mut write_stream).await?;
-    send_psync(primary_address, read_stream, write_stream, command_tx).await?;
+    let is_continued = send_psync(&mut read_stream, &mut write_stream, link, is_failover).await?;
+    Ok((read_stream, write_stream, is_continued))
+}
 
-    Ok(())
+/// Forwards the commands of the primary to be processed, reconnecting whenever the
+/// connection is lost until the link is detached. The host is resolved again for every
+/// reconnection, so that a primary whose address changed is followed.
+async fn replicate(
+    replica_port: u16,
+    (primary_host, primary_port): (String, u16),
+    mut primary_address: SocketAddr,
+    link: Arc<PrimaryLink>,
+    mut read_half: RESPReader<OwnedReadHalf>,
+    write_half: OwnedWriteHalf,
+    command_tx: mpsc::Sender<RedisCommandPacket>,
+) {
+    let mut write_stream = setup_replica_write_stream(write_half);
+    let mut primary_info =
+        ClientConnectionInfo::new(ClientId::primary(), ClientAddress::Tcp(primary_address));
+    let mut read_bytes = link.processed_bytes();
+    loop {
+        loop {
+            let command: RedisCommand = match read_half
+                .read_value()
+                .await
+                .and_then(|value| value.try_into())
+            {
+                Ok(command) => command,
+                // like in Redis, the replica reconnects either way, since a primary that
+                // closed the connection is usually restarting
+                Err(_) if read_half.is_closed() => {
+                    log!(Warning, "primary {primary_address} closed the connection");
+                    break;
+                }
+                Err(err) => {
+                    log!(
+                        Warning,
+                        "lost connection to primary {primary_address}: {err}"
+                    );
+                    break;
+                }
+            };
+
+            read_bytes += Bytes::from(&command).len();
+            let mut write_stream = write_stream.clone();
+            if !command.is_getack() {
+                write_stream.close();
+            }
+
+            let packet = RedisCommandPacket::new(primary_info.clone(), command, write_stream);
+            if command_tx.send(packet).await.is_err() {
+                return;
+            }
+        }
+
+        loop {
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            // the offset to continue from is only known once everything that was read
+            // has been processed
+            while link.processed_bytes() < read_bytes && !link.is_detached() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+
+            if link.is_detached() {
+                return;
+            }
+
+            let offset = link.processed_bytes();
+            log!(
+                Notice,
+                "reconnecting to primary {primary_host}:{primary_port} to continue from offset {offset}"
+            );
+            let connection = async {
+                primary_address = resolve(&primary_host, primary_port).await?;
+                connect(replica_port, primary_address, &link, false).await
+            };
+
+            match connection.await {
+                Ok((new_read_half, new_write_half, is_continued)) => {
+                    read_half = new_read_half;
+                    write_stream = setup_replica_write_stream(new_write_half);
+                    read_bytes = link.processed_bytes();
+                    if is_continued {
+                        log!(
+                            Notice,
+                            "partial resync with primary {primary_address} accepted from offset {offset}"
+                        );
+                    } else {
+                        // a full resync starts over on the first database
+                        primary_info = ClientConnectionInfo::new(
+                            ClientId::primary(),
+                            ClientAddress::Tcp(primary_address),
+                        );
+                        log!(
+                            Notice,
+                            "primary {primary_address} cannot continue from offset {offset}, full resync completed"
+                        );
+                    }
+
+                    break;
+                }
+                Err(err) => {
+                    log!(
+                        Warning,
+                        "unable to reconnect to primary {primary_host}:{primary_port}: {err}"
+                    );
+                }
+            }
+        }
+    }
 }
 
 async fn send_ping(
     read_stream: &mut RESPReader<OwnedReadHalf>,
     write_stream: &mut OwnedWriteHalf,
 ) -> anyhow::Result<()> {
-    write_stream.write_all(&encoding::ping()).await?;
+    write_stream.write_all(&encoding::ping(None)).await?;
     match read_stream.read_value().await {
         Ok(RESPValue::SimpleString(s)) if &*s == b"PONG" => Ok(()),
         _ => Err(anyhow::anyhow!(
@@ -80,14 +293,27 @@ async fn send_replconf_capa(
     }
 }
 
+/// Asks the primary to continue the replication stream from the processed offset, or
+/// for a full resync when there is no history yet. Returns whether the primary
+/// continued the stream.
 async fn send_psync(
-    (host, port): (&str, u16),
-    mut read_half: RESPReader<OwnedReadHalf>,
-    mut write_half: OwnedWriteHalf,
-    command_tx: mpsc::Sender<RedisCommandPacket>,
-) -> anyhow::Result<()> {
-    write_half.write_all(&encoding::psync("?", -1)).await?;
-    let response = read_half.read_value().await?;
+    read_stream: &mut RESPReader<OwnedReadHalf>,
+    write_stream: &mut OwnedWriteHalf,
+    link: &PrimaryLink,
+    is_failover: bool,
+) -> anyhow::Result<bool> {
+    let replication_id = link.replication_id.lock().unwrap().clone();
+    let psync = match replication_id {
+        Some(replication_id) => encoding::psync(
+            &replication_id,
+            link.processed_bytes().try_into()?,
+            is_failover,
+        ),
+        None => encoding::psync("?", -1, false),
+    };
+
+    write_stream.write_all(&psync).await?;
+    let response = read_stream.read_value().await?;
     let response = if let RESPValue::SimpleString(response) = response {
         String::from_utf8(response.to_vec())?
     } else {
@@ -98,53 +324,36 @@ async fn send_psync(
 
     if let Some(primary_info) = response.strip_prefix("FULLRESYNC ") {
         let mut primary_info = primary_info.split_ascii_whitespace();
-        let _replication_id = primary_info.next().unwrap();
-        let _replication_offset = primary_info.next().unwrap().parse::<usize>()?;
-        let _rdb_file = read_half.read_rdb_file().await?;
-
-        let write_stream = setup_replica_write_stream(write_half);
-        let primary_info = ClientConnectionInfo {
-            id: ClientId::primary(),
-            address: (host, port).to_socket_addrs()?.next().ok_or_else(|| {
-                anyhow::anyhow!(
-                    "[redis - error] expected valid host and port to connect to primary"
-                )
-            })?,
-            is_read_blocked: Arc::new(AtomicBool::new(false)),
+        let (Some(replication_id), Some(replication_offset)) =
+            (primary_info.next(), primary_info.next())
+        else {
+            return Err(anyhow::anyhow!(
+                "[redis - error] expected a replication ID and offset after 'FULLRESYNC'"
+            ));
         };
 
-        tokio::spawn(async move {
-            loop {
-                let command: RedisCommand = read_half
-                    .read_value()
-                    .await
-                    .and_then(|value| value.try_into())
-                    .context("[redis - error] unable to parse RESP value into command")?;
-
-                let mut write_stream = write_stream.clone();
-                if !command.is_getack() {
-                    write_stream.close();
-                }
-
-                let packet = RedisCommandPacket::new(primary_info.clone(), command, write_stream);
-                if read_half.is_closed() || command_tx.send(packet).await.is_err() {
-                    break;
-                }
-            }
-
-            anyhow::Ok(())
-        });
+        let replication_offset = replication_offset.parse::<usize>()?;
+        let _rdb_file = read_stream.read_rdb_file().await?;
+        *link.replication_id.lock().unwrap() = Some(replication_id.to_string());
+        link.processed_bytes
+            .store(replication_offset, Ordering::SeqCst);
+        Ok(false)
+    } else if let Some(primary_info) = response.strip_prefix("CONTINUE") {
+        // a primary that changed its replication ID since reports the new one
+        if let Some(replication_id) = primary_info.split_ascii_whitespace().next() {
+            *link.replication_id.lock().unwrap() = Some(replication_id.to_string());
+        }
 
-        Ok(())
+        Ok(true)
     } else {
         Err(anyhow::anyhow!(
-            "[redis - error] expected 'FULLRESYNC' from primary but got '{response}'"
+            "[redis - error] expected 'FULLRESYNC' or 'CONTINUE' from primary but got '{response}'"
         ))
     }
 }
 
 fn setup_replica_write_stream(mut write_half: OwnedWriteHalf) -> RedisWriteStream {
-    let (write_tx, mut write_rx) = mpsc::channel::<Bytes>(32);
+    let (write_tx, mut write_rx) = mpsc::unbounded_channel::<Bytes>();
     let write_stream = RedisWriteStream::new(write_tx);
     tokio::spawn(async move {
         while let Some(bytes) = write_rx.recv().await {
@@ -156,3 +365,109 @@ fn setup_replica_write_stream(mut write_half: OwnedWriteHalf) -> RedisWriteStrea
 
     write_stream
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+        sync::Arc,
+    };
+
+    use bytes::Bytes;
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{tcp::OwnedWriteHalf, TcpListener},
+        sync::mpsc,
+    };
+
+    use crate::redis::resp::{command::RedisCommand, encoding, resp_reader::RESPReader, RESPValue};
+
+    use super::PrimaryLink;
+
+    /// Plays the primary's part of a handshake, replying to `PSYNC` with `reply` and
+    /// returning the `PSYNC` the replica sent.
+    async fn accept_replica(listener: &TcpListener, reply: &[u8]) -> (RESPValue, OwnedWriteHalf) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut read_half = RESPReader::new(read_half);
+        for reply in [&b"+PONG\r\n"[..], b"+OK\r\n", b"+OK\r\n"] {
+            read_half.read_value().await.unwrap();
+            write_half.write_all(reply).await.unwrap();
+        }
+
+        let psync = read_half.read_value().await.unwrap();
+        write_half.write_all(reply).await.unwrap();
+        (psync, write_half)
+    }
+
+    #[tokio::test]
+    async fn resolves_hostnames_and_ip_literals() {
+        let localhost = super::resolve("localhost", 6379).await.unwrap();
+        assert!(localhost.ip().is_loopback());
+        assert_eq!(localhost.port(), 6379);
+
+        for (host, ip) in [
+            ("127.0.0.1", Ipv4Addr::LOCALHOST.into()),
+            ("::1", Ipv6Addr::LOCALHOST.into()),
+            ("[::1]", Ipv6Addr::LOCALHOST.into()),
+        ] {
+            assert_eq!(
+                super::resolve(host, 6380).await.unwrap(),
+                SocketAddr::new(ip, 6380)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn names_the_primary_host_that_cannot_be_resolved() {
+        // the `.invalid` top-level domain is reserved to never resolve
+        let err = super::resolve("primary.invalid", 6379).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("[redis - error] unable to resolve primary host 'primary.invalid'"),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn asks_to_continue_from_the_processed_offset_after_a_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let link = Arc::new(PrimaryLink::default());
+        let (command_tx, mut command_rx) = mpsc::channel(4);
+        let handshake = tokio::spawn(super::complete_handshake(
+            6380,
+            ("127.0.0.1", port),
+            link.clone(),
+            false,
+            command_tx,
+        ));
+
+        let (psync, mut write_half) =
+            accept_replica(&listener, b"+FULLRESYNC abc 100\r\n$3\r\nrdb").await;
+        assert_eq!(Bytes::from(psync), encoding::psync("?", -1, false));
+        handshake.await.unwrap().unwrap();
+
+        let set = || {
+            encoding::array(
+                ["SET", "key", "value"]
+                    .into_iter()
+                    .map(encoding::bulk_string)
+                    .collect(),
+            )
+        };
+        write_half.write_all(&Bytes::from(set())).await.unwrap();
+        command_rx.recv().await.unwrap();
+        // the manager would mark the command as processed once it ran it
+        let set_len = Bytes::from(&RedisCommand::try_from(set()).unwrap()).len();
+        link.add_processed_bytes(set_len);
+        drop(write_half);
+
+        let (psync, _write_half) = accept_replica(&listener, b"+CONTINUE\r\n").await;
+        assert_eq!(
+            Bytes::from(psync),
+            encoding::psync("abc", 100 + set_len as i64, false)
+        );
+        link.detach();
+    }
+}
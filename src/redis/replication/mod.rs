@@ -1,23 +1,33 @@
-use std::{collections::HashMap, fmt::Debug, net::SocketAddr, ops::Deref};
+use std::{collections::HashMap, fmt::Debug, ops::Deref, sync::Arc};
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use tokio::sync::mpsc;
 
-use self::acker::Acker;
+use self::{
+    acker::Acker,
+    backlog::{Backlog, BACKLOG_SIZE},
+    failover::Failover,
+    handshake::PrimaryLink,
+};
 
 use super::{
+    log::{self, log},
     manager::RedisCommandPacket,
-    resp::command::RedisCommand,
-    server::{ClientId, RedisWriteStream},
+    random,
+    resp::{command::RedisCommand, encoding},
+    server::{ClientAddress, ClientId, RedisWriteStream},
 };
 
 mod acker;
+mod backlog;
 pub mod command;
+mod failover;
 pub mod handler;
 pub mod handshake;
 
 pub struct ReplicaInfo {
     id: ClientId,
+    address: ClientAddress,
     write_stream: RedisWriteStream,
     acker: Acker,
 }
@@ -34,46 +44,73 @@ impl Debug for ReplicaInfo {
 pub enum RedisReplicationMode {
     Primary {
         replication_id: String,
-        replication_offset: u64,
         replicas: HashMap<ClientId, ReplicaInfo>,
+        /// The replication offset, the number of bytes replicated in this history.
         replicated_bytes: usize,
+        backlog: Box<Backlog>,
+        replicated_db: Option<usize>,
+        getack_offset: Option<usize>,
+        listening_ports: HashMap<ClientId, u16>,
+        failover: Option<Failover>,
     },
     Replica {
         primary_host: String,
         primary_port: u16,
-        processed_bytes: usize,
+        link: Arc<PrimaryLink>,
     },
 }
 
 impl RedisReplicationMode {
-    pub fn primary(replication_id: String) -> Self {
+    /// A primary with a fresh random replication ID, so that no two primaries that were
+    /// started independently share a history.
+    pub fn primary() -> Self {
+        Self::primary_continuing(random::hex_id(), 0)
+    }
+
+    /// A primary that continues an existing history from its offset, e.g. a replica that
+    /// is promoted by a failover.
+    pub fn primary_continuing(replication_id: String, replication_offset: usize) -> Self {
         Self::Primary {
             replication_id,
-            replication_offset: 0,
             replicas: HashMap::default(),
-            replicated_bytes: 0,
+            replicated_bytes: replication_offset,
+            backlog: Box::new(Backlog::new(BACKLOG_SIZE, replication_offset)),
+            replicated_db: Some(0),
+            getack_offset: None,
+            listening_ports: HashMap::default(),
+            failover: None,
         }
     }
 
     pub fn replica(primary_host: String, primary_port: u16) -> Self {
+        Self::replica_with_link(primary_host, primary_port, Arc::default())
+    }
+
+    fn replica_with_link(primary_host: String, primary_port: u16, link: Arc<PrimaryLink>) -> Self {
         Self::Replica {
             primary_host,
             primary_port,
-            processed_bytes: 0,
+            link,
         }
     }
 }
 
 pub struct RedisReplication {
-    address: SocketAddr,
+    listening_port: u16,
     replication_mode: RedisReplicationMode,
+    command_tx: Option<mpsc::Sender<RedisCommandPacket>>,
 }
 
 impl RedisReplication {
-    pub fn new(address: SocketAddr, replication_mode: RedisReplicationMode) -> Self {
+    pub fn new(listening_port: u16, replication_mode: RedisReplicationMode) -> Self {
+        log::set_role(matches!(
+            replication_mode,
+            RedisReplicationMode::Replica { .. }
+        ));
         Self {
-            address,
+            listening_port,
             replication_mode,
+            command_tx: None,
         }
     }
 
@@ -84,28 +121,69 @@ impl RedisReplication {
         if let RedisReplicationMode::Replica {
             primary_host,
             primary_port,
-            ..
+            link,
         } = &self.replication_mode
         {
             handshake::complete_handshake(
-                self.address.port(),
+                self.listening_port,
                 (primary_host.deref(), *primary_port),
+                link.clone(),
+                false,
                 command_tx.clone(),
             )
             .await?;
         }
 
+        // kept around so that the role can change at runtime, e.g. during a failover
+        self.command_tx = Some(command_tx);
         Ok(())
     }
 
-    pub async fn try_replicate(&mut self, bytes: Bytes) -> anyhow::Result<()> {
+    fn switch_mode(&mut self, replication_mode: RedisReplicationMode) {
+        if let RedisReplicationMode::Replica { link, .. } = &self.replication_mode {
+            link.detach();
+        }
+
+        self.replication_mode = replication_mode;
+        log::set_role(self.is_replica());
+    }
+
+    /// Replaces the replication ID of a primary with a fresh one, so that replicas can no
+    /// longer continue the old history with a partial resync. Replicas that are already
+    /// connected are not told about the change and keep streaming, so they should be made
+    /// to reconnect and resync afterwards. A replica has no ID of its own to change.
+    pub fn change_replication_id(&mut self) {
+        if let RedisReplicationMode::Primary { replication_id, .. } = &mut self.replication_mode {
+            *replication_id = random::hex_id();
+            log!(Notice, "changed replication ID to {replication_id}");
+        }
+    }
+
+    pub fn is_replica(&self) -> bool {
+        matches!(self.replication_mode, RedisReplicationMode::Replica { .. })
+    }
+
+    pub async fn try_replicate(&mut self, db: usize, bytes: Bytes) -> anyhow::Result<()> {
+        self.remove_disconnected_replicas();
         if let RedisReplicationMode::Primary {
             ref replicas,
             ref mut replicated_bytes,
+            ref mut backlog,
+            ref mut replicated_db,
             ..
         } = &mut self.replication_mode
         {
+            let bytes = if *replicated_db != Some(db) {
+                *replicated_db = Some(db);
+                let mut select = BytesMut::from(&encoding::select(db)[..]);
+                select.extend_from_slice(&bytes);
+                select.freeze()
+            } else {
+                bytes
+            };
+
             *replicated_bytes += bytes.len();
+            backlog.push(&bytes);
             for replica_info in replicas.values() {
                 replica_info.write_stream.write(bytes.clone()).await?;
             }
@@ -114,18 +192,56 @@ impl RedisReplication {
         Ok(())
     }
 
-    pub fn post_command_hook(&mut self, command: &RedisCommand) {
-        if let RedisReplicationMode::Replica {
-            processed_bytes, ..
-        } = &mut self.replication_mode
+    /// The number of bytes a command adds to the processed offset of a replica, which is
+    /// only the case for commands sent by its primary.
+    pub fn replicated_len(&self, client_id: ClientId, command: &RedisCommand) -> Option<usize> {
+        let is_from_primary = self.is_replica() && client_id == ClientId::primary();
+        is_from_primary.then(|| Bytes::from(command).len())
+    }
+
+    /// Counts a command of the primary towards the processed offset once it was handled,
+    /// whatever its outcome.
+    pub fn post_command_hook(&mut self, replicated_len: Option<usize>) {
+        if let (RedisReplicationMode::Replica { link, .. }, Some(replicated_len)) =
+            (&self.replication_mode, replicated_len)
         {
-            let bytes = Bytes::from(command);
-            *processed_bytes += bytes.len();
+            link.add_processed_bytes(replicated_len);
         }
     }
 
-    fn add_replica(&mut self, replica_info: ReplicaInfo) {
+    /// Forgets the replicas whose connection was dropped. Their acks are then no longer
+    /// waited for, and dropping their `Acker` ends any `WAIT` subscribed to it.
+    fn remove_disconnected_replicas(&mut self) {
         if let RedisReplicationMode::Primary { replicas, .. } = &mut self.replication_mode {
+            replicas.retain(|_, replica_info| {
+                let is_disconnected = replica_info.write_stream.is_disconnected();
+                if is_disconnected {
+                    log!(Notice, "replica at {} disconnected", replica_info.address);
+                }
+
+                !is_disconnected
+            });
+        }
+    }
+
+    fn add_replica(&mut self, replica_info: ReplicaInfo) {
+        if let RedisReplicationMode::Primary {
+            replicas,
+            replicated_db,
+            getack_offset,
+            ..
+        } = &mut self.replication_mode
+        {
+            // the new replica never saw the in-flight `GETACK`, so the next `WAIT` has to
+            // send a fresh one
+            *getack_offset = None;
+
+            // a freshly synced replica starts out on database 0, so the next write has to
+            // be preceded by a `SELECT` unless writes are already targeting database 0
+            if *replicated_db != Some(0) {
+                *replicated_db = None;
+            }
+
             replicas.insert(replica_info.id, replica_info);
         }
     }
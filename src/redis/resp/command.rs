@@ -1,20 +1,317 @@
 use bytes::Bytes;
 use std::time::{Duration, SystemTime};
 
-use crate::redis::replication::command::{InfoSection, RedisReplicationCommand, ReplConfSection};
+use crate::redis::{
+    error::RedisError,
+    pubsub::command::{PubSubSection, RedisPubSubCommand},
+    random,
+    replication::command::{FailoverSection, RedisReplicationCommand, ReplConfSection},
+    store::{
+        geo::{Coordinates, GeoOrigin, GeoSearch, GeoShape, GeoUnit, SortOrder},
+        sort::Sort,
+        sorted_set::Score,
+        stream::{StreamEntryId, StreamTrim, StreamTrimStrategy, XAddEntryId, XReadGroupId},
+    },
+};
 
-use super::RESPValue;
+use super::{command_table, resp_reader::RESPReader, RESPValue};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ConfigSection {
     Get { keys: Vec<Bytes> },
+    Set { parameters: Vec<(Bytes, Bytes)> },
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AclSection {
+    WhoAmI,
+    List,
+    Cat,
+    SetUser { username: Bytes, rules: Vec<Bytes> },
+    GetUser { username: Bytes },
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SlowLogSection {
+    Get { count: Option<i64> },
+    Len,
+    Reset,
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LatencySection {
+    History { event: Bytes },
+    Latest,
+    Reset { events: Vec<Bytes> },
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ClientSection {
+    NoEvict { is_enabled: bool },
+    NoTouch { is_enabled: bool },
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ClusterSection {
+    Info,
+    MyId,
+    Slots,
+    Shards,
+    Nodes,
+    Help,
+}
+
+/// The subcommands of `FUNCTION` that clients probe for. Scripting is not supported, so
+/// there are never any functions to list or dump, and none can be loaded.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FunctionSection {
+    List,
+    Dump,
+    Load,
+    Help,
+}
+
+/// The subcommands of `SCRIPT`. Scripts are not run, so none are ever cached.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ScriptSection {
+    Load,
+    Exists { sha1s: Vec<Bytes> },
+    Flush,
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CommandSection {
+    Info { names: Vec<Bytes> },
+    Count,
+    Docs { names: Vec<Bytes> },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DebugSection {
+    Object {
+        key: Bytes,
+    },
+    ChangeReplId,
+    /// Reports the replication offset and the offset each replica acknowledged.
+    ReplicateOffset,
+    Reload,
+    StringMatchLen {
+        pattern: Bytes,
+        string: Bytes,
+    },
+    SetActiveExpire {
+        is_enabled: bool,
+    },
+    /// Dumps the memory allocator's state in Redis, which does not apply here.
+    Jmap,
+    /// Stalls the whole server for the duration, like the single thread of Redis would.
+    Sleep {
+        duration: Duration,
+    },
+    /// Any other subcommand, such as `QUICKLIST-PACKED-THRESHOLD` or `LISTPACK`. These
+    /// tune or dump internals this server does not have, so they are accepted without
+    /// doing anything to let test suites that send them carry on.
+    Unsupported {
+        subcommand: Bytes,
+        args: Vec<Bytes>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InfoSection {
+    Server,
+    Clients,
+    Persistence,
+    Stats,
+    Replication,
+    Default,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RedisServerCommand {
-    Ping,
-    Echo { message: Bytes },
-    Config { section: ConfigSection },
+    Ping {
+        message: Option<Bytes>,
+    },
+    Echo {
+        message: Bytes,
+    },
+    Config {
+        section: ConfigSection,
+    },
+    Select {
+        index: usize,
+    },
+    Lolwut {
+        version: Option<usize>,
+    },
+    Quit,
+    Auth {
+        username: Option<Bytes>,
+        password: Bytes,
+    },
+    Acl {
+        section: AclSection,
+    },
+    Info {
+        section: InfoSection,
+    },
+    LastSave,
+    Debug {
+        section: DebugSection,
+    },
+    Command {
+        section: CommandSection,
+    },
+    SlowLog {
+        section: SlowLogSection,
+    },
+    Latency {
+        section: LatencySection,
+    },
+    Cluster {
+        section: ClusterSection,
+    },
+    Client {
+        section: ClientSection,
+    },
+    Function {
+        section: FunctionSection,
+    },
+    /// Calls a function, which always fails since no functions can be loaded. The
+    /// arguments are kept as sent, starting with the name of the function.
+    FCall {
+        is_read_only: bool,
+        args: Vec<Bytes>,
+    },
+    /// Runs a Lua script, which always fails since there is no Lua interpreter.
+    Eval {
+        script: Bytes,
+        keys: Vec<Bytes>,
+        args: Vec<Bytes>,
+    },
+    /// Runs a cached script, which is never found since scripts cannot be loaded.
+    EvalSha {
+        sha1: Bytes,
+        keys: Vec<Bytes>,
+        args: Vec<Bytes>,
+    },
+    Script {
+        section: ScriptSection,
+    },
+    /// Switches the connection to the given RESP version, or keeps it without one.
+    Hello {
+        protocol_version: Option<i64>,
+    },
+    Monitor,
+}
+
+impl RedisServerCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ping { .. } => "ping",
+            Self::Echo { .. } => "echo",
+            Self::Config { .. } => "config",
+            Self::Select { .. } => "select",
+            Self::Lolwut { .. } => "lolwut",
+            Self::Quit => "quit",
+            Self::Auth { .. } => "auth",
+            Self::Acl { .. } => "acl",
+            Self::Info { .. } => "info",
+            Self::LastSave => "lastsave",
+            Self::Debug { .. } => "debug",
+            Self::Command { .. } => "command",
+            Self::SlowLog { .. } => "slowlog",
+            Self::Latency { .. } => "latency",
+            Self::Cluster { .. } => "cluster",
+            Self::Client { .. } => "client",
+            Self::Function { .. } => "function",
+            Self::Eval { .. } => "eval",
+            Self::EvalSha { .. } => "evalsha",
+            Self::Script { .. } => "script",
+            Self::FCall {
+                is_read_only: false,
+                ..
+            } => "fcall",
+            Self::FCall {
+                is_read_only: true, ..
+            } => "fcall_ro",
+            Self::Hello { .. } => "hello",
+            Self::Monitor => "monitor",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExpiryOption {
+    At(SystemTime),
+    Persist,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ObjectSection {
+    Encoding { key: Bytes },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum XInfoSection {
+    Stream { key: Bytes },
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum XGroupSection {
+    Create {
+        key: Bytes,
+        group: Bytes,
+        /// The ID after which entries are delivered, or `None` for `$`, the last ID of
+        /// the stream.
+        id: Option<StreamEntryId>,
+        mkstream: bool,
+    },
+    Help,
+}
+
+/// The unit of the range given to `BITCOUNT`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BitOrByte {
+    Bit,
+    Byte,
+}
+
+/// How `SINTERSTORE`, `SUNIONSTORE` and `SDIFFSTORE` combine their sets.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SetOperation {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// The end of a sorted set that `ZPOPMIN`, `ZPOPMAX` and their blocking variants pop from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MinOrMax {
+    Min,
+    Max,
+}
+
+impl MinOrMax {
+    const NAMES: [(&'static str, Self); 2] = [("min", Self::Min), ("max", Self::Max)];
+}
+
+/// The end of a list that elements are pushed to or popped from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl ListEnd {
+    const NAMES: [(&'static str, Self); 2] = [("left", Self::Left), ("right", Self::Right)];
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -22,6 +319,19 @@ pub enum RedisStoreCommand {
     Get {
         key: Bytes,
     },
+    MGet {
+        keys: Vec<Bytes>,
+    },
+    GetEx {
+        key: Bytes,
+        expiry_option: Option<ExpiryOption>,
+    },
+    GetDel {
+        key: Bytes,
+    },
+    Del {
+        keys: Vec<Bytes>,
+    },
     Set {
         key: Bytes,
         value: Bytes,
@@ -35,14 +345,449 @@ pub enum RedisStoreCommand {
     },
     XAdd {
         key: Bytes,
-        entry_id: Bytes,
+        entry_id: XAddEntryId,
+        no_mkstream: bool,
+        trim: Option<StreamTrim>,
+        fields: Vec<(Bytes, Bytes)>,
+    },
+    XTrim {
+        key: Bytes,
+        trim: StreamTrim,
+    },
+    XInfo {
+        section: XInfoSection,
+    },
+    XGroup {
+        section: XGroupSection,
+    },
+    XReadGroup {
+        group: Bytes,
+        consumer: Bytes,
+        count: Option<usize>,
+        no_ack: bool,
+        /// The keys of the streams to read, each with the ID in `ids` at its position.
+        keys: Vec<Bytes>,
+        ids: Vec<XReadGroupId>,
+    },
+    XAck {
+        key: Bytes,
+        group: Bytes,
+        ids: Vec<StreamEntryId>,
+    },
+    Object {
+        section: ObjectSection,
+    },
+    SwapDb {
+        index: usize,
+        other_index: usize,
+    },
+    Move {
+        key: Bytes,
+        db: usize,
+    },
+    SetBit {
+        key: Bytes,
+        offset: u64,
+        bit: bool,
+    },
+    GetBit {
+        key: Bytes,
+        offset: u64,
+    },
+    BitCount {
+        key: Bytes,
+        range: Option<(i64, i64, BitOrByte)>,
+    },
+    SAdd {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    SMembers {
+        key: Bytes,
+    },
+    SetOperationStore {
+        operation: SetOperation,
+        destination: Bytes,
+        keys: Vec<Bytes>,
+    },
+    /// Counts the members of the intersection of the sets, stopping once it reaches
+    /// `limit`.
+    SInterCard {
+        keys: Vec<Bytes>,
+        limit: Option<usize>,
+    },
+    SMove {
+        source: Bytes,
+        destination: Bytes,
+        member: Bytes,
+    },
+    /// Pushes the elements one after the other, so that `LPUSH` leaves them in reverse.
+    ListPush {
+        key: Bytes,
+        end: ListEnd,
+        elements: Vec<Bytes>,
+    },
+    ListPop {
+        key: Bytes,
+        end: ListEnd,
+        count: Option<usize>,
+    },
+    LLen {
+        key: Bytes,
+    },
+    LRange {
+        key: Bytes,
+        start: i64,
+        stop: i64,
+    },
+    /// Pops up to `count` elements from the first of `keys` holding a list.
+    LMPop {
+        keys: Vec<Bytes>,
+        end: ListEnd,
+        count: Option<usize>,
+    },
+    HSet {
+        key: Bytes,
         fields: Vec<(Bytes, Bytes)>,
     },
+    HGet {
+        key: Bytes,
+        field: Bytes,
+    },
+    HRandField {
+        key: Bytes,
+        count: Option<i64>,
+        with_values: bool,
+    },
+    ZAdd {
+        key: Bytes,
+        members: Vec<(Score, Bytes)>,
+    },
+    ZScore {
+        key: Bytes,
+        member: Bytes,
+    },
+    ZRandMember {
+        key: Bytes,
+        count: Option<i64>,
+        with_scores: bool,
+    },
+    ZPop {
+        key: Bytes,
+        count: Option<usize>,
+        end: MinOrMax,
+    },
+    /// Pops up to `count` members from the first of `keys` holding a sorted set.
+    ZMPop {
+        keys: Vec<Bytes>,
+        end: MinOrMax,
+        count: Option<usize>,
+    },
+    PfAdd {
+        key: Bytes,
+        elements: Vec<Bytes>,
+    },
+    PfCount {
+        keys: Vec<Bytes>,
+    },
+    PfMerge {
+        destination: Bytes,
+        keys: Vec<Bytes>,
+    },
+    BZPop {
+        keys: Vec<Bytes>,
+        /// How long to block for, or `None` to block until a member arrives.
+        timeout: Option<Duration>,
+        end: MinOrMax,
+    },
+    /// Like `ZMPOP`, but blocks until one of the sorted sets has members.
+    BZMPop {
+        keys: Vec<Bytes>,
+        /// How long to block for, or `None` to block until a member arrives.
+        timeout: Option<Duration>,
+        end: MinOrMax,
+        count: Option<usize>,
+    },
+    BLMPop {
+        keys: Vec<Bytes>,
+        /// How long to block for, or `None` to block until an element arrives.
+        timeout: Option<Duration>,
+        end: ListEnd,
+        count: Option<usize>,
+    },
+    GeoAdd {
+        key: Bytes,
+        points: Vec<(Coordinates, Bytes)>,
+    },
+    GeoPos {
+        key: Bytes,
+        members: Vec<Bytes>,
+    },
+    GeoDist {
+        key: Bytes,
+        member: Bytes,
+        other_member: Bytes,
+        unit: GeoUnit,
+    },
+    GeoSearch {
+        key: Bytes,
+        search: GeoSearch,
+    },
+    Sort {
+        key: Bytes,
+        sort: Sort,
+    },
 }
 
 impl RedisStoreCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Get { .. } => "get",
+            Self::MGet { .. } => "mget",
+            Self::GetEx { .. } => "getex",
+            Self::GetDel { .. } => "getdel",
+            Self::Del { .. } => "del",
+            Self::Set { .. } => "set",
+            Self::Keys { .. } => "keys",
+            Self::Type { .. } => "type",
+            Self::XAdd { .. } => "xadd",
+            Self::XTrim { .. } => "xtrim",
+            Self::XInfo { .. } => "xinfo",
+            Self::XGroup { .. } => "xgroup",
+            Self::XReadGroup { .. } => "xreadgroup",
+            Self::XAck { .. } => "xack",
+            Self::Object { .. } => "object",
+            Self::SwapDb { .. } => "swapdb",
+            Self::Move { .. } => "move",
+            Self::SetBit { .. } => "setbit",
+            Self::GetBit { .. } => "getbit",
+            Self::BitCount { .. } => "bitcount",
+            Self::SAdd { .. } => "sadd",
+            Self::SMembers { .. } => "smembers",
+            Self::SetOperationStore { operation, .. } => match operation {
+                SetOperation::Inter => "sinterstore",
+                SetOperation::Union => "sunionstore",
+                SetOperation::Diff => "sdiffstore",
+            },
+            Self::SInterCard { .. } => "sintercard",
+            Self::SMove { .. } => "smove",
+            Self::ListPush {
+                end: ListEnd::Left, ..
+            } => "lpush",
+            Self::ListPush {
+                end: ListEnd::Right,
+                ..
+            } => "rpush",
+            Self::ListPop {
+                end: ListEnd::Left, ..
+            } => "lpop",
+            Self::ListPop {
+                end: ListEnd::Right,
+                ..
+            } => "rpop",
+            Self::LLen { .. } => "llen",
+            Self::LRange { .. } => "lrange",
+            Self::LMPop { .. } => "lmpop",
+            Self::HSet { .. } => "hset",
+            Self::HGet { .. } => "hget",
+            Self::HRandField { .. } => "hrandfield",
+            Self::ZAdd { .. } => "zadd",
+            Self::ZScore { .. } => "zscore",
+            Self::ZRandMember { .. } => "zrandmember",
+            Self::ZPop {
+                end: MinOrMax::Min, ..
+            } => "zpopmin",
+            Self::ZPop {
+                end: MinOrMax::Max, ..
+            } => "zpopmax",
+            Self::ZMPop { .. } => "zmpop",
+            Self::PfAdd { .. } => "pfadd",
+            Self::PfCount { .. } => "pfcount",
+            Self::PfMerge { .. } => "pfmerge",
+            Self::BZPop {
+                end: MinOrMax::Min, ..
+            } => "bzpopmin",
+            Self::BZPop {
+                end: MinOrMax::Max, ..
+            } => "bzpopmax",
+            Self::BZMPop { .. } => "bzmpop",
+            Self::BLMPop { .. } => "blmpop",
+            Self::GeoAdd { .. } => "geoadd",
+            Self::GeoPos { .. } => "geopos",
+            Self::GeoDist { .. } => "geodist",
+            Self::GeoSearch { .. } => "geosearch",
+            Self::Sort { .. } => "sort",
+        }
+    }
+
+    /// Whether the command can change the dataset, which makes it count towards save
+    /// points and get replicated. Every command is listed, so that a new one cannot be
+    /// added without deciding whether it writes.
     pub fn is_write(&self) -> bool {
-        matches!(self, Self::Set { .. })
+        match self {
+            Self::Set { .. }
+            | Self::GetDel { .. }
+            | Self::Del { .. }
+            | Self::XAdd { .. }
+            | Self::XTrim { .. }
+            | Self::XGroup {
+                section: XGroupSection::Create { .. },
+            }
+            | Self::XReadGroup { .. }
+            | Self::XAck { .. }
+            | Self::SwapDb { .. }
+            | Self::Move { .. }
+            | Self::SetBit { .. }
+            | Self::SAdd { .. }
+            | Self::SetOperationStore { .. }
+            | Self::SMove { .. }
+            | Self::ListPush { .. }
+            | Self::ListPop { .. }
+            | Self::LMPop { .. }
+            | Self::HSet { .. }
+            | Self::ZAdd { .. }
+            | Self::ZPop { .. }
+            | Self::ZMPop { .. }
+            | Self::PfAdd { .. }
+            | Self::PfMerge { .. }
+            | Self::GeoAdd { .. } => true,
+            Self::GetEx { expiry_option, .. } => expiry_option.is_some(),
+            // the pop a blocking pop ends up doing is counted and replicated on its own,
+            // whenever that happens
+            Self::BZPop { .. } | Self::BZMPop { .. } | Self::BLMPop { .. } => false,
+            Self::Get { .. }
+            | Self::MGet { .. }
+            | Self::Keys { .. }
+            | Self::Type { .. }
+            | Self::XInfo { .. }
+            | Self::XGroup {
+                section: XGroupSection::Help,
+            }
+            | Self::Object { .. }
+            | Self::GetBit { .. }
+            | Self::BitCount { .. }
+            | Self::SMembers { .. }
+            | Self::SInterCard { .. }
+            | Self::LLen { .. }
+            | Self::LRange { .. }
+            | Self::HGet { .. }
+            | Self::HRandField { .. }
+            | Self::ZScore { .. }
+            | Self::ZRandMember { .. }
+            | Self::PfCount { .. }
+            | Self::GeoPos { .. }
+            | Self::GeoDist { .. }
+            | Self::GeoSearch { .. } => false,
+            Self::Sort { sort, .. } => sort.store.is_some(),
+        }
+    }
+
+    /// The keys whose lookups count as keyspace hits or misses. Like in Redis, these are
+    /// the keys a command reads, but not the ones it writes to.
+    pub fn read_keys(&self) -> &[Bytes] {
+        match self {
+            Self::Get { key }
+            | Self::GetEx { key, .. }
+            | Self::GetDel { key }
+            | Self::Type { key }
+            | Self::XInfo {
+                section: XInfoSection::Stream { key },
+            }
+            | Self::GetBit { key, .. }
+            | Self::BitCount { key, .. }
+            | Self::SMembers { key }
+            | Self::LLen { key }
+            | Self::LRange { key, .. }
+            | Self::HGet { key, .. }
+            | Self::HRandField { key, .. }
+            | Self::ZScore { key, .. }
+            | Self::ZRandMember { key, .. }
+            | Self::GeoPos { key, .. }
+            | Self::GeoDist { key, .. }
+            | Self::GeoSearch { key, .. }
+            | Self::Sort { key, .. } => std::slice::from_ref(key),
+            Self::MGet { keys }
+            | Self::SetOperationStore { keys, .. }
+            | Self::SInterCard { keys, .. }
+            | Self::XReadGroup { keys, .. }
+            | Self::PfCount { keys }
+            | Self::PfMerge { keys, .. } => keys,
+            Self::Set { .. }
+            | Self::Del { .. }
+            | Self::Keys { .. }
+            | Self::XAdd { .. }
+            | Self::XTrim { .. }
+            | Self::XInfo {
+                section: XInfoSection::Help,
+            }
+            | Self::XGroup { .. }
+            | Self::XAck { .. }
+            | Self::Object { .. }
+            | Self::SwapDb { .. }
+            | Self::Move { .. }
+            | Self::SetBit { .. }
+            | Self::SAdd { .. }
+            | Self::SMove { .. }
+            | Self::ListPush { .. }
+            | Self::ListPop { .. }
+            | Self::LMPop { .. }
+            | Self::HSet { .. }
+            | Self::ZAdd { .. }
+            | Self::ZPop { .. }
+            | Self::ZMPop { .. }
+            | Self::PfAdd { .. }
+            | Self::BZPop { .. }
+            | Self::BZMPop { .. }
+            | Self::BLMPop { .. }
+            | Self::GeoAdd { .. } => &[],
+        }
+    }
+}
+
+/// A problem with the arguments of a command that is replied to the client. Unlike
+/// other parse errors, it does not close the connection.
+#[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
+pub enum CommandError {
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongArity(&'static str),
+    #[error("ERR unknown command '{name}', with args beginning with: {args}")]
+    UnknownCommand { name: String, args: String },
+    #[error("ERR {message}")]
+    Syntax {
+        command_name: &'static str,
+        message: String,
+    },
+}
+
+impl CommandError {
+    pub fn command_name(&self) -> &'static str {
+        match self {
+            Self::WrongArity(command_name) | Self::Syntax { command_name, .. } => command_name,
+            Self::UnknownCommand { .. } => "unknown",
+        }
+    }
+
+    /// Turns a parse error of the given parts into the error replied to the client.
+    fn from_parse_error(parts: &[Bytes], err: anyhow::Error) -> Self {
+        match err.downcast::<CommandError>() {
+            Ok(error) => error,
+            Err(err) => match command_table::lookup(&parts[0]) {
+                Some(spec) => Self::Syntax {
+                    command_name: spec.name,
+                    message: err
+                        .to_string()
+                        .trim_start_matches("[redis - error] ")
+                        .replace(['\r', '\n'], " "),
+                },
+                None => Self::UnknownCommand {
+                    name: String::from_utf8_lossy(&parts[0]).into_owned(),
+                    args: parts[1..]
+                        .iter()
+                        .map(|arg| format!("'{}' ", String::from_utf8_lossy(arg)))
+                        .collect(),
+                },
+            },
+        }
     }
 }
 
@@ -51,9 +796,114 @@ pub enum RedisCommand {
     Store(RedisStoreCommand),
     Server(RedisServerCommand),
     Replication(RedisReplicationCommand),
+    PubSub(RedisPubSubCommand),
+    /// A command that is rejected with an error reply, along with its parts as received.
+    Invalid {
+        error: CommandError,
+        args: Vec<Bytes>,
+    },
 }
 
 impl RedisCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Store(command) => command.name(),
+            Self::Server(command) => command.name(),
+            Self::Replication(command) => command.name(),
+            Self::PubSub(command) => command.name(),
+            Self::Invalid { error, .. } => error.command_name(),
+        }
+    }
+
+    /// Whether the command can only be run by an authenticated connection.
+    pub fn requires_auth(&self) -> bool {
+        !matches!(
+            self,
+            Self::Server(RedisServerCommand::Auth { .. } | RedisServerCommand::Quit)
+        )
+    }
+
+    /// Whether the command can be run by a connection that is subscribed to channels.
+    pub fn is_allowed_when_subscribed(&self) -> bool {
+        matches!(
+            self,
+            Self::PubSub(
+                RedisPubSubCommand::Subscribe { .. }
+                    | RedisPubSubCommand::Unsubscribe { .. }
+                    | RedisPubSubCommand::SSubscribe { .. }
+                    | RedisPubSubCommand::SUnsubscribe { .. }
+            ) | Self::Server(RedisServerCommand::Ping { .. } | RedisServerCommand::Quit)
+        )
+    }
+
+    /// Whether the command can be run while the dataset is loaded at startup. Only
+    /// commands that read or write keys have to wait for it, including the `DEBUG`
+    /// subcommands that do, since `DEBUG RELOAD` would save the partial dataset over the
+    /// file being loaded.
+    pub fn is_allowed_while_loading(&self) -> bool {
+        !matches!(
+            self,
+            Self::Store(_)
+                | Self::Server(RedisServerCommand::Debug {
+                    section: DebugSection::Reload | DebugSection::Object { .. },
+                })
+        )
+    }
+
+    /// Whether the command administers the server, which keeps it out of `MONITOR`.
+    /// Containers such as `CONFIG` have no flags in the table, so their subcommands are
+    /// checked here.
+    pub fn is_admin(&self) -> bool {
+        match self {
+            Self::Server(RedisServerCommand::Acl { section }) => !matches!(
+                section,
+                AclSection::WhoAmI | AclSection::Cat | AclSection::Help
+            ),
+            Self::Server(RedisServerCommand::Config { section }) => {
+                !matches!(section, ConfigSection::Help)
+            }
+            Self::Server(RedisServerCommand::SlowLog { section }) => {
+                !matches!(section, SlowLogSection::Help)
+            }
+            Self::Server(RedisServerCommand::Latency { section }) => {
+                !matches!(section, LatencySection::Help)
+            }
+            Self::Server(RedisServerCommand::Client { section }) => {
+                matches!(section, ClientSection::NoEvict { .. })
+            }
+            _ => command_table::lookup(self.name().as_bytes())
+                .is_some_and(|spec| spec.flags.contains(&"admin")),
+        }
+    }
+
+    /// The arguments of the command as they were sent, with passwords redacted for
+    /// `SLOWLOG` and `MONITOR`.
+    pub async fn redacted_args(&self) -> anyhow::Result<Vec<Bytes>> {
+        let encoded = Bytes::from(self);
+        let args = RESPReader::new(&encoded[..])
+            .read_value()
+            .await?
+            .into_array()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|arg| match arg {
+                RESPValue::BulkString(arg) => Some(arg),
+                _ => None,
+            });
+
+        let is_auth = matches!(self, Self::Server(RedisServerCommand::Auth { .. }));
+        Ok(args
+            .enumerate()
+            .map(|(index, arg)| {
+                if is_auth && index > 0 {
+                    Bytes::from_static(b"(redacted)")
+                } else {
+                    arg
+                }
+            })
+            .collect())
+    }
+
     pub fn is_getack(&self) -> bool {
         matches!(
             self,
@@ -64,22 +914,30 @@ impl RedisCommand {
     }
 }
 
+/// Reads the parts of a command in order. The parts are kept so that a rejected command
+/// can still be encoded as it was received.
 struct CommandParser {
     parts: Vec<Bytes>,
+    position: usize,
 }
 
 impl CommandParser {
-    fn new(mut parts: Vec<Bytes>) -> Self {
-        parts.reverse();
-        Self { parts }
+    fn new(parts: Vec<Bytes>) -> Self {
+        Self { parts, position: 0 }
+    }
+
+    fn peek(&self) -> Option<&Bytes> {
+        self.parts.get(self.position)
     }
 
     fn parse_next(&mut self) -> Option<Bytes> {
-        self.parts.pop()
+        let arg = self.peek()?.clone();
+        self.position += 1;
+        Some(arg)
     }
 
     fn expect_arg(&mut self, command_name: &str, arg_name: &str) -> anyhow::Result<Bytes> {
-        if let Some(arg) = self.parts.pop() {
+        if let Some(arg) = self.parse_next() {
             Ok(arg)
         } else {
             Err(anyhow::anyhow!(
@@ -88,53 +946,384 @@ impl CommandParser {
         }
     }
 
-    fn attempt_named_arg(&mut self, command_name: &str, arg_name: &str) -> Option<Bytes> {
-        match self.parts.last() {
-            Some(arg) if arg == arg_name.as_bytes() => {
-                self.parts.pop();
-                self.expect_arg(command_name, arg_name).ok()
-            }
-            _ => None,
+    fn expect_number<T: std::str::FromStr>(
+        &mut self,
+        command_name: &str,
+        arg_name: &str,
+    ) -> anyhow::Result<T> {
+        let arg = self.expect_arg(command_name, arg_name)?;
+        std::str::from_utf8(&arg)
+            .ok()
+            .and_then(|arg| arg.parse().ok())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "[redis - error] argument '{arg_name}' for command '{command_name}' must be a valid number"
+                )
+            })
+    }
+
+    /// The count of a `*RANDFIELD` or `*RANDMEMBER` command, where a negative count picks
+    /// items that may repeat, so its reply grows with the count.
+    fn expect_random_count(&mut self, command_name: &str) -> anyhow::Result<i64> {
+        let count = self.expect_number::<i64>(command_name, "count")?;
+        if count < 0 && count.unsigned_abs() > random::MAX_REPEATED_PICKS {
+            return Err(anyhow::anyhow!("[redis - error] value is out of range"));
         }
+
+        Ok(count)
     }
 
-    fn attempt_flag<T>(&mut self, mapper: impl Fn(&[u8]) -> Option<T>) -> Option<T> {
-        self.parts.last().and_then(|arg| mapper(arg))
+    /// The keys and the remaining arguments of a script, as in `EVAL script 1 key arg`.
+    fn expect_script_args(
+        &mut self,
+        command_name: &str,
+    ) -> anyhow::Result<(Vec<Bytes>, Vec<Bytes>)> {
+        let numkeys = self.expect_number::<usize>(command_name, "numkeys")?;
+        let keys = (0..numkeys)
+            .map(|_| self.expect_arg(command_name, "key"))
+            .collect::<anyhow::Result<_>>()?;
+        let mut args = vec![];
+        while let Some(arg) = self.parse_next() {
+            args.push(arg);
+        }
+
+        Ok((keys, args))
     }
 
-    fn is_finished(&self) -> bool {
-        self.parts.is_empty()
+    /// The keys of a command that first says how many it takes, as in `ZMPOP 2 a b MIN`.
+    fn expect_keys(&mut self, command_name: &str) -> anyhow::Result<Vec<Bytes>> {
+        let numkeys = self.expect_number::<usize>(command_name, "numkeys")?;
+        anyhow::ensure!(
+            numkeys > 0,
+            "[redis - error] argument 'numkeys' for command '{command_name}' must be greater than 0"
+        );
+
+        (0..numkeys)
+            .map(|_| self.expect_arg(command_name, "key"))
+            .collect()
     }
-}
 
-impl TryFrom<RESPValue> for RedisCommand {
-    type Error = anyhow::Error;
+    /// The keys, end and count of `ZMPOP`, `LMPOP` and their blocking variants, as in
+    /// `2 a b MIN COUNT 10`, where the end is named by one of `ends`.
+    fn expect_multi_pop<E: Copy>(
+        &mut self,
+        command_name: &str,
+        ends: [(&str, E); 2],
+    ) -> anyhow::Result<(Vec<Bytes>, E, Option<usize>)> {
+        let keys = self.expect_keys(command_name)?;
+        let end = self.parse_next().and_then(|end| {
+            ends.iter()
+                .find(|(name, _)| end.eq_ignore_ascii_case(name.as_bytes()))
+                .map(|(_, end)| *end)
+        });
+        let Some(end) = end else {
+            return Err(anyhow::anyhow!(
+                "[redis - error] command '{command_name}' requires either '{}' or '{}'",
+                ends[0].0,
+                ends[1].0
+            ));
+        };
 
-    fn try_from(value: RESPValue) -> Result<Self, Self::Error> {
-        let command_parts = value
-            .into_array()
-            .map(|values| {
-                values
-                    .into_iter()
-                    .filter_map(|value| value.into_bulk_string())
-                    .collect::<Vec<_>>()
-            })
+        let count = match self.parse_next() {
+            Some(arg) if arg.eq_ignore_ascii_case(b"count") => {
+                let count = self.expect_number::<usize>(command_name, "count")?;
+                anyhow::ensure!(
+                    count > 0,
+                    "[redis - error] argument 'count' for command '{command_name}' must be greater than 0"
+                );
+                Some(count)
+            }
+            None => None,
+            Some(_) => {
+                return Err(anyhow::anyhow!(
+                    "[redis - error] unknown argument found for command '{command_name}'"
+                ))
+            }
+        };
+
+        Ok((keys, end, count))
+    }
+
+    /// The timeout of a blocking command in seconds, where 0 blocks until it is served.
+    /// Like in Redis, it must fit in a signed 64-bit number of milliseconds.
+    fn timeout(command_name: &str, arg: &[u8]) -> anyhow::Result<Option<Duration>> {
+        const MAX_TIMEOUT: Duration = Duration::from_millis(i64::MAX as u64);
+
+        let timeout = std::str::from_utf8(arg)
+            .ok()
+            .and_then(|timeout| timeout.parse::<f64>().ok())
+            .filter(|timeout| timeout.is_finite() && *timeout >= 0.0)
             .ok_or_else(|| {
-                anyhow::anyhow!("[redis - error] expected command to be an array of bulk strings")
+                anyhow::anyhow!(
+                    "[redis - error] argument 'timeout' for command '{command_name}' must be a non-negative number"
+                )
             })?;
 
-        if command_parts.is_empty() {
-            return Err(anyhow::anyhow!(
-                "[redis - error] expected client input to be a non-empty array of bulk strings"
-            ));
+        let duration = Duration::try_from_secs_f64(timeout)
+            .ok()
+            .filter(|duration| *duration <= MAX_TIMEOUT)
+            .ok_or_else(|| anyhow::anyhow!("[redis - error] timeout is out of range"))?;
+        Ok((timeout > 0.0).then_some(duration))
+    }
+
+    /// An `on` or `off` argument, in any case.
+    fn expect_switch(&mut self, command_name: &str, arg_name: &str) -> anyhow::Result<bool> {
+        let arg = self.expect_arg(command_name, arg_name)?;
+        match &*arg.to_ascii_lowercase() {
+            b"on" => Ok(true),
+            b"off" => Ok(false),
+            _ => Err(anyhow::anyhow!(
+                "[redis - error] argument '{arg_name}' for command '{command_name}' must be 'on' or 'off'"
+            )),
         }
+    }
 
-        let mut parser = CommandParser::new(command_parts);
-        let command_name = parser.parse_next().unwrap().to_ascii_lowercase();
-        match &*command_name {
-            b"get" => parser
+    fn attempt_named_arg(&mut self, command_name: &str, arg_name: &str) -> Option<Bytes> {
+        match self.peek() {
+            Some(arg) if arg == arg_name.as_bytes() => {
+                self.parse_next();
+                self.expect_arg(command_name, arg_name).ok()
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_stream_trim(
+        &mut self,
+        command_name: &str,
+        strategy_name: &[u8],
+    ) -> anyhow::Result<StreamTrim> {
+        let approximate = match self.peek().map(|arg| &arg[..]) {
+            Some(b"~") => {
+                self.parse_next();
+                true
+            }
+            Some(b"=") => {
+                self.parse_next();
+                false
+            }
+            _ => false,
+        };
+
+        let strategy = if strategy_name == b"maxlen" {
+            StreamTrimStrategy::MaxLen(self.expect_number(command_name, "threshold")?)
+        } else {
+            let threshold = self.expect_arg(command_name, "threshold")?;
+            StreamTrimStrategy::MinId(StreamEntryId::parse(&threshold).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "[redis - error] invalid stream ID specified for argument 'minid' for command '{command_name}'"
+                )
+            })?)
+        };
+
+        let limit = match self.peek() {
+            Some(arg) if arg.eq_ignore_ascii_case(b"limit") => {
+                self.parse_next();
+                Some(self.expect_number(command_name, "count")?)
+            }
+            _ => None,
+        };
+
+        // like in Redis, only approximate trims can be limited
+        if limit.is_some() && !approximate {
+            return Err(anyhow::anyhow!(
+                "[redis - error] syntax error, LIMIT cannot be used without the special ~ option"
+            ));
+        }
+
+        Ok(StreamTrim {
+            strategy,
+            approximate,
+            limit,
+        })
+    }
+
+    fn expect_coordinates(&mut self, command_name: &str) -> anyhow::Result<Coordinates> {
+        let longitude = self.expect_arg(command_name, "longitude")?;
+        let latitude = self.expect_arg(command_name, "latitude")?;
+        Coordinates::parse(&longitude, &latitude).ok_or_else(|| {
+            anyhow::anyhow!(
+                "[redis - error] arguments 'longitude' and 'latitude' for command '{command_name}' must be valid floats"
+            )
+        })
+    }
+
+    fn expect_distance(&mut self, command_name: &str, arg_name: &str) -> anyhow::Result<f64> {
+        Some(self.expect_number::<f64>(command_name, arg_name)?)
+            .filter(|distance| distance.is_finite() && *distance >= 0.0)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "[redis - error] argument '{arg_name}' for command '{command_name}' must be a non-negative number"
+                )
+            })
+    }
+
+    fn expect_geo_unit(&mut self, command_name: &str) -> anyhow::Result<GeoUnit> {
+        let unit = self.expect_arg(command_name, "unit")?;
+        GeoUnit::parse(&unit).ok_or_else(|| {
+            anyhow::anyhow!(
+                "[redis - error] unsupported unit provided for command '{command_name}', please use m, km, ft, mi"
+            )
+        })
+    }
+
+    fn attempt_flag<T>(&mut self, mapper: impl Fn(&[u8]) -> Option<T>) -> Option<T> {
+        self.peek().and_then(|arg| mapper(arg))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.position >= self.parts.len()
+    }
+
+    fn into_parts(self) -> Vec<Bytes> {
+        self.parts
+    }
+}
+
+impl TryFrom<RESPValue> for RedisCommand {
+    type Error = anyhow::Error;
+
+    fn try_from(value: RESPValue) -> Result<Self, Self::Error> {
+        let mut parser = CommandParser::from_value(value)?;
+        match Self::parse(&mut parser) {
+            Err(err) => match err.downcast::<CommandError>() {
+                Ok(error) => Ok(RedisCommand::Invalid {
+                    error,
+                    args: parser.into_parts(),
+                }),
+                Err(err) => Err(err),
+            },
+            command => command,
+        }
+    }
+}
+
+impl RedisCommand {
+    /// Parses a command sent by a client. Unlike [`RedisCommand::try_from`], unknown
+    /// commands and invalid arguments become [`RedisCommand::Invalid`] so that the
+    /// client is replied to and can keep using its connection.
+    pub fn from_client(value: RESPValue) -> Result<Self, RedisError> {
+        let mut parser = CommandParser::from_value(value).map_err(RedisError::Transport)?;
+        match Self::parse(&mut parser) {
+            Err(err) => {
+                let args = parser.into_parts();
+                Ok(RedisCommand::Invalid {
+                    error: CommandError::from_parse_error(&args, err),
+                    args,
+                })
+            }
+            Ok(command) => Ok(command),
+        }
+    }
+}
+
+impl CommandParser {
+    fn from_value(value: RESPValue) -> anyhow::Result<Self> {
+        let command_parts = value
+            .into_array()
+            .map(|values| {
+                values
+                    .into_iter()
+                    .filter_map(|value| value.into_bulk_string())
+                    .collect::<Vec<_>>()
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!("[redis - error] expected command to be an array of bulk strings")
+            })?;
+
+        if command_parts.is_empty() {
+            return Err(anyhow::anyhow!(
+                "[redis - error] expected client input to be a non-empty array of bulk strings"
+            ));
+        }
+
+        Ok(CommandParser::new(command_parts))
+    }
+}
+
+impl RedisCommand {
+    fn parse(parser: &mut CommandParser) -> anyhow::Result<Self> {
+        let command_name = parser.parse_next().unwrap().to_ascii_lowercase();
+        match &*command_name {
+            b"get" => parser
                 .expect_arg("get", "key")
                 .map(|key| RedisCommand::Store(RedisStoreCommand::Get { key })),
+            b"mget" => {
+                let mut keys = vec![parser.expect_arg("mget", "key")?];
+                while let Some(key) = parser.parse_next() {
+                    keys.push(key);
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::MGet { keys }))
+            }
+            b"getex" => {
+                let key = parser.expect_arg("getex", "key")?;
+                let invalid_expire_time =
+                    || anyhow::anyhow!("[redis - error] invalid expire time in 'getex' command");
+                let expiry_option = match parser
+                    .parse_next()
+                    .map(|option| option.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"ex") => {
+                        let seconds = parser.expect_number("getex", "seconds")?;
+                        let at = (seconds > 0)
+                            .then(|| from_now(Duration::from_secs(seconds)))
+                            .flatten();
+                        Some(ExpiryOption::At(at.ok_or_else(invalid_expire_time)?))
+                    }
+                    Some(b"px") => {
+                        let millis = parser.expect_number("getex", "milliseconds")?;
+                        let at = (millis > 0)
+                            .then(|| from_now(Duration::from_millis(millis)))
+                            .flatten();
+                        Some(ExpiryOption::At(at.ok_or_else(invalid_expire_time)?))
+                    }
+                    Some(b"exat") => {
+                        let seconds = parser.expect_number("getex", "unix-time-seconds")?;
+                        let at = (seconds > 0)
+                            .then(|| since_epoch(Duration::from_secs(seconds)))
+                            .flatten();
+                        Some(ExpiryOption::At(at.ok_or_else(invalid_expire_time)?))
+                    }
+                    Some(b"pxat") => {
+                        let millis = parser.expect_number("getex", "unix-time-milliseconds")?;
+                        let at = (millis > 0)
+                            .then(|| since_epoch(Duration::from_millis(millis)))
+                            .flatten();
+                        Some(ExpiryOption::At(at.ok_or_else(invalid_expire_time)?))
+                    }
+                    Some(b"persist") => Some(ExpiryOption::Persist),
+                    Some(_) => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] syntax error in arguments for command 'getex'"
+                        ))
+                    }
+                    None => None,
+                };
+
+                if !parser.is_finished() {
+                    return Err(anyhow::anyhow!(
+                        "[redis - error] command 'getex' accepts at most one expiry option"
+                    ));
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::GetEx {
+                    key,
+                    expiry_option,
+                }))
+            }
+            b"getdel" => parser
+                .expect_arg("getdel", "key")
+                .map(|key| RedisCommand::Store(RedisStoreCommand::GetDel { key })),
+            b"del" => {
+                let mut keys = vec![parser.expect_arg("del", "key")?];
+                while let Some(key) = parser.parse_next() {
+                    keys.push(key);
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::Del { keys }))
+            }
             b"set" => {
                 let key = parser.expect_arg("set", "key")?;
                 let value = parser.expect_arg("set", "value")?;
@@ -145,40 +1334,1246 @@ impl TryFrom<RESPValue> for RedisCommand {
                     .map(Duration::from_millis)
                     .map(|duration| SystemTime::now() + duration);
 
-                Ok(RedisCommand::Store(RedisStoreCommand::Set {
-                    key,
-                    value,
-                    px,
+                Ok(RedisCommand::Store(RedisStoreCommand::Set {
+                    key,
+                    value,
+                    px,
+                }))
+            }
+            b"keys" => {
+                let key = parser.expect_arg("keys", "key")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::Keys { key }))
+            }
+            b"type" => {
+                let key = parser.expect_arg("type", "key")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::Type { key }))
+            }
+            b"xadd" => {
+                let arity_error = || CommandError::WrongArity("xadd");
+                let key = parser.parse_next().ok_or_else(arity_error)?;
+                let mut no_mkstream = false;
+                let mut trim = None;
+                let entry_id = loop {
+                    let arg = parser.parse_next().ok_or_else(arity_error)?;
+                    match &*arg.to_ascii_lowercase() {
+                        b"nomkstream" => no_mkstream = true,
+                        strategy_name @ (b"maxlen" | b"minid") => {
+                            trim = Some(parser.expect_stream_trim("xadd", strategy_name)?);
+                        }
+                        _ => {
+                            break XAddEntryId::parse(&arg).ok_or_else(|| {
+                                anyhow::anyhow!(
+                                    "[redis - error] invalid stream ID specified for command 'xadd'"
+                                )
+                            })?
+                        }
+                    }
+                };
+
+                // at least one field is required and each field needs a value
+                if parser.is_finished() {
+                    return Err(arity_error().into());
+                }
+
+                let mut fields = vec![];
+                while let Some(field) = parser.parse_next() {
+                    let value = parser.parse_next().ok_or_else(arity_error)?;
+                    fields.push((field, value));
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::XAdd {
+                    key,
+                    entry_id,
+                    no_mkstream,
+                    trim,
+                    fields,
+                }))
+            }
+            b"xtrim" => {
+                let key = parser.expect_arg("xtrim", "key")?;
+                let strategy_name = parser.expect_arg("xtrim", "strategy")?.to_ascii_lowercase();
+                if !matches!(&*strategy_name, b"maxlen" | b"minid") {
+                    return Err(anyhow::anyhow!("[redis - error] syntax error"));
+                }
+
+                let trim = parser.expect_stream_trim("xtrim", &strategy_name)?;
+                if !parser.is_finished() {
+                    return Err(anyhow::anyhow!("[redis - error] syntax error"));
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::XTrim { key, trim }))
+            }
+            b"xinfo" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"stream") => XInfoSection::Stream {
+                        key: parser.expect_arg("xinfo stream", "key")?,
+                    },
+                    Some(b"help") => XInfoSection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'xinfo'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::XInfo { section }))
+            }
+            b"xgroup" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"create") => {
+                        let key = parser.expect_arg("xgroup create", "key")?;
+                        let group = parser.expect_arg("xgroup create", "group")?;
+                        let id = parser.expect_arg("xgroup create", "id")?;
+                        let id = match &*id {
+                            b"$" => None,
+                            id => Some(StreamEntryId::parse(id).ok_or_else(|| {
+                                anyhow::anyhow!(
+                                    "[redis - error] Invalid stream ID specified as stream command argument"
+                                )
+                            })?),
+                        };
+
+                        let mkstream = match parser.parse_next() {
+                            Some(arg) if arg.eq_ignore_ascii_case(b"mkstream") => true,
+                            None => false,
+                            Some(_) => return Err(anyhow::anyhow!("[redis - error] syntax error")),
+                        };
+
+                        XGroupSection::Create {
+                            key,
+                            group,
+                            id,
+                            mkstream,
+                        }
+                    }
+                    Some(b"help") => XGroupSection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'xgroup'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::XGroup { section }))
+            }
+            b"xreadgroup" => {
+                let arity_error = || CommandError::WrongArity("xreadgroup");
+                let mut group_and_consumer = None;
+                let mut count = None;
+                let mut no_ack = false;
+                loop {
+                    let arg = parser.parse_next().ok_or_else(arity_error)?;
+                    match &*arg.to_ascii_lowercase() {
+                        b"group" => {
+                            let group = parser.parse_next().ok_or_else(arity_error)?;
+                            let consumer = parser.parse_next().ok_or_else(arity_error)?;
+                            group_and_consumer = Some((group, consumer));
+                        }
+                        // like in Redis, a count of 0 means no count
+                        b"count" => {
+                            count = Some(parser.expect_number::<usize>("xreadgroup", "count")?)
+                                .filter(|count| *count > 0);
+                        }
+                        b"noack" => no_ack = true,
+                        b"block" => {
+                            return Err(anyhow::anyhow!(
+                                "[redis - error] BLOCK is not supported for XREADGROUP yet"
+                            ))
+                        }
+                        b"streams" => break,
+                        _ => return Err(anyhow::anyhow!("[redis - error] syntax error")),
+                    }
+                }
+
+                let Some((group, consumer)) = group_and_consumer else {
+                    return Err(anyhow::anyhow!(
+                        "[redis - error] Missing GROUP option for XREADGROUP"
+                    ));
+                };
+
+                let mut args = vec![];
+                while let Some(arg) = parser.parse_next() {
+                    args.push(arg);
+                }
+
+                if args.is_empty() || args.len() % 2 != 0 {
+                    return Err(anyhow::anyhow!(
+                        "[redis - error] Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified."
+                    ));
+                }
+
+                let ids = args
+                    .split_off(args.len() / 2)
+                    .iter()
+                    .map(|id| {
+                        XReadGroupId::parse(id).ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "[redis - error] Invalid stream ID specified as stream command argument"
+                            )
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+
+                Ok(RedisCommand::Store(RedisStoreCommand::XReadGroup {
+                    group,
+                    consumer,
+                    count,
+                    no_ack,
+                    keys: args,
+                    ids,
+                }))
+            }
+            b"xack" => {
+                let arity_error = || CommandError::WrongArity("xack");
+                let key = parser.parse_next().ok_or_else(arity_error)?;
+                let group = parser.parse_next().ok_or_else(arity_error)?;
+                if parser.is_finished() {
+                    return Err(arity_error().into());
+                }
+
+                let mut ids = vec![];
+                while let Some(id) = parser.parse_next() {
+                    ids.push(StreamEntryId::parse(&id).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "[redis - error] Invalid stream ID specified as stream command argument"
+                        )
+                    })?);
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::XAck {
+                    key,
+                    group,
+                    ids,
+                }))
+            }
+            b"object" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"encoding") => ObjectSection::Encoding {
+                        key: parser.expect_arg("object encoding", "key")?,
+                    },
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'object'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::Object { section }))
+            }
+            b"swapdb" => {
+                let index = parser.expect_number("swapdb", "index1")?;
+                let other_index = parser.expect_number("swapdb", "index2")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::SwapDb {
+                    index,
+                    other_index,
+                }))
+            }
+            b"move" => {
+                let key = parser.expect_arg("move", "key")?;
+                let db = parser.expect_number("move", "db")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::Move { key, db }))
+            }
+            b"setbit" => {
+                let key = parser.expect_arg("setbit", "key")?;
+                let offset = parser.expect_number("setbit", "offset")?;
+                let bit = match parser.expect_number::<u8>("setbit", "value")? {
+                    0 => false,
+                    1 => true,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] argument 'value' for command 'setbit' must be 0 or 1"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::SetBit {
+                    key,
+                    offset,
+                    bit,
+                }))
+            }
+            b"getbit" => {
+                let key = parser.expect_arg("getbit", "key")?;
+                let offset = parser.expect_number("getbit", "offset")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::GetBit {
+                    key,
+                    offset,
+                }))
+            }
+            b"bitcount" => {
+                let key = parser.expect_arg("bitcount", "key")?;
+                let range = if parser.is_finished() {
+                    None
+                } else {
+                    let start = parser.expect_number("bitcount", "start")?;
+                    let end = parser.expect_number("bitcount", "end")?;
+                    let unit = match parser
+                        .parse_next()
+                        .map(|unit| unit.to_ascii_lowercase())
+                        .as_deref()
+                    {
+                        None | Some(b"byte") => BitOrByte::Byte,
+                        Some(b"bit") => BitOrByte::Bit,
+                        Some(_) => {
+                            return Err(anyhow::anyhow!(
+                                "[redis - error] unknown argument found for command 'bitcount'"
+                            ))
+                        }
+                    };
+
+                    Some((start, end, unit))
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::BitCount {
+                    key,
+                    range,
+                }))
+            }
+            b"sadd" => {
+                let key = parser.expect_arg("sadd", "key")?;
+                let mut members = vec![parser.expect_arg("sadd", "member")?];
+                while let Some(member) = parser.parse_next() {
+                    members.push(member);
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::SAdd {
+                    key,
+                    members,
+                }))
+            }
+            b"smembers" => {
+                let key = parser.expect_arg("smembers", "key")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::SMembers { key }))
+            }
+            name @ (b"sinterstore" | b"sunionstore" | b"sdiffstore") => {
+                let operation = match name {
+                    b"sinterstore" => SetOperation::Inter,
+                    b"sunionstore" => SetOperation::Union,
+                    _ => SetOperation::Diff,
+                };
+
+                let name = std::str::from_utf8(name)?;
+                let destination = parser.expect_arg(name, "destination")?;
+                let mut keys = vec![parser.expect_arg(name, "key")?];
+                while let Some(key) = parser.parse_next() {
+                    keys.push(key);
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::SetOperationStore {
+                    operation,
+                    destination,
+                    keys,
+                }))
+            }
+            b"sintercard" => {
+                let keys = parser.expect_keys("sintercard")?;
+                let limit = match parser.parse_next() {
+                    Some(arg) if arg.eq_ignore_ascii_case(b"limit") => {
+                        Some(parser.expect_number("sintercard", "limit")?)
+                    }
+                    None => None,
+                    Some(_) => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'sintercard'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::SInterCard {
+                    keys,
+                    // like in Redis, a limit of 0 means no limit
+                    limit: limit.filter(|limit| *limit > 0),
+                }))
+            }
+            b"smove" => {
+                let source = parser.expect_arg("smove", "source")?;
+                let destination = parser.expect_arg("smove", "destination")?;
+                let member = parser.expect_arg("smove", "member")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::SMove {
+                    source,
+                    destination,
+                    member,
+                }))
+            }
+            name @ (b"lpush" | b"rpush") => {
+                let name = std::str::from_utf8(name)?;
+                let key = parser.expect_arg(name, "key")?;
+                let mut elements = vec![parser.expect_arg(name, "element")?];
+                while let Some(element) = parser.parse_next() {
+                    elements.push(element);
+                }
+
+                let end = if name == "lpush" {
+                    ListEnd::Left
+                } else {
+                    ListEnd::Right
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::ListPush {
+                    key,
+                    end,
+                    elements,
+                }))
+            }
+            name @ (b"lpop" | b"rpop") => {
+                let name = std::str::from_utf8(name)?;
+                let key = parser.expect_arg(name, "key")?;
+                let count = if parser.is_finished() {
+                    None
+                } else {
+                    Some(parser.expect_number(name, "count")?)
+                };
+
+                let end = if name == "lpop" {
+                    ListEnd::Left
+                } else {
+                    ListEnd::Right
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::ListPop {
+                    key,
+                    end,
+                    count,
+                }))
+            }
+            b"lmpop" => {
+                let (keys, end, count) = parser.expect_multi_pop("lmpop", ListEnd::NAMES)?;
+                Ok(RedisCommand::Store(RedisStoreCommand::LMPop {
+                    keys,
+                    end,
+                    count,
+                }))
+            }
+            b"blmpop" => {
+                let timeout = parser.expect_arg("blmpop", "timeout")?;
+                let timeout = CommandParser::timeout("blmpop", &timeout)?;
+                let (keys, end, count) = parser.expect_multi_pop("blmpop", ListEnd::NAMES)?;
+                Ok(RedisCommand::Store(RedisStoreCommand::BLMPop {
+                    keys,
+                    timeout,
+                    end,
+                    count,
+                }))
+            }
+            b"llen" => {
+                let key = parser.expect_arg("llen", "key")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::LLen { key }))
+            }
+            b"lrange" => {
+                let key = parser.expect_arg("lrange", "key")?;
+                let start = parser.expect_number("lrange", "start")?;
+                let stop = parser.expect_number("lrange", "stop")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::LRange {
+                    key,
+                    start,
+                    stop,
+                }))
+            }
+            b"hset" => {
+                let key = parser.expect_arg("hset", "key")?;
+                let mut fields = vec![];
+                loop {
+                    let field = parser.expect_arg("hset", "field")?;
+                    let value = parser.expect_arg("hset", "value")?;
+                    fields.push((field, value));
+                    if parser.is_finished() {
+                        break;
+                    }
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::HSet { key, fields }))
+            }
+            b"hget" => {
+                let key = parser.expect_arg("hget", "key")?;
+                let field = parser.expect_arg("hget", "field")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::HGet { key, field }))
+            }
+            b"hrandfield" => {
+                let key = parser.expect_arg("hrandfield", "key")?;
+                let count = if parser.is_finished() {
+                    None
+                } else {
+                    Some(parser.expect_random_count("hrandfield")?)
+                };
+
+                let with_values = match parser
+                    .parse_next()
+                    .map(|arg| arg.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    None => false,
+                    Some(b"withvalues") => true,
+                    Some(_) => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'hrandfield'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::HRandField {
+                    key,
+                    count,
+                    with_values,
+                }))
+            }
+            b"zadd" => {
+                let key = parser.expect_arg("zadd", "key")?;
+                let mut members = vec![];
+                loop {
+                    let score = parser.expect_arg("zadd", "score")?;
+                    let score = Score::parse(&score).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "[redis - error] argument 'score' for command 'zadd' must be a valid float"
+                        )
+                    })?;
+                    let member = parser.expect_arg("zadd", "member")?;
+                    members.push((score, member));
+                    if parser.is_finished() {
+                        break;
+                    }
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::ZAdd {
+                    key,
+                    members,
+                }))
+            }
+            b"zscore" => {
+                let key = parser.expect_arg("zscore", "key")?;
+                let member = parser.expect_arg("zscore", "member")?;
+                Ok(RedisCommand::Store(RedisStoreCommand::ZScore {
+                    key,
+                    member,
+                }))
+            }
+            b"zrandmember" => {
+                let key = parser.expect_arg("zrandmember", "key")?;
+                let count = if parser.is_finished() {
+                    None
+                } else {
+                    Some(parser.expect_random_count("zrandmember")?)
+                };
+
+                let with_scores = match parser
+                    .parse_next()
+                    .map(|arg| arg.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    None => false,
+                    Some(b"withscores") => true,
+                    Some(_) => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'zrandmember'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::ZRandMember {
+                    key,
+                    count,
+                    with_scores,
+                }))
+            }
+            name @ (b"zpopmin" | b"zpopmax") => {
+                let name = std::str::from_utf8(name)?;
+                let key = parser.expect_arg(name, "key")?;
+                let count = if parser.is_finished() {
+                    None
+                } else {
+                    Some(parser.expect_number(name, "count")?)
+                };
+
+                let end = if name == "zpopmin" {
+                    MinOrMax::Min
+                } else {
+                    MinOrMax::Max
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::ZPop {
+                    key,
+                    count,
+                    end,
+                }))
+            }
+            b"zmpop" => {
+                let (keys, end, count) = parser.expect_multi_pop("zmpop", MinOrMax::NAMES)?;
+                Ok(RedisCommand::Store(RedisStoreCommand::ZMPop {
+                    keys,
+                    end,
+                    count,
+                }))
+            }
+            b"pfadd" => {
+                let key = parser.expect_arg("pfadd", "key")?;
+                let mut elements = vec![];
+                while let Some(element) = parser.parse_next() {
+                    elements.push(element);
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::PfAdd {
+                    key,
+                    elements,
+                }))
+            }
+            b"pfcount" => {
+                let mut keys = vec![parser.expect_arg("pfcount", "key")?];
+                while let Some(key) = parser.parse_next() {
+                    keys.push(key);
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::PfCount { keys }))
+            }
+            b"pfmerge" => {
+                let destination = parser.expect_arg("pfmerge", "destkey")?;
+                let mut keys = vec![];
+                while let Some(key) = parser.parse_next() {
+                    keys.push(key);
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::PfMerge {
+                    destination,
+                    keys,
+                }))
+            }
+            name @ (b"bzpopmin" | b"bzpopmax") => {
+                let name = std::str::from_utf8(name)?;
+                let mut keys = vec![parser.expect_arg(name, "key")?];
+                while let Some(key) = parser.parse_next() {
+                    keys.push(key);
+                }
+
+                anyhow::ensure!(
+                    keys.len() > 1,
+                    "[redis - error] command '{name}' requires an argument 'timeout' but was not provided one"
+                );
+
+                let timeout = CommandParser::timeout(name, &keys.pop().unwrap())?;
+
+                let end = if name == "bzpopmin" {
+                    MinOrMax::Min
+                } else {
+                    MinOrMax::Max
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::BZPop {
+                    keys,
+                    timeout,
+                    end,
+                }))
+            }
+            b"bzmpop" => {
+                let timeout = parser.expect_arg("bzmpop", "timeout")?;
+                let timeout = CommandParser::timeout("bzmpop", &timeout)?;
+                let (keys, end, count) = parser.expect_multi_pop("bzmpop", MinOrMax::NAMES)?;
+                Ok(RedisCommand::Store(RedisStoreCommand::BZMPop {
+                    keys,
+                    timeout,
+                    end,
+                    count,
+                }))
+            }
+            b"geoadd" => {
+                let key = parser.expect_arg("geoadd", "key")?;
+                let mut points = vec![];
+                loop {
+                    let coordinates = parser.expect_coordinates("geoadd")?;
+                    let member = parser.expect_arg("geoadd", "member")?;
+                    points.push((coordinates, member));
+                    if parser.is_finished() {
+                        break;
+                    }
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::GeoAdd {
+                    key,
+                    points,
+                }))
+            }
+            b"geopos" => {
+                let key = parser.expect_arg("geopos", "key")?;
+                let mut members = vec![];
+                while let Some(member) = parser.parse_next() {
+                    members.push(member);
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::GeoPos {
+                    key,
+                    members,
+                }))
+            }
+            b"geodist" => {
+                let key = parser.expect_arg("geodist", "key")?;
+                let member = parser.expect_arg("geodist", "member1")?;
+                let other_member = parser.expect_arg("geodist", "member2")?;
+                let unit = if parser.is_finished() {
+                    GeoUnit::Meters
+                } else {
+                    parser.expect_geo_unit("geodist")?
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::GeoDist {
+                    key,
+                    member,
+                    other_member,
+                    unit,
+                }))
+            }
+            b"sort" => {
+                let key = parser.expect_arg("sort", "key")?;
+                let mut sort = Sort {
+                    by: None,
+                    limit: None,
+                    get: vec![],
+                    order: SortOrder::Ascending,
+                    alpha: false,
+                    store: None,
+                };
+
+                while let Some(arg) = parser.parse_next() {
+                    match &*arg.to_ascii_lowercase() {
+                        b"asc" => sort.order = SortOrder::Ascending,
+                        b"desc" => sort.order = SortOrder::Descending,
+                        b"alpha" => sort.alpha = true,
+                        b"limit" => {
+                            let offset = parser.expect_number("sort", "offset")?;
+                            let count = parser.expect_number("sort", "count")?;
+                            sort.limit = Some((offset, count));
+                        }
+                        b"by" => sort.by = Some(parser.expect_arg("sort", "pattern")?),
+                        b"get" => sort.get.push(parser.expect_arg("sort", "pattern")?),
+                        b"store" => sort.store = Some(parser.expect_arg("sort", "destination")?),
+                        _ => {
+                            return Err(anyhow::anyhow!(
+                                "[redis - error] unknown argument found for command 'sort'"
+                            ))
+                        }
+                    }
+                }
+
+                Ok(RedisCommand::Store(RedisStoreCommand::Sort { key, sort }))
+            }
+            b"geosearch" => {
+                let key = parser.expect_arg("geosearch", "key")?;
+                let mut origin = None;
+                let mut shape = None;
+                let mut unit = GeoUnit::Meters;
+                let mut order = None;
+                let mut count = None;
+                let (mut with_coordinates, mut with_distance, mut with_hash) =
+                    (false, false, false);
+
+                while let Some(arg) = parser.parse_next() {
+                    match &*arg.to_ascii_lowercase() {
+                        b"frommember" => {
+                            let member = parser.expect_arg("geosearch", "member")?;
+                            origin = Some(GeoOrigin::Member(member));
+                        }
+                        b"fromlonlat" => {
+                            let coordinates = parser.expect_coordinates("geosearch")?;
+                            origin = Some(GeoOrigin::Coordinates(coordinates));
+                        }
+                        b"byradius" => {
+                            let radius = parser.expect_distance("geosearch", "radius")?;
+                            shape = Some(GeoShape::Radius(radius));
+                            unit = parser.expect_geo_unit("geosearch")?;
+                        }
+                        b"bybox" => {
+                            let width = parser.expect_distance("geosearch", "width")?;
+                            let height = parser.expect_distance("geosearch", "height")?;
+                            shape = Some(GeoShape::Box { width, height });
+                            unit = parser.expect_geo_unit("geosearch")?;
+                        }
+                        b"asc" => order = Some(SortOrder::Ascending),
+                        b"desc" => order = Some(SortOrder::Descending),
+                        b"count" => {
+                            let limit = parser.expect_number::<usize>("geosearch", "count")?;
+                            anyhow::ensure!(
+                                limit > 0,
+                                "[redis - error] argument 'count' for command 'geosearch' must be greater than 0"
+                            );
+
+                            let any = parser
+                                .attempt_flag(|arg| arg.eq_ignore_ascii_case(b"any").then_some(()))
+                                .is_some();
+                            if any {
+                                parser.parse_next();
+                            }
+
+                            count = Some((limit, any));
+                        }
+                        b"withcoord" => with_coordinates = true,
+                        b"withdist" => with_distance = true,
+                        b"withhash" => with_hash = true,
+                        _ => {
+                            return Err(anyhow::anyhow!(
+                                "[redis - error] unknown argument found for command 'geosearch'"
+                            ))
+                        }
+                    }
+                }
+
+                let (Some(origin), Some(shape)) = (origin, shape) else {
+                    return Err(anyhow::anyhow!(
+                        "[redis - error] command 'geosearch' requires exactly one of FROMMEMBER or FROMLONLAT and one of BYRADIUS or BYBOX"
+                    ));
+                };
+
+                Ok(RedisCommand::Store(RedisStoreCommand::GeoSearch {
+                    key,
+                    search: GeoSearch {
+                        origin,
+                        shape,
+                        unit,
+                        order,
+                        count,
+                        with_coordinates,
+                        with_distance,
+                        with_hash,
+                    },
+                }))
+            }
+            b"ping" => {
+                let message = parser.parse_next();
+                Ok(RedisCommand::Server(RedisServerCommand::Ping { message }))
+            }
+            b"subscribe" => {
+                let channel = parser.expect_arg("subscribe", "channel")?;
+                let mut channels = vec![channel];
+                while let Some(channel) = parser.parse_next() {
+                    channels.push(channel);
+                }
+
+                Ok(RedisCommand::PubSub(RedisPubSubCommand::Subscribe {
+                    channels,
+                }))
+            }
+            b"unsubscribe" => {
+                let mut channels = vec![];
+                while let Some(channel) = parser.parse_next() {
+                    channels.push(channel);
+                }
+
+                Ok(RedisCommand::PubSub(RedisPubSubCommand::Unsubscribe {
+                    channels,
+                }))
+            }
+            b"publish" => {
+                let channel = parser.expect_arg("publish", "channel")?;
+                let message = parser.expect_arg("publish", "message")?;
+                Ok(RedisCommand::PubSub(RedisPubSubCommand::Publish {
+                    channel,
+                    message,
+                }))
+            }
+            b"ssubscribe" => {
+                let channel = parser.expect_arg("ssubscribe", "shardchannel")?;
+                let mut channels = vec![channel];
+                while let Some(channel) = parser.parse_next() {
+                    channels.push(channel);
+                }
+
+                Ok(RedisCommand::PubSub(RedisPubSubCommand::SSubscribe {
+                    channels,
+                }))
+            }
+            b"sunsubscribe" => {
+                let mut channels = vec![];
+                while let Some(channel) = parser.parse_next() {
+                    channels.push(channel);
+                }
+
+                Ok(RedisCommand::PubSub(RedisPubSubCommand::SUnsubscribe {
+                    channels,
+                }))
+            }
+            b"spublish" => {
+                let channel = parser.expect_arg("spublish", "shardchannel")?;
+                let message = parser.expect_arg("spublish", "message")?;
+                Ok(RedisCommand::PubSub(RedisPubSubCommand::SPublish {
+                    channel,
+                    message,
+                }))
+            }
+            b"pubsub" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"channels") => PubSubSection::Channels {
+                        pattern: parser.parse_next(),
+                    },
+                    Some(b"numsub") => {
+                        let mut channels = vec![];
+                        while let Some(channel) = parser.parse_next() {
+                            channels.push(channel);
+                        }
+
+                        PubSubSection::NumSub { channels }
+                    }
+                    Some(b"numpat") => PubSubSection::NumPat,
+                    Some(b"help") => PubSubSection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'pubsub'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::PubSub(RedisPubSubCommand::PubSub { section }))
+            }
+            b"echo" => parser
+                .expect_arg("echo", "message")
+                .map(|message| RedisCommand::Server(RedisServerCommand::Echo { message })),
+            b"select" => parser
+                .expect_number("select", "index")
+                .map(|index| RedisCommand::Server(RedisServerCommand::Select { index })),
+            b"lolwut" => {
+                // unknown trailing arguments are ignored rather than rejected
+                let version = match parser.parse_next() {
+                    Some(arg) if arg.eq_ignore_ascii_case(b"version") => {
+                        Some(parser.expect_number("lolwut", "version")?)
+                    }
+                    _ => None,
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Lolwut { version }))
+            }
+            b"quit" => Ok(RedisCommand::Server(RedisServerCommand::Quit)),
+            b"monitor" => Ok(RedisCommand::Server(RedisServerCommand::Monitor)),
+            b"hello" => {
+                let protocol_version = match parser.peek() {
+                    Some(_) => Some(parser.expect_number("hello", "protover")?),
+                    None => None,
+                };
+                // authenticating and naming the connection through `HELLO` is not
+                // supported yet
+                if let Some(option) = parser.parse_next() {
+                    return Err(anyhow::anyhow!(
+                        "[redis - error] unsupported option '{}' provided for command 'hello'",
+                        option.escape_ascii()
+                    ));
+                }
+
+                Ok(RedisCommand::Server(RedisServerCommand::Hello {
+                    protocol_version,
+                }))
+            }
+            b"auth" => {
+                let first = parser.expect_arg("auth", "password")?;
+                let (username, password) = match parser.parse_next() {
+                    Some(password) => (Some(first), password),
+                    None => (None, first),
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Auth {
+                    username,
+                    password,
+                }))
+            }
+            b"acl" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"whoami") => AclSection::WhoAmI,
+                    Some(b"list") => AclSection::List,
+                    Some(b"cat") => AclSection::Cat,
+                    Some(b"setuser") => {
+                        let username = parser.expect_arg("acl", "username")?;
+                        let mut rules = vec![];
+                        while let Some(rule) = parser.parse_next() {
+                            rules.push(rule);
+                        }
+
+                        AclSection::SetUser { username, rules }
+                    }
+                    Some(b"getuser") => AclSection::GetUser {
+                        username: parser.expect_arg("acl", "username")?,
+                    },
+                    Some(b"help") => AclSection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'acl'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Acl { section }))
+            }
+            b"debug" => {
+                let subcommand = parser.expect_arg("debug", "subcommand")?;
+                let section = match &subcommand.to_ascii_lowercase()[..] {
+                    b"object" => DebugSection::Object {
+                        key: parser.expect_arg("debug", "key")?,
+                    },
+                    b"change-repl-id" => DebugSection::ChangeReplId,
+                    b"replicate-offset" => DebugSection::ReplicateOffset,
+                    b"reload" => DebugSection::Reload,
+                    b"stringmatch-len" => DebugSection::StringMatchLen {
+                        pattern: parser.expect_arg("debug", "pattern")?,
+                        string: parser.expect_arg("debug", "string")?,
+                    },
+                    b"set-active-expire" => DebugSection::SetActiveExpire {
+                        is_enabled: parser.expect_number::<i64>("debug", "flag")? != 0,
+                    },
+                    b"jmap" => DebugSection::Jmap,
+                    b"sleep" => {
+                        let seconds = parser.expect_number::<f64>("debug", "seconds")?;
+                        DebugSection::Sleep {
+                            duration: Duration::try_from_secs_f64(seconds)?,
+                        }
+                    }
+                    _ => {
+                        let mut args = vec![];
+                        while let Some(arg) = parser.parse_next() {
+                            args.push(arg);
+                        }
+
+                        DebugSection::Unsupported { subcommand, args }
+                    }
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Debug { section }))
+            }
+            b"command" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"info") => {
+                        let mut names = vec![];
+                        while let Some(name) = parser.parse_next() {
+                            names.push(name);
+                        }
+
+                        CommandSection::Info { names }
+                    }
+                    Some(b"count") => CommandSection::Count,
+                    Some(b"docs") => {
+                        let mut names = vec![];
+                        while let Some(name) = parser.parse_next() {
+                            names.push(name);
+                        }
+
+                        CommandSection::Docs { names }
+                    }
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'command'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Command {
+                    section,
+                }))
+            }
+            b"slowlog" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"get") => SlowLogSection::Get {
+                        count: if parser.is_finished() {
+                            None
+                        } else {
+                            Some(parser.expect_number("slowlog", "count")?)
+                        },
+                    },
+                    Some(b"len") => SlowLogSection::Len,
+                    Some(b"reset") => SlowLogSection::Reset,
+                    Some(b"help") => SlowLogSection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'slowlog'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::SlowLog {
+                    section,
+                }))
+            }
+            b"latency" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"history") => LatencySection::History {
+                        event: parser.expect_arg("latency", "event")?,
+                    },
+                    Some(b"latest") => LatencySection::Latest,
+                    Some(b"reset") => {
+                        let mut events = vec![];
+                        while let Some(event) = parser.parse_next() {
+                            events.push(event);
+                        }
+
+                        LatencySection::Reset { events }
+                    }
+                    Some(b"help") => LatencySection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'latency'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Latency {
+                    section,
                 }))
             }
-            b"keys" => {
-                let key = parser.expect_arg("keys", "key")?;
-                Ok(RedisCommand::Store(RedisStoreCommand::Keys { key }))
+            b"client" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"no-evict") => ClientSection::NoEvict {
+                        is_enabled: parser.expect_switch("client", "enabled")?,
+                    },
+                    Some(b"no-touch") => ClientSection::NoTouch {
+                        is_enabled: parser.expect_switch("client", "enabled")?,
+                    },
+                    Some(b"help") => ClientSection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'client'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Client { section }))
             }
-            b"type" => {
-                let key = parser.expect_arg("type", "key")?;
-                Ok(RedisCommand::Store(RedisStoreCommand::Type { key }))
+            b"cluster" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"info") => ClusterSection::Info,
+                    Some(b"myid") => ClusterSection::MyId,
+                    Some(b"slots") => ClusterSection::Slots,
+                    Some(b"shards") => ClusterSection::Shards,
+                    Some(b"nodes") => ClusterSection::Nodes,
+                    Some(b"help") => ClusterSection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'cluster'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Cluster {
+                    section,
+                }))
             }
-            b"xadd" => {
-                let key = parser.expect_arg("xadd", "key")?;
-                let entry_id = parser.expect_arg("xadd", "id")?;
-                let mut fields = vec![];
-                while !parser.is_finished() {
-                    let field = parser.expect_arg("xadd", "field")?;
-                    let value = parser.expect_arg("xadd", "value")?;
-                    fields.push((field, value));
+            b"function" => {
+                // the arguments of `LIST` and `LOAD` make no difference without functions
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"list") => FunctionSection::List,
+                    Some(b"dump") => FunctionSection::Dump,
+                    Some(b"load") => {
+                        parser.expect_arg("function", "function-code")?;
+                        FunctionSection::Load
+                    }
+                    Some(b"help") => FunctionSection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'function'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Function {
+                    section,
+                }))
+            }
+            name @ (b"fcall" | b"fcall_ro") => {
+                let is_read_only = name == b"fcall_ro";
+                let name = if is_read_only { "fcall_ro" } else { "fcall" };
+                let mut args = vec![
+                    parser.expect_arg(name, "function")?,
+                    parser.expect_arg(name, "numkeys")?,
+                ];
+                while let Some(arg) = parser.parse_next() {
+                    args.push(arg);
                 }
 
-                Ok(RedisCommand::Store(RedisStoreCommand::XAdd {
-                    key,
-                    entry_id,
-                    fields,
+                Ok(RedisCommand::Server(RedisServerCommand::FCall {
+                    is_read_only,
+                    args,
                 }))
             }
-            b"ping" => Ok(RedisCommand::Server(RedisServerCommand::Ping)),
-            b"echo" => parser
-                .expect_arg("echo", "message")
-                .map(|message| RedisCommand::Server(RedisServerCommand::Echo { message })),
+            b"eval" => {
+                let script = parser.expect_arg("eval", "script")?;
+                let (keys, args) = parser.expect_script_args("eval")?;
+                Ok(RedisCommand::Server(RedisServerCommand::Eval {
+                    script,
+                    keys,
+                    args,
+                }))
+            }
+            b"evalsha" => {
+                let sha1 = parser.expect_arg("evalsha", "sha1")?;
+                let (keys, args) = parser.expect_script_args("evalsha")?;
+                Ok(RedisCommand::Server(RedisServerCommand::EvalSha {
+                    sha1,
+                    keys,
+                    args,
+                }))
+            }
+            b"script" => {
+                let section = match parser
+                    .parse_next()
+                    .map(|section| section.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some(b"load") => {
+                        parser.expect_arg("script", "script")?;
+                        ScriptSection::Load
+                    }
+                    Some(b"exists") => {
+                        let mut sha1s = vec![parser.expect_arg("script", "sha1")?];
+                        while let Some(sha1) = parser.parse_next() {
+                            sha1s.push(sha1);
+                        }
+
+                        ScriptSection::Exists { sha1s }
+                    }
+                    // `ASYNC` and `SYNC` make no difference with nothing to flush
+                    Some(b"flush") => ScriptSection::Flush,
+                    Some(b"help") => ScriptSection::Help,
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] unknown argument found for command 'script'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Server(RedisServerCommand::Script { section }))
+            }
             b"config" => {
                 let section = match parser
                     .parse_next()
@@ -193,6 +2588,22 @@ impl TryFrom<RESPValue> for RedisCommand {
 
                         ConfigSection::Get { keys }
                     }
+                    Some(b"set") => {
+                        let mut parameters = vec![];
+                        while let Some(name) = parser.parse_next() {
+                            let value = parser.expect_arg("config set", "value")?;
+                            parameters.push((name, value));
+                        }
+
+                        if parameters.is_empty() {
+                            return Err(anyhow::anyhow!(
+                                "[redis - error] command 'config set' requires a parameter"
+                            ));
+                        }
+
+                        ConfigSection::Set { parameters }
+                    }
+                    Some(b"help") => ConfigSection::Help,
                     _ => {
                         return Err(anyhow::anyhow!(
                             "[redis - error] unknown argument found for command 'config'"
@@ -202,14 +2613,19 @@ impl TryFrom<RESPValue> for RedisCommand {
 
                 Ok(RedisCommand::Server(RedisServerCommand::Config { section }))
             }
-            b"info" => Ok(RedisCommand::Replication(RedisReplicationCommand::Info {
+            b"info" => Ok(RedisCommand::Server(RedisServerCommand::Info {
                 section: parser
-                    .attempt_flag(|byte| match byte {
+                    .attempt_flag(|byte| match &*byte.to_ascii_lowercase() {
+                        b"server" => Some(InfoSection::Server),
+                        b"clients" => Some(InfoSection::Clients),
+                        b"persistence" => Some(InfoSection::Persistence),
+                        b"stats" => Some(InfoSection::Stats),
                         b"replication" => Some(InfoSection::Replication),
                         _ => Some(InfoSection::Default),
                     })
-                    .unwrap(),
+                    .unwrap_or(InfoSection::Default),
             })),
+            b"lastsave" => Ok(RedisCommand::Server(RedisServerCommand::LastSave)),
             b"replconf" => {
                 let section = match parser
                     .parse_next()
@@ -233,11 +2649,13 @@ impl TryFrom<RESPValue> for RedisCommand {
                         ReplConfSection::Capa { capabilities }
                     }
                     Some(b"ack") => {
-                        if let Some(processed_bytes) = parser.parse_next().as_deref() {
-                            let processed_bytes = std::str::from_utf8(processed_bytes)?.parse()?;
-                            ReplConfSection::Ack { processed_bytes }
-                        } else {
-                            return Err(anyhow::anyhow!("[redis - error] expected value for argument 'ack' for command 'replconf'"));
+                        let offset = parser.parse_next().unwrap_or_default();
+                        match std::str::from_utf8(&offset)
+                            .ok()
+                            .and_then(|offset| offset.parse().ok())
+                        {
+                            Some(processed_bytes) => ReplConfSection::Ack { processed_bytes },
+                            None => ReplConfSection::MalformedAck { offset },
                         }
                     }
                     Some(b"getack") => {
@@ -263,9 +2681,13 @@ impl TryFrom<RESPValue> for RedisCommand {
                 let replication_id = String::from_utf8(replication_id.to_vec())?;
                 let replication_offset = parser.expect_arg("psync", "replication_offset")?;
                 let replication_offset = std::str::from_utf8(&replication_offset)?.parse()?;
+                let failover = parser
+                    .parse_next()
+                    .is_some_and(|arg| arg.eq_ignore_ascii_case(b"failover"));
                 Ok(RedisCommand::Replication(RedisReplicationCommand::PSync {
                     replication_id,
                     replication_offset,
+                    failover,
                 }))
             }
             b"wait" => {
@@ -278,6 +2700,57 @@ impl TryFrom<RESPValue> for RedisCommand {
                     timeout,
                 }))
             }
+            b"failover" => {
+                let mut target = None;
+                let mut timeout = None;
+                let mut is_abort = false;
+                while let Some(arg) = parser.parse_next() {
+                    match &*arg.to_ascii_lowercase() {
+                        b"to" => {
+                            let host = parser.expect_arg("failover", "host")?;
+                            let port = parser.expect_number("failover", "port")?;
+                            target = Some((String::from_utf8(host.to_vec())?, port));
+                        }
+                        b"timeout" => timeout = Some(parser.expect_number("failover", "timeout")?),
+                        b"abort" => is_abort = true,
+                        _ => {
+                            return Err(anyhow::anyhow!(
+                                "[redis - error] unknown argument found for command 'failover'"
+                            ))
+                        }
+                    }
+                }
+
+                let section = match (target, is_abort) {
+                    (None, true) => FailoverSection::Abort,
+                    (Some((host, port)), false) => FailoverSection::To {
+                        host,
+                        port,
+                        timeout,
+                    },
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "[redis - error] command 'failover' requires either 'to <host> <port>' or 'abort'"
+                        ))
+                    }
+                };
+
+                Ok(RedisCommand::Replication(
+                    RedisReplicationCommand::Failover { section },
+                ))
+            }
+            b"waitaof" => {
+                let num_local = parser.expect_number("waitaof", "numlocal")?;
+                let num_replicas = parser.expect_number("waitaof", "numreplicas")?;
+                let timeout = parser.expect_number("waitaof", "timeout")?;
+                Ok(RedisCommand::Replication(
+                    RedisReplicationCommand::WaitAof {
+                        num_local,
+                        num_replicas,
+                        timeout,
+                    },
+                ))
+            }
             bytes => Err(anyhow::anyhow!(
                 "[redis - error] received an unprocessable command '{}'",
                 std::str::from_utf8(bytes).unwrap_or("unknown")
@@ -286,13 +2759,45 @@ impl TryFrom<RESPValue> for RedisCommand {
     }
 }
 
+/// The time the given duration from now, truncated to the millisecond like the expiries
+/// sent to replicas, so that a replica ends up with exactly the same expiry.
+fn from_now(duration: Duration) -> Option<SystemTime> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    since_epoch(Duration::from_millis(now.as_millis() as u64).checked_add(duration)?)
+}
+
+/// The time `duration` after the unix epoch, unless it is too far to be written as
+/// milliseconds, e.g. when replicating or saving the expiry.
+fn since_epoch(duration: Duration) -> Option<SystemTime> {
+    (duration.as_millis() <= i64::MAX as u128).then(|| SystemTime::UNIX_EPOCH + duration)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::redis::resp::{
-        command::{RedisCommand, RedisServerCommand},
-        resp_reader::RESPReader,
+    use bytes::Bytes;
+
+    use crate::redis::{
+        replication::command::{FailoverSection, RedisReplicationCommand, ReplConfSection},
+        resp::{
+            command::{
+                ClientSection, ClusterSection, CommandError, DebugSection, ExpiryOption,
+                FunctionSection, ListEnd, MinOrMax, RedisCommand, RedisServerCommand,
+                RedisStoreCommand, ScriptSection, XGroupSection,
+            },
+            encoding,
+            resp_reader::RESPReader,
+        },
+        store::stream::{StreamEntryId, StreamTrim, StreamTrimStrategy, XAddEntryId, XReadGroupId},
     };
 
+    /// Parses a command sent as the given arguments.
+    fn parse(args: &[&str]) -> anyhow::Result<RedisCommand> {
+        let command = encoding::array(args.iter().map(encoding::bulk_string).collect());
+        RedisCommand::try_from(command)
+    }
+
     #[tokio::test]
     async fn parses_ping() {
         let mut stream = RESPReader::new("*1\r\n$4\r\nping\r\n".as_bytes());
@@ -301,7 +2806,570 @@ mod tests {
         assert!(command.is_ok());
         assert_eq!(
             command.unwrap(),
-            RedisCommand::Server(RedisServerCommand::Ping)
+            RedisCommand::Server(RedisServerCommand::Ping { message: None })
+        )
+    }
+
+    #[tokio::test]
+    async fn parses_ping_with_message() {
+        let mut stream = RESPReader::new("*2\r\n$4\r\nping\r\n$5\r\nhello\r\n".as_bytes());
+        let value = stream.read_value().await.unwrap();
+        let command: anyhow::Result<RedisCommand> = value.try_into();
+        assert_eq!(
+            command.unwrap(),
+            RedisCommand::Server(RedisServerCommand::Ping {
+                message: Some(Bytes::from_static(b"hello")),
+            })
+        )
+    }
+
+    #[test]
+    fn rejects_unknown_commands_and_syntax_errors_of_clients() {
+        let command = |parts: &[&'static str]| {
+            encoding::array(
+                parts
+                    .iter()
+                    .map(|part| encoding::bulk_string(*part))
+                    .collect(),
+            )
+        };
+
+        let RedisCommand::Invalid { error, args } =
+            RedisCommand::from_client(command(&["FOO", "a"])).unwrap()
+        else {
+            panic!("expected the unknown command to be invalid");
+        };
+        assert_eq!(
+            error.to_string(),
+            "ERR unknown command 'FOO', with args beginning with: 'a' "
+        );
+        assert_eq!(args, [Bytes::from("FOO"), Bytes::from("a")]);
+
+        let RedisCommand::Invalid { error, .. } =
+            RedisCommand::from_client(command(&["SELECT", "abc"])).unwrap()
+        else {
+            panic!("expected the malformed command to be invalid");
+        };
+        assert_eq!(error.command_name(), "select");
+        assert_eq!(
+            error.to_string(),
+            "ERR argument 'index' for command 'select' must be a valid number"
+        );
+
+        assert!(RedisCommand::try_from(command(&["FOO", "a"])).is_err());
+        assert!(RedisCommand::from_client(encoding::simple_string("OK")).is_err());
+    }
+
+    #[test]
+    fn allows_only_non_data_commands_while_loading() {
+        let get = RedisCommand::Store(RedisStoreCommand::Get {
+            key: Bytes::from_static(b"key"),
+        });
+        assert!(!get.is_allowed_while_loading());
+        assert!(
+            RedisCommand::Server(RedisServerCommand::Ping { message: None })
+                .is_allowed_while_loading()
+        );
+        assert!(
+            RedisCommand::Replication(RedisReplicationCommand::ReplConf {
+                section: ReplConfSection::GetAck,
+            })
+            .is_allowed_while_loading()
+        );
+
+        for section in [
+            DebugSection::Reload,
+            DebugSection::Object {
+                key: Bytes::from_static(b"key"),
+            },
+        ] {
+            assert!(!RedisCommand::Server(RedisServerCommand::Debug { section })
+                .is_allowed_while_loading());
+        }
+        assert!(RedisCommand::Server(RedisServerCommand::Debug {
+            section: DebugSection::ReplicateOffset,
+        })
+        .is_allowed_while_loading());
+    }
+
+    #[test]
+    fn rejects_xadd_without_complete_fields() {
+        for args in [
+            &["XADD", "s", "*"][..],
+            &["XADD", "s", "*", "f"],
+            &["XADD", "s"],
+        ] {
+            assert_eq!(
+                parse(args).unwrap(),
+                RedisCommand::Invalid {
+                    error: CommandError::WrongArity("xadd"),
+                    args: args.iter().map(|arg| Bytes::from(*arg)).collect(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_random_counts_too_large_to_reply_with() {
+        for args in [
+            &["HRANDFIELD", "k", "-9223372036854775808"][..],
+            &["ZRANDMEMBER", "k", "-1000000000000", "WITHSCORES"],
+        ] {
+            let command = encoding::array(args.iter().map(encoding::bulk_string).collect());
+            let RedisCommand::Invalid { error, .. } = RedisCommand::from_client(command).unwrap()
+            else {
+                panic!("expected {args:?} to be rejected");
+            };
+            assert_eq!(error.to_string(), "ERR value is out of range");
+        }
+
+        let command = encoding::array(
+            ["HRANDFIELD", "k", "9223372036854775807"]
+                .iter()
+                .map(encoding::bulk_string)
+                .collect(),
+        );
+        assert!(matches!(
+            RedisCommand::from_client(command).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::HRandField {
+                count: Some(i64::MAX),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_blocking_timeouts_out_of_range() {
+        for args in [
+            &["BZPOPMIN", "k", "1e300"][..],
+            &["BZPOPMAX", "k", "1e19"],
+            &["BZMPOP", "1e300", "1", "k", "MIN"],
+        ] {
+            let command = encoding::array(args.iter().map(encoding::bulk_string).collect());
+            let RedisCommand::Invalid { error, .. } = RedisCommand::from_client(command).unwrap()
+            else {
+                panic!("expected {args:?} to be rejected");
+            };
+            assert_eq!(error.to_string(), "ERR timeout is out of range");
+        }
+
+        let command = encoding::array(
+            ["BZPOPMIN", "k", "1.8e15"]
+                .iter()
+                .map(encoding::bulk_string)
+                .collect(),
+        );
+        assert!(matches!(
+            RedisCommand::from_client(command).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::BZPop {
+                timeout: Some(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parses_the_limit_of_approximate_trims() {
+        let trim = StreamTrim {
+            strategy: StreamTrimStrategy::MaxLen(10),
+            approximate: true,
+            limit: Some(5),
+        };
+        assert_eq!(
+            parse(&["XTRIM", "s", "MAXLEN", "~", "10", "LIMIT", "5"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::XTrim {
+                key: Bytes::from("s"),
+                trim,
+            })
+        );
+        assert_eq!(
+            parse(&["XADD", "s", "MAXLEN", "~", "10", "limit", "5", "*", "f", "v"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::XAdd {
+                key: Bytes::from("s"),
+                entry_id: XAddEntryId::Auto,
+                no_mkstream: false,
+                trim: Some(trim),
+                fields: vec![(Bytes::from("f"), Bytes::from("v"))],
+            })
+        );
+
+        for args in [
+            &["XTRIM", "s", "MAXLEN", "10", "LIMIT", "5"][..],
+            &[
+                "XADD", "s", "MINID", "=", "1-0", "LIMIT", "5", "*", "f", "v",
+            ],
+        ] {
+            let command = encoding::array(args.iter().map(encoding::bulk_string).collect());
+            let RedisCommand::Invalid { error, .. } = RedisCommand::from_client(command).unwrap()
+            else {
+                panic!("expected LIMIT without ~ to be rejected");
+            };
+            assert_eq!(
+                error.to_string(),
+                "ERR syntax error, LIMIT cannot be used without the special ~ option"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_consumer_group_commands() {
+        assert_eq!(
+            parse(&["XGROUP", "create", "s", "g", "$", "mkstream"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::XGroup {
+                section: XGroupSection::Create {
+                    key: Bytes::from("s"),
+                    group: Bytes::from("g"),
+                    id: None,
+                    mkstream: true,
+                }
+            })
+        );
+        assert_eq!(
+            parse(&[
+                "XREADGROUP",
+                "GROUP",
+                "g",
+                "c",
+                "COUNT",
+                "0",
+                "NOACK",
+                "STREAMS",
+                "a",
+                "b",
+                ">",
+                "1-1"
+            ])
+            .unwrap(),
+            RedisCommand::Store(RedisStoreCommand::XReadGroup {
+                group: Bytes::from("g"),
+                consumer: Bytes::from("c"),
+                count: None,
+                no_ack: true,
+                keys: vec![Bytes::from("a"), Bytes::from("b")],
+                ids: vec![
+                    XReadGroupId::New,
+                    XReadGroupId::Pending(StreamEntryId::new(1, 1))
+                ],
+            })
+        );
+        assert_eq!(
+            parse(&["XACK", "s", "g", "1", "2-3"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::XAck {
+                key: Bytes::from("s"),
+                group: Bytes::from("g"),
+                ids: vec![StreamEntryId::new(1, 0), StreamEntryId::new(2, 3)],
+            })
+        );
+
+        assert!(parse(&["XREADGROUP", "GROUP", "g", "c", "STREAMS", "a", "b", ">"]).is_err());
+        assert!(parse(&["XACK", "s", "g", "nope"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn parses_lolwut_ignoring_unknown_args() {
+        let mut stream =
+            RESPReader::new("*3\r\n$6\r\nlolwut\r\n$3\r\nfoo\r\n$3\r\nbar\r\n".as_bytes());
+        let value = stream.read_value().await.unwrap();
+        let command: anyhow::Result<RedisCommand> = value.try_into();
+        assert_eq!(
+            command.unwrap(),
+            RedisCommand::Server(RedisServerCommand::Lolwut { version: None })
+        )
+    }
+
+    #[tokio::test]
+    async fn parses_cluster_subcommands_clients_probe_with() {
+        let mut stream = RESPReader::new(
+            "*2\r\n$7\r\ncluster\r\n$4\r\nINFO\r\n*2\r\n$7\r\nCLUSTER\r\n$5\r\nslots\r\n"
+                .as_bytes(),
+        );
+        for section in [ClusterSection::Info, ClusterSection::Slots] {
+            let value = stream.read_value().await.unwrap();
+            let command: anyhow::Result<RedisCommand> = value.try_into();
+            assert_eq!(
+                command.unwrap(),
+                RedisCommand::Server(RedisServerCommand::Cluster { section })
+            );
+        }
+    }
+
+    #[test]
+    fn parses_function_probes_and_calls() {
+        for (args, section) in [
+            (&["FUNCTION", "list", "WITHCODE"][..], FunctionSection::List),
+            (&["function", "DUMP"], FunctionSection::Dump),
+            (
+                &["function", "load", "#!lua name=lib"],
+                FunctionSection::Load,
+            ),
+        ] {
+            assert_eq!(
+                parse(args).unwrap(),
+                RedisCommand::Server(RedisServerCommand::Function { section })
+            );
+        }
+
+        assert!(parse(&["function", "load"]).is_err());
+        let command = parse(&["fcall_ro", "f", "1", "key"]).unwrap();
+        assert_eq!(command.name(), "fcall_ro");
+        assert_eq!(
+            Bytes::from(&command),
+            Bytes::from(encoding::array(
+                ["FCALL_RO", "f", "1", "key"]
+                    .into_iter()
+                    .map(encoding::bulk_string)
+                    .collect()
+            ))
+        );
+        assert!(parse(&["fcall", "f"]).is_err());
+    }
+
+    #[test]
+    fn parses_the_keys_and_args_of_scripts() {
+        let args = ["EVAL", "return 1", "2", "a", "b", "c"];
+        let command = parse(&args).unwrap();
+        assert_eq!(
+            command,
+            RedisCommand::Server(RedisServerCommand::Eval {
+                script: Bytes::from("return 1"),
+                keys: vec![Bytes::from("a"), Bytes::from("b")],
+                args: vec![Bytes::from("c")],
+            })
+        );
+        assert_eq!(
+            Bytes::from(&command),
+            Bytes::from(encoding::array(
+                args.into_iter().map(encoding::bulk_string).collect()
+            ))
+        );
+
+        assert!(parse(&["evalsha", "abc", "0"]).is_ok());
+        assert!(parse(&["eval", "return 1", "2", "a"]).is_err());
+        assert_eq!(
+            parse(&["script", "EXISTS", "abc", "def"]).unwrap(),
+            RedisCommand::Server(RedisServerCommand::Script {
+                section: ScriptSection::Exists {
+                    sha1s: vec![Bytes::from("abc"), Bytes::from("def")]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parses_client_switches_in_any_case() {
+        assert_eq!(
+            parse(&["CLIENT", "no-evict", "ON"]).unwrap(),
+            RedisCommand::Server(RedisServerCommand::Client {
+                section: ClientSection::NoEvict { is_enabled: true }
+            })
+        );
+        assert_eq!(
+            parse(&["client", "NO-TOUCH", "off"]).unwrap(),
+            RedisCommand::Server(RedisServerCommand::Client {
+                section: ClientSection::NoTouch { is_enabled: false }
+            })
+        );
+        assert!(parse(&["client", "no-touch", "yes"]).is_err());
+        assert!(parse(&["client", "no-evict"]).is_err());
+    }
+
+    #[test]
+    fn parses_list_pushes_and_pops() {
+        assert_eq!(
+            parse(&["LPUSH", "list", "a", "b"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::ListPush {
+                key: Bytes::from("list"),
+                end: ListEnd::Left,
+                elements: vec![Bytes::from("a"), Bytes::from("b")],
+            })
+        );
+        assert_eq!(
+            parse(&["rpop", "list", "2"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::ListPop {
+                key: Bytes::from("list"),
+                end: ListEnd::Right,
+                count: Some(2),
+            })
+        );
+        assert_eq!(
+            parse(&["lrange", "list", "-3", "-1"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::LRange {
+                key: Bytes::from("list"),
+                start: -3,
+                stop: -1,
+            })
+        );
+        assert_eq!(
+            parse(&["lmpop", "2", "a", "b", "RIGHT"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::LMPop {
+                keys: vec![Bytes::from("a"), Bytes::from("b")],
+                end: ListEnd::Right,
+                count: None,
+            })
+        );
+        assert!(parse(&["lmpop", "1", "a", "min"]).is_err());
+        assert_eq!(
+            parse(&["blmpop", "0", "1", "a", "left", "count", "2"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::BLMPop {
+                keys: vec![Bytes::from("a")],
+                timeout: None,
+                end: ListEnd::Left,
+                count: Some(2),
+            })
+        );
+        assert!(parse(&["rpush", "list"]).is_err());
+        assert!(parse(&["lpop", "list", "-1"]).is_err());
+    }
+
+    #[test]
+    fn parses_the_number_of_keys_of_multi_key_pops() {
+        assert_eq!(
+            parse(&["ZMPOP", "2", "a", "b", "max", "COUNT", "3"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::ZMPop {
+                keys: vec![Bytes::from("a"), Bytes::from("b")],
+                end: MinOrMax::Max,
+                count: Some(3),
+            })
+        );
+        assert_eq!(
+            parse(&["sintercard", "1", "a", "limit", "0"]).unwrap(),
+            RedisCommand::Store(RedisStoreCommand::SInterCard {
+                keys: vec![Bytes::from("a")],
+                limit: None,
+            })
+        );
+        // the keys run out, or a key is taken for where to pop from
+        assert!(parse(&["zmpop", "3", "a", "b", "min"]).is_err());
+        assert!(parse(&["zmpop", "1", "a", "b", "min"]).is_err());
+        assert!(parse(&["zmpop", "0", "min"]).is_err());
+        assert!(parse(&["zmpop", "1", "a", "min", "count", "0"]).is_err());
+    }
+
+    #[test]
+    fn parses_malformed_acks_without_failing() {
+        for (offset, section) in [
+            (
+                &["12"][..],
+                ReplConfSection::Ack {
+                    processed_bytes: 12,
+                },
+            ),
+            (
+                &["-1"],
+                ReplConfSection::MalformedAck {
+                    offset: Bytes::from_static(b"-1"),
+                },
+            ),
+            (
+                &["garbage"],
+                ReplConfSection::MalformedAck {
+                    offset: Bytes::from_static(b"garbage"),
+                },
+            ),
+            (
+                &[],
+                ReplConfSection::MalformedAck {
+                    offset: Bytes::new(),
+                },
+            ),
+        ] {
+            let args = ["REPLCONF", "ACK"].iter().chain(offset);
+            let command = encoding::array(args.map(|arg| encoding::bulk_string(*arg)).collect());
+            assert_eq!(
+                RedisCommand::try_from(command).unwrap(),
+                RedisCommand::Replication(RedisReplicationCommand::ReplConf { section })
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_unsupported_debug_subcommands() {
+        let args = ["DEBUG", "QUICKLIST-PACKED-THRESHOLD", "1K"];
+        let command = parse(&args).unwrap();
+        assert_eq!(
+            command,
+            RedisCommand::Server(RedisServerCommand::Debug {
+                section: DebugSection::Unsupported {
+                    subcommand: Bytes::from_static(b"QUICKLIST-PACKED-THRESHOLD"),
+                    args: vec![Bytes::from_static(b"1K")],
+                },
+            })
+        );
+        // re-encoded as it was sent, for the slow log and `MONITOR`
+        assert_eq!(
+            Bytes::from(&command),
+            Bytes::from(encoding::array(
+                args.iter().map(encoding::bulk_string).collect()
+            ))
+        );
+
+        assert!(parse(&["DEBUG"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn parses_failover() {
+        let mut stream = RESPReader::new(
+            "*6\r\n$8\r\nfailover\r\n$2\r\nto\r\n$9\r\nlocalhost\r\n$4\r\n6380\r\n$7\r\ntimeout\r\n$3\r\n100\r\n"
+                .as_bytes(),
+        );
+        let value = stream.read_value().await.unwrap();
+        let command: anyhow::Result<RedisCommand> = value.try_into();
+        assert_eq!(
+            command.unwrap(),
+            RedisCommand::Replication(RedisReplicationCommand::Failover {
+                section: FailoverSection::To {
+                    host: "localhost".to_string(),
+                    port: 6380,
+                    timeout: Some(100),
+                },
+            })
+        )
+    }
+
+    #[tokio::test]
+    async fn parses_getex() {
+        let mut stream =
+            RESPReader::new("*3\r\n$5\r\ngetex\r\n$3\r\nkey\r\n$7\r\npersist\r\n".as_bytes());
+        let value = stream.read_value().await.unwrap();
+        let command: anyhow::Result<RedisCommand> = value.try_into();
+        assert_eq!(
+            command.unwrap(),
+            RedisCommand::Store(RedisStoreCommand::GetEx {
+                key: Bytes::from_static(b"key"),
+                expiry_option: Some(ExpiryOption::Persist),
+            })
         )
     }
+
+    #[tokio::test]
+    async fn rejects_getex_with_multiple_expiry_options() {
+        let mut stream = RESPReader::new(
+            "*5\r\n$5\r\ngetex\r\n$3\r\nkey\r\n$2\r\nex\r\n$2\r\n10\r\n$7\r\npersist\r\n"
+                .as_bytes(),
+        );
+        let value = stream.read_value().await.unwrap();
+        let command: anyhow::Result<RedisCommand> = value.try_into();
+        assert!(command.is_err());
+    }
+
+    #[test]
+    fn rejects_getex_expire_times_that_are_zero_or_too_far() {
+        for args in [
+            ["getex", "key", "ex", "0"],
+            ["getex", "key", "px", "0"],
+            ["getex", "key", "exat", "0"],
+            ["getex", "key", "pxat", "0"],
+            ["getex", "key", "ex", "18446744073709551615"],
+            ["getex", "key", "px", "18446744073709551615"],
+            ["getex", "key", "exat", "9223372036854775807"],
+            ["getex", "key", "pxat", "18446744073709551615"],
+        ] {
+            assert_eq!(
+                parse(&args).unwrap_err().to_string(),
+                "[redis - error] invalid expire time in 'getex' command"
+            );
+        }
+
+        assert!(parse(&["getex", "key", "pxat", "9223372036854775807"]).is_ok());
+    }
 }
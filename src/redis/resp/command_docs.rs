@@ -0,0 +1,1080 @@
+use super::{encoding, RESPValue};
+
+/// What `COMMAND DOCS` reports about a command.
+///
+/// The arguments are written in a compact syntax rather than spelled out as nested
+/// structures:
+/// - `name` is an argument, which is a string unless written `name:type`. Only `key`
+///   is a key without saying so.
+/// - `TOKEN` is a literal word, and `name:TOKEN` names it. A literal word followed by
+///   a single argument becomes the token of that argument.
+/// - `[...]` is optional, `(...)` only groups, and `...` after an argument repeats it.
+/// - `a | b` inside a group picks one of the alternatives. A group can be named by
+///   starting it with `name:`, which every group with alternatives has to be.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommandDocs {
+    pub name: &'static str,
+    pub summary: &'static str,
+    /// The Redis version that introduced the command.
+    pub since: &'static str,
+    pub group: &'static str,
+    pub syntax: &'static str,
+}
+
+impl CommandDocs {
+    const fn new(
+        name: &'static str,
+        summary: &'static str,
+        since: &'static str,
+        group: &'static str,
+        syntax: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            summary,
+            since,
+            group,
+            syntax,
+        }
+    }
+
+    /// The documentation of the command, as a map for RESP3 connections and a flat array
+    /// of names and values otherwise. Commands without arguments leave them out, like in
+    /// Redis.
+    pub fn docs(&self, is_resp3: bool) -> RESPValue {
+        let mut fields = vec![
+            ("summary", encoding::bulk_string(self.summary)),
+            ("since", encoding::bulk_string(self.since)),
+            ("group", encoding::bulk_string(self.group)),
+        ];
+        let arguments = self.arguments().unwrap_or_default();
+        if !arguments.is_empty() {
+            fields.push((
+                "arguments",
+                encoding::array(
+                    arguments
+                        .iter()
+                        .map(|argument| argument.docs(is_resp3))
+                        .collect(),
+                ),
+            ));
+        }
+
+        fields_to_resp(is_resp3, fields)
+    }
+
+    /// The arguments described by the syntax, or `None` if the syntax is malformed.
+    fn arguments(&self) -> Option<Vec<Argument>> {
+        let mut parser = SyntaxParser {
+            tokens: tokenize(self.syntax),
+            position: 0,
+        };
+        let arguments = parser.parse_sequence()?;
+        parser.is_finished().then(|| pair_tokens(arguments))
+    }
+}
+
+const TYPES: &[&str] = &["string", "integer", "double", "key", "pattern", "unix-time"];
+
+#[derive(Debug, PartialEq, Eq)]
+struct Argument {
+    name: String,
+    /// One of `TYPES`, or `pure-token`, `block` or `oneof`.
+    kind: &'static str,
+    token: Option<&'static str>,
+    optional: bool,
+    multiple: bool,
+    arguments: Vec<Argument>,
+}
+
+impl Argument {
+    fn new(name: impl Into<String>, kind: &'static str) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            token: None,
+            optional: false,
+            multiple: false,
+            arguments: vec![],
+        }
+    }
+
+    fn is_bare_token(&self) -> bool {
+        self.kind == "pure-token" && !self.optional && !self.multiple
+    }
+
+    /// Whether a literal word right before the argument is its token.
+    fn takes_token(&self) -> bool {
+        TYPES.contains(&self.kind) && self.token.is_none() && !self.optional
+    }
+
+    fn docs(&self, is_resp3: bool) -> RESPValue {
+        let mut fields = vec![
+            ("name", encoding::bulk_string(&self.name)),
+            ("type", encoding::bulk_string(self.kind)),
+        ];
+        if let Some(token) = self.token {
+            fields.push(("token", encoding::bulk_string(token)));
+        }
+
+        let flags = [("optional", self.optional), ("multiple", self.multiple)]
+            .into_iter()
+            .filter(|(_, is_set)| *is_set)
+            .map(|(flag, _)| encoding::simple_string(flag))
+            .collect::<Vec<_>>();
+        if !flags.is_empty() {
+            fields.push(("flags", encoding::array(flags)));
+        }
+
+        if !self.arguments.is_empty() {
+            fields.push((
+                "arguments",
+                encoding::array(
+                    self.arguments
+                        .iter()
+                        .map(|argument| argument.docs(is_resp3))
+                        .collect(),
+                ),
+            ));
+        }
+
+        fields_to_resp(is_resp3, fields)
+    }
+}
+
+fn fields_to_resp(is_resp3: bool, fields: Vec<(&str, RESPValue)>) -> RESPValue {
+    encoding::map_or_array(
+        is_resp3,
+        fields
+            .into_iter()
+            .map(|(name, value)| (encoding::bulk_string(name), value))
+            .collect(),
+    )
+}
+
+fn tokenize(syntax: &'static str) -> Vec<&'static str> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (index, char) in syntax.char_indices() {
+        if char.is_whitespace() || matches!(char, '[' | ']' | '(' | ')' | '|') {
+            if let Some(start) = start.take() {
+                tokens.push(&syntax[start..index]);
+            }
+
+            if !char.is_whitespace() {
+                tokens.push(&syntax[index..index + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(index);
+        }
+    }
+
+    if let Some(start) = start {
+        tokens.push(&syntax[start..]);
+    }
+
+    tokens
+}
+
+/// Gives each literal word that is directly followed by a single argument to that
+/// argument as its token.
+fn pair_tokens(arguments: Vec<Argument>) -> Vec<Argument> {
+    let mut paired: Vec<Argument> = vec![];
+    for mut argument in arguments {
+        match paired.last() {
+            Some(previous) if previous.is_bare_token() && argument.takes_token() => {
+                argument.token = paired.pop().and_then(|previous| previous.token);
+            }
+            _ => {}
+        }
+
+        paired.push(argument);
+    }
+
+    paired
+}
+
+struct SyntaxParser {
+    tokens: Vec<&'static str>,
+    position: usize,
+}
+
+impl SyntaxParser {
+    fn peek(&self) -> Option<&'static str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<&'static str> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn is_finished(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    /// The arguments up to the end of the enclosing group or alternative, with their
+    /// tokens not paired up yet.
+    fn parse_sequence(&mut self) -> Option<Vec<Argument>> {
+        let mut arguments: Vec<Argument> = vec![];
+        while let Some(token) = self.peek() {
+            match token {
+                "]" | ")" | "|" => break,
+                "[" | "(" => {
+                    self.next();
+                    let mut group = self.parse_group(if token == "[" { "]" } else { ")" })?;
+                    group.optional |= token == "[";
+                    arguments.push(group);
+                }
+                "..." => {
+                    self.next();
+                    arguments.last_mut()?.multiple = true;
+                }
+                word => {
+                    self.next();
+                    arguments.push(Self::parse_word(word)?);
+                }
+            }
+        }
+
+        Some(arguments)
+    }
+
+    fn parse_group(&mut self, closing: &str) -> Option<Argument> {
+        let label = self
+            .peek()
+            .and_then(|token| token.strip_suffix(':'))
+            .filter(|label| !label.is_empty());
+        if label.is_some() {
+            self.next();
+        }
+
+        let mut alternatives = vec![self.parse_sequence()?];
+        while self.peek() == Some("|") {
+            self.next();
+            alternatives.push(self.parse_sequence()?);
+        }
+
+        if self.next()? != closing {
+            return None;
+        }
+
+        if alternatives.len() == 1 {
+            return alternatives
+                .pop()
+                .and_then(|sequence| block(label, sequence));
+        }
+
+        let mut one_of = Argument::new(label?, "oneof");
+        one_of.arguments = alternatives
+            .into_iter()
+            .map(|sequence| block(None, sequence))
+            .collect::<Option<_>>()?;
+        Some(one_of)
+    }
+
+    fn parse_word(word: &'static str) -> Option<Argument> {
+        let is_literal = |word: &str| !word.chars().any(|char| char.is_ascii_lowercase());
+        let (name, kind) = match word.split_once(':') {
+            Some((name, literal))
+                if !name.is_empty() && !literal.is_empty() && is_literal(literal) =>
+            {
+                let mut argument = Argument::new(name, "pure-token");
+                argument.token = Some(literal);
+                return Some(argument);
+            }
+            Some((name, kind)) => (name, *TYPES.iter().find(|&&known| known == kind)?),
+            None if is_literal(word) => {
+                let mut argument = Argument::new(word.to_ascii_lowercase(), "pure-token");
+                argument.token = Some(word);
+                return Some(argument);
+            }
+            None if word == "key" => (word, "key"),
+            None => (word, "string"),
+        };
+
+        (!name.is_empty()).then(|| Argument::new(name, kind))
+    }
+}
+
+/// Turns the arguments of a group into a single argument. A group starting with a literal
+/// word that is not just the token of the argument after it becomes a block with that
+/// token, as in `LIMIT offset count`.
+fn block(label: Option<&str>, mut sequence: Vec<Argument>) -> Option<Argument> {
+    let starts_with_token = sequence.first().is_some_and(Argument::is_bare_token);
+    let is_token_of_argument = sequence.len() == 2 && sequence[1].takes_token();
+    if starts_with_token && sequence.len() > 1 && !is_token_of_argument {
+        let token = sequence.remove(0);
+        let mut block = Argument::new(label.map_or(token.name, str::to_string), "block");
+        block.token = token.token;
+        block.arguments = pair_tokens(sequence);
+        return Some(block);
+    }
+
+    let mut sequence = pair_tokens(sequence);
+    if sequence.len() == 1 {
+        let mut argument = sequence.pop()?;
+        if let Some(label) = label {
+            argument.name = label.to_string();
+        }
+
+        return Some(argument);
+    }
+
+    let name = label.map_or_else(
+        || sequence.first().map(|first| first.name.clone()),
+        |label| Some(label.to_string()),
+    )?;
+    let mut block = Argument::new(name, "block");
+    block.arguments = sequence;
+    Some(block)
+}
+
+/// The documentation of every command in `command_table::COMMANDS`, with the summary,
+/// version and group Redis gives it. The arguments are the ones this server accepts,
+/// which for some commands are fewer than in Redis.
+pub const DOCS: &[CommandDocs] = &[
+    CommandDocs::new(
+        "get",
+        "Returns the string value of a key.",
+        "1.0.0",
+        "string",
+        "key",
+    ),
+    CommandDocs::new(
+        "mget",
+        "Atomically returns the string values of one or more keys.",
+        "1.0.0",
+        "string",
+        "key ...",
+    ),
+    CommandDocs::new(
+        "getex",
+        "Returns the string value of a key after setting its expiration time.",
+        "6.2.0",
+        "string",
+        "key [expiration: EX seconds:integer | PX milliseconds:integer \
+         | EXAT unix-time-seconds:unix-time | PXAT unix-time-milliseconds:unix-time | PERSIST]",
+    ),
+    CommandDocs::new(
+        "getdel",
+        "Returns the string value of a key after deleting the key.",
+        "6.2.0",
+        "string",
+        "key",
+    ),
+    CommandDocs::new(
+        "del",
+        "Deletes one or more keys.",
+        "1.0.0",
+        "generic",
+        "key ...",
+    ),
+    CommandDocs::new(
+        "set",
+        "Sets the string value of a key, optionally expiring it after some milliseconds.",
+        "1.0.0",
+        "string",
+        "key value [PX milliseconds:integer]",
+    ),
+    CommandDocs::new(
+        "keys",
+        "Returns all key names that match a pattern.",
+        "1.0.0",
+        "generic",
+        "pattern:pattern",
+    ),
+    CommandDocs::new(
+        "type",
+        "Determines the type of value stored at a key.",
+        "1.0.0",
+        "generic",
+        "key",
+    ),
+    CommandDocs::new(
+        "xadd",
+        "Appends a new entry to a stream, creating the key if it doesn't exist.",
+        "5.0.0",
+        "stream",
+        "key [NOMKSTREAM] \
+         [trim: (strategy: MAXLEN | MINID) [operator: equal:= | approximately:~] threshold \
+         [LIMIT count:integer]] \
+         (id-selector: auto-id:* | id) (data: field value) ...",
+    ),
+    CommandDocs::new(
+        "xtrim",
+        "Deletes messages from the beginning of a stream.",
+        "5.0.0",
+        "stream",
+        "key (strategy: MAXLEN | MINID) [operator: equal:= | approximately:~] threshold \
+         [LIMIT count:integer]",
+    ),
+    CommandDocs::new(
+        "xinfo",
+        "Returns information about a stream.",
+        "5.0.0",
+        "stream",
+        "(subcommand: STREAM key | HELP)",
+    ),
+    CommandDocs::new(
+        "xgroup",
+        "Creates a consumer group.",
+        "5.0.0",
+        "stream",
+        "(subcommand: CREATE key group (id-selector: id | new-id:$) [MKSTREAM] | HELP)",
+    ),
+    CommandDocs::new(
+        "xreadgroup",
+        "Returns new or historical messages from a stream for a consumer in a group.",
+        "5.0.0",
+        "stream",
+        "GROUP group consumer [COUNT count:integer] [NOACK] STREAMS key ... id ...",
+    ),
+    CommandDocs::new(
+        "xack",
+        "Returns the number of messages that were successfully acknowledged by the \
+         consumer group member of a stream.",
+        "5.0.0",
+        "stream",
+        "key group id ...",
+    ),
+    CommandDocs::new(
+        "object",
+        "Returns the internal encoding of a value.",
+        "2.2.3",
+        "generic",
+        "ENCODING key",
+    ),
+    CommandDocs::new(
+        "swapdb",
+        "Swaps two databases.",
+        "4.0.0",
+        "server",
+        "index1:integer index2:integer",
+    ),
+    CommandDocs::new(
+        "move",
+        "Moves a key to another database.",
+        "1.0.0",
+        "generic",
+        "key db:integer",
+    ),
+    CommandDocs::new(
+        "setbit",
+        "Sets or clears the bit at an offset of a string, creating the key if it doesn't exist.",
+        "2.2.0",
+        "bitmap",
+        "key offset:integer value:integer",
+    ),
+    CommandDocs::new(
+        "getbit",
+        "Returns the bit at an offset of a string.",
+        "2.2.0",
+        "bitmap",
+        "key offset:integer",
+    ),
+    CommandDocs::new(
+        "bitcount",
+        "Counts the set bits in a string.",
+        "2.6.0",
+        "bitmap",
+        "key [range: start:integer end:integer [unit: BYTE | BIT]]",
+    ),
+    CommandDocs::new(
+        "sadd",
+        "Adds one or more members to a set, creating the key if it doesn't exist.",
+        "1.0.0",
+        "set",
+        "key member ...",
+    ),
+    CommandDocs::new(
+        "smembers",
+        "Returns all members of a set.",
+        "1.0.0",
+        "set",
+        "key",
+    ),
+    CommandDocs::new(
+        "sintercard",
+        "Returns the number of members of the intersection of multiple sets.",
+        "7.0.0",
+        "set",
+        "numkeys:integer key ... [LIMIT limit:integer]",
+    ),
+    CommandDocs::new(
+        "sinterstore",
+        "Stores the intersection of multiple sets in a key.",
+        "1.0.0",
+        "set",
+        "destination:key key ...",
+    ),
+    CommandDocs::new(
+        "sunionstore",
+        "Stores the union of multiple sets in a key.",
+        "1.0.0",
+        "set",
+        "destination:key key ...",
+    ),
+    CommandDocs::new(
+        "sdiffstore",
+        "Stores the difference of multiple sets in a key.",
+        "1.0.0",
+        "set",
+        "destination:key key ...",
+    ),
+    CommandDocs::new(
+        "smove",
+        "Moves a member from one set to another.",
+        "1.0.0",
+        "set",
+        "source:key destination:key member",
+    ),
+    CommandDocs::new(
+        "lpush",
+        "Prepends one or more elements to a list. Creates the key if it doesn't exist.",
+        "1.0.0",
+        "list",
+        "key element ...",
+    ),
+    CommandDocs::new(
+        "rpush",
+        "Appends one or more elements to a list. Creates the key if it doesn't exist.",
+        "1.0.0",
+        "list",
+        "key element ...",
+    ),
+    CommandDocs::new(
+        "lpop",
+        "Returns the first elements in a list after removing it. Deletes the list if the \
+         last element was popped.",
+        "1.0.0",
+        "list",
+        "key [count:integer]",
+    ),
+    CommandDocs::new(
+        "rpop",
+        "Returns and removes the last elements of the list. Deletes the list if the last \
+         element was popped.",
+        "1.0.0",
+        "list",
+        "key [count:integer]",
+    ),
+    CommandDocs::new(
+        "lmpop",
+        "Returns multiple elements from a list after removing them. Deletes the list if the \
+         last element was popped.",
+        "7.0.0",
+        "list",
+        "numkeys:integer key ... (where: LEFT | RIGHT) [COUNT count:integer]",
+    ),
+    CommandDocs::new(
+        "blmpop",
+        "Pops the first element from one of multiple lists. Blocks until an element is \
+         available otherwise. Deletes the list if the last element was popped.",
+        "7.0.0",
+        "list",
+        "timeout:double numkeys:integer key ... (where: LEFT | RIGHT) [COUNT count:integer]",
+    ),
+    CommandDocs::new(
+        "llen",
+        "Returns the length of a list.",
+        "1.0.0",
+        "list",
+        "key",
+    ),
+    CommandDocs::new(
+        "lrange",
+        "Returns a range of elements from a list.",
+        "1.0.0",
+        "list",
+        "key start:integer stop:integer",
+    ),
+    CommandDocs::new(
+        "hset",
+        "Creates or modifies the values of fields in a hash.",
+        "2.0.0",
+        "hash",
+        "key (data: field value) ...",
+    ),
+    CommandDocs::new(
+        "hget",
+        "Returns the value of a field in a hash.",
+        "2.0.0",
+        "hash",
+        "key field",
+    ),
+    CommandDocs::new(
+        "hrandfield",
+        "Returns one or more random fields from a hash.",
+        "6.2.0",
+        "hash",
+        "key [options: count:integer [WITHVALUES]]",
+    ),
+    CommandDocs::new(
+        "zadd",
+        "Adds one or more members to a sorted set, creating the key if it doesn't exist.",
+        "1.2.0",
+        "sorted-set",
+        "key (data: score:double member) ...",
+    ),
+    CommandDocs::new(
+        "zscore",
+        "Returns the score of a member in a sorted set.",
+        "1.2.0",
+        "sorted-set",
+        "key member",
+    ),
+    CommandDocs::new(
+        "zrandmember",
+        "Returns one or more random members from a sorted set.",
+        "6.2.0",
+        "sorted-set",
+        "key [options: count:integer [WITHSCORES]]",
+    ),
+    CommandDocs::new(
+        "zpopmin",
+        "Removes and returns the members with the lowest scores from a sorted set.",
+        "5.0.0",
+        "sorted-set",
+        "key [count:integer]",
+    ),
+    CommandDocs::new(
+        "zpopmax",
+        "Removes and returns the members with the highest scores from a sorted set.",
+        "5.0.0",
+        "sorted-set",
+        "key [count:integer]",
+    ),
+    CommandDocs::new(
+        "zmpop",
+        "Removes and returns the members with the lowest or highest scores from the first \
+         non-empty sorted set.",
+        "7.0.0",
+        "sorted-set",
+        "numkeys:integer key ... (where: MIN | MAX) [COUNT count:integer]",
+    ),
+    CommandDocs::new(
+        "bzpopmin",
+        "Removes and returns the member with the lowest score from one or more sorted sets, \
+         blocking until one is available.",
+        "5.0.0",
+        "sorted-set",
+        "key ... timeout:double",
+    ),
+    CommandDocs::new(
+        "bzpopmax",
+        "Removes and returns the member with the highest score from one or more sorted sets, \
+         blocking until one is available.",
+        "5.0.0",
+        "sorted-set",
+        "key ... timeout:double",
+    ),
+    CommandDocs::new(
+        "bzmpop",
+        "Removes and returns the members with the lowest or highest scores from the first \
+         non-empty sorted set, blocking until one is available.",
+        "7.0.0",
+        "sorted-set",
+        "timeout:double numkeys:integer key ... (where: MIN | MAX) [COUNT count:integer]",
+    ),
+    CommandDocs::new(
+        "pfadd",
+        "Adds elements to a HyperLogLog, creating the key if it doesn't exist.",
+        "2.8.9",
+        "hyperloglog",
+        "key [element ...]",
+    ),
+    CommandDocs::new(
+        "pfcount",
+        "Returns the approximate number of distinct elements added to the HyperLogLogs.",
+        "2.8.9",
+        "hyperloglog",
+        "key ...",
+    ),
+    CommandDocs::new(
+        "pfmerge",
+        "Merges one or more HyperLogLogs into a single key.",
+        "2.8.9",
+        "hyperloglog",
+        "destkey:key [sourcekey:key ...]",
+    ),
+    CommandDocs::new(
+        "geoadd",
+        "Adds one or more members to a geospatial index, creating the key if it doesn't exist.",
+        "3.2.0",
+        "geo",
+        "key (data: longitude:double latitude:double member) ...",
+    ),
+    CommandDocs::new(
+        "geopos",
+        "Returns the longitude and latitude of members of a geospatial index.",
+        "3.2.0",
+        "geo",
+        "key [member ...]",
+    ),
+    CommandDocs::new(
+        "geodist",
+        "Returns the distance between two members of a geospatial index.",
+        "3.2.0",
+        "geo",
+        "key member1 member2 [unit: M | KM | FT | MI]",
+    ),
+    CommandDocs::new(
+        "geosearch",
+        "Returns the members of a geospatial index that are inside a box or a circle.",
+        "6.2.0",
+        "geo",
+        "key (from: FROMMEMBER member | FROMLONLAT longitude:double latitude:double) \
+         (by: BYRADIUS radius:double (unit: M | KM | FT | MI) \
+         | BYBOX width:double height:double (unit: M | KM | FT | MI)) \
+         [order: ASC | DESC] [count-block: COUNT count:integer [ANY]] \
+         [WITHCOORD] [WITHDIST] [WITHHASH]",
+    ),
+    CommandDocs::new(
+        "sort",
+        "Sorts the elements in a list, a set, or a sorted set, optionally storing the result.",
+        "1.0.0",
+        "generic",
+        "key [BY pattern:pattern] [limit: LIMIT offset:integer count:integer] \
+         [GET pattern:pattern ...] [order: ASC | DESC] [ALPHA] [STORE destination:key]",
+    ),
+    CommandDocs::new(
+        "ping",
+        "Returns the server's liveliness response.",
+        "1.0.0",
+        "connection",
+        "[message]",
+    ),
+    CommandDocs::new(
+        "echo",
+        "Returns the given string.",
+        "1.0.0",
+        "connection",
+        "message",
+    ),
+    CommandDocs::new(
+        "select",
+        "Changes the selected database.",
+        "1.0.0",
+        "connection",
+        "index:integer",
+    ),
+    CommandDocs::new(
+        "lolwut",
+        "Displays computer art and the Redis version.",
+        "5.0.0",
+        "server",
+        "[VERSION version:integer]",
+    ),
+    CommandDocs::new("quit", "Closes the connection.", "1.0.0", "connection", ""),
+    CommandDocs::new(
+        "auth",
+        "Authenticates the connection.",
+        "1.0.0",
+        "connection",
+        "[username] password",
+    ),
+    CommandDocs::new(
+        "hello",
+        "Handshakes with the server, optionally switching the protocol version.",
+        "6.0.0",
+        "connection",
+        "[protover:integer]",
+    ),
+    CommandDocs::new(
+        "acl",
+        "Inspects and changes the users allowed to connect.",
+        "6.0.0",
+        "server",
+        "(subcommand: WHOAMI | LIST | CAT | SETUSER username [rule ...] \
+         | GETUSER username | HELP)",
+    ),
+    CommandDocs::new(
+        "command",
+        "Returns information about the commands the server implements.",
+        "2.8.13",
+        "server",
+        "(subcommand: INFO [command-name ...] | COUNT | DOCS [command-name ...])",
+    ),
+    CommandDocs::new(
+        "config",
+        "Returns or changes the values of configuration parameters.",
+        "2.0.0",
+        "server",
+        "(subcommand: GET parameter ... | SET parameter value ... | HELP)",
+    ),
+    CommandDocs::new(
+        "debug",
+        "A container for debugging commands.",
+        "1.0.0",
+        "server",
+        "subcommand [argument ...]",
+    ),
+    CommandDocs::new(
+        "info",
+        "Returns information and statistics about the server.",
+        "1.0.0",
+        "server",
+        "[section]",
+    ),
+    CommandDocs::new(
+        "monitor",
+        "Listens for all commands received by the server in real time.",
+        "1.0.0",
+        "server",
+        "",
+    ),
+    CommandDocs::new(
+        "slowlog",
+        "Inspects the commands that took too long to execute.",
+        "2.2.12",
+        "server",
+        "(subcommand: GET [count:integer] | LEN | RESET | HELP)",
+    ),
+    CommandDocs::new(
+        "latency",
+        "Inspects the latency spikes recorded by the server.",
+        "2.8.13",
+        "server",
+        "(subcommand: HISTORY event | LATEST | RESET [event ...] | HELP)",
+    ),
+    CommandDocs::new(
+        "cluster",
+        "Returns information about the cluster the server belongs to.",
+        "3.0.0",
+        "cluster",
+        "(subcommand: INFO | MYID | SLOTS | SHARDS | NODES | HELP)",
+    ),
+    CommandDocs::new(
+        "client",
+        "Changes how the server treats the connection.",
+        "2.4.0",
+        "connection",
+        "(subcommand: NO-EVICT (enabled: ON | OFF) | NO-TOUCH (enabled: ON | OFF) | HELP)",
+    ),
+    CommandDocs::new(
+        "function",
+        "Lists, dumps or loads functions, of which there are none since scripts are not run.",
+        "7.0.0",
+        "scripting",
+        "(subcommand: LIST [LIBRARYNAME library-name-pattern:string] [WITHCODE] | DUMP | LOAD [REPLACE] function-code:string | HELP)",
+    ),
+    CommandDocs::new(
+        "eval",
+        "Executes a Lua script, which fails since scripts are not run.",
+        "2.6.0",
+        "scripting",
+        "script:string numkeys:integer [key:key ...] [arg:string ...]",
+    ),
+    CommandDocs::new(
+        "evalsha",
+        "Executes a cached Lua script, of which there are none since scripts are not run.",
+        "2.6.0",
+        "scripting",
+        "sha1:string numkeys:integer [key:key ...] [arg:string ...]",
+    ),
+    CommandDocs::new(
+        "script",
+        "Manages the Lua script cache, which is always empty since scripts are not run.",
+        "2.6.0",
+        "scripting",
+        "(subcommand: LOAD script:string | EXISTS sha1:string [sha1:string ...] | FLUSH [(flush-type: ASYNC | SYNC)] | HELP)",
+    ),
+    CommandDocs::new(
+        "fcall",
+        "Invokes a function, which fails since scripts are not run.",
+        "7.0.0",
+        "scripting",
+        "function:string numkeys:integer [key:key ...] [arg:string ...]",
+    ),
+    CommandDocs::new(
+        "fcall_ro",
+        "Invokes a read-only function, which fails since scripts are not run.",
+        "7.0.0",
+        "scripting",
+        "function:string numkeys:integer [key:key ...] [arg:string ...]",
+    ),
+    CommandDocs::new(
+        "lastsave",
+        "Returns the Unix timestamp of the last successful save to disk.",
+        "1.0.0",
+        "server",
+        "",
+    ),
+    CommandDocs::new(
+        "subscribe",
+        "Listens for messages published to channels.",
+        "2.0.0",
+        "pubsub",
+        "channel ...",
+    ),
+    CommandDocs::new(
+        "unsubscribe",
+        "Stops listening to messages published to channels.",
+        "2.0.0",
+        "pubsub",
+        "[channel ...]",
+    ),
+    CommandDocs::new(
+        "publish",
+        "Posts a message to a channel.",
+        "2.0.0",
+        "pubsub",
+        "channel message",
+    ),
+    CommandDocs::new(
+        "pubsub",
+        "Inspects the state of the pub-sub channels.",
+        "2.8.0",
+        "pubsub",
+        "(subcommand: CHANNELS [pattern:pattern] | NUMSUB [channel ...] | NUMPAT | HELP)",
+    ),
+    CommandDocs::new(
+        "ssubscribe",
+        "Listens for messages published to shard channels.",
+        "7.0.0",
+        "pubsub",
+        "shardchannel ...",
+    ),
+    CommandDocs::new(
+        "sunsubscribe",
+        "Stops listening to messages published to shard channels.",
+        "7.0.0",
+        "pubsub",
+        "[shardchannel ...]",
+    ),
+    CommandDocs::new(
+        "spublish",
+        "Posts a message to a shard channel.",
+        "7.0.0",
+        "pubsub",
+        "shardchannel message",
+    ),
+    CommandDocs::new(
+        "replconf",
+        "An internal command for configuring the replication stream.",
+        "3.0.0",
+        "server",
+        "",
+    ),
+    CommandDocs::new(
+        "psync",
+        "An internal command used in replication.",
+        "2.8.0",
+        "server",
+        "replicationid offset:integer [FAILOVER]",
+    ),
+    CommandDocs::new(
+        "wait",
+        "Blocks until the writes sent by the connection have reached a number of replicas.",
+        "3.0.0",
+        "generic",
+        "numreplicas:integer timeout:integer",
+    ),
+    CommandDocs::new(
+        "waitaof",
+        "Blocks until the writes sent by the connection have been written to the \
+         append-only files of the server and a number of replicas.",
+        "7.2.0",
+        "generic",
+        "numlocal:integer numreplicas:integer timeout:integer",
+    ),
+    CommandDocs::new(
+        "failover",
+        "Starts a coordinated failover to one of the replicas.",
+        "6.2.0",
+        "server",
+        "[target: TO host port:integer] [ABORT] [TIMEOUT milliseconds:integer]",
+    ),
+];
+
+pub fn lookup(name: &[u8]) -> Option<&'static CommandDocs> {
+    DOCS.iter()
+        .find(|docs| docs.name.as_bytes().eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redis::resp::command_table::COMMANDS;
+
+    use super::{Argument, CommandDocs, DOCS};
+
+    fn argument(name: &str, kind: &'static str) -> Argument {
+        Argument::new(name, kind)
+    }
+
+    #[test]
+    fn documents_every_command_in_the_table() {
+        assert_eq!(DOCS.len(), COMMANDS.len());
+        for spec in COMMANDS {
+            let docs = super::lookup(spec.name.as_bytes())
+                .unwrap_or_else(|| panic!("{} is not documented", spec.name));
+            assert!(
+                docs.arguments().is_some(),
+                "the syntax of {} is malformed",
+                spec.name
+            );
+        }
+    }
+
+    #[test]
+    fn parses_the_syntax_of_arguments() {
+        let docs = CommandDocs::new(
+            "test",
+            "",
+            "",
+            "",
+            "key [order: ASC | DESC] [limit: LIMIT offset:integer count:integer] \
+             [BY pattern:pattern] (data: field value) ... [auto-id:*]",
+        );
+
+        let mut order = argument("order", "oneof");
+        order.optional = true;
+        order.arguments = ["asc", "desc"]
+            .into_iter()
+            .map(|name| {
+                let mut token = argument(name, "pure-token");
+                token.token = Some(if name == "asc" { "ASC" } else { "DESC" });
+                token
+            })
+            .collect();
+
+        let mut limit = argument("limit", "block");
+        limit.optional = true;
+        limit.token = Some("LIMIT");
+        limit.arguments = vec![argument("offset", "integer"), argument("count", "integer")];
+
+        let mut by = argument("pattern", "pattern");
+        by.optional = true;
+        by.token = Some("BY");
+
+        let mut data = argument("data", "block");
+        data.multiple = true;
+        data.arguments = vec![argument("field", "string"), argument("value", "string")];
+
+        let mut auto_id = argument("auto-id", "pure-token");
+        auto_id.optional = true;
+        auto_id.token = Some("*");
+
+        assert_eq!(
+            docs.arguments(),
+            Some(vec![
+                argument("key", "key"),
+                order,
+                limit,
+                by,
+                data,
+                auto_id
+            ])
+        );
+
+        for malformed in ["key [value", "(ASC | DESC)", "key:number", "... key"] {
+            assert_eq!(
+                CommandDocs::new("test", "", "", "", malformed).arguments(),
+                None,
+                "{malformed}"
+            );
+        }
+    }
+}
@@ -0,0 +1,690 @@
+use super::{encoding, RESPValue};
+
+/// What `COMMAND INFO` reports about a command. A negative arity is the minimum number
+/// of arguments, counting the command name, and a negative last key counts from the end.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub arity: i64,
+    pub flags: &'static [&'static str],
+    pub first_key: i64,
+    pub last_key: i64,
+    pub key_step: i64,
+    pub categories: &'static [&'static str],
+}
+
+impl CommandSpec {
+    const fn new(
+        name: &'static str,
+        arity: i64,
+        flags: &'static [&'static str],
+        (first_key, last_key, key_step): (i64, i64, i64),
+        categories: &'static [&'static str],
+    ) -> Self {
+        Self {
+            name,
+            arity,
+            flags,
+            first_key,
+            last_key,
+            key_step,
+            categories,
+        }
+    }
+
+    pub fn info(&self) -> RESPValue {
+        encoding::array(vec![
+            encoding::bulk_string(self.name),
+            encoding::integer(self.arity),
+            encoding::array(self.flags.iter().map(encoding::simple_string).collect()),
+            encoding::integer(self.first_key),
+            encoding::integer(self.last_key),
+            encoding::integer(self.key_step),
+            encoding::array(
+                self.categories
+                    .iter()
+                    .map(|category| encoding::simple_string(format!("@{category}")))
+                    .collect(),
+            ),
+        ])
+    }
+}
+
+const NO_KEYS: (i64, i64, i64) = (0, 0, 0);
+const FIRST_KEY: (i64, i64, i64) = (1, 1, 1);
+const ALL_KEYS: (i64, i64, i64) = (1, -1, 1);
+
+/// The commands this server implements, with the arity, flags and categories Redis
+/// reports for them.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new(
+        "get",
+        2,
+        &["readonly", "fast"],
+        FIRST_KEY,
+        &["read", "string", "fast"],
+    ),
+    CommandSpec::new(
+        "mget",
+        -2,
+        &["readonly", "fast"],
+        ALL_KEYS,
+        &["read", "string", "fast"],
+    ),
+    CommandSpec::new(
+        "getex",
+        -2,
+        &["write", "fast"],
+        FIRST_KEY,
+        &["write", "string", "fast"],
+    ),
+    CommandSpec::new(
+        "getdel",
+        2,
+        &["write", "fast"],
+        FIRST_KEY,
+        &["write", "string", "fast"],
+    ),
+    CommandSpec::new(
+        "del",
+        -2,
+        &["write"],
+        ALL_KEYS,
+        &["keyspace", "write", "slow"],
+    ),
+    CommandSpec::new(
+        "set",
+        -3,
+        &["write", "denyoom"],
+        FIRST_KEY,
+        &["write", "string", "slow"],
+    ),
+    CommandSpec::new(
+        "keys",
+        2,
+        &["readonly"],
+        NO_KEYS,
+        &["keyspace", "read", "slow", "dangerous"],
+    ),
+    CommandSpec::new(
+        "type",
+        2,
+        &["readonly", "fast"],
+        FIRST_KEY,
+        &["keyspace", "read", "fast"],
+    ),
+    CommandSpec::new(
+        "xadd",
+        -5,
+        &["write", "denyoom", "fast"],
+        FIRST_KEY,
+        &["write", "stream", "fast"],
+    ),
+    CommandSpec::new(
+        "xtrim",
+        -4,
+        &["write"],
+        FIRST_KEY,
+        &["write", "stream", "slow"],
+    ),
+    CommandSpec::new("xinfo", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new("xgroup", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new(
+        "xreadgroup",
+        -7,
+        &["write", "blocking", "movablekeys"],
+        NO_KEYS,
+        &["write", "stream", "slow", "blocking"],
+    ),
+    CommandSpec::new(
+        "xack",
+        -4,
+        &["write", "fast"],
+        FIRST_KEY,
+        &["write", "stream", "fast"],
+    ),
+    CommandSpec::new("object", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new(
+        "swapdb",
+        3,
+        &["write", "fast"],
+        NO_KEYS,
+        &["keyspace", "write", "fast", "dangerous"],
+    ),
+    CommandSpec::new(
+        "move",
+        3,
+        &["write", "fast"],
+        FIRST_KEY,
+        &["keyspace", "write", "fast"],
+    ),
+    CommandSpec::new(
+        "setbit",
+        4,
+        &["write", "denyoom"],
+        FIRST_KEY,
+        &["write", "bitmap", "slow"],
+    ),
+    CommandSpec::new(
+        "getbit",
+        3,
+        &["readonly", "fast"],
+        FIRST_KEY,
+        &["read", "bitmap", "fast"],
+    ),
+    CommandSpec::new(
+        "bitcount",
+        -2,
+        &["readonly"],
+        FIRST_KEY,
+        &["read", "bitmap", "slow"],
+    ),
+    CommandSpec::new(
+        "sadd",
+        -3,
+        &["write", "denyoom", "fast"],
+        FIRST_KEY,
+        &["write", "set", "fast"],
+    ),
+    CommandSpec::new(
+        "smembers",
+        2,
+        &["readonly"],
+        FIRST_KEY,
+        &["read", "set", "slow"],
+    ),
+    CommandSpec::new(
+        "sintercard",
+        -3,
+        &["readonly", "movablekeys"],
+        NO_KEYS,
+        &["read", "set", "slow"],
+    ),
+    CommandSpec::new(
+        "sinterstore",
+        -3,
+        &["write", "denyoom"],
+        ALL_KEYS,
+        &["write", "set", "slow"],
+    ),
+    CommandSpec::new(
+        "sunionstore",
+        -3,
+        &["write", "denyoom"],
+        ALL_KEYS,
+        &["write", "set", "slow"],
+    ),
+    CommandSpec::new(
+        "sdiffstore",
+        -3,
+        &["write", "denyoom"],
+        ALL_KEYS,
+        &["write", "set", "slow"],
+    ),
+    CommandSpec::new(
+        "smove",
+        4,
+        &["write", "fast"],
+        (1, 2, 1),
+        &["write", "set", "fast"],
+    ),
+    CommandSpec::new(
+        "lpush",
+        -3,
+        &["write", "denyoom", "fast"],
+        FIRST_KEY,
+        &["write", "list", "fast"],
+    ),
+    CommandSpec::new(
+        "rpush",
+        -3,
+        &["write", "denyoom", "fast"],
+        FIRST_KEY,
+        &["write", "list", "fast"],
+    ),
+    CommandSpec::new(
+        "lpop",
+        -2,
+        &["write", "fast"],
+        FIRST_KEY,
+        &["write", "list", "fast"],
+    ),
+    CommandSpec::new(
+        "rpop",
+        -2,
+        &["write", "fast"],
+        FIRST_KEY,
+        &["write", "list", "fast"],
+    ),
+    CommandSpec::new(
+        "lmpop",
+        -4,
+        &["write", "movablekeys"],
+        NO_KEYS,
+        &["write", "list", "slow"],
+    ),
+    CommandSpec::new(
+        "blmpop",
+        -5,
+        &["write", "blocking", "movablekeys"],
+        NO_KEYS,
+        &["write", "list", "slow", "blocking"],
+    ),
+    CommandSpec::new(
+        "llen",
+        2,
+        &["readonly", "fast"],
+        FIRST_KEY,
+        &["read", "list", "fast"],
+    ),
+    CommandSpec::new(
+        "lrange",
+        4,
+        &["readonly"],
+        FIRST_KEY,
+        &["read", "list", "slow"],
+    ),
+    CommandSpec::new(
+        "hset",
+        -4,
+        &["write", "denyoom", "fast"],
+        FIRST_KEY,
+        &["write", "hash", "fast"],
+    ),
+    CommandSpec::new(
+        "hget",
+        3,
+        &["readonly", "fast"],
+        FIRST_KEY,
+        &["read", "hash", "fast"],
+    ),
+    CommandSpec::new(
+        "hrandfield",
+        -2,
+        &["readonly"],
+        FIRST_KEY,
+        &["read", "hash", "slow"],
+    ),
+    CommandSpec::new(
+        "zadd",
+        -4,
+        &["write", "denyoom", "fast"],
+        FIRST_KEY,
+        &["write", "sortedset", "fast"],
+    ),
+    CommandSpec::new(
+        "zscore",
+        3,
+        &["readonly", "fast"],
+        FIRST_KEY,
+        &["read", "sortedset", "fast"],
+    ),
+    CommandSpec::new(
+        "zrandmember",
+        -2,
+        &["readonly"],
+        FIRST_KEY,
+        &["read", "sortedset", "slow"],
+    ),
+    CommandSpec::new(
+        "zpopmin",
+        -2,
+        &["write", "fast"],
+        FIRST_KEY,
+        &["write", "sortedset", "fast"],
+    ),
+    CommandSpec::new(
+        "zpopmax",
+        -2,
+        &["write", "fast"],
+        FIRST_KEY,
+        &["write", "sortedset", "fast"],
+    ),
+    CommandSpec::new(
+        "zmpop",
+        -4,
+        &["write", "movablekeys"],
+        NO_KEYS,
+        &["write", "sortedset", "slow"],
+    ),
+    CommandSpec::new(
+        "bzpopmin",
+        -3,
+        &["write", "noscript", "blocking", "fast"],
+        (1, -2, 1),
+        &["write", "sortedset", "fast", "blocking"],
+    ),
+    CommandSpec::new(
+        "bzmpop",
+        -5,
+        &["write", "blocking", "movablekeys"],
+        NO_KEYS,
+        &["write", "sortedset", "slow", "blocking"],
+    ),
+    CommandSpec::new(
+        "bzpopmax",
+        -3,
+        &["write", "noscript", "blocking", "fast"],
+        (1, -2, 1),
+        &["write", "sortedset", "fast", "blocking"],
+    ),
+    CommandSpec::new(
+        "pfadd",
+        -2,
+        &["write", "denyoom", "fast"],
+        FIRST_KEY,
+        &["write", "hyperloglog", "fast"],
+    ),
+    CommandSpec::new(
+        "pfcount",
+        -2,
+        &["readonly", "may_replicate"],
+        ALL_KEYS,
+        &["read", "hyperloglog", "slow"],
+    ),
+    CommandSpec::new(
+        "pfmerge",
+        -2,
+        &["write", "denyoom"],
+        ALL_KEYS,
+        &["write", "hyperloglog", "slow"],
+    ),
+    CommandSpec::new(
+        "geoadd",
+        -5,
+        &["write", "denyoom"],
+        FIRST_KEY,
+        &["write", "geo", "slow"],
+    ),
+    CommandSpec::new(
+        "geopos",
+        -2,
+        &["readonly"],
+        FIRST_KEY,
+        &["read", "geo", "slow"],
+    ),
+    CommandSpec::new(
+        "geodist",
+        -4,
+        &["readonly"],
+        FIRST_KEY,
+        &["read", "geo", "slow"],
+    ),
+    CommandSpec::new(
+        "geosearch",
+        -7,
+        &["readonly"],
+        FIRST_KEY,
+        &["read", "geo", "slow"],
+    ),
+    CommandSpec::new(
+        "sort",
+        -2,
+        &["write", "denyoom", "movablekeys"],
+        FIRST_KEY,
+        &["write", "set", "sortedset", "list", "slow", "dangerous"],
+    ),
+    CommandSpec::new("ping", -1, &["fast"], NO_KEYS, &["fast", "connection"]),
+    CommandSpec::new("echo", 2, &["fast"], NO_KEYS, &["fast", "connection"]),
+    CommandSpec::new(
+        "select",
+        2,
+        &["loading", "stale", "fast"],
+        NO_KEYS,
+        &["fast", "connection"],
+    ),
+    CommandSpec::new(
+        "lolwut",
+        -1,
+        &["readonly", "fast"],
+        NO_KEYS,
+        &["read", "fast"],
+    ),
+    CommandSpec::new(
+        "quit",
+        -1,
+        &[
+            "noscript",
+            "loading",
+            "stale",
+            "fast",
+            "no_auth",
+            "allow_busy",
+        ],
+        NO_KEYS,
+        &["fast", "connection"],
+    ),
+    CommandSpec::new(
+        "auth",
+        -2,
+        &[
+            "noscript",
+            "loading",
+            "stale",
+            "fast",
+            "no_auth",
+            "allow_busy",
+        ],
+        NO_KEYS,
+        &["fast", "connection"],
+    ),
+    CommandSpec::new(
+        "hello",
+        -1,
+        &[
+            "noscript",
+            "loading",
+            "stale",
+            "fast",
+            "no_auth",
+            "allow_busy",
+        ],
+        NO_KEYS,
+        &["fast", "connection"],
+    ),
+    CommandSpec::new("acl", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new(
+        "command",
+        -1,
+        &["loading", "stale"],
+        NO_KEYS,
+        &["slow", "connection"],
+    ),
+    CommandSpec::new("config", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new(
+        "debug",
+        -2,
+        &["admin", "noscript", "loading", "stale", "protected"],
+        NO_KEYS,
+        &["admin", "slow", "dangerous"],
+    ),
+    CommandSpec::new(
+        "info",
+        -1,
+        &["loading", "stale"],
+        NO_KEYS,
+        &["slow", "dangerous"],
+    ),
+    CommandSpec::new(
+        "monitor",
+        1,
+        &["admin", "noscript", "loading", "stale"],
+        NO_KEYS,
+        &["admin", "slow", "dangerous"],
+    ),
+    CommandSpec::new("slowlog", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new("latency", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new("cluster", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new("client", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new("function", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new(
+        "eval",
+        -3,
+        &[
+            "noscript",
+            "stale",
+            "skip_monitor",
+            "no_mandatory_keys",
+            "movablekeys",
+        ],
+        NO_KEYS,
+        &["slow", "scripting"],
+    ),
+    CommandSpec::new(
+        "evalsha",
+        -3,
+        &[
+            "noscript",
+            "stale",
+            "skip_monitor",
+            "no_mandatory_keys",
+            "movablekeys",
+        ],
+        NO_KEYS,
+        &["slow", "scripting"],
+    ),
+    CommandSpec::new("script", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new(
+        "fcall",
+        -3,
+        &["noscript", "stale", "movablekeys"],
+        NO_KEYS,
+        &["slow", "scripting"],
+    ),
+    CommandSpec::new(
+        "fcall_ro",
+        -3,
+        &["noscript", "stale", "readonly", "movablekeys"],
+        NO_KEYS,
+        &["slow", "scripting"],
+    ),
+    CommandSpec::new(
+        "lastsave",
+        1,
+        &["loading", "stale", "fast"],
+        NO_KEYS,
+        &["admin", "fast", "dangerous"],
+    ),
+    CommandSpec::new(
+        "subscribe",
+        -2,
+        &["pubsub", "noscript", "loading", "stale"],
+        NO_KEYS,
+        &["pubsub", "slow"],
+    ),
+    CommandSpec::new(
+        "unsubscribe",
+        -1,
+        &["pubsub", "noscript", "loading", "stale"],
+        NO_KEYS,
+        &["pubsub", "slow"],
+    ),
+    CommandSpec::new(
+        "publish",
+        3,
+        &["pubsub", "loading", "stale", "fast", "may_replicate"],
+        NO_KEYS,
+        &["pubsub", "fast"],
+    ),
+    CommandSpec::new("pubsub", -2, &[], NO_KEYS, &["slow"]),
+    CommandSpec::new(
+        "ssubscribe",
+        -2,
+        &["pubsub", "noscript", "loading", "stale"],
+        ALL_KEYS,
+        &["pubsub", "slow"],
+    ),
+    CommandSpec::new(
+        "sunsubscribe",
+        -1,
+        &["pubsub", "noscript", "loading", "stale"],
+        ALL_KEYS,
+        &["pubsub", "slow"],
+    ),
+    CommandSpec::new(
+        "spublish",
+        3,
+        &["pubsub", "loading", "stale", "fast", "may_replicate"],
+        FIRST_KEY,
+        &["pubsub", "fast"],
+    ),
+    CommandSpec::new(
+        "replconf",
+        -1,
+        &["admin", "noscript", "loading", "stale", "allow_busy"],
+        NO_KEYS,
+        &["admin", "slow", "dangerous"],
+    ),
+    CommandSpec::new(
+        "psync",
+        -3,
+        &["admin", "noscript", "no_async_loading", "no_multi"],
+        NO_KEYS,
+        &["admin", "slow", "dangerous"],
+    ),
+    CommandSpec::new("wait", 3, &["noscript"], NO_KEYS, &["slow", "connection"]),
+    CommandSpec::new(
+        "waitaof",
+        4,
+        &["noscript"],
+        NO_KEYS,
+        &["slow", "connection"],
+    ),
+    CommandSpec::new(
+        "failover",
+        -1,
+        &["admin", "noscript", "stale"],
+        NO_KEYS,
+        &["admin", "slow", "dangerous"],
+    ),
+];
+
+/// Looks up a command by name, ignoring case.
+pub fn lookup(name: &[u8]) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name.as_bytes().eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::redis::resp::{command::RedisCommand, encoding};
+
+    use super::COMMANDS;
+
+    #[test]
+    fn describes_commands() {
+        let info = Bytes::from(super::lookup(b"GET").unwrap().info());
+        assert_eq!(
+            info,
+            &b"*7\r\n$3\r\nget\r\n:2\r\n*2\r\n+readonly\r\n+fast\r\n:1\r\n:1\r\n:1\r\n*3\r\n+@read\r\n+@string\r\n+@fast\r\n"[..]
+        );
+        assert!(super::lookup(b"nosuchcommand").is_none());
+    }
+
+    #[test]
+    fn lists_each_command_once_under_the_name_it_parses_as() {
+        for (index, spec) in COMMANDS.iter().enumerate() {
+            assert!(
+                COMMANDS[..index]
+                    .iter()
+                    .all(|other| other.name != spec.name),
+                "{} is listed twice",
+                spec.name
+            );
+
+            // a command with only its name is either parsed or rejected for missing
+            // arguments, never as an unknown command
+            let command = encoding::array(vec![encoding::bulk_string(spec.name)]);
+            if let Err(err) = RedisCommand::try_from(command) {
+                assert!(
+                    !err.to_string().contains("unprocessable command"),
+                    "{} is not parsed: {err}",
+                    spec.name
+                );
+            }
+        }
+    }
+}
@@ -1,10 +1,25 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use bytes::Bytes;
 
 use crate::redis::{
-    replication::command::{InfoSection, RedisReplicationCommand, ReplConfSection},
-    resp::command::{ConfigSection, RedisCommand, RedisServerCommand, RedisStoreCommand},
+    pubsub::command::{PubSubSection, RedisPubSubCommand},
+    replication::command::{FailoverSection, RedisReplicationCommand, ReplConfSection},
+    resp::{
+        command::{
+            AclSection, BitOrByte, ClientSection, ClusterSection, CommandSection, ConfigSection,
+            DebugSection, ExpiryOption, FunctionSection, InfoSection, LatencySection, ListEnd,
+            MinOrMax, ObjectSection, RedisCommand, RedisServerCommand, RedisStoreCommand,
+            ScriptSection, SetOperation, SlowLogSection, XGroupSection, XInfoSection,
+        },
+        RESPValue,
+    },
+    store::{
+        geo::{Coordinates, GeoOrigin, GeoSearch, GeoShape, GeoUnit, SortOrder},
+        sort::Sort,
+        sorted_set::Score,
+        stream::{StreamEntryId, StreamTrim, StreamTrimStrategy, XAddEntryId, XReadGroupId},
+    },
 };
 
 use super::{array, bulk_string};
@@ -13,6 +28,40 @@ pub fn get(key: impl AsRef<[u8]>) -> Bytes {
     array(vec![bulk_string("GET"), bulk_string(key)]).into()
 }
 
+pub fn mget(keys: &[impl AsRef<[u8]>]) -> Bytes {
+    let mut values = vec![bulk_string("MGET")];
+    values.extend(keys.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn getex(key: impl AsRef<[u8]>, expiry_option: Option<&ExpiryOption>) -> Bytes {
+    let mut values = vec![bulk_string("GETEX"), bulk_string(key)];
+    match expiry_option {
+        Some(ExpiryOption::At(timestamp)) => {
+            let timestamp = timestamp
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+
+            values.push(bulk_string("PXAT"));
+            values.push(bulk_string(format!("{}", timestamp.as_millis())));
+        }
+        Some(ExpiryOption::Persist) => values.push(bulk_string("PERSIST")),
+        None => {}
+    }
+
+    array(values).into()
+}
+
+pub fn getdel(key: impl AsRef<[u8]>) -> Bytes {
+    array(vec![bulk_string("GETDEL"), bulk_string(key)]).into()
+}
+
+pub fn del(keys: &[impl AsRef<[u8]>]) -> Bytes {
+    let mut values = vec![bulk_string("DEL")];
+    values.extend(keys.iter().map(bulk_string));
+    array(values).into()
+}
+
 pub fn set(key: impl AsRef<[u8]>, value: impl AsRef<[u8]>, px: Option<&SystemTime>) -> Bytes {
     let mut values = vec![bulk_string("SET"), bulk_string(key), bulk_string(value)];
     if let Some(px) = px {
@@ -38,10 +87,21 @@ pub fn ty(key: &Bytes) -> Bytes {
 
 pub fn xadd(
     key: impl AsRef<[u8]>,
-    entry_id: impl AsRef<[u8]>,
+    entry_id: &XAddEntryId,
+    no_mkstream: bool,
+    trim: Option<&StreamTrim>,
     fields: &[(impl AsRef<[u8]>, impl AsRef<[u8]>)],
 ) -> Bytes {
-    let mut values = vec![bulk_string("XADD"), bulk_string(key), bulk_string(entry_id)];
+    let mut values = vec![bulk_string("XADD"), bulk_string(key)];
+    if no_mkstream {
+        values.push(bulk_string("NOMKSTREAM"));
+    }
+
+    if let Some(trim) = trim {
+        values.extend(stream_trim(trim));
+    }
+
+    values.push(bulk_string(entry_id.to_string()));
     for (field, value) in fields {
         values.push(bulk_string(field));
         values.push(bulk_string(value));
@@ -50,14 +110,663 @@ pub fn xadd(
     array(values).into()
 }
 
-pub fn ping() -> Bytes {
-    array(vec![bulk_string("PING")]).into()
+pub fn xtrim(key: impl AsRef<[u8]>, trim: &StreamTrim) -> Bytes {
+    let mut values = vec![bulk_string("XTRIM"), bulk_string(key)];
+    values.extend(stream_trim(trim));
+    array(values).into()
+}
+
+fn stream_trim(trim: &StreamTrim) -> Vec<RESPValue> {
+    let (strategy_name, threshold) = match trim.strategy {
+        StreamTrimStrategy::MaxLen(max_len) => ("MAXLEN", max_len.to_string()),
+        StreamTrimStrategy::MinId(min_id) => ("MINID", min_id.to_string()),
+    };
+
+    let mut values = vec![
+        bulk_string(strategy_name),
+        bulk_string(if trim.approximate { "~" } else { "=" }),
+        bulk_string(threshold),
+    ];
+    if let Some(limit) = trim.limit {
+        values.push(bulk_string("LIMIT"));
+        values.push(bulk_string(limit.to_string()));
+    }
+
+    values
+}
+
+pub fn object(section: &ObjectSection) -> Bytes {
+    let mut values = vec![bulk_string("OBJECT")];
+    match section {
+        ObjectSection::Encoding { key } => {
+            values.push(bulk_string("ENCODING"));
+            values.push(bulk_string(key));
+        }
+    }
+
+    array(values).into()
+}
+
+pub fn xgroup(section: &XGroupSection) -> Bytes {
+    let mut values = vec![bulk_string("XGROUP")];
+    match section {
+        XGroupSection::Create {
+            key,
+            group,
+            id,
+            mkstream,
+        } => {
+            values.push(bulk_string("CREATE"));
+            values.push(bulk_string(key));
+            values.push(bulk_string(group));
+            values.push(bulk_string(
+                id.map_or_else(|| "$".to_string(), |id| id.to_string()),
+            ));
+            if *mkstream {
+                values.push(bulk_string("MKSTREAM"));
+            }
+        }
+        XGroupSection::Help => values.push(bulk_string("HELP")),
+    }
+
+    array(values).into()
+}
+
+pub fn xreadgroup(
+    group: impl AsRef<[u8]>,
+    consumer: impl AsRef<[u8]>,
+    count: Option<usize>,
+    no_ack: bool,
+    keys: &[impl AsRef<[u8]>],
+    ids: &[XReadGroupId],
+) -> Bytes {
+    let mut values = vec![
+        bulk_string("XREADGROUP"),
+        bulk_string("GROUP"),
+        bulk_string(group),
+        bulk_string(consumer),
+    ];
+    if let Some(count) = count {
+        values.push(bulk_string("COUNT"));
+        values.push(bulk_string(count.to_string()));
+    }
+
+    if no_ack {
+        values.push(bulk_string("NOACK"));
+    }
+
+    values.push(bulk_string("STREAMS"));
+    values.extend(keys.iter().map(bulk_string));
+    values.extend(ids.iter().map(|id| bulk_string(id.to_string())));
+    array(values).into()
+}
+
+pub fn xack(key: impl AsRef<[u8]>, group: impl AsRef<[u8]>, ids: &[StreamEntryId]) -> Bytes {
+    let mut values = vec![bulk_string("XACK"), bulk_string(key), bulk_string(group)];
+    values.extend(ids.iter().map(|id| bulk_string(id.to_string())));
+    array(values).into()
+}
+
+pub fn xinfo(section: &XInfoSection) -> Bytes {
+    let mut values = vec![bulk_string("XINFO")];
+    match section {
+        XInfoSection::Stream { key } => {
+            values.push(bulk_string("STREAM"));
+            values.push(bulk_string(key));
+        }
+        XInfoSection::Help => values.push(bulk_string("HELP")),
+    }
+
+    array(values).into()
+}
+
+pub fn swapdb(index: usize, other_index: usize) -> Bytes {
+    array(vec![
+        bulk_string("SWAPDB"),
+        bulk_string(format!("{}", index)),
+        bulk_string(format!("{}", other_index)),
+    ])
+    .into()
+}
+
+pub fn move_key(key: impl AsRef<[u8]>, db: usize) -> Bytes {
+    array(vec![
+        bulk_string("MOVE"),
+        bulk_string(key),
+        bulk_string(format!("{}", db)),
+    ])
+    .into()
+}
+
+pub fn setbit(key: impl AsRef<[u8]>, offset: u64, bit: bool) -> Bytes {
+    array(vec![
+        bulk_string("SETBIT"),
+        bulk_string(key),
+        bulk_string(format!("{}", offset)),
+        bulk_string(if bit { "1" } else { "0" }),
+    ])
+    .into()
+}
+
+pub fn getbit(key: impl AsRef<[u8]>, offset: u64) -> Bytes {
+    array(vec![
+        bulk_string("GETBIT"),
+        bulk_string(key),
+        bulk_string(format!("{}", offset)),
+    ])
+    .into()
+}
+
+pub fn bitcount(key: impl AsRef<[u8]>, range: Option<(i64, i64, BitOrByte)>) -> Bytes {
+    let mut values = vec![bulk_string("BITCOUNT"), bulk_string(key)];
+    if let Some((start, end, unit)) = range {
+        values.push(bulk_string(format!("{}", start)));
+        values.push(bulk_string(format!("{}", end)));
+        values.push(bulk_string(match unit {
+            BitOrByte::Bit => "BIT",
+            BitOrByte::Byte => "BYTE",
+        }));
+    }
+
+    array(values).into()
+}
+
+pub fn sadd(key: impl AsRef<[u8]>, members: &[impl AsRef<[u8]>]) -> Bytes {
+    let mut values = vec![bulk_string("SADD"), bulk_string(key)];
+    values.extend(members.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn smembers(key: impl AsRef<[u8]>) -> Bytes {
+    array(vec![bulk_string("SMEMBERS"), bulk_string(key)]).into()
+}
+
+pub fn set_operation_store(
+    operation: SetOperation,
+    destination: impl AsRef<[u8]>,
+    keys: &[impl AsRef<[u8]>],
+) -> Bytes {
+    let name = match operation {
+        SetOperation::Inter => "SINTERSTORE",
+        SetOperation::Union => "SUNIONSTORE",
+        SetOperation::Diff => "SDIFFSTORE",
+    };
+
+    let mut values = vec![bulk_string(name), bulk_string(destination)];
+    values.extend(keys.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn sintercard(keys: &[impl AsRef<[u8]>], limit: Option<usize>) -> Bytes {
+    let mut values = vec![
+        bulk_string("SINTERCARD"),
+        bulk_string(keys.len().to_string()),
+    ];
+    values.extend(keys.iter().map(bulk_string));
+    if let Some(limit) = limit {
+        values.push(bulk_string("LIMIT"));
+        values.push(bulk_string(limit.to_string()));
+    }
+
+    array(values).into()
+}
+
+pub fn smove(
+    source: impl AsRef<[u8]>,
+    destination: impl AsRef<[u8]>,
+    member: impl AsRef<[u8]>,
+) -> Bytes {
+    array(vec![
+        bulk_string("SMOVE"),
+        bulk_string(source),
+        bulk_string(destination),
+        bulk_string(member),
+    ])
+    .into()
+}
+
+pub fn list_push(key: impl AsRef<[u8]>, end: ListEnd, elements: &[impl AsRef<[u8]>]) -> Bytes {
+    let name = match end {
+        ListEnd::Left => "LPUSH",
+        ListEnd::Right => "RPUSH",
+    };
+
+    let mut values = vec![bulk_string(name), bulk_string(key)];
+    values.extend(elements.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn list_pop(key: impl AsRef<[u8]>, end: ListEnd, count: Option<usize>) -> Bytes {
+    let name = match end {
+        ListEnd::Left => "LPOP",
+        ListEnd::Right => "RPOP",
+    };
+
+    let mut values = vec![bulk_string(name), bulk_string(key)];
+    if let Some(count) = count {
+        values.push(bulk_string(count.to_string()));
+    }
+
+    array(values).into()
+}
+
+pub fn lmpop(keys: &[impl AsRef<[u8]>], end: ListEnd, count: Option<usize>) -> Bytes {
+    let mut values = vec![bulk_string("LMPOP"), bulk_string(keys.len().to_string())];
+    values.extend(keys.iter().map(bulk_string));
+    values.push(bulk_string(match end {
+        ListEnd::Left => "LEFT",
+        ListEnd::Right => "RIGHT",
+    }));
+    if let Some(count) = count {
+        values.push(bulk_string("COUNT"));
+        values.push(bulk_string(count.to_string()));
+    }
+
+    array(values).into()
+}
+
+pub fn blmpop(
+    keys: &[impl AsRef<[u8]>],
+    timeout: Option<Duration>,
+    end: ListEnd,
+    count: Option<usize>,
+) -> Bytes {
+    let mut values = vec![bulk_string("BLMPOP")];
+    values.push(bulk_string(format!(
+        "{}",
+        timeout.map_or(0.0, |timeout| timeout.as_secs_f64())
+    )));
+    values.push(bulk_string(keys.len().to_string()));
+    values.extend(keys.iter().map(bulk_string));
+    values.push(bulk_string(match end {
+        ListEnd::Left => "LEFT",
+        ListEnd::Right => "RIGHT",
+    }));
+    if let Some(count) = count {
+        values.push(bulk_string("COUNT"));
+        values.push(bulk_string(count.to_string()));
+    }
+
+    array(values).into()
+}
+
+pub fn llen(key: impl AsRef<[u8]>) -> Bytes {
+    array(vec![bulk_string("LLEN"), bulk_string(key)]).into()
+}
+
+pub fn lrange(key: impl AsRef<[u8]>, start: i64, stop: i64) -> Bytes {
+    array(vec![
+        bulk_string("LRANGE"),
+        bulk_string(key),
+        bulk_string(start.to_string()),
+        bulk_string(stop.to_string()),
+    ])
+    .into()
+}
+
+pub fn hset(key: impl AsRef<[u8]>, fields: &[(impl AsRef<[u8]>, impl AsRef<[u8]>)]) -> Bytes {
+    let mut values = vec![bulk_string("HSET"), bulk_string(key)];
+    for (field, value) in fields {
+        values.push(bulk_string(field));
+        values.push(bulk_string(value));
+    }
+
+    array(values).into()
+}
+
+pub fn hget(key: impl AsRef<[u8]>, field: impl AsRef<[u8]>) -> Bytes {
+    array(vec![
+        bulk_string("HGET"),
+        bulk_string(key),
+        bulk_string(field),
+    ])
+    .into()
+}
+
+pub fn hrandfield(key: impl AsRef<[u8]>, count: Option<i64>, with_values: bool) -> Bytes {
+    let mut values = vec![bulk_string("HRANDFIELD"), bulk_string(key)];
+    if let Some(count) = count {
+        values.push(bulk_string(format!("{}", count)));
+    }
+
+    if with_values {
+        values.push(bulk_string("WITHVALUES"));
+    }
+
+    array(values).into()
+}
+
+pub fn zadd(key: impl AsRef<[u8]>, members: &[(Score, impl AsRef<[u8]>)]) -> Bytes {
+    let mut values = vec![bulk_string("ZADD"), bulk_string(key)];
+    for (score, member) in members {
+        values.push(bulk_string(score.to_string()));
+        values.push(bulk_string(member));
+    }
+
+    array(values).into()
+}
+
+pub fn zscore(key: impl AsRef<[u8]>, member: impl AsRef<[u8]>) -> Bytes {
+    array(vec![
+        bulk_string("ZSCORE"),
+        bulk_string(key),
+        bulk_string(member),
+    ])
+    .into()
+}
+
+pub fn zrandmember(key: impl AsRef<[u8]>, count: Option<i64>, with_scores: bool) -> Bytes {
+    let mut values = vec![bulk_string("ZRANDMEMBER"), bulk_string(key)];
+    if let Some(count) = count {
+        values.push(bulk_string(format!("{}", count)));
+    }
+
+    if with_scores {
+        values.push(bulk_string("WITHSCORES"));
+    }
+
+    array(values).into()
+}
+
+pub fn zpop(key: impl AsRef<[u8]>, count: Option<usize>, end: MinOrMax) -> Bytes {
+    let name = match end {
+        MinOrMax::Min => "ZPOPMIN",
+        MinOrMax::Max => "ZPOPMAX",
+    };
+
+    let mut values = vec![bulk_string(name), bulk_string(key)];
+    if let Some(count) = count {
+        values.push(bulk_string(format!("{}", count)));
+    }
+
+    array(values).into()
+}
+
+pub fn zmpop(keys: &[impl AsRef<[u8]>], end: MinOrMax, count: Option<usize>) -> Bytes {
+    let mut values = vec![bulk_string("ZMPOP"), bulk_string(keys.len().to_string())];
+    values.extend(keys.iter().map(bulk_string));
+    values.push(bulk_string(match end {
+        MinOrMax::Min => "MIN",
+        MinOrMax::Max => "MAX",
+    }));
+    if let Some(count) = count {
+        values.push(bulk_string("COUNT"));
+        values.push(bulk_string(count.to_string()));
+    }
+
+    array(values).into()
+}
+
+pub fn pfadd(key: impl AsRef<[u8]>, elements: &[impl AsRef<[u8]>]) -> Bytes {
+    let mut values = vec![bulk_string("PFADD"), bulk_string(key)];
+    values.extend(elements.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn pfcount(keys: &[impl AsRef<[u8]>]) -> Bytes {
+    let mut values = vec![bulk_string("PFCOUNT")];
+    values.extend(keys.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn pfmerge(destination: impl AsRef<[u8]>, keys: &[impl AsRef<[u8]>]) -> Bytes {
+    let mut values = vec![bulk_string("PFMERGE"), bulk_string(destination)];
+    values.extend(keys.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn bzpop(keys: &[impl AsRef<[u8]>], timeout: Option<Duration>, end: MinOrMax) -> Bytes {
+    let name = match end {
+        MinOrMax::Min => "BZPOPMIN",
+        MinOrMax::Max => "BZPOPMAX",
+    };
+
+    let mut values = vec![bulk_string(name)];
+    values.extend(keys.iter().map(bulk_string));
+    values.push(bulk_string(format!(
+        "{}",
+        timeout.map_or(0.0, |timeout| timeout.as_secs_f64())
+    )));
+    array(values).into()
+}
+
+pub fn bzmpop(
+    keys: &[impl AsRef<[u8]>],
+    timeout: Option<Duration>,
+    end: MinOrMax,
+    count: Option<usize>,
+) -> Bytes {
+    let mut values = vec![bulk_string("BZMPOP")];
+    values.push(bulk_string(format!(
+        "{}",
+        timeout.map_or(0.0, |timeout| timeout.as_secs_f64())
+    )));
+    values.push(bulk_string(keys.len().to_string()));
+    values.extend(keys.iter().map(bulk_string));
+    values.push(bulk_string(match end {
+        MinOrMax::Min => "MIN",
+        MinOrMax::Max => "MAX",
+    }));
+    if let Some(count) = count {
+        values.push(bulk_string("COUNT"));
+        values.push(bulk_string(count.to_string()));
+    }
+
+    array(values).into()
+}
+
+pub fn geoadd(key: impl AsRef<[u8]>, points: &[(Coordinates, impl AsRef<[u8]>)]) -> Bytes {
+    let mut values = vec![bulk_string("GEOADD"), bulk_string(key)];
+    for (coordinates, member) in points {
+        values.push(bulk_string(coordinates.longitude.to_string()));
+        values.push(bulk_string(coordinates.latitude.to_string()));
+        values.push(bulk_string(member));
+    }
+
+    array(values).into()
+}
+
+pub fn geopos(key: impl AsRef<[u8]>, members: &[impl AsRef<[u8]>]) -> Bytes {
+    let mut values = vec![bulk_string("GEOPOS"), bulk_string(key)];
+    values.extend(members.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn geodist(
+    key: impl AsRef<[u8]>,
+    member: impl AsRef<[u8]>,
+    other_member: impl AsRef<[u8]>,
+    unit: GeoUnit,
+) -> Bytes {
+    array(vec![
+        bulk_string("GEODIST"),
+        bulk_string(key),
+        bulk_string(member),
+        bulk_string(other_member),
+        bulk_string(unit.name()),
+    ])
+    .into()
+}
+
+pub fn sort(key: impl AsRef<[u8]>, sort: &Sort) -> Bytes {
+    let mut values = vec![bulk_string("SORT"), bulk_string(key)];
+    if let Some(by) = &sort.by {
+        values.push(bulk_string("BY"));
+        values.push(bulk_string(by));
+    }
+
+    if let Some((offset, count)) = sort.limit {
+        values.push(bulk_string("LIMIT"));
+        values.push(bulk_string(offset.to_string()));
+        values.push(bulk_string(count.to_string()));
+    }
+
+    for pattern in &sort.get {
+        values.push(bulk_string("GET"));
+        values.push(bulk_string(pattern));
+    }
+
+    if sort.order == SortOrder::Descending {
+        values.push(bulk_string("DESC"));
+    }
+
+    if sort.alpha {
+        values.push(bulk_string("ALPHA"));
+    }
+
+    if let Some(destination) = &sort.store {
+        values.push(bulk_string("STORE"));
+        values.push(bulk_string(destination));
+    }
+
+    array(values).into()
+}
+
+pub fn geosearch(key: impl AsRef<[u8]>, search: &GeoSearch) -> Bytes {
+    let mut values = vec![bulk_string("GEOSEARCH"), bulk_string(key)];
+    match &search.origin {
+        GeoOrigin::Member(member) => {
+            values.push(bulk_string("FROMMEMBER"));
+            values.push(bulk_string(member));
+        }
+        GeoOrigin::Coordinates(coordinates) => {
+            values.push(bulk_string("FROMLONLAT"));
+            values.push(bulk_string(coordinates.longitude.to_string()));
+            values.push(bulk_string(coordinates.latitude.to_string()));
+        }
+    }
+
+    match search.shape {
+        GeoShape::Radius(radius) => {
+            values.push(bulk_string("BYRADIUS"));
+            values.push(bulk_string(radius.to_string()));
+        }
+        GeoShape::Box { width, height } => {
+            values.push(bulk_string("BYBOX"));
+            values.push(bulk_string(width.to_string()));
+            values.push(bulk_string(height.to_string()));
+        }
+    }
+
+    values.push(bulk_string(search.unit.name()));
+    match search.order {
+        Some(SortOrder::Ascending) => values.push(bulk_string("ASC")),
+        Some(SortOrder::Descending) => values.push(bulk_string("DESC")),
+        None => {}
+    }
+
+    if let Some((count, any)) = search.count {
+        values.push(bulk_string("COUNT"));
+        values.push(bulk_string(count.to_string()));
+        if any {
+            values.push(bulk_string("ANY"));
+        }
+    }
+
+    for (is_set, flag) in [
+        (search.with_coordinates, "WITHCOORD"),
+        (search.with_distance, "WITHDIST"),
+        (search.with_hash, "WITHHASH"),
+    ] {
+        if is_set {
+            values.push(bulk_string(flag));
+        }
+    }
+
+    array(values).into()
+}
+
+pub fn ping(message: Option<&[u8]>) -> Bytes {
+    let mut values = vec![bulk_string("PING")];
+    if let Some(message) = message {
+        values.push(bulk_string(message));
+    }
+
+    array(values).into()
 }
 
 pub fn echo(message: impl AsRef<[u8]>) -> Bytes {
     array(vec![bulk_string("ECHO"), bulk_string(message)]).into()
 }
 
+pub fn lolwut(version: Option<usize>) -> Bytes {
+    let mut values = vec![bulk_string("LOLWUT")];
+    if let Some(version) = version {
+        values.push(bulk_string("VERSION"));
+        values.push(bulk_string(format!("{}", version)));
+    }
+
+    array(values).into()
+}
+
+pub fn quit() -> Bytes {
+    array(vec![bulk_string("QUIT")]).into()
+}
+
+pub fn auth(username: Option<&Bytes>, password: impl AsRef<[u8]>) -> Bytes {
+    let mut values = vec![bulk_string("AUTH")];
+    if let Some(username) = username {
+        values.push(bulk_string(username));
+    }
+
+    values.push(bulk_string(password));
+    array(values).into()
+}
+
+pub fn acl(section: &AclSection) -> Bytes {
+    let mut values = vec![bulk_string("ACL")];
+    match section {
+        AclSection::WhoAmI => values.push(bulk_string("WHOAMI")),
+        AclSection::List => values.push(bulk_string("LIST")),
+        AclSection::Cat => values.push(bulk_string("CAT")),
+        AclSection::SetUser { username, rules } => {
+            values.push(bulk_string("SETUSER"));
+            values.push(bulk_string(username));
+            values.extend(rules.iter().map(bulk_string));
+        }
+        AclSection::GetUser { username } => {
+            values.push(bulk_string("GETUSER"));
+            values.push(bulk_string(username));
+        }
+        AclSection::Help => values.push(bulk_string("HELP")),
+    }
+
+    array(values).into()
+}
+
+pub fn debug(section: &DebugSection) -> Bytes {
+    let mut values = vec![bulk_string("DEBUG")];
+    match section {
+        DebugSection::Object { key } => {
+            values.push(bulk_string("OBJECT"));
+            values.push(bulk_string(key));
+        }
+        DebugSection::ChangeReplId => values.push(bulk_string("CHANGE-REPL-ID")),
+        DebugSection::ReplicateOffset => values.push(bulk_string("REPLICATE-OFFSET")),
+        DebugSection::Reload => values.push(bulk_string("RELOAD")),
+        DebugSection::StringMatchLen { pattern, string } => {
+            values.push(bulk_string("STRINGMATCH-LEN"));
+            values.push(bulk_string(pattern));
+            values.push(bulk_string(string));
+        }
+        DebugSection::SetActiveExpire { is_enabled } => {
+            values.push(bulk_string("SET-ACTIVE-EXPIRE"));
+            values.push(bulk_string(if *is_enabled { "1" } else { "0" }));
+        }
+        DebugSection::Jmap => values.push(bulk_string("JMAP")),
+        DebugSection::Sleep { duration } => {
+            values.push(bulk_string("SLEEP"));
+            values.push(bulk_string(duration.as_secs_f64().to_string()));
+        }
+        DebugSection::Unsupported { subcommand, args } => {
+            values.push(bulk_string(subcommand));
+            values.extend(args.iter().map(bulk_string));
+        }
+    }
+
+    array(values).into()
+}
+
 pub fn config(section: &ConfigSection) -> Bytes {
     let mut values = vec![bulk_string("CONFIG")];
     match section {
@@ -67,21 +776,246 @@ pub fn config(section: &ConfigSection) -> Bytes {
                 values.push(bulk_string(key));
             }
         }
+        ConfigSection::Set { parameters } => {
+            values.push(bulk_string("SET"));
+            for (name, value) in parameters {
+                values.push(bulk_string(name));
+                values.push(bulk_string(value));
+            }
+        }
+        ConfigSection::Help => values.push(bulk_string("HELP")),
     }
 
     array(values).into()
 }
 
+pub fn select(index: usize) -> Bytes {
+    array(vec![
+        bulk_string("SELECT"),
+        bulk_string(format!("{}", index)),
+    ])
+    .into()
+}
+
 pub fn info(section: InfoSection) -> Bytes {
     let mut values = vec![bulk_string("INFO")];
     match section {
         InfoSection::Default => {}
+        InfoSection::Server => values.push(bulk_string("server")),
+        InfoSection::Clients => values.push(bulk_string("clients")),
+        InfoSection::Persistence => values.push(bulk_string("persistence")),
+        InfoSection::Stats => values.push(bulk_string("stats")),
         InfoSection::Replication => values.push(bulk_string("replication")),
     }
 
     array(values).into()
 }
 
+pub fn subscribe(channels: &[Bytes]) -> Bytes {
+    let mut values = vec![bulk_string("SUBSCRIBE")];
+    values.extend(channels.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn unsubscribe(channels: &[Bytes]) -> Bytes {
+    let mut values = vec![bulk_string("UNSUBSCRIBE")];
+    values.extend(channels.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn publish(channel: &Bytes, message: &Bytes) -> Bytes {
+    array(vec![
+        bulk_string("PUBLISH"),
+        bulk_string(channel),
+        bulk_string(message),
+    ])
+    .into()
+}
+
+pub fn pubsub(section: &PubSubSection) -> Bytes {
+    let mut values = vec![bulk_string("PUBSUB")];
+    match section {
+        PubSubSection::Channels { pattern } => {
+            values.push(bulk_string("CHANNELS"));
+            values.extend(pattern.iter().map(bulk_string));
+        }
+        PubSubSection::NumSub { channels } => {
+            values.push(bulk_string("NUMSUB"));
+            values.extend(channels.iter().map(bulk_string));
+        }
+        PubSubSection::NumPat => values.push(bulk_string("NUMPAT")),
+        PubSubSection::Help => values.push(bulk_string("HELP")),
+    }
+
+    array(values).into()
+}
+
+pub fn ssubscribe(channels: &[Bytes]) -> Bytes {
+    let mut values = vec![bulk_string("SSUBSCRIBE")];
+    values.extend(channels.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn sunsubscribe(channels: &[Bytes]) -> Bytes {
+    let mut values = vec![bulk_string("SUNSUBSCRIBE")];
+    values.extend(channels.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn spublish(channel: &Bytes, message: &Bytes) -> Bytes {
+    array(vec![
+        bulk_string("SPUBLISH"),
+        bulk_string(channel),
+        bulk_string(message),
+    ])
+    .into()
+}
+
+pub fn command(section: &CommandSection) -> Bytes {
+    let mut values = vec![bulk_string("COMMAND")];
+    match section {
+        CommandSection::Info { names } => {
+            values.push(bulk_string("INFO"));
+            values.extend(names.iter().map(bulk_string));
+        }
+        CommandSection::Count => values.push(bulk_string("COUNT")),
+        CommandSection::Docs { names } => {
+            values.push(bulk_string("DOCS"));
+            values.extend(names.iter().map(bulk_string));
+        }
+    }
+
+    array(values).into()
+}
+
+pub fn slowlog(section: &SlowLogSection) -> Bytes {
+    let mut values = vec![bulk_string("SLOWLOG")];
+    match section {
+        SlowLogSection::Get { count } => {
+            values.push(bulk_string("GET"));
+            if let Some(count) = count {
+                values.push(bulk_string(count.to_string()));
+            }
+        }
+        SlowLogSection::Len => values.push(bulk_string("LEN")),
+        SlowLogSection::Reset => values.push(bulk_string("RESET")),
+        SlowLogSection::Help => values.push(bulk_string("HELP")),
+    }
+
+    array(values).into()
+}
+
+pub fn latency(section: &LatencySection) -> Bytes {
+    let mut values = vec![bulk_string("LATENCY")];
+    match section {
+        LatencySection::History { event } => {
+            values.push(bulk_string("HISTORY"));
+            values.push(bulk_string(event));
+        }
+        LatencySection::Latest => values.push(bulk_string("LATEST")),
+        LatencySection::Reset { events } => {
+            values.push(bulk_string("RESET"));
+            values.extend(events.iter().map(bulk_string));
+        }
+        LatencySection::Help => values.push(bulk_string("HELP")),
+    }
+
+    array(values).into()
+}
+
+pub fn client(section: ClientSection) -> Bytes {
+    let switch = |is_enabled| bulk_string(if is_enabled { "ON" } else { "OFF" });
+    let mut values = vec![bulk_string("CLIENT")];
+    match section {
+        ClientSection::NoEvict { is_enabled } => {
+            values.push(bulk_string("NO-EVICT"));
+            values.push(switch(is_enabled));
+        }
+        ClientSection::NoTouch { is_enabled } => {
+            values.push(bulk_string("NO-TOUCH"));
+            values.push(switch(is_enabled));
+        }
+        ClientSection::Help => values.push(bulk_string("HELP")),
+    }
+
+    array(values).into()
+}
+
+pub fn cluster(section: ClusterSection) -> Bytes {
+    let section = match section {
+        ClusterSection::Info => "INFO",
+        ClusterSection::MyId => "MYID",
+        ClusterSection::Slots => "SLOTS",
+        ClusterSection::Shards => "SHARDS",
+        ClusterSection::Nodes => "NODES",
+        ClusterSection::Help => "HELP",
+    };
+
+    array(vec![bulk_string("CLUSTER"), bulk_string(section)]).into()
+}
+
+pub fn function(section: FunctionSection) -> Bytes {
+    let section = match section {
+        FunctionSection::List => "LIST",
+        FunctionSection::Dump => "DUMP",
+        // the code is not kept, since it is never run
+        FunctionSection::Load => "LOAD",
+        FunctionSection::Help => "HELP",
+    };
+
+    array(vec![bulk_string("FUNCTION"), bulk_string(section)]).into()
+}
+
+pub fn fcall(is_read_only: bool, args: &[Bytes]) -> Bytes {
+    let name = if is_read_only { "FCALL_RO" } else { "FCALL" };
+    let mut values = vec![bulk_string(name)];
+    values.extend(args.iter().map(bulk_string));
+    array(values).into()
+}
+
+pub fn eval(name: &str, script: &[u8], keys: &[Bytes], args: &[Bytes]) -> Bytes {
+    let mut values = vec![
+        bulk_string(name),
+        bulk_string(script),
+        bulk_string(keys.len().to_string()),
+    ];
+    values.extend(keys.iter().chain(args).map(bulk_string));
+    array(values).into()
+}
+
+pub fn script(section: &ScriptSection) -> Bytes {
+    let mut values = vec![bulk_string("SCRIPT")];
+    match section {
+        // the script is not kept, since it is never run
+        ScriptSection::Load => values.push(bulk_string("LOAD")),
+        ScriptSection::Exists { sha1s } => {
+            values.push(bulk_string("EXISTS"));
+            values.extend(sha1s.iter().map(bulk_string));
+        }
+        ScriptSection::Flush => values.push(bulk_string("FLUSH")),
+        ScriptSection::Help => values.push(bulk_string("HELP")),
+    }
+
+    array(values).into()
+}
+
+pub fn hello(protocol_version: Option<i64>) -> Bytes {
+    let mut values = vec![bulk_string("HELLO")];
+    if let Some(protocol_version) = protocol_version {
+        values.push(bulk_string(protocol_version.to_string()));
+    }
+
+    array(values).into()
+}
+
+pub fn monitor() -> Bytes {
+    array(vec![bulk_string("MONITOR")]).into()
+}
+
+pub fn lastsave() -> Bytes {
+    array(vec![bulk_string("LASTSAVE")]).into()
+}
+
 pub fn replconf_port(listening_port: u16) -> Bytes {
     array(vec![
         bulk_string("REPLCONF"),
@@ -101,13 +1035,10 @@ pub fn replconf_capa(capabilities: &[Bytes]) -> Bytes {
     array(values).into()
 }
 
+/// The encoding is static since it is built for every `WAIT` that has to block and
+/// compared against everything a replica receives.
 pub fn replconf_get_ack() -> Bytes {
-    array(vec![
-        bulk_string("REPLCONF"),
-        bulk_string("GETACK"),
-        bulk_string("*"),
-    ])
-    .into()
+    Bytes::from_static(b"*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n")
 }
 
 pub fn replconf_ack(processed_bytes: usize) -> Bytes {
@@ -119,13 +1050,39 @@ pub fn replconf_ack(processed_bytes: usize) -> Bytes {
     .into()
 }
 
-pub fn psync(replication_id: &str, replication_offset: i64) -> Bytes {
-    array(vec![
+pub fn psync(replication_id: &str, replication_offset: i64, failover: bool) -> Bytes {
+    let mut values = vec![
         bulk_string("PSYNC"),
         bulk_string(replication_id),
         bulk_string(format!("{}", replication_offset)),
-    ])
-    .into()
+    ];
+    if failover {
+        values.push(bulk_string("FAILOVER"));
+    }
+
+    array(values).into()
+}
+
+pub fn failover(section: &FailoverSection) -> Bytes {
+    let mut values = vec![bulk_string("FAILOVER")];
+    match section {
+        FailoverSection::To {
+            host,
+            port,
+            timeout,
+        } => {
+            values.push(bulk_string("TO"));
+            values.push(bulk_string(host));
+            values.push(bulk_string(format!("{}", port)));
+            if let Some(timeout) = timeout {
+                values.push(bulk_string("TIMEOUT"));
+                values.push(bulk_string(format!("{}", timeout)));
+            }
+        }
+        FailoverSection::Abort => values.push(bulk_string("ABORT")),
+    }
+
+    array(values).into()
 }
 
 pub fn wait(num_replicas: usize, timeout: usize) -> Bytes {
@@ -137,12 +1094,26 @@ pub fn wait(num_replicas: usize, timeout: usize) -> Bytes {
     .into()
 }
 
+pub fn waitaof(num_local: usize, num_replicas: usize, timeout: usize) -> Bytes {
+    array(vec![
+        bulk_string("WAITAOF"),
+        bulk_string(format!("{}", num_local)),
+        bulk_string(format!("{}", num_replicas)),
+        bulk_string(format!("{}", timeout)),
+    ])
+    .into()
+}
+
 impl From<&RedisCommand> for Bytes {
     fn from(command: &RedisCommand) -> Self {
         match command {
             RedisCommand::Store(command) => command.into(),
             RedisCommand::Server(command) => command.into(),
             RedisCommand::Replication(command) => command.into(),
+            RedisCommand::PubSub(command) => command.into(),
+            RedisCommand::Invalid { args, .. } => {
+                array(args.iter().map(bulk_string).collect()).into()
+            }
         }
     }
 }
@@ -151,14 +1122,98 @@ impl From<&RedisStoreCommand> for Bytes {
     fn from(command: &RedisStoreCommand) -> Self {
         match command {
             RedisStoreCommand::Get { key } => get(key),
+            RedisStoreCommand::MGet { keys } => mget(keys),
+            RedisStoreCommand::GetEx { key, expiry_option } => getex(key, expiry_option.as_ref()),
+            RedisStoreCommand::GetDel { key } => getdel(key),
+            RedisStoreCommand::Del { keys } => del(keys),
             RedisStoreCommand::Set { key, value, px } => set(key, value, px.as_ref()),
             RedisStoreCommand::Keys { key } => keys(key),
             RedisStoreCommand::Type { key } => ty(key),
             RedisStoreCommand::XAdd {
                 key,
                 entry_id,
+                no_mkstream,
+                trim,
                 fields,
-            } => xadd(key, entry_id, fields),
+            } => xadd(key, entry_id, *no_mkstream, trim.as_ref(), fields),
+            RedisStoreCommand::XTrim { key, trim } => xtrim(key, trim),
+            RedisStoreCommand::XInfo { section } => xinfo(section),
+            RedisStoreCommand::XGroup { section } => xgroup(section),
+            RedisStoreCommand::XReadGroup {
+                group,
+                consumer,
+                count,
+                no_ack,
+                keys,
+                ids,
+            } => xreadgroup(group, consumer, *count, *no_ack, keys, ids),
+            RedisStoreCommand::XAck { key, group, ids } => xack(key, group, ids),
+            RedisStoreCommand::Object { section } => object(section),
+            RedisStoreCommand::SwapDb { index, other_index } => swapdb(*index, *other_index),
+            RedisStoreCommand::Move { key, db } => move_key(key, *db),
+            RedisStoreCommand::SetBit { key, offset, bit } => setbit(key, *offset, *bit),
+            RedisStoreCommand::GetBit { key, offset } => getbit(key, *offset),
+            RedisStoreCommand::BitCount { key, range } => bitcount(key, *range),
+            RedisStoreCommand::SAdd { key, members } => sadd(key, members),
+            RedisStoreCommand::SMembers { key } => smembers(key),
+            RedisStoreCommand::SetOperationStore {
+                operation,
+                destination,
+                keys,
+            } => set_operation_store(*operation, destination, keys),
+            RedisStoreCommand::SInterCard { keys, limit } => sintercard(keys, *limit),
+            RedisStoreCommand::SMove {
+                source,
+                destination,
+                member,
+            } => smove(source, destination, member),
+            RedisStoreCommand::ListPush { key, end, elements } => list_push(key, *end, elements),
+            RedisStoreCommand::ListPop { key, end, count } => list_pop(key, *end, *count),
+            RedisStoreCommand::LLen { key } => llen(key),
+            RedisStoreCommand::LMPop { keys, end, count } => lmpop(keys, *end, *count),
+            RedisStoreCommand::BLMPop {
+                keys,
+                timeout,
+                end,
+                count,
+            } => blmpop(keys, *timeout, *end, *count),
+            RedisStoreCommand::LRange { key, start, stop } => lrange(key, *start, *stop),
+            RedisStoreCommand::HSet { key, fields } => hset(key, fields),
+            RedisStoreCommand::HGet { key, field } => hget(key, field),
+            RedisStoreCommand::HRandField {
+                key,
+                count,
+                with_values,
+            } => hrandfield(key, *count, *with_values),
+            RedisStoreCommand::ZAdd { key, members } => zadd(key, members),
+            RedisStoreCommand::ZScore { key, member } => zscore(key, member),
+            RedisStoreCommand::ZRandMember {
+                key,
+                count,
+                with_scores,
+            } => zrandmember(key, *count, *with_scores),
+            RedisStoreCommand::ZPop { key, count, end } => zpop(key, *count, *end),
+            RedisStoreCommand::ZMPop { keys, end, count } => zmpop(keys, *end, *count),
+            RedisStoreCommand::PfAdd { key, elements } => pfadd(key, elements),
+            RedisStoreCommand::PfCount { keys } => pfcount(keys),
+            RedisStoreCommand::PfMerge { destination, keys } => pfmerge(destination, keys),
+            RedisStoreCommand::BZPop { keys, timeout, end } => bzpop(keys, *timeout, *end),
+            RedisStoreCommand::BZMPop {
+                keys,
+                timeout,
+                end,
+                count,
+            } => bzmpop(keys, *timeout, *end, *count),
+            RedisStoreCommand::GeoAdd { key, points } => geoadd(key, points),
+            RedisStoreCommand::GeoPos { key, members } => geopos(key, members),
+            RedisStoreCommand::GeoDist {
+                key,
+                member,
+                other_member,
+                unit,
+            } => geodist(key, member, other_member, *unit),
+            RedisStoreCommand::GeoSearch { key, search } => geosearch(key, search),
+            RedisStoreCommand::Sort { key, sort: options } => sort(key, options),
         }
     }
 }
@@ -166,9 +1221,43 @@ impl From<&RedisStoreCommand> for Bytes {
 impl From<&RedisServerCommand> for Bytes {
     fn from(command: &RedisServerCommand) -> Self {
         match command {
-            RedisServerCommand::Ping => ping(),
+            RedisServerCommand::Ping { message } => ping(message.as_deref()),
             RedisServerCommand::Echo { message } => echo(message),
             RedisServerCommand::Config { section } => config(section),
+            RedisServerCommand::Select { index } => select(*index),
+            RedisServerCommand::Lolwut { version } => lolwut(*version),
+            RedisServerCommand::Quit => quit(),
+            RedisServerCommand::Auth { username, password } => auth(username.as_ref(), password),
+            RedisServerCommand::Acl { section } => acl(section),
+            RedisServerCommand::Info { section } => info(*section),
+            RedisServerCommand::LastSave => lastsave(),
+            RedisServerCommand::Monitor => monitor(),
+            RedisServerCommand::Hello { protocol_version } => hello(*protocol_version),
+            RedisServerCommand::Debug { section } => debug(section),
+            RedisServerCommand::Command { section } => self::command(section),
+            RedisServerCommand::SlowLog { section } => slowlog(section),
+            RedisServerCommand::Latency { section } => latency(section),
+            RedisServerCommand::Cluster { section } => cluster(*section),
+            RedisServerCommand::Client { section } => client(*section),
+            RedisServerCommand::Function { section } => function(*section),
+            RedisServerCommand::Eval { script, keys, args } => eval("EVAL", script, keys, args),
+            RedisServerCommand::EvalSha { sha1, keys, args } => eval("EVALSHA", sha1, keys, args),
+            RedisServerCommand::Script { section } => script(section),
+            RedisServerCommand::FCall { is_read_only, args } => fcall(*is_read_only, args),
+        }
+    }
+}
+
+impl From<&RedisPubSubCommand> for Bytes {
+    fn from(command: &RedisPubSubCommand) -> Self {
+        match command {
+            RedisPubSubCommand::Subscribe { channels } => subscribe(channels),
+            RedisPubSubCommand::Unsubscribe { channels } => unsubscribe(channels),
+            RedisPubSubCommand::Publish { channel, message } => publish(channel, message),
+            RedisPubSubCommand::SSubscribe { channels } => ssubscribe(channels),
+            RedisPubSubCommand::SUnsubscribe { channels } => sunsubscribe(channels),
+            RedisPubSubCommand::SPublish { channel, message } => spublish(channel, message),
+            RedisPubSubCommand::PubSub { section } => pubsub(section),
         }
     }
 }
@@ -176,7 +1265,6 @@ impl From<&RedisServerCommand> for Bytes {
 impl From<&RedisReplicationCommand> for Bytes {
     fn from(command: &RedisReplicationCommand) -> Self {
         match command {
-            RedisReplicationCommand::Info { section } => info(*section),
             RedisReplicationCommand::ReplConf {
                 section: ReplConfSection::Port { listening_port },
             } => replconf_port(*listening_port),
@@ -189,14 +1277,29 @@ impl From<&RedisReplicationCommand> for Bytes {
             RedisReplicationCommand::ReplConf {
                 section: ReplConfSection::Ack { processed_bytes },
             } => replconf_ack(*processed_bytes),
+            RedisReplicationCommand::ReplConf {
+                section: ReplConfSection::MalformedAck { offset },
+            } => array(vec![
+                bulk_string("REPLCONF"),
+                bulk_string("ACK"),
+                bulk_string(offset),
+            ])
+            .into(),
             RedisReplicationCommand::PSync {
                 replication_id,
                 replication_offset,
-            } => psync(replication_id, *replication_offset),
+                failover: is_failover,
+            } => psync(replication_id, *replication_offset, *is_failover),
+            RedisReplicationCommand::Failover { section } => failover(section),
             RedisReplicationCommand::Wait {
                 num_replicas,
                 timeout,
             } => wait(*num_replicas, *timeout),
+            RedisReplicationCommand::WaitAof {
+                num_local,
+                num_replicas,
+                timeout,
+            } => waitaof(*num_local, *num_replicas, *timeout),
         }
     }
 }
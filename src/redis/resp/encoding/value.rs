@@ -7,6 +7,11 @@ pub fn simple_string(bytes: impl AsRef<[u8]>) -> RESPValue {
     RESPValue::SimpleString(bytes)
 }
 
+pub fn simple_error(bytes: impl AsRef<[u8]>) -> RESPValue {
+    let bytes = Bytes::copy_from_slice(bytes.as_ref());
+    RESPValue::SimpleError(bytes)
+}
+
 pub fn integer(value: impl Into<i64>) -> RESPValue {
     RESPValue::Integer(value.into())
 }
@@ -20,10 +25,50 @@ pub fn null_bulk_string() -> RESPValue {
     RESPValue::NullBulkString
 }
 
+pub fn null_array() -> RESPValue {
+    RESPValue::NullArray
+}
+
 pub fn array(values: Vec<RESPValue>) -> RESPValue {
     RESPValue::Array(values)
 }
 
+pub fn map(entries: Vec<(RESPValue, RESPValue)>) -> RESPValue {
+    RESPValue::Map(entries)
+}
+
+/// A map for RESP3 connections, or the flat array of its keys and values that RESP2
+/// connections get instead.
+pub fn map_or_array(is_resp3: bool, entries: Vec<(RESPValue, RESPValue)>) -> RESPValue {
+    if is_resp3 {
+        map(entries)
+    } else {
+        array(
+            entries
+                .into_iter()
+                .flat_map(|(key, value)| [key, value])
+                .collect(),
+        )
+    }
+}
+
+pub fn push(values: Vec<RESPValue>) -> RESPValue {
+    RESPValue::Push(values)
+}
+
+/// Builds the reply to `<command> HELP`, framing the subcommand descriptions the same
+/// way Redis does.
+pub fn help(command_name: &str, lines: &[&str]) -> RESPValue {
+    let mut values = vec![bulk_string(format!(
+        "{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+        command_name.to_ascii_uppercase()
+    ))];
+    values.extend(lines.iter().map(bulk_string));
+    values.push(bulk_string("HELP"));
+    values.push(bulk_string("    Print this help."));
+    array(values)
+}
+
 impl From<RESPValue> for Bytes {
     fn from(value: RESPValue) -> Self {
         let mut output = BytesMut::new();
@@ -62,6 +107,22 @@ impl From<RESPValue> for Bytes {
             RESPValue::NullArray => {
                 output.extend_from_slice(b"*-1\r\n");
             }
+            RESPValue::Map(entries) => {
+                let prefix = format!("%{}\r\n", entries.len());
+                output.extend_from_slice(prefix.as_bytes());
+                for (key, value) in entries {
+                    output.extend_from_slice(&Bytes::from(key));
+                    output.extend_from_slice(&Bytes::from(value));
+                }
+            }
+            RESPValue::Push(values) => {
+                let prefix = format!(">{}\r\n", values.len());
+                output.extend_from_slice(prefix.as_bytes());
+                values
+                    .into_iter()
+                    .map(Bytes::from)
+                    .for_each(|bytes| output.extend_from_slice(&bytes));
+            }
         }
 
         output.freeze()
@@ -1,4 +1,6 @@
 pub mod command;
+pub mod command_docs;
+pub mod command_table;
 pub mod encoding;
 pub mod resp_reader;
 
@@ -13,6 +15,11 @@ pub enum RESPValue {
     NullBulkString,
     Array(Vec<RESPValue>),
     NullArray,
+    /// A RESP3 map, only sent to connections that switched to RESP3 with `HELLO`.
+    Map(Vec<(RESPValue, RESPValue)>),
+    /// A RESP3 out-of-band message, such as a pub-sub message, which clients can tell
+    /// apart from the replies to their commands.
+    Push(Vec<RESPValue>),
 }
 
 impl RESPValue {
@@ -21,10 +21,42 @@ macro_rules! check_eof {
     };
 }
 
+/// The default of the largest bulk string a client may send, like `proto-max-bulk-len`.
+pub const DEFAULT_MAX_BULK_LEN: usize = 512 << 20;
+
+/// The most elements an array may declare, which is the limit of Redis as well.
+const MAX_ARRAY_LEN: i64 = i32::MAX as i64;
+
+/// The longest inline command, which is the limit of Redis as well.
+const MAX_INLINE_LEN: usize = 64 << 10;
+
+/// A request that is rejected before anything more is buffered for it. Unlike other
+/// parse errors, it is replied to the client before the connection is closed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, thiserror::Error)]
+pub enum ProtocolError {
+    #[error("Protocol error: invalid bulk length")]
+    InvalidBulkLength,
+    #[error("Protocol error: invalid multibulk length")]
+    InvalidMultiBulkLength,
+    #[error("Protocol error: too big inline request")]
+    TooBigInlineRequest,
+}
+
+/// Whether a value starts with `byte`, as opposed to an inline command, which is plain
+/// text such as `PING` typed into a raw connection.
+fn is_data_tag(byte: u8) -> bool {
+    matches!(byte, b'+' | b'-' | b':' | b'$' | b'*')
+}
+
 pub struct RESPReader<R> {
     inner: R,
     buf: BytesMut,
+    /// How far the value at the start of `buf` has been validated.
     cursor: usize,
+    /// The number of elements still to be validated for each array the cursor is in,
+    /// from the outermost array to the innermost one.
+    pending_elements: Vec<usize>,
+    max_bulk_len: usize,
     is_closed: bool,
 }
 
@@ -34,10 +66,17 @@ impl<R: AsyncRead + Unpin> RESPReader<R> {
             inner,
             buf: BytesMut::with_capacity(4096),
             cursor: 0,
+            pending_elements: vec![],
+            max_bulk_len: DEFAULT_MAX_BULK_LEN,
             is_closed: false,
         }
     }
 
+    pub fn with_max_bulk_len(mut self, max_bulk_len: usize) -> Self {
+        self.max_bulk_len = max_bulk_len;
+        self
+    }
+
     pub fn is_closed(&self) -> bool {
         self.is_closed
     }
@@ -46,6 +85,7 @@ impl<R: AsyncRead + Unpin> RESPReader<R> {
         loop {
             self.cursor = 0;
             if self.check_rdb_file()? {
+                self.cursor = 0;
                 let bytes = self.parse_rdb_file();
                 return Ok(bytes);
             }
@@ -99,10 +139,18 @@ impl<R: AsyncRead + Unpin> RESPReader<R> {
 
     pub async fn read_value(&mut self) -> anyhow::Result<RESPValue> {
         loop {
-            self.cursor = 0;
-            if !self.buf.is_empty() && self.check()? {
-                let value = self.parse();
-                return Ok(value);
+            match self.check() {
+                Ok(true) => {
+                    self.cursor = 0;
+                    let value = self.parse();
+                    return Ok(value);
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    self.cursor = 0;
+                    self.pending_elements.clear();
+                    return Err(err);
+                }
             }
 
             let n = self.inner.read_buf(&mut self.buf).await?;
@@ -115,7 +163,56 @@ impl<R: AsyncRead + Unpin> RESPReader<R> {
         }
     }
 
+    /// Checks whether a complete value is buffered. Progress is kept between calls, so
+    /// when a value arrives over many reads only the newly read bytes are validated
+    /// instead of the whole value again.
     fn check(&mut self) -> anyhow::Result<bool> {
+        // the format is told apart for every command, so inline commands and arrays can
+        // follow each other on the same connection
+        while self.pending_elements.is_empty()
+            && self.buf.first().is_some_and(|&byte| !is_data_tag(byte))
+        {
+            check_eof!(self.check_inline()?);
+            if !self.buf[..self.cursor].iter().all(u8::is_ascii_whitespace) {
+                return Ok(true);
+            }
+
+            // like in Redis, empty lines are skipped
+            self.buf.advance(self.cursor);
+            self.cursor = 0;
+        }
+
+        loop {
+            let token_start = self.cursor;
+            let open_arrays = self.pending_elements.len();
+            if !self.check_token()? {
+                self.cursor = token_start;
+                return Ok(false);
+            }
+
+            if self.pending_elements.len() > open_arrays {
+                continue;
+            }
+
+            // a complete value might be the last element of the arrays it is nested in
+            loop {
+                let Some(remaining) = self.pending_elements.last_mut() else {
+                    return Ok(true);
+                };
+
+                *remaining -= 1;
+                if *remaining > 0 {
+                    break;
+                }
+
+                self.pending_elements.pop();
+            }
+        }
+    }
+
+    /// Checks a single scalar value or the header of an array, whose elements are then
+    /// checked one at a time by `check`.
+    fn check_token(&mut self) -> anyhow::Result<bool> {
         let data_tag = handle_eof!(self.check_advance());
         match data_tag {
             b'+' => self.check_resp_simple_string(),
@@ -132,6 +229,10 @@ impl<R: AsyncRead + Unpin> RESPReader<R> {
 
     fn parse(&mut self) -> RESPValue {
         let data_tag = self.buf[0];
+        if !is_data_tag(data_tag) {
+            return self.parse_inline();
+        }
+
         self.buf.advance(1);
         match data_tag {
             b'+' => self.parse_resp_simple_string(),
@@ -143,6 +244,31 @@ impl<R: AsyncRead + Unpin> RESPReader<R> {
         }
     }
 
+    /// Checks that a whole line is buffered, which is read as the parts of a command
+    /// separated by whitespace.
+    fn check_inline(&mut self) -> anyhow::Result<bool> {
+        match self.buf.iter().position(|&byte| byte == b'\n') {
+            Some(end) if end < MAX_INLINE_LEN => {
+                self.cursor = end + 1;
+                Ok(true)
+            }
+            None if self.buf.len() < MAX_INLINE_LEN => Ok(false),
+            _ => Err(ProtocolError::TooBigInlineRequest.into()),
+        }
+    }
+
+    fn parse_inline(&mut self) -> RESPValue {
+        let end = self.buf.iter().position(|&byte| byte == b'\n').unwrap();
+        let line = self.buf.split_to(end + 1).freeze();
+        let parts = line
+            .split(u8::is_ascii_whitespace)
+            .filter(|part| !part.is_empty())
+            .map(|part| RESPValue::BulkString(line.slice_ref(part)))
+            .collect();
+
+        RESPValue::Array(parts)
+    }
+
     fn check_resp_simple_string(&mut self) -> anyhow::Result<bool> {
         check_eof!(self.check_read_until(|byte| byte == b'\r')?);
         self.check_crlf()
@@ -221,6 +347,11 @@ impl<R: AsyncRead + Unpin> RESPReader<R> {
             return Ok(true);
         }
 
+        // rejected before waiting for the data, so the declared length is never buffered
+        if length as u64 > self.max_bulk_len as u64 {
+            return Err(ProtocolError::InvalidBulkLength.into());
+        }
+
         if self.buf.get(self.cursor + length as usize - 1).is_none() {
             return Ok(false);
         }
@@ -270,12 +401,12 @@ impl<R: AsyncRead + Unpin> RESPReader<R> {
             ));
         }
 
-        if length == -1 {
-            return Ok(true);
+        if length > MAX_ARRAY_LEN {
+            return Err(ProtocolError::InvalidMultiBulkLength.into());
         }
 
-        for _ in 0..length {
-            check_eof!(self.check()?)
+        if length > 0 {
+            self.pending_elements.push(length as usize);
         }
 
         Ok(true)
@@ -380,9 +511,21 @@ impl<R: AsyncRead + Unpin> RESPReader<R> {
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+        time::{Duration, Instant},
+    };
+
     use bytes::Bytes;
+    use tokio::{
+        io::{AsyncRead, AsyncWriteExt, ReadBuf},
+        time::timeout,
+    };
+
+    use crate::redis::resp::command::{RedisCommand, RedisServerCommand};
 
-    use super::{RESPReader, RESPValue};
+    use super::{ProtocolError, RESPReader, RESPValue};
 
     #[tokio::test]
     async fn parses_simple_string() {
@@ -394,6 +537,23 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn parses_values_after_rdb_file() {
+        let mut stream =
+            RESPReader::new("+FULLRESYNC id 0\r\n$3\r\nrdb*1\r\n$4\r\nPING\r\n".as_bytes());
+        let value = stream.read_value().await;
+        assert_eq!(
+            value.unwrap(),
+            RESPValue::SimpleString(Bytes::from_static(b"FULLRESYNC id 0"))
+        );
+        assert_eq!(stream.read_rdb_file().await.unwrap(), &b"rdb"[..]);
+        let value = stream.read_value().await;
+        assert_eq!(
+            value.unwrap(),
+            RESPValue::Array(vec![RESPValue::BulkString(Bytes::from_static(b"PING"))])
+        );
+    }
+
     #[tokio::test]
     async fn parses_simple_error() {
         let mut stream = RESPReader::new("-ERR key does not exist\r\n".as_bytes());
@@ -442,6 +602,96 @@ mod tests {
         assert!(value.is_err());
     }
 
+    #[tokio::test]
+    async fn rejects_oversized_lengths_before_their_data_arrives() {
+        // the client never sends the data, so waiting for it would hang the test
+        let (mut client, server) = tokio::io::duplex(64);
+        client.write_all(b"$1000000000\r\n").await.unwrap();
+        let mut stream = RESPReader::new(server);
+        let err = timeout(Duration::from_secs(1), stream.read_value())
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::InvalidBulkLength)
+        );
+        assert!(stream.buf.capacity() < 1 << 20);
+
+        let mut stream =
+            RESPReader::new("$5\r\nhello\r\n$6\r\nhello!\r\n".as_bytes()).with_max_bulk_len(5);
+        assert_eq!(
+            stream.read_value().await.unwrap(),
+            RESPValue::BulkString(Bytes::from_static(b"hello"))
+        );
+        let err = stream.read_value().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::InvalidBulkLength)
+        );
+
+        let mut stream = RESPReader::new("*2147483648\r\n".as_bytes());
+        let err = stream.read_value().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::InvalidMultiBulkLength)
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_inline_commands_between_arrays() {
+        let mut stream = RESPReader::new("PING\r\n*1\r\n$4\r\nPING\r\n".as_bytes());
+        for _ in 0..2 {
+            let command = RedisCommand::try_from(stream.read_value().await.unwrap());
+            assert!(matches!(
+                command.unwrap(),
+                RedisCommand::Server(RedisServerCommand::Ping { .. })
+            ));
+        }
+
+        let mut stream = RESPReader::new(ChunkedReader {
+            data: b"\r\n  set  key\tvalue\n*1\r\n$4\r\nPING\r\necho hello\r\n".to_vec(),
+            chunk_size: 3,
+            position: 0,
+        });
+        assert_eq!(
+            stream.read_value().await.unwrap(),
+            RESPValue::Array(vec![
+                RESPValue::BulkString(Bytes::from_static(b"set")),
+                RESPValue::BulkString(Bytes::from_static(b"key")),
+                RESPValue::BulkString(Bytes::from_static(b"value")),
+            ])
+        );
+        assert_eq!(
+            stream.read_value().await.unwrap(),
+            RESPValue::Array(vec![RESPValue::BulkString(Bytes::from_static(b"PING"))])
+        );
+        assert_eq!(
+            stream.read_value().await.unwrap(),
+            RESPValue::Array(vec![
+                RESPValue::BulkString(Bytes::from_static(b"echo")),
+                RESPValue::BulkString(Bytes::from_static(b"hello")),
+            ])
+        );
+
+        let mut stream = RESPReader::new(&[b'a'; 70_000][..]);
+        let err = stream.read_value().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ProtocolError>(),
+            Some(&ProtocolError::TooBigInlineRequest)
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_binary_bulk_string() {
+        let mut stream = RESPReader::new(&b"$7\r\n\x00\xff\xfe\r\nab\r\n"[..]);
+        let value = stream.read_value().await;
+        assert_eq!(
+            value.unwrap(),
+            RESPValue::BulkString(Bytes::from_static(b"\x00\xff\xfe\r\nab"))
+        );
+    }
+
     #[tokio::test]
     async fn parses_array() {
         let mut stream =
@@ -461,4 +711,81 @@ mod tests {
         let value = stream.read_value().await;
         assert_eq!(value.unwrap(), RESPValue::NullArray);
     }
+
+    struct ChunkedReader {
+        data: Vec<u8>,
+        position: usize,
+        chunk_size: usize,
+    }
+
+    impl AsyncRead for ChunkedReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let end = (self.position + self.chunk_size)
+                .min(self.data.len())
+                .min(self.position + buf.remaining());
+            buf.put_slice(&self.data[self.position..end]);
+            self.position = end;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn command(index: usize) -> Vec<u8> {
+        let value = index.to_string();
+        format!(
+            "*3\r\n$3\r\nSET\r\n${}\r\n{value}\r\n${}\r\n{value}\r\n",
+            value.len(),
+            value.len()
+        )
+        .into_bytes()
+    }
+
+    #[tokio::test]
+    async fn parses_ten_thousand_commands_from_one_segment() {
+        let data = (0..10_000).flat_map(command).collect::<Vec<_>>();
+        let mut stream = RESPReader::new(ChunkedReader {
+            chunk_size: data.len(),
+            data,
+            position: 0,
+        });
+
+        let start = Instant::now();
+        for index in 0..10_000 {
+            let value = stream.read_value().await.unwrap();
+            let RESPValue::Array(values) = value else {
+                panic!("expected an array");
+            };
+            assert_eq!(values[1], RESPValue::BulkString(index.to_string().into()));
+        }
+
+        // re-validating from the start of each value made this quadratic
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn parses_large_array_split_across_reads() {
+        let elements = 10_000;
+        let mut data = format!("*{elements}\r\n").into_bytes();
+        for index in 0..elements {
+            let value = index.to_string();
+            data.extend_from_slice(format!("${}\r\n{value}\r\n", value.len()).as_bytes());
+        }
+
+        let mut stream = RESPReader::new(ChunkedReader {
+            data,
+            position: 0,
+            chunk_size: 7,
+        });
+
+        let start = Instant::now();
+        let RESPValue::Array(values) = stream.read_value().await.unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(values.len(), elements);
+        assert_eq!(values[9_999], RESPValue::BulkString("9999".into()));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
 }
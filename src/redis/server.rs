@@ -1,21 +1,38 @@
 use std::{
     fmt::Display,
-    net::SocketAddr,
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
     ops::AddAssign,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
+    time::Duration,
 };
 
+use anyhow::Context;
 use bytes::Bytes;
 use tokio::{
-    io::AsyncWriteExt,
-    net::{TcpListener, ToSocketAddrs},
-    sync::mpsc,
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpSocket, TcpStream, UnixListener, UnixStream},
+    runtime::{self, Handle},
+    sync::{mpsc, Notify},
+    time::timeout,
 };
 
-use super::resp::{command::RedisCommand, resp_reader::RESPReader};
+use super::{
+    error::RedisError,
+    log::log,
+    replication::command::RedisReplicationCommand,
+    resp::{
+        command::RedisCommand,
+        resp_reader::{RESPReader, DEFAULT_MAX_BULK_LEN},
+        RESPValue,
+    },
+    slowlog::SlowLogConfig,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ClientId(usize);
@@ -24,6 +41,15 @@ impl ClientId {
     pub fn primary() -> Self {
         Self(usize::MAX)
     }
+
+    pub fn value(self) -> usize {
+        self.0
+    }
+
+    #[cfg(test)]
+    pub fn new(id: usize) -> Self {
+        Self(id)
+    }
 }
 
 impl Display for ClientId {
@@ -38,16 +64,185 @@ impl AddAssign<usize> for ClientId {
     }
 }
 
+pub struct ServerConfig {
+    pub bind_addresses: Vec<IpAddr>,
+    pub port: u16,
+    pub unix_socket: Option<PathBuf>,
+    pub tcp_backlog: u32,
+    /// Whether `SO_KEEPALIVE` is set on connections. The probe interval cannot be set, so
+    /// unlike in Redis this is a switch rather than a number of seconds.
+    pub tcp_keepalive: bool,
+    pub timeout: u64,
+    pub output_buffer_limits: OutputBufferLimits,
+    pub slowlog: SlowLogConfig,
+    /// The number of milliseconds from which latency spikes are recorded, or 0 to not
+    /// record them.
+    pub latency_monitor_threshold: u64,
+    /// The number of dedicated threads that read, parse and write the traffic of client
+    /// connections, or 1 to do so on the runtime that executes commands.
+    pub io_threads: usize,
+    /// The largest bulk string a client may send, checked before it is buffered.
+    pub proto_max_bulk_len: usize,
+}
+
+impl ServerConfig {
+    pub fn new(
+        bind_addresses: Vec<IpAddr>,
+        port: u16,
+        unix_socket: Option<PathBuf>,
+        tcp_backlog: u32,
+        tcp_keepalive: bool,
+        timeout: u64,
+        output_buffer_limits: OutputBufferLimits,
+    ) -> Self {
+        Self {
+            bind_addresses,
+            port,
+            unix_socket,
+            tcp_backlog,
+            tcp_keepalive,
+            timeout,
+            output_buffer_limits,
+            slowlog: SlowLogConfig::default(),
+            latency_monitor_threshold: 0,
+            io_threads: 1,
+            proto_max_bulk_len: DEFAULT_MAX_BULK_LEN,
+        }
+    }
+
+    pub fn addresses(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.bind_addresses
+            .iter()
+            .map(|&address| SocketAddr::new(address, self.port))
+    }
+}
+
+/// How many bytes may wait to be written to a client, by the kind of client, where 0
+/// means there is no limit.
+///
+/// Replicas and pub/sub subscribers are fed by the commands of other clients, which
+/// cannot wait for them, so they are disconnected once they fall this far behind.
+/// Everyone else only receives replies to their own commands, so instead their commands
+/// stop being read until the replies are written, which holds up no other client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputBufferLimits {
+    pub normal: usize,
+    pub replica: usize,
+    pub pubsub: usize,
+}
+
+impl Default for OutputBufferLimits {
+    fn default() -> Self {
+        Self {
+            normal: 1 << 20,
+            replica: 256 << 20,
+            pubsub: 32 << 20,
+        }
+    }
+}
+
+impl OutputBufferLimits {
+    /// Parses pairs of a class, one of `normal`, `replica` (or `slave`) and `pubsub`, and
+    /// a limit such as `32mb`, keeping the defaults of classes that are not given.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut limits = Self::default();
+        let values = value.split_ascii_whitespace().collect::<Vec<_>>();
+        if values.is_empty() || values.len() % 2 != 0 {
+            return None;
+        }
+
+        for pair in values.chunks_exact(2) {
+            let limit = parse_size(pair[1])?;
+            match &*pair[0].to_ascii_lowercase() {
+                "normal" => limits.normal = limit,
+                "replica" | "slave" => limits.replica = limit,
+                "pubsub" => limits.pubsub = limit,
+                _ => return None,
+            }
+        }
+
+        Some(limits)
+    }
+}
+
+/// Parses a number of bytes with an optional unit, where `k`, `m` and `g` are powers of
+/// 1000 and `kb`, `mb` and `gb` powers of 1024, like in Redis.
+pub fn parse_size(value: &str) -> Option<usize> {
+    let value = value.to_ascii_lowercase();
+    let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = match &value[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1 << 10,
+        "m" => 1000 * 1000,
+        "mb" => 1 << 20,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1 << 30,
+        _ => return None,
+    };
+
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+impl Display for OutputBufferLimits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "normal {} replica {} pubsub {}",
+            self.normal, self.replica, self.pubsub
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct RedisServer {
     id: ClientId,
-    listener: TcpListener,
+    idle_timeout: Option<Duration>,
+    output_buffer_limit: usize,
+    proto_max_bulk_len: usize,
+    io_threads: Option<IoThreads>,
+    connection_rx: mpsc::Receiver<std::io::Result<Connection>>,
 }
 
-pub struct RedisReadStream(mpsc::Receiver<anyhow::Result<RedisCommand>>);
+/// Runtimes on dedicated threads that connections are handed out to in turn, so their
+/// socket I/O and RESP parsing do not compete with command execution.
+#[derive(Debug)]
+struct IoThreads {
+    handles: Vec<Handle>,
+    next: usize,
+}
+
+impl IoThreads {
+    fn start(count: usize) -> std::io::Result<Self> {
+        let handles = (0..count)
+            .map(|index| {
+                let runtime = runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?;
+                let handle = runtime.handle().clone();
+                // the threads live as long as the process, like the listeners do
+                std::thread::Builder::new()
+                    .name(format!("redis-io-{index}"))
+                    .spawn(move || runtime.block_on(std::future::pending::<()>()))?;
+
+                Ok(handle)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(Self { handles, next: 0 })
+    }
+
+    fn next_handle(&mut self) -> Handle {
+        let handle = self.handles[self.next].clone();
+        self.next = (self.next + 1) % self.handles.len();
+        handle
+    }
+}
+
+pub struct RedisReadStream(mpsc::Receiver<Result<RedisCommand, RedisError>>);
 
 impl RedisReadStream {
-    pub async fn read(&mut self) -> anyhow::Result<Option<RedisCommand>> {
+    pub async fn read(&mut self) -> Result<Option<RedisCommand>, RedisError> {
         match self.0.recv().await {
             Some(Ok(command)) => Ok(Some(command)),
             Some(Err(err)) => Err(err),
@@ -56,36 +251,137 @@ impl RedisReadStream {
     }
 }
 
+/// The bytes written to a connection that did not reach its socket yet, shared by every
+/// clone of its write stream and the tasks driving the connection.
+#[derive(Debug, Default)]
+struct OutputBuffer {
+    pending: AtomicUsize,
+    is_disconnected: AtomicBool,
+    disconnect_signal: Notify,
+    /// Notified whenever bytes reach the socket or the client is unblocked, which is
+    /// what a reading task that stopped reading waits for.
+    wake_signal: Notify,
+}
+
+impl OutputBuffer {
+    fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Counts bytes written to the connection, returning how many are pending now.
+    fn push(&self, len: usize) -> usize {
+        self.pending.fetch_add(len, Ordering::SeqCst) + len
+    }
+
+    fn drain(&self, len: usize) {
+        self.pending.fetch_sub(len, Ordering::SeqCst);
+        self.wake_signal.notify_waiters();
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.is_disconnected.load(Ordering::SeqCst)
+    }
+
+    fn disconnect(&self) {
+        self.is_disconnected.store(true, Ordering::SeqCst);
+        self.disconnect_signal.notify_waiters();
+    }
+
+    async fn disconnected(&self) {
+        loop {
+            // registered before checking, so that a disconnect in between is not missed
+            let signal = self.disconnect_signal.notified();
+            if self.is_disconnected() {
+                return;
+            }
+
+            signal.await;
+        }
+    }
+}
+
+/// The sending side of a connection. Every clone writes to the same channel, which is
+/// drained in order by a single task, so replies and pub/sub messages for a connection
+/// reach the socket in the order they were written and are never interleaved.
+///
+/// Writing never waits for the client, since all clients share one command loop. Instead
+/// the bytes still to be written are counted, see [`OutputBufferLimits`].
 #[derive(Clone)]
 pub struct RedisWriteStream {
     should_send: bool,
-    tx: mpsc::Sender<Bytes>,
+    /// The number of pending bytes past which writing through this stream drops the
+    /// connection, if any.
+    output_buffer_limit: Option<usize>,
+    output_buffer: Arc<OutputBuffer>,
+    tx: mpsc::UnboundedSender<Bytes>,
 }
 
 impl RedisWriteStream {
-    pub fn new(tx: mpsc::Sender<Bytes>) -> Self {
+    pub fn new(tx: mpsc::UnboundedSender<Bytes>) -> Self {
         Self {
             should_send: true,
+            output_buffer_limit: None,
+            output_buffer: Arc::default(),
             tx,
         }
     }
 
+    #[cfg(test)]
     pub fn sink() -> Self {
-        let (tx, mut rx) = mpsc::channel(32);
-        tokio::spawn(async move {
-            while rx.recv().await.is_some() {}
-        });
-        
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
         Self::new(tx)
     }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.output_buffer.is_disconnected()
+    }
+
+    /// Resolves once the connection is dropped, however that happens.
+    pub async fn disconnected(&self) {
+        self.output_buffer.disconnected().await
+    }
+
+    /// Closes the connection, dropping whatever was not sent to it yet.
+    pub fn disconnect(&self) {
+        self.output_buffer.disconnect();
+    }
+
+    /// Makes the connection check again whether it may read, for a client unblocked
+    /// after its reply was written.
+    pub fn wake_reader(&self) {
+        self.output_buffer.wake_signal.notify_waiters();
+    }
+
+    /// Drops the connection when a write through the returned stream leaves more than
+    /// `limit` bytes pending, unless `limit` is 0.
+    pub fn with_output_buffer_limit(mut self, limit: usize) -> Self {
+        self.output_buffer_limit = (limit > 0).then_some(limit);
+        self
+    }
 }
 
 impl RedisWriteStream {
+    /// Queues bytes for the connection. Writes to a connection that was dropped for
+    /// falling behind are discarded.
     pub async fn write(&self, bytes: impl Into<Bytes>) -> anyhow::Result<()> {
-        if self.should_send {
-            self.tx.send(bytes.into()).await?;
+        if !self.should_send || self.output_buffer.is_disconnected() {
+            return Ok(());
+        }
+
+        let bytes = bytes.into();
+        let pending = self.output_buffer.push(bytes.len());
+        if let Some(limit) = self.output_buffer_limit.filter(|&limit| pending > limit) {
+            log!(
+                Warning,
+                "dropping client with {pending} bytes pending, over its output buffer limit of {limit}"
+            );
+            self.output_buffer.disconnect();
+            return Ok(());
         }
 
+        self.tx.send(bytes)?;
         Ok(())
     }
 
@@ -94,64 +390,741 @@ impl RedisWriteStream {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl Display for ClientAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientAddress::Tcp(address) => write!(f, "{address}"),
+            ClientAddress::Unix(path) => write!(f, "{}:0", path.display()),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ClientConnectionInfo {
     pub id: ClientId,
-    pub address: SocketAddr,
+    pub address: ClientAddress,
     pub is_read_blocked: Arc<AtomicBool>,
+    pub selected_db: Arc<AtomicUsize>,
+    pub subscription_count: Arc<AtomicUsize>,
+    pub user: Arc<Mutex<Option<Bytes>>>,
+    /// The RESP version the connection speaks, 2 until it switches with `HELLO`.
+    pub protocol_version: Arc<AtomicU8>,
+    /// Set once the client stops sending commands, because it disconnected or quit.
+    pub is_closed: Arc<AtomicBool>,
+    /// Set with `CLIENT NO-EVICT`. The server never evicts clients, so it is only recorded.
+    pub is_no_evict: Arc<AtomicBool>,
+    /// Set with `CLIENT NO-TOUCH`. Keys have no access time yet, so it is only recorded.
+    pub is_no_touch: Arc<AtomicBool>,
+}
+
+impl ClientConnectionInfo {
+    pub fn new(id: ClientId, address: ClientAddress) -> Self {
+        Self {
+            id,
+            address,
+            is_read_blocked: Arc::new(AtomicBool::new(false)),
+            selected_db: Arc::new(AtomicUsize::new(0)),
+            subscription_count: Arc::new(AtomicUsize::new(0)),
+            user: Arc::new(Mutex::new(None)),
+            protocol_version: Arc::new(AtomicU8::new(2)),
+            is_closed: Arc::new(AtomicBool::new(false)),
+            is_no_evict: Arc::new(AtomicBool::new(false)),
+            is_no_touch: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn selected_db(&self) -> usize {
+        self.selected_db.load(Ordering::SeqCst)
+    }
+
+    /// The user the connection authenticated as, if it has authenticated at all.
+    pub fn user(&self) -> Option<Bytes> {
+        self.user.lock().unwrap().clone()
+    }
+
+    pub fn set_user(&self, user: Bytes) {
+        *self.user.lock().unwrap() = Some(user);
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription_count.load(Ordering::SeqCst) > 0
+    }
+
+    pub fn protocol_version(&self) -> u8 {
+        self.protocol_version.load(Ordering::SeqCst)
+    }
+
+    pub fn is_resp3(&self) -> bool {
+        self.protocol_version() == 3
+    }
+
+    pub fn close(&self) {
+        self.is_closed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.is_closed.load(Ordering::SeqCst)
+    }
+
+    pub fn set_no_evict(&self, is_enabled: bool) {
+        self.is_no_evict.store(is_enabled, Ordering::SeqCst);
+    }
+
+    pub fn set_no_touch(&self, is_enabled: bool) {
+        self.is_no_touch.store(is_enabled, Ordering::SeqCst);
+    }
+}
+
+/// Connection counters shared by the accept loop and the connection tasks.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    connected_clients: Arc<AtomicUsize>,
+    total_connections_received: Arc<AtomicUsize>,
+}
+
+impl ConnectionStats {
+    pub fn connected_clients(&self) -> usize {
+        self.connected_clients.load(Ordering::SeqCst)
+    }
+
+    pub fn total_connections_received(&self) -> usize {
+        self.total_connections_received.load(Ordering::SeqCst)
+    }
+
+    /// Records an accepted connection, which stays connected until the returned
+    /// guard is dropped.
+    pub fn connect(&self) -> ConnectionGuard {
+        self.connected_clients.fetch_add(1, Ordering::SeqCst);
+        self.total_connections_received
+            .fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard(self.connected_clients.clone())
+    }
+}
+
+/// Marks a connection as disconnected when dropped, however its task ends.
+#[derive(Debug)]
+pub struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug)]
+enum Connection {
+    Tcp(TcpStream, SocketAddr),
+    Unix(UnixStream, PathBuf),
+}
+
+impl Connection {
+    fn address(&self) -> ClientAddress {
+        match self {
+            Connection::Tcp(_, address) => ClientAddress::Tcp(*address),
+            Connection::Unix(_, path) => ClientAddress::Unix(path.clone()),
+        }
+    }
 }
 
 impl RedisServer {
-    pub async fn start(addresses: impl ToSocketAddrs) -> anyhow::Result<Self> {
-        let listener = TcpListener::bind(addresses).await?;
+    pub async fn start(config: &ServerConfig) -> anyhow::Result<Self> {
+        let (connection_tx, connection_rx) = mpsc::channel(32);
+        for address in config.addresses() {
+            let listener = Self::listen(address, config).with_context(|| {
+                format!("[redis - error] unable to bind listener to address {address}")
+            })?;
+
+            let connection_tx = connection_tx.clone();
+            tokio::spawn(async move {
+                loop {
+                    let connection = listener
+                        .accept()
+                        .await
+                        .map(|(stream, address)| Connection::Tcp(stream, address));
+
+                    if connection_tx.send(connection).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        if let Some(path) = &config.unix_socket {
+            Self::remove_stale_socket(path)?;
+            let listener = UnixListener::bind(path).with_context(|| {
+                format!(
+                    "[redis - error] unable to bind listener to unix socket {}",
+                    path.display()
+                )
+            })?;
+
+            let path = path.clone();
+            tokio::spawn(async move {
+                loop {
+                    let connection = listener
+                        .accept()
+                        .await
+                        .map(|(stream, _)| Connection::Unix(stream, path.clone()));
+
+                    if connection_tx.send(connection).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let io_threads = (config.io_threads > 1)
+            .then(|| IoThreads::start(config.io_threads))
+            .transpose()
+            .context("[redis - error] unable to start I/O threads")?;
+
         Ok(Self {
             id: ClientId(0),
-            listener,
+            idle_timeout: (config.timeout > 0).then(|| Duration::from_secs(config.timeout)),
+            output_buffer_limit: config.output_buffer_limits.normal,
+            proto_max_bulk_len: config.proto_max_bulk_len,
+            io_threads,
+            connection_rx,
         })
     }
 
+    fn listen(address: SocketAddr, config: &ServerConfig) -> std::io::Result<TcpListener> {
+        let socket = if address.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+
+        // accepted connections inherit `SO_KEEPALIVE` from the listening socket, so
+        // replica links and idle clients that silently go away are eventually reaped
+        socket.set_keepalive(config.tcp_keepalive)?;
+        socket.set_reuseaddr(true)?;
+        socket.bind(address)?;
+        socket.listen(config.tcp_backlog)
+    }
+
+    /// Removes a socket file left behind by a previous run, which would otherwise make
+    /// binding fail. Anything else at the path is kept, and fails the startup instead.
+    fn remove_stale_socket(path: &Path) -> anyhow::Result<()> {
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => Ok(std::fs::remove_file(path)?),
+            Ok(_) => Err(anyhow::anyhow!(
+                "[redis - error] unable to use {} as unix socket, it exists and is not a socket",
+                path.display()
+            )),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     pub async fn accept(
         &mut self,
     ) -> anyhow::Result<(RedisReadStream, RedisWriteStream, ClientConnectionInfo)> {
-        let (stream, address) = self.listener.accept().await?;
-        let (read_half, mut write_half) = stream.into_split();
-        let mut read_half = RESPReader::new(read_half);
+        let connection = self.connection_rx.recv().await.ok_or_else(|| {
+            anyhow::anyhow!("[redis - error] all listeners stopped accepting connections")
+        })??;
+
+        let id = self.id;
+        self.id += 1;
+        let client_info = ClientConnectionInfo::new(id, connection.address());
+        let is_read_blocked = client_info.is_read_blocked.clone();
+        let subscription_count = client_info.subscription_count.clone();
+        // sockets are registered with the runtime they are created on, so a connection
+        // handed to an I/O thread has to be registered anew from within its runtime,
+        // which is also where the stream tasks are spawned then
+        let io_thread = self.io_threads.as_mut().map(IoThreads::next_handle);
+        let _io_thread = io_thread.as_ref().map(Handle::enter);
+        let (read_stream, write_stream) = match connection {
+            Connection::Tcp(stream, _) => {
+                let stream = if io_thread.is_some() {
+                    TcpStream::from_std(stream.into_std()?)?
+                } else {
+                    stream
+                };
+                let (read_half, write_half) = stream.into_split();
+                Self::spawn_stream_tasks(
+                    read_half,
+                    write_half,
+                    is_read_blocked,
+                    subscription_count,
+                    self.idle_timeout,
+                    self.output_buffer_limit,
+                    self.proto_max_bulk_len,
+                )
+            }
+            Connection::Unix(stream, _) => {
+                let stream = if io_thread.is_some() {
+                    UnixStream::from_std(stream.into_std()?)?
+                } else {
+                    stream
+                };
+                let (read_half, write_half) = stream.into_split();
+                Self::spawn_stream_tasks(
+                    read_half,
+                    write_half,
+                    is_read_blocked,
+                    subscription_count,
+                    self.idle_timeout,
+                    self.output_buffer_limit,
+                    self.proto_max_bulk_len,
+                )
+            }
+        };
+
+        Ok((read_stream, write_stream, client_info))
+    }
+
+    pub(super) fn spawn_stream_tasks(
+        read_half: impl AsyncRead + Unpin + Send + 'static,
+        mut write_half: impl AsyncWrite + Unpin + Send + 'static,
+        read_block_signal: Arc<AtomicBool>,
+        subscription_count: Arc<AtomicUsize>,
+        mut idle_timeout: Option<Duration>,
+        output_buffer_limit: usize,
+        proto_max_bulk_len: usize,
+    ) -> (RedisReadStream, RedisWriteStream) {
+        let mut read_half = RESPReader::new(read_half).with_max_bulk_len(proto_max_bulk_len);
         let (read_tx, read_rx) = mpsc::channel(32);
-        let (write_tx, mut write_rx) = mpsc::channel::<Bytes>(32);
-        let is_read_blocked = Arc::new(AtomicBool::new(false));
-        let read_block_signal = is_read_blocked.clone();
+        let (write_tx, mut write_rx) = mpsc::unbounded_channel::<Bytes>();
+        let write_stream = RedisWriteStream::new(write_tx);
+        let output_buffer = write_stream.output_buffer.clone();
+        // like Redis, subscribers and blocked clients are not idle however long they
+        // stay silent
+        let is_waiting = {
+            let read_block_signal = read_block_signal.clone();
+            move || {
+                read_block_signal.load(Ordering::Relaxed)
+                    || subscription_count.load(Ordering::Relaxed) > 0
+            }
+        };
+        let is_backed_up = {
+            let output_buffer = output_buffer.clone();
+            move || output_buffer_limit > 0 && output_buffer.pending() > output_buffer_limit
+        };
+
         tokio::spawn(async move {
+            let mut is_replica_link = false;
             loop {
-                while read_block_signal.load(Ordering::Relaxed) {}
-                let command = read_half
-                    .read_value()
-                    .await
-                    .and_then(|value| value.try_into());
+                // clients can stay blocked for a long time, e.g. by `BZPOPMIN` without a
+                // timeout, so waiting must not keep a runtime thread busy, and the same
+                // goes for clients that do not read their replies
+                loop {
+                    // registered before checking, so that a wake up in between is not missed
+                    let woken = output_buffer.wake_signal.notified();
+                    if !read_block_signal.load(Ordering::SeqCst) && !is_backed_up() {
+                        break;
+                    }
+
+                    tokio::select! {
+                        _ = woken => {}
+                        _ = output_buffer.disconnected() => return,
+                    }
+                }
+                // stop reading as soon as the connection is no longer processed, e.g. after `QUIT`
+                let value = tokio::select! {
+                    value = Self::read_value_before(&mut read_half, idle_timeout, &is_waiting) => value,
+                    _ = read_tx.closed() => break,
+                    _ = output_buffer.disconnected() => break,
+                };
+
+                let Some(value) = value else {
+                    log!(
+                        Verbose,
+                        "closing client connection after being idle for too long"
+                    );
+                    break;
+                };
 
-                if read_half.is_closed() || read_tx.send(command).await.is_err() {
+                // whatever follows a malformed value cannot be told apart from garbage
+                let is_malformed = value.is_err();
+                let command = value
+                    .map_err(RedisError::from)
+                    .and_then(RedisCommand::from_client);
+                // replicas only talk back when asked for an acknowledgement, so the link
+                // must not be closed for being idle
+                if matches!(
+                    command,
+                    Ok(RedisCommand::Replication(
+                        RedisReplicationCommand::PSync { .. }
+                    ))
+                ) {
+                    idle_timeout = None;
+                    is_replica_link = true;
+                }
+
+                if read_half.is_closed() || read_tx.send(command).await.is_err() || is_malformed {
                     break;
                 }
             }
+
+            // the write stream of a replica is kept by the primary after the connection
+            // is processed, so it has to learn that the replica is gone
+            if is_replica_link {
+                output_buffer.disconnect();
+            }
         });
 
+        let output_buffer = write_stream.output_buffer.clone();
         tokio::spawn(async move {
-            while let Some(bytes) = write_rx.recv().await {
-                if write_half.write_all(&bytes).await.is_err() {
-                    break;
+            let write_all = async {
+                while let Some(bytes) = write_rx.recv().await {
+                    if write_half.write_all(&bytes).await.is_err() {
+                        break;
+                    }
+
+                    output_buffer.drain(bytes.len());
                 }
+            };
+
+            tokio::select! {
+                _ = write_all => {}
+                _ = output_buffer.disconnected() => {}
             }
+
+            // nothing written from now on could reach the client
+            output_buffer.disconnect();
         });
 
-        let id = self.id;
-        self.id += 1;
-        Ok((
-            RedisReadStream(read_rx),
-            RedisWriteStream::new(write_tx),
-            ClientConnectionInfo {
-                id,
-                address,
-                is_read_blocked,
-            },
-        ))
+        (RedisReadStream(read_rx), write_stream)
+    }
+
+    /// Reads the next value, or returns `None` when nothing arrives within `idle_timeout`
+    /// while the client is not waiting for anything.
+    async fn read_value_before<R: AsyncRead + Unpin>(
+        read_half: &mut RESPReader<R>,
+        idle_timeout: Option<Duration>,
+        is_waiting: impl Fn() -> bool,
+    ) -> Option<anyhow::Result<RESPValue>> {
+        let Some(idle_timeout) = idle_timeout else {
+            return Some(read_half.read_value().await);
+        };
+
+        // the read is kept across timeouts, since a value may already be partly read
+        let read_value = read_half.read_value();
+        tokio::pin!(read_value);
+        loop {
+            match timeout(idle_timeout, &mut read_value).await {
+                Ok(value) => return Some(value),
+                Err(_) if is_waiting() => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, SocketAddr},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpStream, UnixStream},
+        time::timeout,
+    };
+
+    use crate::redis::resp::{
+        command::{RedisCommand, RedisServerCommand},
+        encoding,
+    };
+
+    use super::{
+        ClientAddress, OutputBufferLimits, RedisServer, ServerConfig, DEFAULT_MAX_BULK_LEN,
+    };
+
+    #[tokio::test]
+    async fn closes_idle_connections() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (read_half, write_half) = tokio::io::split(server);
+        let (mut read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            Some(Duration::from_millis(50)),
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        assert!(read_stream.read().await.unwrap().is_none());
+        drop(write_stream);
+
+        let mut reply = vec![];
+        client.read_to_end(&mut reply).await.unwrap();
+        assert!(reply.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_subscribed_clients_open_while_idle() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (read_half, write_half) = tokio::io::split(server);
+        let (mut read_stream, _write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(1)),
+            Some(Duration::from_millis(20)),
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        assert!(matches!(
+            read_stream.read().await.unwrap(),
+            Some(RedisCommand::Server(RedisServerCommand::Ping { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn keeps_blocked_clients_open_while_idle() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (read_half, write_half) = tokio::io::split(server);
+        let is_read_blocked = Arc::new(AtomicBool::new(false));
+        let (mut read_stream, _write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            is_read_blocked.clone(),
+            Arc::new(AtomicUsize::new(0)),
+            Some(Duration::from_millis(20)),
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        // blocked only once the command is processed, while the next read already waits
+        client
+            .write_all(b"*3\r\n$5\r\nBLPOP\r\n$1\r\nk\r\n$1\r\n0\r\n")
+            .await
+            .unwrap();
+        assert!(read_stream.read().await.unwrap().is_some());
+        is_read_blocked.store(true, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        is_read_blocked.store(false, Ordering::SeqCst);
+
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        assert!(matches!(
+            read_stream.read().await.unwrap(),
+            Some(RedisCommand::Server(RedisServerCommand::Ping { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn resumes_reading_once_the_reply_of_a_blocked_client_is_written() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (read_half, write_half) = tokio::io::split(server);
+        let is_read_blocked = Arc::new(AtomicBool::new(true));
+        let (mut read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            is_read_blocked.clone(),
+            Arc::new(AtomicUsize::new(0)),
+            None,
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        assert!(timeout(Duration::from_millis(50), read_stream.read())
+            .await
+            .is_err());
+
+        is_read_blocked.store(false, Ordering::SeqCst);
+        write_stream.write(encoding::null_array()).await.unwrap();
+        assert!(matches!(
+            read_stream.read().await.unwrap(),
+            Some(RedisCommand::Server(RedisServerCommand::Ping { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stops_reading_from_clients_that_do_not_read_their_replies() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (read_half, write_half) = tokio::io::split(server);
+        let (mut read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            None,
+            100,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        write_stream.write(vec![b'a'; 300]).await.unwrap();
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        assert!(timeout(Duration::from_millis(50), read_stream.read())
+            .await
+            .is_err());
+
+        let mut reply = vec![0; 300];
+        client.read_exact(&mut reply).await.unwrap();
+        assert!(matches!(
+            read_stream.read().await.unwrap(),
+            Some(RedisCommand::Server(RedisServerCommand::Ping { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn drops_clients_over_their_output_buffer_limit() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (read_half, write_half) = tokio::io::split(server);
+        let (mut read_stream, write_stream) = RedisServer::spawn_stream_tasks(
+            read_half,
+            write_half,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            None,
+            0,
+            DEFAULT_MAX_BULK_LEN,
+        );
+
+        let subscriber = write_stream.clone().with_output_buffer_limit(100);
+        for _ in 0..10 {
+            subscriber.write(vec![b'a'; 30]).await.unwrap();
+        }
+
+        assert!(read_stream.read().await.unwrap().is_none());
+        // writes to the dropped connection are discarded instead of failing
+        write_stream.write("+OK\r\n").await.unwrap();
+        drop((write_stream, subscriber));
+
+        let mut reply = vec![];
+        client.read_to_end(&mut reply).await.unwrap();
+        assert!(reply.len() <= 100);
+    }
+
+    #[tokio::test]
+    async fn accepts_connections_on_every_bound_address() {
+        // every address shares the port, so a free one is picked up front
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let bind_addresses = vec![IpAddr::from([127, 0, 0, 1]), IpAddr::from([127, 0, 0, 2])];
+        let config = ServerConfig::new(
+            bind_addresses.clone(),
+            port,
+            None,
+            511,
+            false,
+            0,
+            OutputBufferLimits::default(),
+        );
+        let mut server = RedisServer::start(&config).await.unwrap();
+
+        for address in bind_addresses {
+            let mut client = TcpStream::connect(SocketAddr::new(address, port))
+                .await
+                .unwrap();
+            let (mut read_stream, write_stream, client_info) = server.accept().await.unwrap();
+            let ClientAddress::Tcp(peer) = client_info.address else {
+                panic!("expected a TCP client, got {}", client_info.address);
+            };
+            assert_eq!(peer, client.local_addr().unwrap());
+
+            client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+            assert!(matches!(
+                read_stream.read().await.unwrap(),
+                Some(RedisCommand::Server(RedisServerCommand::Ping { .. }))
+            ));
+            write_stream.write("+PONG\r\n").await.unwrap();
+            let mut reply = [0; 7];
+            client.read_exact(&mut reply).await.unwrap();
+            assert_eq!(&reply, b"+PONG\r\n");
+        }
+    }
+
+    #[tokio::test]
+    async fn replaces_only_stale_unix_sockets() {
+        let path = std::env::temp_dir().join(format!("stale-{}.sock", std::process::id()));
+        let config = ServerConfig::new(
+            vec![],
+            0,
+            Some(path.clone()),
+            511,
+            false,
+            0,
+            OutputBufferLimits::default(),
+        );
+
+        // the socket of a previous run that was not cleaned up
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(RedisServer::start(&config).await.is_ok());
+        std::fs::remove_file(&path).unwrap();
+
+        std::fs::write(&path, "not a socket").unwrap();
+        assert!(RedisServer::start(&config).await.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"not a socket");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn serves_connections_on_io_threads() {
+        let path = std::env::temp_dir().join(format!("io-threads-{}.sock", std::process::id()));
+        let mut config = ServerConfig::new(
+            vec![],
+            0,
+            Some(path.clone()),
+            511,
+            false,
+            0,
+            OutputBufferLimits::default(),
+        );
+        config.io_threads = 2;
+        let mut server = RedisServer::start(&config).await.unwrap();
+
+        for _ in 0..3 {
+            let mut client = UnixStream::connect(&path).await.unwrap();
+            let (mut read_stream, write_stream, _) = server.accept().await.unwrap();
+
+            client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+            assert!(matches!(
+                read_stream.read().await.unwrap(),
+                Some(RedisCommand::Server(RedisServerCommand::Ping { .. }))
+            ));
+
+            write_stream.write("+PONG\r\n").await.unwrap();
+            let mut reply = [0; 7];
+            client.read_exact(&mut reply).await.unwrap();
+            assert_eq!(&reply, b"+PONG\r\n");
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn parses_output_buffer_limits() {
+        assert_eq!(
+            OutputBufferLimits::parse("pubsub 8mb slave 1k normal 0"),
+            Some(OutputBufferLimits {
+                normal: 0,
+                replica: 1000,
+                pubsub: 8 << 20,
+            })
+        );
+        assert_eq!(
+            OutputBufferLimits::parse("replica 64"),
+            Some(OutputBufferLimits {
+                replica: 64,
+                ..OutputBufferLimits::default()
+            })
+        );
+        assert_eq!(OutputBufferLimits::parse("pubsub"), None);
+        assert_eq!(OutputBufferLimits::parse("pubsub 32mb 8mb 60"), None);
+        assert_eq!(OutputBufferLimits::parse("master 1mb"), None);
+        assert_eq!(OutputBufferLimits::parse("normal 1tb"), None);
     }
 }
@@ -0,0 +1,255 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+use super::{
+    resp::{command::RedisCommand, encoding, RESPValue},
+    server::ClientAddress,
+};
+
+/// Like in Redis, only this many arguments of a command are kept, the last one saying how
+/// many were left out.
+const MAX_ARGS: usize = 32;
+/// Like in Redis, longer arguments are cut off, saying how many bytes were left out.
+const MAX_ARG_LEN: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowLogConfig {
+    /// Commands taking at least this many microseconds are logged, so 0 logs every command
+    /// and a negative value none.
+    pub log_slower_than: i64,
+    /// The number of entries kept, after which the oldest ones are dropped.
+    pub max_len: usize,
+}
+
+impl Default for SlowLogConfig {
+    fn default() -> Self {
+        Self {
+            log_slower_than: 10000,
+            max_len: 128,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SlowLogEntry {
+    id: u64,
+    /// When the command was executed, in seconds since the epoch.
+    timestamp: u64,
+    duration: Duration,
+    args: Vec<Bytes>,
+    client_address: ClientAddress,
+}
+
+/// The most recent commands that took too long to execute, newest first.
+#[derive(Debug)]
+pub struct SlowLog {
+    pub config: SlowLogConfig,
+    entries: VecDeque<SlowLogEntry>,
+    next_id: u64,
+}
+
+impl SlowLog {
+    pub fn new(config: SlowLogConfig) -> Self {
+        Self {
+            config,
+            entries: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn is_slow(&self, duration: Duration) -> bool {
+        u128::try_from(self.config.log_slower_than)
+            .is_ok_and(|log_slower_than| duration.as_micros() >= log_slower_than)
+    }
+
+    pub async fn push(
+        &mut self,
+        command: &RedisCommand,
+        duration: Duration,
+        client_address: ClientAddress,
+    ) -> anyhow::Result<()> {
+        let args = Self::args(command).await?;
+        self.entries.push_front(SlowLogEntry {
+            id: self.next_id,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            duration,
+            args,
+            client_address,
+        });
+        self.entries.truncate(self.config.max_len);
+        self.next_id += 1;
+        Ok(())
+    }
+
+    /// The arguments of a command as they are logged, shortened like in Redis and with
+    /// passwords redacted.
+    async fn args(command: &RedisCommand) -> anyhow::Result<Vec<Bytes>> {
+        let args = command.redacted_args().await?;
+        let len = args.len().min(MAX_ARGS);
+        Ok(args
+            .iter()
+            .take(len)
+            .enumerate()
+            .map(|(index, arg)| {
+                if len < args.len() && index == len - 1 {
+                    Bytes::from(format!("... ({} more arguments)", args.len() - len + 1))
+                } else if arg.len() > MAX_ARG_LEN {
+                    let mut shortened = arg[..MAX_ARG_LEN].to_vec();
+                    shortened.extend_from_slice(
+                        format!("... ({} more bytes)", arg.len() - MAX_ARG_LEN).as_bytes(),
+                    );
+                    Bytes::from(shortened)
+                } else {
+                    arg.clone()
+                }
+            })
+            .collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The newest `count` entries, or every entry without a count.
+    pub fn get(&self, count: Option<usize>) -> RESPValue {
+        let entries = self
+            .entries
+            .iter()
+            .take(count.unwrap_or(usize::MAX))
+            .map(|entry| {
+                encoding::array(vec![
+                    encoding::integer(entry.id as i64),
+                    encoding::integer(entry.timestamp as i64),
+                    encoding::integer(entry.duration.as_micros() as i64),
+                    encoding::array(entry.args.iter().map(encoding::bulk_string).collect()),
+                    encoding::bulk_string(entry.client_address.to_string()),
+                    // clients cannot be named yet
+                    encoding::bulk_string(""),
+                ])
+            })
+            .collect();
+
+        encoding::array(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use bytes::Bytes;
+
+    use crate::redis::{
+        resp::{
+            command::{CommandError, RedisCommand, RedisServerCommand, RedisStoreCommand},
+            encoding, RESPValue,
+        },
+        server::ClientAddress,
+    };
+
+    use super::{SlowLog, SlowLogConfig};
+
+    fn address() -> ClientAddress {
+        ClientAddress::Tcp(SocketAddr::from(([127, 0, 0, 1], 5000)))
+    }
+
+    fn args(entry: RESPValue) -> Vec<RESPValue> {
+        entry
+            .into_array()
+            .unwrap()
+            .swap_remove(3)
+            .into_array()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn keeps_the_newest_slow_commands() {
+        let mut slowlog = SlowLog::new(SlowLogConfig {
+            log_slower_than: 100,
+            max_len: 2,
+        });
+        assert!(!slowlog.is_slow(Duration::from_micros(99)));
+        assert!(slowlog.is_slow(Duration::from_micros(100)));
+
+        for key in ["a", "b", "c"] {
+            let get = RedisCommand::Store(RedisStoreCommand::Get {
+                key: Bytes::from(key),
+            });
+            slowlog
+                .push(&get, Duration::from_micros(150), address())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(slowlog.len(), 2);
+        let entry = slowlog.get(None).into_array().unwrap().swap_remove(0);
+        let entry = entry.into_array().unwrap();
+        assert_eq!(entry[0], encoding::integer(2));
+        assert_eq!(entry[2], encoding::integer(150));
+        assert_eq!(
+            entry[3],
+            encoding::array(vec![
+                encoding::bulk_string("GET"),
+                encoding::bulk_string("c")
+            ])
+        );
+        assert_eq!(entry[4], encoding::bulk_string("127.0.0.1:5000"));
+        assert_eq!(slowlog.get(Some(1)).into_array().unwrap().len(), 1);
+
+        slowlog.reset();
+        assert_eq!(slowlog.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn shortens_and_redacts_arguments() {
+        let mut slowlog = SlowLog::new(SlowLogConfig::default());
+        let echo = RedisCommand::Server(RedisServerCommand::Echo {
+            message: Bytes::from("x".repeat(200)),
+        });
+        let invalid = RedisCommand::Invalid {
+            error: CommandError::WrongArity("xadd"),
+            args: (0..40).map(|arg| Bytes::from(arg.to_string())).collect(),
+        };
+        let auth = RedisCommand::Server(RedisServerCommand::Auth {
+            username: Some(Bytes::from("user")),
+            password: Bytes::from("secret"),
+        });
+        for command in [&echo, &invalid, &auth] {
+            slowlog
+                .push(command, Duration::ZERO, address())
+                .await
+                .unwrap();
+        }
+
+        let mut entries = slowlog.get(None).into_array().unwrap().into_iter();
+        assert_eq!(
+            args(entries.next().unwrap()),
+            vec![
+                encoding::bulk_string("AUTH"),
+                encoding::bulk_string("(redacted)"),
+                encoding::bulk_string("(redacted)"),
+            ]
+        );
+
+        let invalid_args = args(entries.next().unwrap());
+        assert_eq!(invalid_args.len(), 32);
+        assert_eq!(
+            invalid_args[31],
+            encoding::bulk_string("... (9 more arguments)")
+        );
+
+        let echo_args = args(entries.next().unwrap());
+        assert_eq!(
+            echo_args[1],
+            encoding::bulk_string(format!("{}... (72 more bytes)", "x".repeat(128)))
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use bytes::Bytes;
+
+use crate::redis::resp::command::BitOrByte;
+
+/// The largest bit offset accepted, which limits strings to 512MB like Redis.
+pub const MAX_BIT_OFFSET: u64 = 512 * 1024 * 1024 * 8 - 1;
+
+/// Returns the bit at `offset`, counting from the most significant bit of the first
+/// byte. Bits past the end of the string are 0.
+pub fn get_bit(value: &[u8], offset: u64) -> bool {
+    usize::try_from(offset / 8)
+        .ok()
+        .and_then(|index| value.get(index))
+        .is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
+
+/// Sets the bit at `offset`, growing the string with zero bytes as needed, and returns
+/// the new string along with the previous value of the bit.
+pub fn set_bit(value: &[u8], offset: u64, bit: bool) -> (Bytes, bool) {
+    let index = (offset / 8) as usize;
+    let mask = 0x80 >> (offset % 8);
+    let mut bytes = value.to_vec();
+    if bytes.len() <= index {
+        bytes.resize(index + 1, 0);
+    }
+
+    let old_bit = bytes[index] & mask != 0;
+    if bit {
+        bytes[index] |= mask;
+    } else {
+        bytes[index] &= !mask;
+    }
+
+    (Bytes::from(bytes), old_bit)
+}
+
+/// Counts the set bits, optionally only within an inclusive range of bytes or bits.
+/// Negative indices count from the end of the string.
+pub fn bit_count(value: &[u8], range: Option<(i64, i64, BitOrByte)>) -> usize {
+    let Some((start, end, unit)) = range else {
+        return value.iter().map(|byte| byte.count_ones() as usize).sum();
+    };
+
+    let len = match unit {
+        BitOrByte::Byte => value.len() as i64,
+        BitOrByte::Bit => value.len() as i64 * 8,
+    };
+
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 { len + end } else { end.min(len - 1) };
+    if start > end || start >= len {
+        return 0;
+    }
+
+    match unit {
+        BitOrByte::Byte => value[start as usize..=end as usize]
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum(),
+        BitOrByte::Bit => (start as u64..=end as u64)
+            .filter(|&offset| get_bit(value, offset))
+            .count(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redis::resp::command::BitOrByte;
+
+    use super::{bit_count, get_bit, set_bit};
+
+    #[test]
+    fn sets_bits_and_grows_strings() {
+        let (value, old_bit) = set_bit(b"", 10, true);
+        assert!(!old_bit);
+        assert_eq!(&value[..], b"\x00\x20");
+        assert!(get_bit(&value, 10));
+        assert!(!get_bit(&value, 11));
+        assert!(!get_bit(&value, 1000));
+
+        let (value, old_bit) = set_bit(&value, 10, false);
+        assert!(old_bit);
+        assert_eq!(&value[..], b"\x00\x00");
+    }
+
+    #[test]
+    fn counts_bits_in_ranges() {
+        assert_eq!(bit_count(b"foobar", None), 26);
+        assert_eq!(bit_count(b"foobar", Some((0, 0, BitOrByte::Byte))), 4);
+        assert_eq!(bit_count(b"foobar", Some((1, 1, BitOrByte::Byte))), 6);
+        assert_eq!(bit_count(b"foobar", Some((1, 1, BitOrByte::Bit))), 1);
+        assert_eq!(bit_count(b"foobar", Some((5, 30, BitOrByte::Bit))), 17);
+        assert_eq!(bit_count(b"foobar", Some((-2, -1, BitOrByte::Byte))), 7);
+        assert_eq!(bit_count(b"foobar", Some((3, 1, BitOrByte::Byte))), 0);
+        assert_eq!(bit_count(b"foobar", Some((10, 20, BitOrByte::Byte))), 0);
+    }
+}
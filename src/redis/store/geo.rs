@@ -0,0 +1,334 @@
+use bytes::Bytes;
+
+use super::sorted_set::SortedSet;
+
+/// The latitudes that can be encoded, the limits of the Web Mercator projection.
+pub const LATITUDE_MIN: f64 = -85.05112878;
+pub const LATITUDE_MAX: f64 = 85.05112878;
+pub const LONGITUDE_MIN: f64 = -180.0;
+pub const LONGITUDE_MAX: f64 = 180.0;
+/// The number of bits used for each of the longitude and the latitude.
+const STEP: u32 = 26;
+/// The earth radius Redis uses for distances, which makes them match Redis exactly.
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+
+/// A longitude and latitude in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinates {
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+// coordinates are only parsed from finite numbers or decoded from geohashes, so they
+// are never NaN
+impl Eq for Coordinates {}
+
+impl Coordinates {
+    pub fn parse(longitude: &[u8], latitude: &[u8]) -> Option<Self> {
+        let parse = |bytes: &[u8]| {
+            std::str::from_utf8(bytes)
+                .ok()?
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+        };
+
+        Some(Self {
+            longitude: parse(longitude)?,
+            latitude: parse(latitude)?,
+        })
+    }
+
+    /// Whether the coordinates are within the range a geohash can encode.
+    pub fn is_valid(&self) -> bool {
+        (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&self.longitude)
+            && (LATITUDE_MIN..=LATITUDE_MAX).contains(&self.latitude)
+    }
+
+    /// Encodes the coordinates as the 52-bit interleaved geohash Redis stores as the
+    /// score, with latitude bits in the even positions and longitude bits in the odd ones.
+    pub fn geohash(&self) -> u64 {
+        let latitude_offset = (self.latitude - LATITUDE_MIN) / (LATITUDE_MAX - LATITUDE_MIN);
+        let longitude_offset = (self.longitude - LONGITUDE_MIN) / (LONGITUDE_MAX - LONGITUDE_MIN);
+        let latitude_bits = (latitude_offset * (1u64 << STEP) as f64) as u32;
+        let longitude_bits = (longitude_offset * (1u64 << STEP) as f64) as u32;
+        spread(latitude_bits) | (spread(longitude_bits) << 1)
+    }
+
+    /// Decodes a geohash to the center of the area it covers.
+    pub fn from_geohash(geohash: u64) -> Self {
+        let latitude_bits = squash(geohash);
+        let longitude_bits = squash(geohash >> 1);
+        let cell_count = (1u64 << STEP) as f64;
+        let center = |bits: u32, min: f64, max: f64| {
+            let low = min + (bits as f64 / cell_count) * (max - min);
+            let high = min + ((bits as f64 + 1.0) / cell_count) * (max - min);
+            ((low + high) / 2.0).clamp(min, max)
+        };
+
+        Self {
+            longitude: center(longitude_bits, LONGITUDE_MIN, LONGITUDE_MAX),
+            latitude: center(latitude_bits, LATITUDE_MIN, LATITUDE_MAX),
+        }
+    }
+
+    /// The great-circle distance in meters, using the haversine formula.
+    pub fn distance(&self, other: &Self) -> f64 {
+        let latitude = self.latitude.to_radians();
+        let other_latitude = other.latitude.to_radians();
+        let u = ((other_latitude - latitude) / 2.0).sin();
+        let v = ((other.longitude.to_radians() - self.longitude.to_radians()) / 2.0).sin();
+        2.0 * EARTH_RADIUS_IN_METERS
+            * (u * u + latitude.cos() * other_latitude.cos() * v * v)
+                .sqrt()
+                .asin()
+    }
+}
+
+/// Spreads the bits of `value` out to the even positions of a `u64`.
+fn spread(value: u32) -> u64 {
+    let mut value = value as u64;
+    value = (value | (value << 16)) & 0x0000FFFF0000FFFF;
+    value = (value | (value << 8)) & 0x00FF00FF00FF00FF;
+    value = (value | (value << 4)) & 0x0F0F0F0F0F0F0F0F;
+    value = (value | (value << 2)) & 0x3333333333333333;
+    (value | (value << 1)) & 0x5555555555555555
+}
+
+/// Gathers the bits in the even positions of `value`, the inverse of `spread`.
+fn squash(value: u64) -> u32 {
+    let mut value = value & 0x5555555555555555;
+    value = (value | (value >> 1)) & 0x3333333333333333;
+    value = (value | (value >> 2)) & 0x0F0F0F0F0F0F0F0F;
+    value = (value | (value >> 4)) & 0x00FF00FF00FF00FF;
+    value = (value | (value >> 8)) & 0x0000FFFF0000FFFF;
+    ((value | (value >> 16)) & 0x00000000FFFFFFFF) as u32
+}
+
+/// Formats a coordinate the way Redis replies with them, with 17 decimals and without
+/// trailing zeroes.
+pub fn format_coordinate(value: f64) -> String {
+    let formatted = format!("{value:.17}");
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoUnit {
+    Meters,
+    Kilometers,
+    Miles,
+    Feet,
+}
+
+impl GeoUnit {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        match &*bytes.to_ascii_lowercase() {
+            b"m" => Some(Self::Meters),
+            b"km" => Some(Self::Kilometers),
+            b"mi" => Some(Self::Miles),
+            b"ft" => Some(Self::Feet),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Meters => "m",
+            Self::Kilometers => "km",
+            Self::Miles => "mi",
+            Self::Feet => "ft",
+        }
+    }
+
+    pub fn meters(self) -> f64 {
+        match self {
+            Self::Meters => 1.0,
+            Self::Kilometers => 1000.0,
+            Self::Miles => 1609.34,
+            Self::Feet => 0.3048,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeoOrigin {
+    Member(Bytes),
+    Coordinates(Coordinates),
+}
+
+/// The area searched by `GEOSEARCH`, in the unit it was given in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+// shapes are only built from non-negative numbers, which are never NaN
+impl Eq for GeoShape {}
+
+impl GeoShape {
+    /// The distance in meters from the center to the point, or `None` if the point is
+    /// outside the shape. Boxes are checked the same way as Redis, measuring the
+    /// longitude distance along the point's latitude.
+    pub fn distance_within(
+        &self,
+        unit: GeoUnit,
+        center: &Coordinates,
+        point: &Coordinates,
+    ) -> Option<f64> {
+        let distance = center.distance(point);
+        match *self {
+            GeoShape::Radius(radius) => (distance <= radius * unit.meters()).then_some(distance),
+            GeoShape::Box { width, height } => {
+                let latitude_distance = EARTH_RADIUS_IN_METERS
+                    * (point.latitude.to_radians() - center.latitude.to_radians()).abs();
+                let longitude_distance = Coordinates {
+                    longitude: center.longitude,
+                    latitude: point.latitude,
+                }
+                .distance(point);
+                let is_inside = latitude_distance <= height * unit.meters() / 2.0
+                    && longitude_distance <= width * unit.meters() / 2.0;
+                is_inside.then_some(distance)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeoSearch {
+    pub origin: GeoOrigin,
+    pub shape: GeoShape,
+    pub unit: GeoUnit,
+    pub order: Option<SortOrder>,
+    /// The maximum number of results and whether any matches will do, rather than
+    /// the closest ones.
+    pub count: Option<(usize, bool)>,
+    pub with_coordinates: bool,
+    pub with_distance: bool,
+    pub with_hash: bool,
+}
+
+/// A member found by `GEOSEARCH`, with its distance from the center in meters.
+#[derive(Debug)]
+pub struct GeoMatch<'a> {
+    pub member: &'a Bytes,
+    pub distance: f64,
+    pub geohash: u64,
+    pub coordinates: Coordinates,
+}
+
+impl GeoSearch {
+    /// Finds the members of the sorted set within the shape around the center. Every
+    /// member is checked, and the matches are sorted and limited as requested. Matches
+    /// are sorted closest first when a count is given without `ANY`, like in Redis.
+    pub fn matches<'a>(
+        &self,
+        sorted_set: &'a SortedSet,
+        center: &Coordinates,
+    ) -> Vec<GeoMatch<'a>> {
+        let limit = match self.count {
+            Some((count, true)) => count,
+            _ => usize::MAX,
+        };
+
+        let mut matches = sorted_set
+            .iter()
+            .filter_map(|(member, score)| {
+                let geohash = score.value() as u64;
+                let coordinates = Coordinates::from_geohash(geohash);
+                let distance = self
+                    .shape
+                    .distance_within(self.unit, center, &coordinates)?;
+                Some(GeoMatch {
+                    member,
+                    distance,
+                    geohash,
+                    coordinates,
+                })
+            })
+            .take(limit)
+            .collect::<Vec<_>>();
+
+        let order = match (self.order, self.count) {
+            (Some(order), _) => Some(order),
+            (None, Some((_, false))) => Some(SortOrder::Ascending),
+            (None, _) => None,
+        };
+
+        match order {
+            Some(SortOrder::Ascending) => matches.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(SortOrder::Descending) => {
+                matches.sort_by(|a, b| b.distance.total_cmp(&a.distance))
+            }
+            None => {}
+        }
+
+        if let Some((count, _)) = self.count {
+            matches.truncate(count);
+        }
+
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_coordinate, Coordinates, GeoShape, GeoUnit};
+
+    fn coordinates(longitude: &str, latitude: &str) -> Coordinates {
+        Coordinates::parse(longitude.as_bytes(), latitude.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn encodes_geohashes_like_redis() {
+        // the scores and positions Redis reports for the `GEOADD` example in its docs
+        let palermo = coordinates("13.361389", "38.115556");
+        let catania = coordinates("15.087269", "37.502669");
+        assert_eq!(palermo.geohash(), 3479099956230698);
+        assert_eq!(catania.geohash(), 3479447370796909);
+
+        let decoded = Coordinates::from_geohash(palermo.geohash());
+        assert_eq!(format_coordinate(decoded.longitude), "13.36138933897018433");
+        assert_eq!(format_coordinate(decoded.latitude), "38.11555639549629859");
+
+        let distance = Coordinates::from_geohash(palermo.geohash())
+            .distance(&Coordinates::from_geohash(catania.geohash()));
+        assert_eq!(format!("{:.4}", distance), "166274.1516");
+        assert!(!coordinates("0", "86").is_valid());
+        assert!(Coordinates::parse(b"nan", b"0").is_none());
+    }
+
+    #[test]
+    fn checks_points_against_shapes() {
+        let center = coordinates("15", "37");
+        let palermo = coordinates("13.361389", "38.115556");
+        let distance =
+            GeoShape::Radius(200.0).distance_within(GeoUnit::Kilometers, &center, &palermo);
+        assert_eq!(format!("{:.4}", distance.unwrap() / 1000.0), "190.4424");
+        assert!(GeoShape::Radius(150.0)
+            .distance_within(GeoUnit::Kilometers, &center, &palermo)
+            .is_none());
+        assert!(GeoShape::Box {
+            width: 400.0,
+            height: 400.0
+        }
+        .distance_within(GeoUnit::Kilometers, &center, &palermo)
+        .is_some());
+        assert!(GeoShape::Box {
+            width: 200.0,
+            height: 400.0
+        }
+        .distance_within(GeoUnit::Kilometers, &center, &palermo)
+        .is_none());
+    }
+}
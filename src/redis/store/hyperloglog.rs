@@ -0,0 +1,295 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// The number of bits of the hash used to pick a register.
+const P: u32 = 14;
+const REGISTER_COUNT: usize = 1 << P;
+/// The number of hash bits left to count leading zeroes in.
+const Q: u32 = 64 - P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+const HEADER_SIZE: usize = 16;
+const DENSE_SIZE: usize = HEADER_SIZE + (REGISTER_COUNT * REGISTER_BITS).div_ceil(8);
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+/// Why a string could not be read as a HyperLogLog.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HyperLogLogError {
+    /// The string was not written by a `PF*` command.
+    NotHyperLogLog,
+    /// The string has a HyperLogLog header but its registers are malformed.
+    Corrupted,
+}
+
+/// A HyperLogLog with one byte per register. It is stored in strings using the same
+/// layout as Redis: a `HYLL` header followed by the registers, read in either the dense
+/// or the sparse encoding and always written in the dense one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        Self {
+            registers: vec![0; REGISTER_COUNT],
+        }
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, HyperLogLogError> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != b"HYLL" {
+            return Err(HyperLogLogError::NotHyperLogLog);
+        }
+
+        let registers = &bytes[HEADER_SIZE..];
+        match bytes[4] {
+            DENSE if bytes.len() == DENSE_SIZE => Ok(Self {
+                registers: (0..REGISTER_COUNT)
+                    .map(|index| get_dense_register(registers, index))
+                    .collect(),
+            }),
+            SPARSE => Self::parse_sparse(registers),
+            _ => Err(HyperLogLogError::Corrupted),
+        }
+    }
+
+    /// Reads the sparse encoding, a run-length encoding of the registers made of
+    /// `ZERO`, `XZERO` and `VAL` opcodes.
+    fn parse_sparse(mut opcodes: &[u8]) -> Result<Self, HyperLogLogError> {
+        let mut registers = Vec::with_capacity(REGISTER_COUNT);
+        while let Some((&opcode, rest)) = opcodes.split_first() {
+            opcodes = rest;
+            let (value, run_length) = match opcode >> 6 {
+                // ZERO: 00xxxxxx
+                0b00 => (0, (opcode & 0x3f) as usize + 1),
+                // XZERO: 01xxxxxx yyyyyyyy
+                0b01 => {
+                    let (&low, rest) = opcodes.split_first().ok_or(HyperLogLogError::Corrupted)?;
+                    opcodes = rest;
+                    (0, ((((opcode & 0x3f) as usize) << 8) | low as usize) + 1)
+                }
+                // VAL: 1vvvvvxx
+                _ => (((opcode >> 2) & 0x1f) + 1, (opcode & 0x3) as usize + 1),
+            };
+
+            if registers.len() + run_length > REGISTER_COUNT {
+                return Err(HyperLogLogError::Corrupted);
+            }
+
+            registers.extend(std::iter::repeat_n(value, run_length));
+        }
+
+        if registers.len() != REGISTER_COUNT {
+            return Err(HyperLogLogError::Corrupted);
+        }
+
+        Ok(Self { registers })
+    }
+
+    /// Adds an element, returning whether a register changed and with it the estimate.
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let hash = murmur_hash_64a(element, 0xadc83b19);
+        let index = (hash & (REGISTER_COUNT as u64 - 1)) as usize;
+        // the extra bit makes sure the count of trailing zeroes ends within Q bits
+        let hash = (hash >> P) | (1 << Q);
+        let count = hash.trailing_zeros() as u8 + 1;
+        if count > self.registers[index] {
+            self.registers[index] = count;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    /// Estimates the cardinality with the improved estimator by Otmar Ertl that Redis
+    /// uses, see "New cardinality estimation algorithms for HyperLogLog sketches".
+    pub fn count(&self) -> u64 {
+        let m = REGISTER_COUNT as f64;
+        let mut histogram = [0u32; Q as usize + 2];
+        for &register in &self.registers {
+            histogram[register as usize] += 1;
+        }
+
+        let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+        for &registers in histogram[1..=Q as usize].iter().rev() {
+            z += registers as f64;
+            z *= 0.5;
+        }
+
+        z += m * sigma(histogram[0] as f64 / m);
+        const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+        (ALPHA_INF * m * m / z).round() as u64
+    }
+
+    /// Encodes the registers in the dense encoding. The cached cardinality is marked
+    /// as invalid, since it is never kept up to date.
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(DENSE_SIZE);
+        buf.put_slice(b"HYLL");
+        buf.put_u8(DENSE);
+        buf.put_bytes(0, 3);
+        buf.put_bytes(0, 7);
+        buf.put_u8(1 << 7);
+        let mut registers = vec![0; DENSE_SIZE - HEADER_SIZE];
+        for (index, &value) in self.registers.iter().enumerate() {
+            set_dense_register(&mut registers, index, value.min(REGISTER_MAX));
+        }
+
+        buf.put_slice(&registers);
+        buf.freeze()
+    }
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn get_dense_register(registers: &[u8], index: usize) -> u8 {
+    let byte = index * REGISTER_BITS / 8;
+    let first_bit = index * REGISTER_BITS % 8;
+    let low = registers[byte] as u16;
+    let high = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+    (((low | (high << 8)) >> first_bit) as u8) & REGISTER_MAX
+}
+
+fn set_dense_register(registers: &mut [u8], index: usize, value: u8) {
+    let byte = index * REGISTER_BITS / 8;
+    let first_bit = index * REGISTER_BITS % 8;
+    let bits = (value as u16) << first_bit;
+    let mask = (REGISTER_MAX as u16) << first_bit;
+    registers[byte] = (registers[byte] & !(mask as u8)) | bits as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next = (*next & !((mask >> 8) as u8)) | (bits >> 8) as u8;
+    }
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// MurmurHash64A by Austin Appleby, reading blocks as little endian like Redis does.
+fn murmur_hash_64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let blocks = key.chunks_exact(8);
+    let tail = blocks.remainder();
+    for block in blocks {
+        let mut k = u64::from_le_bytes(block.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    if !tail.is_empty() {
+        for (index, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * index);
+        }
+
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HyperLogLog, HyperLogLogError, REGISTER_COUNT};
+
+    #[test]
+    fn estimates_cardinality_and_merges() {
+        let mut first = HyperLogLog::new();
+        assert!(first.add(b"a"));
+        assert!(!first.add(b"a"));
+        for element in [&b"b"[..], b"c", b"d", b"e", b"f", b"g"] {
+            first.add(element);
+        }
+
+        assert_eq!(first.count(), 7);
+
+        let mut second = HyperLogLog::new();
+        for index in 0..10_000 {
+            second.add(index.to_string().as_bytes());
+        }
+
+        let estimate = second.count() as f64;
+        assert!((estimate - 10_000.0).abs() / 10_000.0 < 0.02, "{estimate}");
+
+        second.merge(&first);
+        let merged = second.count() as f64;
+        assert!((merged - 10_007.0).abs() / 10_007.0 < 0.02, "{merged}");
+    }
+
+    #[test]
+    fn round_trips_dense_and_reads_sparse() {
+        let mut hyperloglog = HyperLogLog::new();
+        for index in 0..1000 {
+            hyperloglog.add(index.to_string().as_bytes());
+        }
+
+        let bytes = hyperloglog.to_bytes();
+        assert_eq!(HyperLogLog::parse(&bytes), Ok(hyperloglog));
+
+        // an empty sparse HyperLogLog is a single XZERO covering every register
+        let mut sparse = b"HYLL\x01\0\0\0\0\0\0\0\0\0\0\0".to_vec();
+        let run_length = REGISTER_COUNT - 1;
+        sparse.extend_from_slice(&[0x40 | (run_length >> 8) as u8, run_length as u8]);
+        assert_eq!(HyperLogLog::parse(&sparse), Ok(HyperLogLog::new()));
+
+        sparse.pop();
+        assert_eq!(
+            HyperLogLog::parse(&sparse),
+            Err(HyperLogLogError::Corrupted)
+        );
+        assert_eq!(
+            HyperLogLog::parse(b"not a hyperloglog"),
+            Err(HyperLogLogError::NotHyperLogLog)
+        );
+    }
+}
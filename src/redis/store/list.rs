@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+use crate::redis::resp::command::ListEnd;
+
+/// The bytes a listpack spends on its header and end marker.
+const LISTPACK_OVERHEAD: usize = 7;
+/// The most bytes a node limited by its number of entries may take up, like
+/// `SIZE_SAFETY_LIMIT` in Redis.
+const SIZE_SAFETY_LIMIT: usize = 8192;
+
+/// What a single node of a list may hold, as set by `list-max-listpack-size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeLimit {
+    Entries(usize),
+    Bytes(usize),
+}
+
+impl NodeLimit {
+    fn new(list_max_listpack_size: i64) -> Self {
+        match usize::try_from(list_max_listpack_size) {
+            Ok(entries) if entries > 0 => Self::Entries(entries),
+            // -1 is 4KB and every step down doubles it, up to -5 for 64KB
+            _ => {
+                let step = list_max_listpack_size.unsigned_abs().clamp(1, 5) - 1;
+                Self::Bytes(4096 << step)
+            }
+        }
+    }
+
+    fn fits(self, entries: usize, bytes: usize) -> bool {
+        match self {
+            Self::Entries(max_entries) => entries <= max_entries && bytes <= SIZE_SAFETY_LIMIT,
+            Self::Bytes(max_bytes) => bytes <= max_bytes,
+        }
+    }
+
+    /// The limit a quicklist has to shrink below to become a listpack again, which is
+    /// half of the one it grew past so that a list at the limit does not flip back and
+    /// forth.
+    fn halved(self) -> Self {
+        match self {
+            Self::Entries(max_entries) => Self::Entries(max_entries / 2),
+            Self::Bytes(max_bytes) => Self::Bytes(max_bytes / 2),
+        }
+    }
+}
+
+/// The elements of a list, along with the encoding Redis would use for it. Lists are
+/// always stored the same way, so the encoding is only tracked: a list is a listpack
+/// while it fits in a single node and a quicklist of nodes once it grows past that.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct List {
+    elements: VecDeque<Bytes>,
+    /// The size of the elements in a listpack, kept up to date so that the encoding
+    /// never has to be worked out from every element.
+    listpack_size: usize,
+    is_quicklist: bool,
+}
+
+impl List {
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Bytes> {
+        self.elements.iter()
+    }
+
+    pub fn push(&mut self, end: ListEnd, element: Bytes, list_max_listpack_size: i64) {
+        self.listpack_size += entry_size(&element);
+        match end {
+            ListEnd::Left => self.elements.push_front(element),
+            ListEnd::Right => self.elements.push_back(element),
+        }
+
+        let limit = NodeLimit::new(list_max_listpack_size);
+        if !limit.fits(self.len(), self.size()) {
+            self.is_quicklist = true;
+        }
+    }
+
+    pub fn pop(&mut self, end: ListEnd, list_max_listpack_size: i64) -> Option<Bytes> {
+        let element = match end {
+            ListEnd::Left => self.elements.pop_front(),
+            ListEnd::Right => self.elements.pop_back(),
+        }?;
+
+        self.listpack_size -= entry_size(&element);
+        let limit = NodeLimit::new(list_max_listpack_size);
+        if limit.halved().fits(self.len(), self.size()) {
+            self.is_quicklist = false;
+        }
+
+        Some(element)
+    }
+
+    /// The elements from `start` to `stop`, both included, where negative indices count
+    /// from the end. Indices past either end are clamped.
+    pub fn range(&self, start: i64, stop: i64) -> impl Iterator<Item = &Bytes> {
+        let len = self.len() as i64;
+        let index = |index: i64| if index < 0 { len + index } else { index };
+        let start = index(start).max(0);
+        let stop = index(stop).min(len - 1);
+        let count = usize::try_from(stop - start + 1).unwrap_or(0);
+        self.elements.iter().skip(start as usize).take(count)
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        if self.is_quicklist {
+            "quicklist"
+        } else {
+            "listpack"
+        }
+    }
+
+    /// The number of nodes the list is split into, where a listpack is a single node.
+    /// Like in Redis, each node is filled up to the limit before the next one is started.
+    pub fn quicklist_nodes(&self, list_max_listpack_size: i64) -> usize {
+        if !self.is_quicklist {
+            return 1;
+        }
+
+        let limit = NodeLimit::new(list_max_listpack_size);
+        let mut nodes = 0;
+        let (mut entries, mut size) = (0, LISTPACK_OVERHEAD);
+        for element in &self.elements {
+            let element_size = entry_size(element);
+            if entries == 0 || !limit.fits(entries + 1, size + element_size) {
+                nodes += 1;
+                (entries, size) = (0, LISTPACK_OVERHEAD);
+            }
+
+            entries += 1;
+            size += element_size;
+        }
+
+        nodes
+    }
+
+    /// The size the elements take up in a single listpack.
+    pub fn size(&self) -> usize {
+        LISTPACK_OVERHEAD + self.listpack_size
+    }
+}
+
+/// The bytes an element takes up in a listpack when stored as a string: a header with
+/// its length, the element itself, and the length of both written backwards. Integers
+/// can be stored in less, which is not accounted for.
+fn entry_size(element: &[u8]) -> usize {
+    let header = match element.len() {
+        0..=63 => 1,
+        64..=4095 => 2,
+        _ => 5,
+    };
+    let encoded = header + element.len();
+    let backlen = match encoded {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    };
+
+    encoded + backlen
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::redis::resp::command::ListEnd;
+
+    use super::List;
+
+    fn list(elements: &[&'static str], list_max_listpack_size: i64) -> List {
+        let mut list = List::default();
+        for element in elements {
+            list.push(
+                ListEnd::Right,
+                Bytes::from(*element),
+                list_max_listpack_size,
+            );
+        }
+
+        list
+    }
+
+    #[test]
+    fn turns_into_a_quicklist_past_the_node_limit_and_back_below_half_of_it() {
+        let mut list = list(&["0", "1", "2", "3"], 4);
+        assert_eq!(list.encoding(), "listpack");
+
+        list.push(ListEnd::Left, Bytes::from("4"), 4);
+        assert_eq!(list.encoding(), "quicklist");
+        assert_eq!(list.quicklist_nodes(4), 2);
+
+        list.pop(ListEnd::Right, 4);
+        list.pop(ListEnd::Right, 4);
+        assert_eq!(list.encoding(), "quicklist");
+        list.pop(ListEnd::Right, 4);
+        assert_eq!(list.encoding(), "listpack");
+    }
+
+    #[test]
+    fn limits_nodes_by_size_for_negative_limits() {
+        let mut list = List::default();
+        let element = Bytes::from(vec![b'a'; 1000]);
+        for _ in 0..4 {
+            list.push(ListEnd::Right, element.clone(), -1);
+        }
+        assert_eq!(list.encoding(), "listpack");
+
+        list.push(ListEnd::Right, element.clone(), -1);
+        assert_eq!(list.encoding(), "quicklist");
+        assert_eq!(list.quicklist_nodes(-1), 2);
+
+        // the same list fits in a single node of 8KB
+        let mut list = List::default();
+        for _ in 0..5 {
+            list.push(ListEnd::Right, element.clone(), -2);
+        }
+        assert_eq!(list.encoding(), "listpack");
+    }
+
+    #[test]
+    fn clamps_ranges_to_the_list() {
+        let list = list(&["a", "b", "c"], -2);
+        let range = |start, stop| list.range(start, stop).cloned().collect::<Vec<_>>();
+        assert_eq!(range(0, -1), ["a", "b", "c"]);
+        assert_eq!(range(-2, 10), ["b", "c"]);
+        assert_eq!(range(-10, 0), ["a"]);
+        assert!(range(2, 1).is_empty());
+        assert!(range(5, 10).is_empty());
+    }
+}
@@ -0,0 +1,2538 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use bytes::Bytes;
+
+use self::{
+    geo::{format_coordinate, Coordinates, GeoOrigin},
+    hyperloglog::{HyperLogLog, HyperLogLogError},
+    list::List,
+    set::Set,
+    sort::SortError,
+    sorted_set::{Score, SortedSet},
+    stream::{DeliveredEntry, Stream, StreamEntryId, XAddEntryId, XReadGroupId},
+};
+
+use super::{
+    error::RedisError,
+    glob, random,
+    resp::{
+        command::{
+            ExpiryOption, ListEnd, MinOrMax, ObjectSection, RedisStoreCommand, SetOperation,
+            XGroupSection, XInfoSection,
+        },
+        encoding, RESPValue,
+    },
+    server::RedisWriteStream,
+};
+
+mod bits;
+pub mod geo;
+mod hyperloglog;
+pub mod list;
+pub mod set;
+pub mod sort;
+pub mod sorted_set;
+pub mod stream;
+
+type StoreKey = Bytes;
+/// A key along with the members popped from it and their scores.
+type Popped = (Bytes, Vec<(Bytes, Score)>);
+
+/// The error every command replies with when a key holds another type than it works on.
+/// Clients match on it, so it has to be exactly the one Redis sends.
+pub const WRONG_TYPE_ERROR: &str =
+    "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoreValue {
+    String {
+        value: Bytes,
+        expiration: Option<SystemTime>,
+    },
+    Stream(Stream),
+    Set(Set),
+    Hash(HashMap<Bytes, Bytes>),
+    SortedSet(SortedSet),
+    List(List),
+}
+
+impl StoreValue {
+    /// The name of the internal representation, as reported by `DEBUG OBJECT`. Values
+    /// are always stored the same way but report the encoding Redis would pick.
+    pub fn encoding(&self, config: &EncodingConfig) -> &'static str {
+        match self {
+            StoreValue::String { value, .. } => {
+                if is_integer(value) {
+                    "int"
+                } else if value.len() <= 44 {
+                    "embstr"
+                } else {
+                    "raw"
+                }
+            }
+            StoreValue::Stream(_) => "stream",
+            StoreValue::Set(set) => set.encoding(),
+            StoreValue::Hash(fields) => {
+                let is_small = fields.len() <= config.hash_max_listpack_entries
+                    && fields.iter().all(|(field, value)| {
+                        field.len() <= config.hash_max_listpack_value
+                            && value.len() <= config.hash_max_listpack_value
+                    });
+                if is_small {
+                    "listpack"
+                } else {
+                    "hashtable"
+                }
+            }
+            StoreValue::SortedSet(sorted_set) => {
+                if sorted_set.len() <= 128
+                    && sorted_set.iter().all(|(member, _)| member.len() <= 64)
+                {
+                    "listpack"
+                } else {
+                    "skiplist"
+                }
+            }
+            StoreValue::List(list) => list.encoding(),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        matches!(
+            self,
+            StoreValue::String {
+                expiration: Some(expiration),
+                ..
+            } if *expiration <= SystemTime::now()
+        )
+    }
+}
+
+fn is_integer(value: &[u8]) -> bool {
+    value.len() <= 20 && std::str::from_utf8(value).is_ok_and(|value| value.parse::<i64>().is_ok())
+}
+
+type Database = HashMap<StoreKey, StoreValue>;
+
+/// The size thresholds below which aggregates report a compact encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingConfig {
+    /// Positive values limit the entries of a list node, negative values its size,
+    /// from -1 for 4KB up to -5 for 64KB.
+    pub list_max_listpack_size: i64,
+    /// The most members of a set of integers that is still an intset.
+    pub set_max_intset_entries: usize,
+    /// The most members of any other set that is still a listpack.
+    pub set_max_listpack_entries: usize,
+    /// The longest member, in bytes, of a set that is still a listpack.
+    pub set_max_listpack_value: usize,
+    pub hash_max_listpack_entries: usize,
+    /// The longest field or value, in bytes, of a hash that is still a listpack.
+    pub hash_max_listpack_value: usize,
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        Self {
+            list_max_listpack_size: -2,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+        }
+    }
+}
+
+/// Counters about the keys, reported by `INFO`. Hits and misses count the key lookups
+/// that found or missed their key, for working out hit ratios.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KeyspaceStats {
+    pub keyspace_hits: u64,
+    pub keyspace_misses: u64,
+    /// Keys removed because they expired, whether a command or the expire cycle found them.
+    pub expired_keys: u64,
+    /// Keys removed to stay under a memory limit, which is not enforced yet.
+    pub evicted_keys: u64,
+}
+
+#[derive(Debug)]
+pub struct RedisStore {
+    pub encoding_config: EncodingConfig,
+    databases: Vec<Database>,
+    /// The number of writes that changed the keyspace since the last save.
+    dirty: usize,
+    keyspace_stats: KeyspaceStats,
+}
+
+impl RedisStore {
+    pub fn new(database_count: usize) -> Self {
+        Self {
+            encoding_config: EncodingConfig::default(),
+            databases: (0..database_count).map(|_| Database::default()).collect(),
+            dirty: 0,
+            keyspace_stats: KeyspaceStats::default(),
+        }
+    }
+
+    /// Iterates over the entries of each database, in order of their index.
+    pub fn databases(
+        &self,
+    ) -> impl Iterator<Item = impl Iterator<Item = (&StoreKey, &StoreValue)>> {
+        self.databases.iter().map(|database| database.iter())
+    }
+
+    /// Looks up a key without changing it, treating expired keys as missing.
+    pub fn get(&self, db: usize, key: &[u8]) -> Option<&StoreValue> {
+        self.databases
+            .get(db)?
+            .get(key)
+            .filter(|value| !value.is_expired())
+    }
+
+    /// Pops up to `count` members from the first of `keys` that holds a sorted set,
+    /// returning the key along with the popped members. Fails with the error reply when a
+    /// key holds another type.
+    pub fn pop_any(
+        &mut self,
+        db: usize,
+        keys: &[Bytes],
+        end: MinOrMax,
+        count: usize,
+    ) -> Result<Option<Popped>, RESPValue> {
+        let Some(items) = self.databases.get_mut(db) else {
+            return Ok(None);
+        };
+        let stats = &mut self.keyspace_stats;
+
+        for key in keys {
+            Self::remove_if_expired(items, stats, key);
+            let sorted_set = match items.get_mut(key) {
+                Some(StoreValue::SortedSet(sorted_set)) => sorted_set,
+                Some(_) => return Err(Self::wrong_type()),
+                None => continue,
+            };
+
+            let popped = (0..count)
+                .map_while(|_| sorted_set.pop(end))
+                .collect::<Vec<_>>();
+            if popped.is_empty() {
+                continue;
+            }
+
+            if sorted_set.is_empty() {
+                items.remove(key);
+            }
+
+            self.dirty += 1;
+            return Ok(Some((key.clone(), popped)));
+        }
+
+        Ok(None)
+    }
+
+    /// Pops up to `count` elements from the first of `keys` that holds a list, like
+    /// [`Self::pop_any`] does for sorted sets.
+    pub fn pop_any_list(
+        &mut self,
+        db: usize,
+        keys: &[Bytes],
+        end: ListEnd,
+        count: usize,
+    ) -> Result<Option<(Bytes, Vec<Bytes>)>, RESPValue> {
+        let Some(items) = self.databases.get_mut(db) else {
+            return Ok(None);
+        };
+        let stats = &mut self.keyspace_stats;
+        let list_max_listpack_size = self.encoding_config.list_max_listpack_size;
+
+        for key in keys {
+            Self::remove_if_expired(items, stats, key);
+            let list = match items.get_mut(key) {
+                Some(StoreValue::List(list)) => list,
+                Some(_) => return Err(Self::wrong_type()),
+                None => continue,
+            };
+
+            // lists are never left empty, so there is always something to pop
+            let popped = (0..count)
+                .map_while(|_| list.pop(end, list_max_listpack_size))
+                .collect::<Vec<_>>();
+            if list.is_empty() {
+                items.remove(key);
+            }
+
+            self.dirty += 1;
+            return Ok(Some((key.clone(), popped)));
+        }
+
+        Ok(None)
+    }
+
+    pub fn dirty(&self) -> usize {
+        self.dirty
+    }
+
+    /// Takes the dataset of `store`, e.g. one loaded from disk, while the encoding
+    /// configuration and the keyspace stats stay those of the running server.
+    pub fn replace_dataset(&mut self, store: RedisStore) {
+        self.databases = store.databases;
+        self.dirty = store.dirty;
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = 0;
+    }
+
+    /// Forgets the first `changes` writes, which a snapshot that finished saving covers,
+    /// while the writes made since it was taken still count.
+    pub fn clear_dirty_before(&mut self, changes: usize) {
+        self.dirty = self.dirty.saturating_sub(changes);
+    }
+
+    pub fn database_count(&self) -> usize {
+        self.databases.len()
+    }
+
+    pub fn keyspace_stats(&self) -> KeyspaceStats {
+        self.keyspace_stats
+    }
+
+    /// Counts the keys a command reads as hits or misses, so that every read command
+    /// reports them the same way. Expired keys are removed first, so they count as misses.
+    fn record_lookups(&mut self, db: usize, command: &RedisStoreCommand) {
+        let Some(items) = self.databases.get_mut(db) else {
+            return;
+        };
+        let stats = &mut self.keyspace_stats;
+
+        for key in command.read_keys() {
+            Self::remove_if_expired(items, stats, key);
+            if items.contains_key(key) {
+                stats.keyspace_hits += 1;
+            } else {
+                stats.keyspace_misses += 1;
+            }
+        }
+    }
+
+    /// Runs a command against the given database. The client is replied to with
+    /// [`RedisError::Command`] errors, while other errors mean its connection is unusable.
+    pub async fn handle(
+        &mut self,
+        db: usize,
+        command: &RedisStoreCommand,
+        write_stream: RedisWriteStream,
+    ) -> Result<(), RedisError> {
+        self.execute(db, command, write_stream)
+            .await
+            .map_err(RedisError::from)
+    }
+
+    async fn execute(
+        &mut self,
+        db: usize,
+        command: &RedisStoreCommand,
+        write_stream: RedisWriteStream,
+    ) -> anyhow::Result<()> {
+        self.record_lookups(db, command);
+
+        let items = self
+            .databases
+            .get_mut(db)
+            .ok_or_else(|| RedisError::Command("ERR DB index is out of range".to_string()))?;
+        let stats = &mut self.keyspace_stats;
+
+        match command {
+            RedisStoreCommand::Get { key } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(StoreValue::String { value, .. }) => encoding::bulk_string(value),
+                    Some(
+                        StoreValue::Stream(_)
+                        | StoreValue::Set(_)
+                        | StoreValue::Hash(_)
+                        | StoreValue::SortedSet(_)
+                        | StoreValue::List(_),
+                    ) => Self::wrong_type(),
+                    _ => encoding::null_bulk_string(),
+                };
+
+                write_stream.write(value).await?;
+                Ok(())
+            }
+            RedisStoreCommand::MGet { keys } => {
+                // unlike `GET`, keys holding anything but a string are null rather than
+                // an error, so that one of them does not fail the whole lookup
+                let values = keys
+                    .iter()
+                    .map(|key| {
+                        Self::remove_if_expired(items, stats, key);
+                        match items.get(key) {
+                            Some(StoreValue::String { value, .. }) => encoding::bulk_string(value),
+                            _ => encoding::null_bulk_string(),
+                        }
+                    })
+                    .collect();
+
+                write_stream.write(encoding::array(values)).await
+            }
+            RedisStoreCommand::GetEx { key, expiry_option } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get_mut(key) {
+                    Some(StoreValue::String { value, expiration }) => {
+                        match expiry_option {
+                            Some(ExpiryOption::At(timestamp)) => *expiration = Some(*timestamp),
+                            Some(ExpiryOption::Persist) => *expiration = None,
+                            None => {}
+                        }
+                        if expiry_option.is_some() {
+                            self.dirty += 1;
+                        }
+
+                        encoding::bulk_string(value)
+                    }
+                    Some(
+                        StoreValue::Stream(_)
+                        | StoreValue::Set(_)
+                        | StoreValue::Hash(_)
+                        | StoreValue::SortedSet(_)
+                        | StoreValue::List(_),
+                    ) => Self::wrong_type(),
+                    None => encoding::null_bulk_string(),
+                };
+
+                write_stream.write(value).await?;
+                Ok(())
+            }
+            RedisStoreCommand::GetDel { key } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(StoreValue::String { value, .. }) => {
+                        let value = encoding::bulk_string(value);
+                        items.remove(key);
+                        self.dirty += 1;
+                        value
+                    }
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::null_bulk_string(),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::Del { keys } => {
+                let removed = keys
+                    .iter()
+                    .filter(|key| {
+                        Self::remove_if_expired(items, stats, key);
+                        items.remove(*key).is_some()
+                    })
+                    .count();
+                if removed > 0 {
+                    self.dirty += 1;
+                }
+
+                write_stream.write(encoding::integer(removed as i64)).await
+            }
+            RedisStoreCommand::Set { key, value, px } => {
+                self.dirty += 1;
+                items.insert(
+                    key.clone(),
+                    StoreValue::String {
+                        value: value.clone(),
+                        expiration: px.as_ref().copied(),
+                    },
+                );
+
+                write_stream.write(Bytes::from_static(b"+OK\r\n")).await?;
+                Ok(())
+            }
+            RedisStoreCommand::Keys { key } => {
+                let is_all = &**key == b"*";
+                let keys = items
+                    .keys()
+                    .filter(|item_key| is_all || glob::matches(key, item_key))
+                    .map(encoding::bulk_string)
+                    .collect();
+                write_stream.write(encoding::array(keys)).await
+            }
+            RedisStoreCommand::Type { key } => {
+                let value = match items.get(key) {
+                    Some(StoreValue::String { .. }) => encoding::simple_string(b"string"),
+                    Some(StoreValue::Stream(_)) => encoding::simple_string(b"stream"),
+                    Some(StoreValue::Set(_)) => encoding::simple_string(b"set"),
+                    Some(StoreValue::Hash(_)) => encoding::simple_string(b"hash"),
+                    Some(StoreValue::SortedSet(_)) => encoding::simple_string(b"zset"),
+                    Some(StoreValue::List(_)) => encoding::simple_string(b"list"),
+                    None => encoding::simple_string(b"none"),
+                };
+
+                write_stream.write(value).await?;
+                Ok(())
+            }
+            RedisStoreCommand::XAdd {
+                key,
+                entry_id,
+                no_mkstream,
+                trim,
+                fields,
+            } => {
+                Self::remove_if_expired(items, stats, key);
+                if *no_mkstream && !items.contains_key(key) {
+                    return write_stream.write(encoding::null_bulk_string()).await;
+                }
+
+                if *entry_id == XAddEntryId::Explicit(StreamEntryId::default()) {
+                    let error = "ERR The ID specified in XADD must be greater than 0-0";
+                    return write_stream.write(encoding::simple_error(error)).await;
+                }
+
+                let stream = items
+                    .entry(key.clone())
+                    .or_insert_with(|| StoreValue::Stream(Stream::default()));
+
+                let StoreValue::Stream(stream) = stream else {
+                    return write_stream.write(Self::wrong_type()).await;
+                };
+
+                let Some(entry_id) = stream.next_id(*entry_id) else {
+                    let error = "ERR The ID specified in XADD is equal or smaller than the target stream top item";
+                    return write_stream.write(encoding::simple_error(error)).await;
+                };
+
+                stream.add(entry_id, fields.clone());
+                if let Some(trim) = trim {
+                    stream.trim(*trim);
+                }
+                self.dirty += 1;
+
+                write_stream
+                    .write(encoding::bulk_string(entry_id.to_string()))
+                    .await
+            }
+            RedisStoreCommand::XTrim { key, trim } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get_mut(key) {
+                    Some(StoreValue::Stream(stream)) => {
+                        let removed = stream.trim(*trim);
+                        if removed > 0 {
+                            self.dirty += 1;
+                        }
+
+                        encoding::integer(removed as i64)
+                    }
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::integer(0),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::Object {
+                section: ObjectSection::Encoding { key },
+            } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(value) => encoding::bulk_string(value.encoding(&self.encoding_config)),
+                    None => encoding::null_bulk_string(),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::XInfo {
+                section: XInfoSection::Help,
+            } => {
+                write_stream
+                    .write(encoding::help(
+                        "xinfo",
+                        &["STREAM <key>", "    Show information about the stream."],
+                    ))
+                    .await
+            }
+            RedisStoreCommand::XInfo {
+                section: XInfoSection::Stream { key },
+            } => {
+                write_stream
+                    .write(Self::stream_info(items.get(key), false))
+                    .await
+            }
+            RedisStoreCommand::XGroup {
+                section: XGroupSection::Help,
+            } => {
+                write_stream
+                    .write(encoding::help(
+                        "xgroup",
+                        &[
+                            "CREATE <key> <groupname> <id|$> [MKSTREAM]",
+                            "    Create a new consumer group. Options are:",
+                            "    * MKSTREAM",
+                            "      Create the empty stream if it does not exist.",
+                        ],
+                    ))
+                    .await
+            }
+            RedisStoreCommand::XGroup {
+                section:
+                    XGroupSection::Create {
+                        key,
+                        group,
+                        id,
+                        mkstream,
+                    },
+            } => {
+                Self::remove_if_expired(items, stats, key);
+                if *mkstream {
+                    items
+                        .entry(key.clone())
+                        .or_insert_with(|| StoreValue::Stream(Stream::default()));
+                }
+
+                let value = match items.get_mut(key) {
+                    Some(StoreValue::Stream(stream)) => {
+                        let id = id.unwrap_or_else(|| stream.last_id());
+                        if stream.create_group(group.clone(), id) {
+                            self.dirty += 1;
+                            encoding::simple_string("OK")
+                        } else {
+                            encoding::simple_error("BUSYGROUP Consumer Group name already exists")
+                        }
+                    }
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::simple_error(
+                        "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.",
+                    ),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::XReadGroup {
+                group,
+                consumer,
+                count,
+                no_ack,
+                keys,
+                ids,
+            } => {
+                // like in Redis, every stream is checked before any entry is delivered
+                for key in keys {
+                    Self::remove_if_expired(items, stats, key);
+                    let error = match items.get(key) {
+                        Some(StoreValue::Stream(stream)) if stream.group(group).is_some() => {
+                            continue
+                        }
+                        Some(StoreValue::Stream(_)) | None => encoding::simple_error(format!(
+                            "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                            String::from_utf8_lossy(key),
+                            String::from_utf8_lossy(group),
+                        )),
+                        Some(_) => Self::wrong_type(),
+                    };
+
+                    return write_stream.write(error).await;
+                }
+
+                let mut streams = vec![];
+                for (key, id) in keys.iter().zip(ids) {
+                    let Some(StoreValue::Stream(stream)) = items.get_mut(key) else {
+                        continue;
+                    };
+
+                    let entries = stream
+                        .read_group(group, consumer, *id, *count, *no_ack)
+                        .unwrap_or_default();
+                    // streams without new entries are left out, but the pending entries
+                    // of a consumer are listed even when there are none
+                    if *id == XReadGroupId::New {
+                        if entries.is_empty() {
+                            continue;
+                        }
+
+                        // delivering new entries moves the group along
+                        self.dirty += 1;
+                    }
+
+                    streams.push(encoding::array(vec![
+                        encoding::bulk_string(key),
+                        encoding::array(entries.into_iter().map(Self::delivered_entry).collect()),
+                    ]));
+                }
+
+                let value = if streams.is_empty() {
+                    encoding::null_array()
+                } else {
+                    encoding::array(streams)
+                };
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::XAck { key, group, ids } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get_mut(key) {
+                    Some(StoreValue::Stream(stream)) => {
+                        let acked = stream.ack(group, ids).unwrap_or(0);
+                        if acked > 0 {
+                            self.dirty += 1;
+                        }
+
+                        encoding::integer(acked as i64)
+                    }
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::integer(0),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::SwapDb { index, other_index } => {
+                let database_count = self.databases.len();
+                if *index >= database_count || *other_index >= database_count {
+                    return write_stream
+                        .write(encoding::simple_error("ERR DB index is out of range"))
+                        .await;
+                }
+
+                // connections keep their numeric selection, so a client that selected one of
+                // the swapped indices now sees the other database's contents
+                self.databases.swap(*index, *other_index);
+                self.dirty += 1;
+                write_stream.write(encoding::simple_string("OK")).await
+            }
+            RedisStoreCommand::Move { key, db: target_db } => {
+                if *target_db >= self.databases.len() {
+                    return write_stream
+                        .write(encoding::simple_error("ERR DB index is out of range"))
+                        .await;
+                }
+
+                if *target_db == db {
+                    return write_stream
+                        .write(encoding::simple_error(
+                            "ERR source and destination objects are the same",
+                        ))
+                        .await;
+                }
+
+                let is_moved = match self.databases[db].remove(key) {
+                    Some(value) if value.is_expired() => {
+                        self.keyspace_stats.expired_keys += 1;
+                        false
+                    }
+                    Some(value) if self.databases[*target_db].contains_key(key) => {
+                        self.databases[db].insert(key.clone(), value);
+                        false
+                    }
+                    Some(value) => {
+                        self.databases[*target_db].insert(key.clone(), value);
+                        self.dirty += 1;
+                        true
+                    }
+                    None => false,
+                };
+
+                write_stream.write(encoding::integer(is_moved)).await
+            }
+            RedisStoreCommand::SetBit { key, offset, bit } => {
+                if *offset > bits::MAX_BIT_OFFSET {
+                    return write_stream
+                        .write(encoding::simple_error(
+                            "ERR bit offset is not an integer or out of range",
+                        ))
+                        .await;
+                }
+
+                Self::remove_if_expired(items, stats, key);
+                let value = items.entry(key.clone()).or_insert(StoreValue::String {
+                    value: Bytes::new(),
+                    expiration: None,
+                });
+
+                let StoreValue::String { value, .. } = value else {
+                    return write_stream.write(Self::wrong_type()).await;
+                };
+
+                let (new_value, old_bit) = bits::set_bit(value, *offset, *bit);
+                *value = new_value;
+                self.dirty += 1;
+                write_stream.write(encoding::integer(old_bit)).await
+            }
+            RedisStoreCommand::GetBit { key, offset } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(StoreValue::String { value, .. }) => {
+                        encoding::integer(bits::get_bit(value, *offset))
+                    }
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::integer(0),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::BitCount { key, range } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(StoreValue::String { value, .. }) => {
+                        encoding::integer(bits::bit_count(value, *range) as i64)
+                    }
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::integer(0),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::SAdd { key, members } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = items
+                    .entry(key.clone())
+                    .or_insert_with(|| StoreValue::Set(Set::default()));
+
+                let StoreValue::Set(set) = value else {
+                    return write_stream.write(Self::wrong_type()).await;
+                };
+
+                let added = members
+                    .iter()
+                    .filter(|member| set.insert((*member).clone(), &self.encoding_config))
+                    .count();
+                if added > 0 {
+                    self.dirty += 1;
+                }
+
+                write_stream.write(encoding::integer(added as i64)).await
+            }
+            RedisStoreCommand::SMembers { key } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(StoreValue::Set(members)) => {
+                        encoding::array(members.iter().map(encoding::bulk_string).collect())
+                    }
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::array(vec![]),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::SetOperationStore {
+                operation,
+                destination,
+                keys,
+            } => {
+                let Some(result) = set::combine(items, keys, *operation) else {
+                    return write_stream.write(Self::wrong_type()).await;
+                };
+
+                // the destination is replaced whatever it held before, and an empty
+                // result removes it since Redis never stores empty sets
+                let cardinality = result.len();
+                self.dirty += 1;
+                if result.is_empty() {
+                    items.remove(destination);
+                } else {
+                    let set = Set::from_members(result, &self.encoding_config);
+                    items.insert(destination.clone(), StoreValue::Set(set));
+                }
+
+                write_stream
+                    .write(encoding::integer(cardinality as i64))
+                    .await
+            }
+            RedisStoreCommand::SInterCard { keys, limit } => {
+                let value = match set::combine(items, keys, SetOperation::Inter) {
+                    Some(result) => {
+                        encoding::integer(result.len().min(limit.unwrap_or(usize::MAX)) as i64)
+                    }
+                    None => Self::wrong_type(),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::SMove {
+                source,
+                destination,
+                member,
+            } => {
+                Self::remove_if_expired(items, stats, source);
+                Self::remove_if_expired(items, stats, destination);
+                let is_member = match (items.get(source), items.get(destination)) {
+                    (Some(StoreValue::Set(members)), Some(StoreValue::Set(_)) | None) => {
+                        members.contains(member)
+                    }
+                    (None, Some(StoreValue::Set(_)) | None) => false,
+                    _ => return write_stream.write(Self::wrong_type()).await,
+                };
+
+                if is_member && source != destination {
+                    if let Some(StoreValue::Set(members)) = items.get_mut(source) {
+                        members.remove(member);
+                        if members.is_empty() {
+                            items.remove(source);
+                        }
+                    }
+
+                    let value = items
+                        .entry(destination.clone())
+                        .or_insert_with(|| StoreValue::Set(Set::default()));
+                    if let StoreValue::Set(members) = value {
+                        members.insert(member.clone(), &self.encoding_config);
+                    }
+                    self.dirty += 1;
+                }
+
+                write_stream.write(encoding::integer(is_member)).await
+            }
+            RedisStoreCommand::ListPush { key, end, elements } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = items
+                    .entry(key.clone())
+                    .or_insert_with(|| StoreValue::List(List::default()));
+
+                let StoreValue::List(list) = value else {
+                    return write_stream.write(Self::wrong_type()).await;
+                };
+
+                for element in elements {
+                    list.push(
+                        *end,
+                        element.clone(),
+                        self.encoding_config.list_max_listpack_size,
+                    );
+                }
+                self.dirty += 1;
+
+                write_stream
+                    .write(encoding::integer(list.len() as i64))
+                    .await
+            }
+            RedisStoreCommand::ListPop { key, end, count } => {
+                Self::remove_if_expired(items, stats, key);
+                let list = match items.get_mut(key) {
+                    Some(StoreValue::List(list)) => list,
+                    Some(_) => return write_stream.write(Self::wrong_type()).await,
+                    None if count.is_some() => {
+                        return write_stream.write(encoding::null_array()).await
+                    }
+                    None => return write_stream.write(encoding::null_bulk_string()).await,
+                };
+
+                let list_max_listpack_size = self.encoding_config.list_max_listpack_size;
+                let popped = (0..count.unwrap_or(1))
+                    .map_while(|_| list.pop(*end, list_max_listpack_size))
+                    .collect::<Vec<_>>();
+                if list.is_empty() {
+                    items.remove(key);
+                }
+                if !popped.is_empty() {
+                    self.dirty += 1;
+                }
+
+                let value = match count {
+                    Some(_) => encoding::array(popped.iter().map(encoding::bulk_string).collect()),
+                    None => popped
+                        .first()
+                        .map_or_else(encoding::null_bulk_string, encoding::bulk_string),
+                };
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::LMPop { keys, end, count }
+            | RedisStoreCommand::BLMPop {
+                keys, end, count, ..
+            } => {
+                // blocking is up to the caller, so on its own this only pops what is there
+                let value = match self.pop_any_list(db, keys, *end, count.unwrap_or(1)) {
+                    Ok(Some((key, popped))) => Self::list_multi_pop_reply(key, popped),
+                    Ok(None) => encoding::null_array(),
+                    Err(error) => error,
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::LLen { key } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(StoreValue::List(list)) => encoding::integer(list.len() as i64),
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::integer(0),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::LRange { key, start, stop } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(StoreValue::List(list)) => encoding::array(
+                        list.range(*start, *stop)
+                            .map(encoding::bulk_string)
+                            .collect(),
+                    ),
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::array(vec![]),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::HSet { key, fields } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = items
+                    .entry(key.clone())
+                    .or_insert_with(|| StoreValue::Hash(HashMap::new()));
+
+                let StoreValue::Hash(hash) = value else {
+                    return write_stream.write(Self::wrong_type()).await;
+                };
+
+                let added = fields
+                    .iter()
+                    .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+                    .count();
+                self.dirty += 1;
+
+                write_stream.write(encoding::integer(added as i64)).await
+            }
+            RedisStoreCommand::HGet { key, field } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(StoreValue::Hash(hash)) => hash
+                        .get(field)
+                        .map_or_else(encoding::null_bulk_string, encoding::bulk_string),
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::null_bulk_string(),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::HRandField {
+                key,
+                count,
+                with_values,
+            } => {
+                Self::remove_if_expired(items, stats, key);
+                let hash = match items.get(key) {
+                    Some(StoreValue::Hash(hash)) => Some(hash),
+                    Some(_) => return write_stream.write(Self::wrong_type()).await,
+                    None => None,
+                };
+
+                let fields = hash
+                    .map(|hash| hash.iter().collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let value = match count {
+                    None => random::sample(&fields, 1)
+                        .first()
+                        .map_or_else(encoding::null_bulk_string, |(field, _)| {
+                            encoding::bulk_string(field)
+                        }),
+                    Some(count) => encoding::array(
+                        random::sample(&fields, *count)
+                            .into_iter()
+                            .flat_map(|(field, value)| {
+                                let value = with_values.then(|| encoding::bulk_string(value));
+                                std::iter::once(encoding::bulk_string(field)).chain(value)
+                            })
+                            .collect(),
+                    ),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::ZAdd { key, members } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = items
+                    .entry(key.clone())
+                    .or_insert_with(|| StoreValue::SortedSet(SortedSet::default()));
+
+                let StoreValue::SortedSet(sorted_set) = value else {
+                    return write_stream.write(Self::wrong_type()).await;
+                };
+
+                let mut is_changed = false;
+                let added = members
+                    .iter()
+                    .filter(|(score, member)| {
+                        is_changed |= sorted_set.score(member) != Some(*score);
+                        sorted_set.insert(member.clone(), *score)
+                    })
+                    .count();
+                if is_changed {
+                    self.dirty += 1;
+                }
+
+                write_stream.write(encoding::integer(added as i64)).await
+            }
+            RedisStoreCommand::ZScore { key, member } => {
+                Self::remove_if_expired(items, stats, key);
+                let value = match items.get(key) {
+                    Some(StoreValue::SortedSet(sorted_set)) => sorted_set
+                        .score(member)
+                        .map_or_else(encoding::null_bulk_string, |score| {
+                            encoding::bulk_string(score.to_string())
+                        }),
+                    Some(_) => Self::wrong_type(),
+                    None => encoding::null_bulk_string(),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::ZRandMember {
+                key,
+                count,
+                with_scores,
+            } => {
+                Self::remove_if_expired(items, stats, key);
+                let sorted_set = match items.get(key) {
+                    Some(StoreValue::SortedSet(sorted_set)) => Some(sorted_set),
+                    Some(_) => return write_stream.write(Self::wrong_type()).await,
+                    None => None,
+                };
+
+                let members = sorted_set
+                    .map(|sorted_set| sorted_set.iter().collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let value = match count {
+                    None => random::sample(&members, 1)
+                        .first()
+                        .map_or_else(encoding::null_bulk_string, |(member, _)| {
+                            encoding::bulk_string(member)
+                        }),
+                    Some(count) => encoding::array(
+                        random::sample(&members, *count)
+                            .into_iter()
+                            .flat_map(|(member, score)| {
+                                let score =
+                                    with_scores.then(|| encoding::bulk_string(score.to_string()));
+                                std::iter::once(encoding::bulk_string(member)).chain(score)
+                            })
+                            .collect(),
+                    ),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::ZPop { key, count, end } => {
+                Self::remove_if_expired(items, stats, key);
+                let sorted_set = match items.get_mut(key) {
+                    Some(StoreValue::SortedSet(sorted_set)) => sorted_set,
+                    Some(_) => return write_stream.write(Self::wrong_type()).await,
+                    None => return write_stream.write(encoding::array(vec![])).await,
+                };
+
+                let mut values = vec![];
+                for _ in 0..count.unwrap_or(1) {
+                    let Some((member, score)) = sorted_set.pop(*end) else {
+                        break;
+                    };
+
+                    values.push(encoding::bulk_string(member));
+                    values.push(encoding::bulk_string(score.to_string()));
+                }
+
+                if sorted_set.is_empty() {
+                    items.remove(key);
+                }
+                if !values.is_empty() {
+                    self.dirty += 1;
+                }
+
+                write_stream.write(encoding::array(values)).await
+            }
+            RedisStoreCommand::ZMPop { keys, end, count }
+            | RedisStoreCommand::BZMPop {
+                keys, end, count, ..
+            } => {
+                // blocking is up to the caller, so on its own this only pops what is there
+                let value = match self.pop_any(db, keys, *end, count.unwrap_or(1)) {
+                    Ok(Some((key, popped))) => Self::multi_pop_reply(key, popped),
+                    Ok(None) => encoding::null_array(),
+                    Err(error) => error,
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::PfAdd { key, elements } => {
+                Self::remove_if_expired(items, stats, key);
+                let (mut hyperloglog, expiration) = match Self::get_hyperloglog(items, key) {
+                    Ok(Some(hyperloglog)) => hyperloglog,
+                    Ok(None) => (HyperLogLog::new(), None),
+                    Err(error) => return write_stream.write(error).await,
+                };
+
+                let mut is_changed = !items.contains_key(key);
+                for element in elements {
+                    is_changed |= hyperloglog.add(element);
+                }
+
+                if is_changed {
+                    items.insert(
+                        key.clone(),
+                        StoreValue::String {
+                            value: hyperloglog.to_bytes(),
+                            expiration,
+                        },
+                    );
+                    self.dirty += 1;
+                }
+
+                write_stream.write(encoding::integer(is_changed)).await
+            }
+            RedisStoreCommand::PfCount { keys } => {
+                let mut union = HyperLogLog::new();
+                for key in keys {
+                    Self::remove_if_expired(items, stats, key);
+                    match Self::get_hyperloglog(items, key) {
+                        Ok(Some((hyperloglog, _))) => union.merge(&hyperloglog),
+                        Ok(None) => {}
+                        Err(error) => return write_stream.write(error).await,
+                    }
+                }
+
+                write_stream
+                    .write(encoding::integer(union.count() as i64))
+                    .await
+            }
+            RedisStoreCommand::PfMerge { destination, keys } => {
+                Self::remove_if_expired(items, stats, destination);
+                let (mut merged, expiration) = match Self::get_hyperloglog(items, destination) {
+                    Ok(Some(hyperloglog)) => hyperloglog,
+                    Ok(None) => (HyperLogLog::new(), None),
+                    Err(error) => return write_stream.write(error).await,
+                };
+
+                for key in keys {
+                    Self::remove_if_expired(items, stats, key);
+                    match Self::get_hyperloglog(items, key) {
+                        Ok(Some((hyperloglog, _))) => merged.merge(&hyperloglog),
+                        Ok(None) => {}
+                        Err(error) => return write_stream.write(error).await,
+                    }
+                }
+
+                items.insert(
+                    destination.clone(),
+                    StoreValue::String {
+                        value: merged.to_bytes(),
+                        expiration,
+                    },
+                );
+                self.dirty += 1;
+
+                write_stream.write(encoding::simple_string("OK")).await
+            }
+            RedisStoreCommand::BZPop { keys, end, .. } => {
+                // blocking is up to the caller, so on its own this only pops what is there
+                let value = match self.pop_any(db, keys, *end, 1) {
+                    Ok(Some((key, mut popped))) => {
+                        let (member, score) = popped.swap_remove(0);
+                        encoding::array(vec![
+                            encoding::bulk_string(key),
+                            encoding::bulk_string(member),
+                            encoding::bulk_string(score.to_string()),
+                        ])
+                    }
+                    Ok(None) => encoding::null_array(),
+                    Err(error) => error,
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::GeoAdd { key, points } => {
+                if let Some((coordinates, _)) = points
+                    .iter()
+                    .find(|(coordinates, _)| !coordinates.is_valid())
+                {
+                    return write_stream
+                        .write(Self::invalid_coordinates(coordinates))
+                        .await;
+                }
+
+                Self::remove_if_expired(items, stats, key);
+                let value = items
+                    .entry(key.clone())
+                    .or_insert_with(|| StoreValue::SortedSet(SortedSet::default()));
+
+                let StoreValue::SortedSet(sorted_set) = value else {
+                    return write_stream.write(Self::wrong_type()).await;
+                };
+
+                let mut is_changed = false;
+                let added = points
+                    .iter()
+                    .filter(|(coordinates, member)| {
+                        let score = Score::new(coordinates.geohash() as f64).unwrap();
+                        is_changed |= sorted_set.score(member) != Some(score);
+                        sorted_set.insert(member.clone(), score)
+                    })
+                    .count();
+                if is_changed {
+                    self.dirty += 1;
+                }
+
+                write_stream.write(encoding::integer(added as i64)).await
+            }
+            RedisStoreCommand::GeoPos { key, members } => {
+                Self::remove_if_expired(items, stats, key);
+                let sorted_set = match items.get(key) {
+                    Some(StoreValue::SortedSet(sorted_set)) => Some(sorted_set),
+                    Some(_) => return write_stream.write(Self::wrong_type()).await,
+                    None => None,
+                };
+
+                let positions = members
+                    .iter()
+                    .map(|member| {
+                        sorted_set
+                            .and_then(|sorted_set| sorted_set.score(member))
+                            .map_or_else(encoding::null_array, |score| {
+                                Self::coordinates(&Coordinates::from_geohash(score.value() as u64))
+                            })
+                    })
+                    .collect();
+
+                write_stream.write(encoding::array(positions)).await
+            }
+            RedisStoreCommand::GeoDist {
+                key,
+                member,
+                other_member,
+                unit,
+            } => {
+                Self::remove_if_expired(items, stats, key);
+                let sorted_set = match items.get(key) {
+                    Some(StoreValue::SortedSet(sorted_set)) => sorted_set,
+                    Some(_) => return write_stream.write(Self::wrong_type()).await,
+                    None => return write_stream.write(encoding::null_bulk_string()).await,
+                };
+
+                let position = |member: &[u8]| {
+                    let score = sorted_set.score(member)?;
+                    Some(Coordinates::from_geohash(score.value() as u64))
+                };
+
+                let value = match (position(member), position(other_member)) {
+                    (Some(position), Some(other_position)) => encoding::bulk_string(format!(
+                        "{:.4}",
+                        position.distance(&other_position) / unit.meters()
+                    )),
+                    _ => encoding::null_bulk_string(),
+                };
+
+                write_stream.write(value).await
+            }
+            RedisStoreCommand::Sort { key, sort } => {
+                Self::remove_if_expired(items, stats, key);
+                let reply = match (sort.sort(items, key), &sort.store) {
+                    // like in Redis, a `GET` pattern pointing to nothing is stored as an
+                    // empty string
+                    (Ok(values), Some(destination)) => {
+                        let len = values.len();
+                        let mut list = List::default();
+                        for value in values {
+                            list.push(
+                                ListEnd::Right,
+                                value.unwrap_or_default(),
+                                self.encoding_config.list_max_listpack_size,
+                            );
+                        }
+
+                        let replaced = if list.is_empty() {
+                            items.remove(destination)
+                        } else {
+                            items.insert(destination.clone(), StoreValue::List(list))
+                        };
+                        if len > 0 || replaced.is_some() {
+                            self.dirty += 1;
+                        }
+
+                        encoding::integer(len as i64)
+                    }
+                    (Ok(values), None) => encoding::array(
+                        values
+                            .iter()
+                            .map(|value| {
+                                value
+                                    .as_ref()
+                                    .map_or_else(encoding::null_bulk_string, encoding::bulk_string)
+                            })
+                            .collect(),
+                    ),
+                    (Err(SortError::WrongType), _) => Self::wrong_type(),
+                    (Err(SortError::NotNumber), _) => encoding::simple_error(
+                        "ERR One or more scores can't be converted into double",
+                    ),
+                };
+
+                write_stream.write(reply).await
+            }
+            RedisStoreCommand::GeoSearch { key, search } => {
+                Self::remove_if_expired(items, stats, key);
+                let sorted_set = match items.get(key) {
+                    Some(StoreValue::SortedSet(sorted_set)) => sorted_set,
+                    Some(_) => return write_stream.write(Self::wrong_type()).await,
+                    None => return write_stream.write(encoding::array(vec![])).await,
+                };
+
+                let center = match &search.origin {
+                    GeoOrigin::Member(member) => match sorted_set.score(member) {
+                        Some(score) => Coordinates::from_geohash(score.value() as u64),
+                        None => {
+                            return write_stream
+                                .write(encoding::simple_error(
+                                    "ERR could not decode requested zset member",
+                                ))
+                                .await
+                        }
+                    },
+                    GeoOrigin::Coordinates(coordinates) if !coordinates.is_valid() => {
+                        return write_stream
+                            .write(Self::invalid_coordinates(coordinates))
+                            .await
+                    }
+                    GeoOrigin::Coordinates(coordinates) => *coordinates,
+                };
+
+                let is_plain =
+                    !search.with_coordinates && !search.with_distance && !search.with_hash;
+                let matches = search
+                    .matches(sorted_set, &center)
+                    .into_iter()
+                    .map(|found| {
+                        if is_plain {
+                            return encoding::bulk_string(found.member);
+                        }
+
+                        let mut values = vec![encoding::bulk_string(found.member)];
+                        if search.with_distance {
+                            values.push(encoding::bulk_string(format!(
+                                "{:.4}",
+                                found.distance / search.unit.meters()
+                            )));
+                        }
+
+                        if search.with_hash {
+                            values.push(encoding::integer(found.geohash as i64));
+                        }
+
+                        if search.with_coordinates {
+                            values.push(Self::coordinates(&found.coordinates));
+                        }
+
+                        encoding::array(values)
+                    })
+                    .collect();
+
+                write_stream.write(encoding::array(matches)).await
+            }
+        }
+    }
+
+    fn invalid_coordinates(coordinates: &Coordinates) -> RESPValue {
+        encoding::simple_error(format!(
+            "ERR invalid longitude,latitude pair {:.6},{:.6}",
+            coordinates.longitude, coordinates.latitude
+        ))
+    }
+
+    fn coordinates(coordinates: &Coordinates) -> RESPValue {
+        encoding::array(vec![
+            encoding::bulk_string(format_coordinate(coordinates.longitude)),
+            encoding::bulk_string(format_coordinate(coordinates.latitude)),
+        ])
+    }
+
+    /// Removes the key when it expired, counting it as an expired key.
+    fn remove_if_expired(items: &mut Database, stats: &mut KeyspaceStats, key: &StoreKey) {
+        if items.get(key).is_some_and(StoreValue::is_expired) {
+            items.remove(key);
+            stats.expired_keys += 1;
+        }
+    }
+
+    /// Reads the HyperLogLog stored at the key along with the key's expiration, failing
+    /// with the error reply when the key holds anything else.
+    fn get_hyperloglog(
+        items: &Database,
+        key: &StoreKey,
+    ) -> Result<Option<(HyperLogLog, Option<SystemTime>)>, RESPValue> {
+        let (value, expiration) = match items.get(key) {
+            Some(StoreValue::String { value, expiration }) => (value, *expiration),
+            Some(_) => return Err(Self::wrong_type()),
+            None => return Ok(None),
+        };
+
+        match HyperLogLog::parse(value) {
+            Ok(hyperloglog) => Ok(Some((hyperloglog, expiration))),
+            Err(HyperLogLogError::NotHyperLogLog) => Err(encoding::simple_error(
+                "WRONGTYPE Key is not a valid HyperLogLog string value.",
+            )),
+            Err(HyperLogLogError::Corrupted) => Err(encoding::simple_error(
+                "INVALIDOBJ Corrupted HLL object detected",
+            )),
+        }
+    }
+
+    /// The reply of `ZMPOP` and `BZMPOP`, the key followed by the popped members and
+    /// their scores.
+    pub fn multi_pop_reply(key: Bytes, popped: Vec<(Bytes, Score)>) -> RESPValue {
+        encoding::array(vec![
+            encoding::bulk_string(key),
+            encoding::array(
+                popped
+                    .into_iter()
+                    .map(|(member, score)| {
+                        encoding::array(vec![
+                            encoding::bulk_string(member),
+                            encoding::bulk_string(score.to_string()),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ])
+    }
+
+    /// The reply of `LMPOP` and `BLMPOP`, the key followed by the popped elements.
+    pub fn list_multi_pop_reply(key: Bytes, popped: Vec<Bytes>) -> RESPValue {
+        encoding::array(vec![
+            encoding::bulk_string(key),
+            encoding::array(popped.into_iter().map(encoding::bulk_string).collect()),
+        ])
+    }
+
+    fn wrong_type() -> RESPValue {
+        encoding::simple_error(WRONG_TYPE_ERROR)
+    }
+
+    /// The reply to XINFO STREAM for the value at its key, which is a map for RESP3
+    /// connections.
+    pub fn stream_info(value: Option<&StoreValue>, is_resp3: bool) -> RESPValue {
+        match value {
+            Some(StoreValue::Stream(stream)) => encoding::map_or_array(
+                is_resp3,
+                vec![
+                    (
+                        encoding::bulk_string("length"),
+                        encoding::integer(stream.len() as i64),
+                    ),
+                    (
+                        encoding::bulk_string("last-generated-id"),
+                        encoding::bulk_string(stream.last_id().to_string()),
+                    ),
+                    (
+                        encoding::bulk_string("first-entry"),
+                        Self::stream_entry(stream.first_entry()),
+                    ),
+                    (
+                        encoding::bulk_string("last-entry"),
+                        Self::stream_entry(stream.last_entry()),
+                    ),
+                ],
+            ),
+            Some(_) => Self::wrong_type(),
+            None => encoding::simple_error("ERR no such key"),
+        }
+    }
+
+    fn stream_entry(entry: Option<(&StreamEntryId, &Vec<(Bytes, Bytes)>)>) -> RESPValue {
+        match entry {
+            Some((id, fields)) => encoding::array(vec![
+                encoding::bulk_string(id.to_string()),
+                encoding::array(
+                    fields
+                        .iter()
+                        .flat_map(|(field, value)| {
+                            [encoding::bulk_string(field), encoding::bulk_string(value)]
+                        })
+                        .collect(),
+                ),
+            ]),
+            None => encoding::null_bulk_string(),
+        }
+    }
+
+    /// An entry read by a consumer, with a null in place of its fields when it was
+    /// deleted since it was delivered.
+    fn delivered_entry((id, fields): DeliveredEntry) -> RESPValue {
+        match fields {
+            Some(fields) => Self::stream_entry(Some((&id, &fields))),
+            None => encoding::array(vec![
+                encoding::bulk_string(id.to_string()),
+                encoding::null_array(),
+            ]),
+        }
+    }
+
+    /// Sets a key to a value as is, replacing any previous value. This is how values
+    /// are loaded from an RDB file, so it does not count as a write.
+    pub fn insert(&mut self, db: usize, key: StoreKey, value: StoreValue) {
+        self.databases[db].insert(key, value);
+    }
+
+    /// Removes the expired keys of every database rather than waiting for a command to
+    /// touch them, returning how many were removed.
+    pub fn remove_expired(&mut self) -> usize {
+        let mut removed = 0;
+        for database in &mut self.databases {
+            let len = database.len();
+            database.retain(|_, value| !value.is_expired());
+            removed += len - database.len();
+        }
+
+        self.keyspace_stats.expired_keys += removed as u64;
+        removed
+    }
+
+    pub fn merge(&mut self, other: RedisStore) {
+        for (database, other_database) in self.databases.iter_mut().zip(other.databases) {
+            database.extend(other_database);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, SystemTime},
+    };
+
+    use bytes::Bytes;
+    use tokio::sync::mpsc;
+
+    use crate::redis::{
+        resp::{
+            command::{
+                ListEnd, MinOrMax, ObjectSection, RedisStoreCommand, SetOperation, XGroupSection,
+                XInfoSection,
+            },
+            encoding,
+        },
+        server::RedisWriteStream,
+    };
+
+    use super::{
+        sorted_set::Score,
+        stream::{StreamEntryId, StreamTrim, StreamTrimStrategy, XAddEntryId, XReadGroupId},
+        EncodingConfig, KeyspaceStats, RedisStore, StoreValue, WRONG_TYPE_ERROR,
+    };
+
+    #[tokio::test]
+    async fn round_trips_binary_values() {
+        let mut store = RedisStore::new(1);
+        let key = Bytes::from_static(b"\xffkey");
+        let value = Bytes::from_static(b"\x00\xff\xfe");
+        let set = RedisStoreCommand::Set {
+            key: key.clone(),
+            value,
+            px: None,
+        };
+        store
+            .handle(0, &set, RedisWriteStream::sink())
+            .await
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let get = RedisStoreCommand::Get { key };
+        store
+            .handle(0, &get, RedisWriteStream::new(tx))
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            Bytes::from_static(b"$3\r\n\x00\xff\xfe\r\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn removes_only_expired_keys() {
+        let mut store = RedisStore::new(2);
+        let now = Some(SystemTime::now());
+        for (db, key, px) in [(0, "expired", now), (0, "kept", None), (1, "expired", now)] {
+            let set = RedisStoreCommand::Set {
+                key: Bytes::from(key),
+                value: Bytes::from_static(b"value"),
+                px,
+            };
+            store
+                .handle(db, &set, RedisWriteStream::sink())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(store.remove_expired(), 2);
+        assert!(store.get(0, b"kept").is_some());
+        assert_eq!(store.remove_expired(), 0);
+    }
+
+    #[tokio::test]
+    async fn counts_keyspace_hits_and_misses() {
+        let mut store = RedisStore::new(1);
+        let key = |key: &'static str| Bytes::from_static(key.as_bytes());
+        let commands = [
+            RedisStoreCommand::Set {
+                key: key("string"),
+                value: key("value"),
+                px: None,
+            },
+            RedisStoreCommand::Set {
+                key: key("expired"),
+                value: key("value"),
+                px: Some(SystemTime::now()),
+            },
+            RedisStoreCommand::HSet {
+                key: key("hash"),
+                fields: vec![(key("field"), key("value"))],
+            },
+            RedisStoreCommand::Get { key: key("string") },
+            RedisStoreCommand::Get {
+                key: key("missing"),
+            },
+            RedisStoreCommand::Get {
+                key: key("expired"),
+            },
+            // a missing field still finds the key, like in Redis
+            RedisStoreCommand::HGet {
+                key: key("hash"),
+                field: key("missing"),
+            },
+            RedisStoreCommand::MGet {
+                keys: vec![key("string"), key("hash"), key("missing")],
+            },
+        ];
+        for command in &commands {
+            store
+                .handle(0, command, RedisWriteStream::sink())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            store.keyspace_stats(),
+            KeyspaceStats {
+                keyspace_hits: 4,
+                keyspace_misses: 3,
+                expired_keys: 1,
+                evicted_keys: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_only_writes_that_change_the_keyspace() {
+        let mut store = RedisStore::new(1);
+        let key = |key: &'static str| Bytes::from_static(key.as_bytes());
+        let sadd = |key: Bytes| RedisStoreCommand::SAdd {
+            key,
+            members: vec![Bytes::from("member")],
+        };
+        let zmpop = RedisStoreCommand::ZMPop {
+            keys: vec![key("zset")],
+            end: MinOrMax::Min,
+            count: None,
+        };
+        let commands = [
+            (sadd(key("set")), 1),
+            (sadd(key("set")), 1),
+            (
+                RedisStoreCommand::Set {
+                    key: key("string"),
+                    value: key("value"),
+                    px: None,
+                },
+                2,
+            ),
+            (sadd(key("string")), 2),
+            (
+                RedisStoreCommand::Del {
+                    keys: vec![key("missing")],
+                },
+                2,
+            ),
+            (
+                RedisStoreCommand::ZAdd {
+                    key: key("zset"),
+                    members: vec![(Score::new(1.0).unwrap(), key("member"))],
+                },
+                3,
+            ),
+            (zmpop.clone(), 4),
+            (zmpop, 4),
+        ];
+        for (command, dirty) in &commands {
+            store
+                .handle(0, command, RedisWriteStream::sink())
+                .await
+                .unwrap();
+            assert_eq!(store.dirty(), *dirty, "{command:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_keys_removed_after_expiring() {
+        let mut store = RedisStore::new(1);
+        let expires_at = SystemTime::now() + Duration::from_millis(10);
+        for key in ["read", "collected"] {
+            let set = RedisStoreCommand::Set {
+                key: Bytes::from(key),
+                value: Bytes::from_static(b"value"),
+                px: Some(expires_at),
+            };
+            store
+                .handle(0, &set, RedisWriteStream::sink())
+                .await
+                .unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let get = RedisStoreCommand::Get {
+            key: Bytes::from("read"),
+        };
+        store
+            .handle(0, &get, RedisWriteStream::new(tx))
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), Bytes::from_static(b"$-1\r\n"));
+        assert_eq!(store.keyspace_stats().expired_keys, 1);
+
+        // the expire cycle counts the keys it removes too
+        assert_eq!(store.remove_expired(), 1);
+        assert_eq!(store.keyspace_stats().expired_keys, 2);
+        assert_eq!(store.keyspace_stats().evicted_keys, 0);
+    }
+
+    #[tokio::test]
+    async fn trims_streams_in_batches_of_the_limit() {
+        let mut store = RedisStore::new(1);
+        let key = Bytes::from_static(b"stream");
+        for ms in 1..=5 {
+            let xadd = RedisStoreCommand::XAdd {
+                key: key.clone(),
+                entry_id: XAddEntryId::Explicit(StreamEntryId::new(ms, 0)),
+                no_mkstream: false,
+                trim: None,
+                fields: vec![(Bytes::from_static(b"field"), Bytes::from_static(b"value"))],
+            };
+            store
+                .handle(0, &xadd, RedisWriteStream::sink())
+                .await
+                .unwrap();
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let xtrim = RedisStoreCommand::XTrim {
+            key: key.clone(),
+            trim: StreamTrim {
+                strategy: StreamTrimStrategy::MaxLen(0),
+                approximate: true,
+                limit: Some(2),
+            },
+        };
+        for _ in 0..4 {
+            store
+                .handle(0, &xtrim, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        let mut replies = vec![];
+        while let Ok(reply) = rx.try_recv() {
+            replies.push(reply);
+        }
+        assert_eq!(replies, [":2\r\n", ":2\r\n", ":1\r\n", ":0\r\n"]);
+    }
+
+    #[tokio::test]
+    async fn replies_with_pending_entries_even_once_deleted() {
+        let mut store = RedisStore::new(1);
+        let key = Bytes::from_static(b"stream");
+        let group = Bytes::from_static(b"group");
+        let consumer = Bytes::from_static(b"consumer");
+        let read_group = |id| RedisStoreCommand::XReadGroup {
+            group: group.clone(),
+            consumer: consumer.clone(),
+            count: None,
+            no_ack: false,
+            keys: vec![key.clone()],
+            ids: vec![id],
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for command in [
+            RedisStoreCommand::XGroup {
+                section: XGroupSection::Create {
+                    key: key.clone(),
+                    group: group.clone(),
+                    id: None,
+                    mkstream: true,
+                },
+            },
+            read_group(XReadGroupId::New),
+            RedisStoreCommand::XAdd {
+                key: key.clone(),
+                entry_id: XAddEntryId::Explicit(StreamEntryId::new(1, 0)),
+                no_mkstream: false,
+                trim: None,
+                fields: vec![(Bytes::from_static(b"field"), Bytes::from_static(b"value"))],
+            },
+            read_group(XReadGroupId::New),
+            RedisStoreCommand::XTrim {
+                key: key.clone(),
+                trim: StreamTrim {
+                    strategy: StreamTrimStrategy::MaxLen(0),
+                    approximate: false,
+                    limit: None,
+                },
+            },
+            read_group(XReadGroupId::Pending(StreamEntryId::default())),
+            RedisStoreCommand::XAck {
+                key: key.clone(),
+                group: group.clone(),
+                ids: vec![StreamEntryId::new(1, 0)],
+            },
+            read_group(XReadGroupId::Pending(StreamEntryId::default())),
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        let mut replies = vec![];
+        while let Ok(reply) = rx.try_recv() {
+            replies.push(reply);
+        }
+        assert_eq!(
+            replies,
+            [
+                "+OK\r\n",
+                "*-1\r\n",
+                "$3\r\n1-0\r\n",
+                "*1\r\n*2\r\n$6\r\nstream\r\n*1\r\n*2\r\n$3\r\n1-0\r\n*2\r\n$5\r\nfield\r\n$5\r\nvalue\r\n",
+                ":1\r\n",
+                "*1\r\n*2\r\n$6\r\nstream\r\n*1\r\n*2\r\n$3\r\n1-0\r\n*-1\r\n",
+                ":1\r\n",
+                "*1\r\n*2\r\n$6\r\nstream\r\n*0\r\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn adds_stream_entries_only_over_expired_keys_of_another_type() {
+        let mut store = RedisStore::new(1);
+        let xadd = |key: &'static str| RedisStoreCommand::XAdd {
+            key: Bytes::from(key),
+            entry_id: XAddEntryId::Explicit(StreamEntryId::new(1, 0)),
+            no_mkstream: true,
+            trim: None,
+            fields: vec![(Bytes::from_static(b"field"), Bytes::from_static(b"value"))],
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for command in [
+            RedisStoreCommand::Set {
+                key: Bytes::from_static(b"string"),
+                value: Bytes::from_static(b"value"),
+                px: None,
+            },
+            RedisStoreCommand::Set {
+                key: Bytes::from_static(b"expired"),
+                value: Bytes::from_static(b"value"),
+                px: Some(SystemTime::now()),
+            },
+            xadd("string"),
+            xadd("expired"),
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        let mut replies = vec![];
+        while let Ok(reply) = rx.try_recv() {
+            replies.push(reply);
+        }
+        assert_eq!(
+            replies,
+            [
+                "+OK\r\n".to_string(),
+                "+OK\r\n".to_string(),
+                format!("-{WRONG_TYPE_ERROR}\r\n"),
+                "$-1\r\n".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn gets_null_for_values_mget_cannot_read() {
+        let mut store = RedisStore::new(1);
+        for command in [
+            RedisStoreCommand::Set {
+                key: Bytes::from_static(b"string"),
+                value: Bytes::from_static(b"value"),
+                px: None,
+            },
+            RedisStoreCommand::XAdd {
+                key: Bytes::from_static(b"stream"),
+                entry_id: XAddEntryId::Auto,
+                no_mkstream: false,
+                trim: None,
+                fields: vec![(Bytes::from_static(b"field"), Bytes::from_static(b"value"))],
+            },
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::sink())
+                .await
+                .unwrap();
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mget = RedisStoreCommand::MGet {
+            keys: vec![
+                Bytes::from_static(b"string"),
+                Bytes::from_static(b"stream"),
+                Bytes::from_static(b"missing"),
+            ],
+        };
+        store
+            .handle(0, &mget, RedisWriteStream::new(tx.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            Bytes::from(encoding::array(vec![
+                encoding::bulk_string("value"),
+                encoding::null_bulk_string(),
+                encoding::null_bulk_string(),
+            ]))
+        );
+
+        let get = RedisStoreCommand::Get {
+            key: Bytes::from_static(b"stream"),
+        };
+        store
+            .handle(0, &get, RedisWriteStream::new(tx))
+            .await
+            .unwrap();
+        assert!(rx.recv().await.unwrap().starts_with(b"-WRONGTYPE"));
+    }
+
+    #[tokio::test]
+    async fn replies_to_type_mismatches_with_the_exact_redis_error() {
+        let mut store = RedisStore::new(1);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let sadd = RedisStoreCommand::SAdd {
+            key: Bytes::from_static(b"set"),
+            members: vec![Bytes::from_static(b"member")],
+        };
+        store
+            .handle(0, &sadd, RedisWriteStream::sink())
+            .await
+            .unwrap();
+
+        for command in [
+            RedisStoreCommand::Get {
+                key: Bytes::from_static(b"set"),
+            },
+            RedisStoreCommand::XInfo {
+                section: XInfoSection::Stream {
+                    key: Bytes::from_static(b"set"),
+                },
+            },
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+            assert_eq!(
+                rx.recv().await.unwrap(),
+                Bytes::from_static(
+                    b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+                )
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_set_intersections() {
+        let mut store = RedisStore::new(1);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let bytes = |values: &[&'static str]| -> Vec<Bytes> {
+            values
+                .iter()
+                .map(|value| Bytes::from_static(value.as_bytes()))
+                .collect()
+        };
+
+        for command in [
+            RedisStoreCommand::SAdd {
+                key: Bytes::from_static(b"a"),
+                members: bytes(&["1", "2"]),
+            },
+            RedisStoreCommand::SAdd {
+                key: Bytes::from_static(b"b"),
+                members: bytes(&["2", "3"]),
+            },
+            RedisStoreCommand::SetOperationStore {
+                operation: SetOperation::Inter,
+                destination: Bytes::from_static(b"dst"),
+                keys: bytes(&["a", "b"]),
+            },
+            RedisStoreCommand::SetOperationStore {
+                operation: SetOperation::Inter,
+                destination: Bytes::from_static(b"a"),
+                keys: bytes(&["a", "missing"]),
+            },
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(rx.recv().await.unwrap(), &b":2\r\n"[..]);
+        assert_eq!(rx.recv().await.unwrap(), &b":2\r\n"[..]);
+        assert_eq!(rx.recv().await.unwrap(), &b":1\r\n"[..]);
+        assert_eq!(rx.recv().await.unwrap(), &b":0\r\n"[..]);
+        assert!(matches!(
+            store.get(0, b"dst"),
+            Some(StoreValue::Set(members)) if members.len() == 1 && members.contains(&b"2"[..])
+        ));
+        assert!(store.get(0, b"a").is_none());
+    }
+
+    #[tokio::test]
+    async fn counts_set_intersections_up_to_the_limit() {
+        let mut store = RedisStore::new(1);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let bytes = |values: &[&'static str]| -> Vec<Bytes> {
+            values
+                .iter()
+                .map(|value| Bytes::from_static(value.as_bytes()))
+                .collect()
+        };
+
+        for command in [
+            RedisStoreCommand::SAdd {
+                key: Bytes::from_static(b"a"),
+                members: bytes(&["1", "2", "3"]),
+            },
+            RedisStoreCommand::SAdd {
+                key: Bytes::from_static(b"b"),
+                members: bytes(&["1", "2", "3", "4"]),
+            },
+            RedisStoreCommand::Set {
+                key: Bytes::from_static(b"string"),
+                value: Bytes::from_static(b"value"),
+                px: None,
+            },
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::sink())
+                .await
+                .unwrap();
+        }
+
+        for (keys, limit, reply) in [
+            (&["a", "b"][..], None, &b":3\r\n"[..]),
+            (&["a", "b"], Some(2), b":2\r\n"),
+            (&["a", "missing"], None, b":0\r\n"),
+            (&["a", "string"], None, b"-WRONGTYPE"),
+        ] {
+            let sintercard = RedisStoreCommand::SInterCard {
+                keys: bytes(keys),
+                limit,
+            };
+            store
+                .handle(0, &sintercard, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+            assert!(rx.recv().await.unwrap().starts_with(reply));
+        }
+    }
+
+    #[tokio::test]
+    async fn pops_from_the_first_non_empty_sorted_set() {
+        let mut store = RedisStore::new(1);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let zadd = RedisStoreCommand::ZAdd {
+            key: Bytes::from_static(b"zset"),
+            members: [(1.0, "a"), (2.0, "b"), (3.0, "c")]
+                .into_iter()
+                .map(|(score, member)| (Score::new(score).unwrap(), Bytes::from(member)))
+                .collect(),
+        };
+        store
+            .handle(0, &zadd, RedisWriteStream::sink())
+            .await
+            .unwrap();
+
+        let zmpop = |end, count| RedisStoreCommand::ZMPop {
+            keys: vec![Bytes::from_static(b"missing"), Bytes::from_static(b"zset")],
+            end,
+            count,
+        };
+        for command in [
+            zmpop(MinOrMax::Max, Some(2)),
+            zmpop(MinOrMax::Min, Some(5)),
+            zmpop(MinOrMax::Min, None),
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        let popped = |members: &[(&'static str, &'static str)]| {
+            Bytes::from(encoding::array(vec![
+                encoding::bulk_string("zset"),
+                encoding::array(
+                    members
+                        .iter()
+                        .map(|(member, score)| {
+                            encoding::array(vec![
+                                encoding::bulk_string(member),
+                                encoding::bulk_string(score),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ]))
+        };
+        assert_eq!(rx.recv().await.unwrap(), popped(&[("c", "3"), ("b", "2")]));
+        assert_eq!(rx.recv().await.unwrap(), popped(&[("a", "1")]));
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            Bytes::from(encoding::null_array())
+        );
+        assert!(store.get(0, b"zset").is_none());
+    }
+
+    #[tokio::test]
+    async fn moves_members_between_sets() {
+        let mut store = RedisStore::new(1);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let smove = |member: &'static str| RedisStoreCommand::SMove {
+            source: Bytes::from_static(b"source"),
+            destination: Bytes::from_static(b"destination"),
+            member: Bytes::from_static(member.as_bytes()),
+        };
+
+        for command in [
+            RedisStoreCommand::SAdd {
+                key: Bytes::from_static(b"source"),
+                members: vec![Bytes::from_static(b"member")],
+            },
+            smove("member"),
+            smove("member"),
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(rx.recv().await.unwrap(), &b":1\r\n"[..]);
+        assert_eq!(rx.recv().await.unwrap(), &b":1\r\n"[..]);
+        assert_eq!(rx.recv().await.unwrap(), &b":0\r\n"[..]);
+        assert!(store.get(0, b"source").is_none());
+        assert!(matches!(
+            store.get(0, b"destination"),
+            Some(StoreValue::Set(members)) if members.contains(&b"member"[..])
+        ));
+    }
+
+    #[tokio::test]
+    async fn picks_random_hash_fields() {
+        let mut store = RedisStore::new(1);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let hrandfield = |key: &'static str, count, with_values| RedisStoreCommand::HRandField {
+            key: Bytes::from_static(key.as_bytes()),
+            count,
+            with_values,
+        };
+
+        for command in [
+            RedisStoreCommand::HSet {
+                key: Bytes::from_static(b"hash"),
+                fields: vec![(Bytes::from_static(b"field"), Bytes::from_static(b"value"))],
+            },
+            hrandfield("hash", None, false),
+            hrandfield("hash", Some(5), true),
+            hrandfield("hash", Some(-2), false),
+            hrandfield("missing", None, false),
+            hrandfield("missing", Some(-2), true),
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(rx.recv().await.unwrap(), &b":1\r\n"[..]);
+        assert_eq!(rx.recv().await.unwrap(), &b"$5\r\nfield\r\n"[..]);
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            &b"*2\r\n$5\r\nfield\r\n$5\r\nvalue\r\n"[..]
+        );
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            &b"*2\r\n$5\r\nfield\r\n$5\r\nfield\r\n"[..]
+        );
+        assert_eq!(rx.recv().await.unwrap(), &b"$-1\r\n"[..]);
+        assert_eq!(rx.recv().await.unwrap(), &b"*0\r\n"[..]);
+    }
+
+    #[test]
+    fn reports_string_encodings() {
+        for (value, encoding) in [
+            (&b"12345"[..], "int"),
+            (b"-9223372036854775808", "int"),
+            (b"hello", "embstr"),
+            (&[b'x'; 44], "embstr"),
+            (&[b'x'; 45], "raw"),
+        ] {
+            let value = StoreValue::String {
+                value: Bytes::copy_from_slice(value),
+                expiration: None,
+            };
+            assert_eq!(value.encoding(&EncodingConfig::default()), encoding);
+        }
+    }
+
+    #[tokio::test]
+    async fn converts_sets_as_they_outgrow_intset_and_listpack() {
+        let mut store = RedisStore::new(1);
+        store.encoding_config = EncodingConfig {
+            set_max_intset_entries: 2,
+            set_max_listpack_entries: 3,
+            set_max_listpack_value: 4,
+            ..EncodingConfig::default()
+        };
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for (key, member, encoding) in [
+            ("set", "1", "intset"),
+            ("set", "2", "intset"),
+            // a member that is not an integer turns it into a listpack
+            ("set", "a", "listpack"),
+            ("set", "b", "hashtable"),
+            ("ints", "1", "intset"),
+            ("ints", "2", "intset"),
+            ("ints", "3", "listpack"),
+            ("long", "abcde", "hashtable"),
+        ] {
+            let key = Bytes::from_static(key.as_bytes());
+            let sadd = RedisStoreCommand::SAdd {
+                key: key.clone(),
+                members: vec![Bytes::from_static(member.as_bytes())],
+            };
+            store
+                .handle(0, &sadd, RedisWriteStream::sink())
+                .await
+                .unwrap();
+            let object = RedisStoreCommand::Object {
+                section: ObjectSection::Encoding { key },
+            };
+            store
+                .handle(0, &object, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+            assert_eq!(
+                rx.recv().await.unwrap(),
+                format!("${}\r\n{encoding}\r\n", encoding.len())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn turns_lists_into_quicklists_once_they_outgrow_a_node() {
+        let mut store = RedisStore::new(1);
+        store.encoding_config.list_max_listpack_size = 3;
+        let key = Bytes::from_static(b"list");
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for (end, encoding) in [
+            (ListEnd::Right, "listpack"),
+            (ListEnd::Left, "listpack"),
+            (ListEnd::Right, "listpack"),
+            (ListEnd::Left, "quicklist"),
+        ] {
+            let push = RedisStoreCommand::ListPush {
+                key: key.clone(),
+                end,
+                elements: vec![Bytes::from_static(b"element")],
+            };
+            store
+                .handle(0, &push, RedisWriteStream::sink())
+                .await
+                .unwrap();
+            let object = RedisStoreCommand::Object {
+                section: ObjectSection::Encoding { key: key.clone() },
+            };
+            store
+                .handle(0, &object, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+            assert_eq!(
+                rx.recv().await.unwrap(),
+                format!("${}\r\n{encoding}\r\n", encoding.len())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn pushes_and_pops_at_either_end_of_lists() {
+        let mut store = RedisStore::new(1);
+        let key = |key: &'static str| Bytes::from_static(key.as_bytes());
+        let elements = |elements: &[&'static str]| elements.iter().map(|e| key(e)).collect();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for command in [
+            RedisStoreCommand::ListPush {
+                key: key("list"),
+                end: ListEnd::Right,
+                elements: elements(&["c", "d"]),
+            },
+            // pushed one at a time, so they end up in reverse
+            RedisStoreCommand::ListPush {
+                key: key("list"),
+                end: ListEnd::Left,
+                elements: elements(&["b", "a"]),
+            },
+            RedisStoreCommand::LRange {
+                key: key("list"),
+                start: 0,
+                stop: -1,
+            },
+            RedisStoreCommand::ListPop {
+                key: key("list"),
+                end: ListEnd::Left,
+                count: None,
+            },
+            RedisStoreCommand::ListPop {
+                key: key("list"),
+                end: ListEnd::Right,
+                count: Some(5),
+            },
+            RedisStoreCommand::LLen { key: key("list") },
+            RedisStoreCommand::ListPop {
+                key: key("list"),
+                end: ListEnd::Left,
+                count: None,
+            },
+            RedisStoreCommand::ListPop {
+                key: key("list"),
+                end: ListEnd::Left,
+                count: Some(1),
+            },
+            RedisStoreCommand::Type { key: key("list") },
+            RedisStoreCommand::Set {
+                key: key("string"),
+                value: key("value"),
+                px: None,
+            },
+            RedisStoreCommand::ListPush {
+                key: key("string"),
+                end: ListEnd::Left,
+                elements: elements(&["a"]),
+            },
+            RedisStoreCommand::LRange {
+                key: key("string"),
+                start: 0,
+                stop: -1,
+            },
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        let mut replies = vec![];
+        while let Ok(reply) = rx.try_recv() {
+            replies.push(reply);
+        }
+        let wrong_type = format!("-{WRONG_TYPE_ERROR}\r\n");
+        assert_eq!(
+            replies,
+            [
+                ":2\r\n",
+                ":4\r\n",
+                "*4\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n$1\r\nd\r\n",
+                "$1\r\na\r\n",
+                "*3\r\n$1\r\nd\r\n$1\r\nc\r\n$1\r\nb\r\n",
+                ":0\r\n",
+                "$-1\r\n",
+                "*-1\r\n",
+                "+none\r\n",
+                "+OK\r\n",
+                &wrong_type,
+                &wrong_type,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn pops_from_the_first_list_that_is_not_empty() {
+        let mut store = RedisStore::new(1);
+        let key = |key: &'static str| Bytes::from_static(key.as_bytes());
+        let lmpop = |keys: &[&'static str], end, count| RedisStoreCommand::LMPop {
+            keys: keys.iter().map(|k| key(k)).collect(),
+            end,
+            count,
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for command in [
+            RedisStoreCommand::ListPush {
+                key: key("list"),
+                end: ListEnd::Right,
+                elements: vec![key("a"), key("b"), key("c")],
+            },
+            RedisStoreCommand::Set {
+                key: key("string"),
+                value: key("value"),
+                px: None,
+            },
+            lmpop(&["missing", "list"], ListEnd::Right, Some(2)),
+            lmpop(&["missing", "list"], ListEnd::Left, Some(5)),
+            lmpop(&["missing", "list"], ListEnd::Left, None),
+            lmpop(&["string", "list"], ListEnd::Left, None),
+        ] {
+            store
+                .handle(0, &command, RedisWriteStream::new(tx.clone()))
+                .await
+                .unwrap();
+        }
+
+        let mut replies = vec![];
+        while let Ok(reply) = rx.try_recv() {
+            replies.push(reply);
+        }
+        assert_eq!(
+            replies,
+            [
+                ":3\r\n".to_string(),
+                "+OK\r\n".to_string(),
+                "*2\r\n$4\r\nlist\r\n*2\r\n$1\r\nc\r\n$1\r\nb\r\n".to_string(),
+                "*2\r\n$4\r\nlist\r\n*1\r\n$1\r\na\r\n".to_string(),
+                "*-1\r\n".to_string(),
+                format!("-{WRONG_TYPE_ERROR}\r\n"),
+            ]
+        );
+        assert_eq!(store.get(0, b"list"), None);
+    }
+
+    #[test]
+    fn reports_hashtable_once_a_hash_outgrows_either_threshold() {
+        let config = EncodingConfig {
+            hash_max_listpack_entries: 2,
+            hash_max_listpack_value: 4,
+            ..EncodingConfig::default()
+        };
+        let mut fields = HashMap::new();
+        for (field, value, encoding) in [
+            ("a", "1", "listpack"),
+            ("b", "1234", "listpack"),
+            ("c", "1", "hashtable"),
+        ] {
+            fields.insert(Bytes::from(field), Bytes::from(value));
+            let hash = StoreValue::Hash(fields.clone());
+            assert_eq!(hash.encoding(&config), encoding);
+        }
+
+        let long_value = HashMap::from([(Bytes::from("a"), Bytes::from("12345"))]);
+        assert_eq!(StoreValue::Hash(long_value).encoding(&config), "hashtable");
+        let long_field = HashMap::from([(Bytes::from("abcde"), Bytes::from("1"))]);
+        assert_eq!(StoreValue::Hash(long_field).encoding(&config), "hashtable");
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use crate::redis::resp::command::SetOperation;
+
+use super::{is_integer, Database, EncodingConfig, StoreValue};
+
+/// The encoding Redis would use for a set. Like in Redis, a set only ever moves on to a
+/// bigger encoding, so removing members or lowering the thresholds keeps the one it has.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum SetEncoding {
+    #[default]
+    Intset,
+    Listpack,
+    Hashtable,
+}
+
+/// The members of a set, along with the encoding Redis would use for it. Sets are
+/// always stored the same way, so the encoding is only tracked, and it is updated as
+/// members are added so that it never has to be worked out from every member.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Set {
+    members: HashSet<Bytes>,
+    encoding: SetEncoding,
+}
+
+impl Set {
+    /// A set of `members`, encoded as if they were added one at a time.
+    pub fn from_members(members: impl IntoIterator<Item = Bytes>, config: &EncodingConfig) -> Self {
+        let mut set = Self::default();
+        for member in members {
+            set.insert(member, config);
+        }
+
+        set
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.members.contains(member)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Bytes> {
+        self.members.iter()
+    }
+
+    /// Adds the member, returning whether it was not in the set yet.
+    pub fn insert(&mut self, member: Bytes, config: &EncodingConfig) -> bool {
+        let is_integer_member = is_integer(&member);
+        let member_len = member.len();
+        if !self.members.insert(member) {
+            return false;
+        }
+
+        self.encoding = match self.encoding {
+            SetEncoding::Intset
+                if is_integer_member && self.len() <= config.set_max_intset_entries =>
+            {
+                SetEncoding::Intset
+            }
+            // the other members are integers, which only have to be measured this once
+            SetEncoding::Intset => {
+                let fits = self.len() <= config.set_max_listpack_entries
+                    && self
+                        .members
+                        .iter()
+                        .all(|member| member.len() <= config.set_max_listpack_value);
+                if fits {
+                    SetEncoding::Listpack
+                } else {
+                    SetEncoding::Hashtable
+                }
+            }
+            SetEncoding::Listpack
+                if self.len() > config.set_max_listpack_entries
+                    || member_len > config.set_max_listpack_value =>
+            {
+                SetEncoding::Hashtable
+            }
+            encoding => encoding,
+        };
+
+        true
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        self.members.remove(member)
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self.encoding {
+            SetEncoding::Intset => "intset",
+            SetEncoding::Listpack => "listpack",
+            SetEncoding::Hashtable => "hashtable",
+        }
+    }
+}
+
+/// Combines the sets stored at `keys`, in order, where missing keys count as empty
+/// sets. Returns `None` when one of the keys holds a value that is not a set.
+pub fn combine(
+    items: &Database,
+    keys: &[Bytes],
+    operation: SetOperation,
+) -> Option<HashSet<Bytes>> {
+    let empty = Set::default();
+    let mut sets = Vec::with_capacity(keys.len());
+    for key in keys {
+        match items.get(key) {
+            Some(StoreValue::Set(members)) => sets.push(members),
+            Some(value) if !value.is_expired() => return None,
+            _ => sets.push(&empty),
+        }
+    }
+
+    let Some((first, rest)) = sets.split_first() else {
+        return Some(HashSet::new());
+    };
+
+    let result = match operation {
+        SetOperation::Inter => first
+            .iter()
+            .filter(|member| rest.iter().all(|set| set.contains(member)))
+            .cloned()
+            .collect(),
+        SetOperation::Union => sets.iter().flat_map(|set| set.iter()).cloned().collect(),
+        SetOperation::Diff => first
+            .iter()
+            .filter(|member| !rest.iter().any(|set| set.contains(member)))
+            .cloned()
+            .collect(),
+    };
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bytes::Bytes;
+
+    use crate::redis::{
+        resp::command::SetOperation,
+        store::{Database, EncodingConfig},
+    };
+
+    use super::{combine, Set, StoreValue};
+
+    fn members(members: &[&'static str]) -> HashSet<Bytes> {
+        members
+            .iter()
+            .map(|member| Bytes::from_static(member.as_bytes()))
+            .collect()
+    }
+
+    fn set(members: &[&'static str]) -> Set {
+        Set::from_members(
+            members
+                .iter()
+                .map(|member| Bytes::from_static(member.as_bytes())),
+            &EncodingConfig::default(),
+        )
+    }
+
+    #[test]
+    fn combines_sets_treating_missing_keys_as_empty() {
+        let mut items = Database::default();
+        items.insert(Bytes::from_static(b"a"), StoreValue::Set(set(&["1", "2"])));
+        items.insert(Bytes::from_static(b"b"), StoreValue::Set(set(&["2", "3"])));
+        items.insert(
+            Bytes::from_static(b"string"),
+            StoreValue::String {
+                value: Bytes::from_static(b"value"),
+                expiration: None,
+            },
+        );
+
+        let keys = |names: &[&'static str]| -> Vec<Bytes> {
+            names
+                .iter()
+                .map(|name| Bytes::from_static(name.as_bytes()))
+                .collect()
+        };
+
+        for (operation, names, expected) in [
+            (SetOperation::Inter, &["a", "b"][..], &["2"][..]),
+            (SetOperation::Union, &["a", "b"], &["1", "2", "3"]),
+            (SetOperation::Diff, &["a", "b"], &["1"]),
+            (SetOperation::Inter, &["a", "missing"], &[]),
+            (SetOperation::Diff, &["a", "missing"], &["1", "2"]),
+        ] {
+            assert_eq!(
+                combine(&items, &keys(names), operation),
+                Some(members(expected))
+            );
+        }
+
+        assert_eq!(
+            combine(&items, &keys(&["a", "string"]), SetOperation::Union),
+            None
+        );
+    }
+
+    #[test]
+    fn moves_on_to_bigger_encodings_but_never_back() {
+        let config = EncodingConfig {
+            set_max_intset_entries: 2,
+            set_max_listpack_entries: 3,
+            set_max_listpack_value: 4,
+            ..EncodingConfig::default()
+        };
+        let mut set = Set::default();
+        for (member, encoding) in [
+            ("1", "intset"),
+            ("2", "intset"),
+            ("a", "listpack"),
+            ("b", "hashtable"),
+        ] {
+            set.insert(Bytes::from(member), &config);
+            assert_eq!(set.encoding(), encoding);
+        }
+
+        set.remove(b"a");
+        set.remove(b"b");
+        assert_eq!(set.encoding(), "hashtable");
+
+        let set = Set::from_members(["1", "2", "3", "4"].map(Bytes::from), &config);
+        assert_eq!(set.encoding(), "hashtable");
+        let mut listpack = Set::from_members(["a"].map(Bytes::from), &config);
+        assert_eq!(listpack.encoding(), "listpack");
+        listpack.insert(Bytes::from("abcde"), &config);
+        assert_eq!(listpack.encoding(), "hashtable");
+    }
+}
@@ -0,0 +1,317 @@
+use bytes::Bytes;
+
+use super::{geo::SortOrder, sorted_set::Score, Database, StoreValue};
+
+/// The options of `SORT`, which sorts the elements of a list or the members of a set or
+/// sorted set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sort {
+    /// The pattern of the keys holding the weights to sort by. A pattern without `*`
+    /// leaves the members unsorted.
+    pub by: Option<Bytes>,
+    /// The offset and count of the members to return, where a negative count returns
+    /// every member from the offset on.
+    pub limit: Option<(i64, i64)>,
+    /// The patterns of the values returned instead of the members, where `#` is the
+    /// member itself.
+    pub get: Vec<Bytes>,
+    pub order: SortOrder,
+    /// Whether members are compared as strings rather than as numbers.
+    pub alpha: bool,
+    /// The key to store the result in as a list, instead of replying with it.
+    pub store: Option<Bytes>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SortError {
+    WrongType,
+    /// Sorting by number found a weight that is not one.
+    NotNumber,
+}
+
+impl Sort {
+    /// The elements of the list, set or sorted set at the key, sorted and limited as
+    /// requested and replaced by what the `GET` patterns point to. Patterns pointing to
+    /// nothing give `None`.
+    pub fn sort(&self, items: &Database, key: &[u8]) -> Result<Vec<Option<Bytes>>, SortError> {
+        let mut members = match items.get(key) {
+            Some(StoreValue::List(list)) => list.iter().collect::<Vec<_>>(),
+            Some(StoreValue::Set(members)) => members.iter().collect(),
+            Some(StoreValue::SortedSet(sorted_set)) => {
+                sorted_set.iter().map(|(member, _)| member).collect()
+            }
+            Some(_) => return Err(SortError::WrongType),
+            None => vec![],
+        };
+
+        let is_sorted = self.by.as_ref().is_none_or(|by| by.contains(&b'*'));
+        if is_sorted {
+            members = self.sorted(items, members)?;
+        } else if self.order == SortOrder::Descending {
+            members.reverse();
+        }
+
+        let (offset, count) = self.limit.unwrap_or((0, -1));
+        let window = members
+            .into_iter()
+            .skip(usize::try_from(offset).unwrap_or(0))
+            .take(usize::try_from(count).unwrap_or(usize::MAX));
+
+        if self.get.is_empty() {
+            return Ok(window.map(|member| Some(member.clone())).collect());
+        }
+
+        Ok(window
+            .flat_map(|member| {
+                self.get
+                    .iter()
+                    .map(move |pattern| lookup(items, pattern, member))
+            })
+            .collect())
+    }
+
+    /// Sorts the members by their weights, or by themselves without `BY`. Like in Redis,
+    /// members with equal weights are ordered by member, and a missing weight counts as
+    /// the lowest string or as 0.
+    fn sorted<'a>(
+        &self,
+        items: &Database,
+        members: Vec<&'a Bytes>,
+    ) -> Result<Vec<&'a Bytes>, SortError> {
+        let mut weighted = members
+            .into_iter()
+            .map(|member| {
+                let weight = match &self.by {
+                    Some(by) => lookup(items, by, member),
+                    None => Some(member.clone()),
+                };
+                let score = match (&weight, self.alpha) {
+                    (_, true) => None,
+                    (Some(weight), false) => {
+                        Some(Score::parse(weight).ok_or(SortError::NotNumber)?)
+                    }
+                    (None, false) => Score::new(0.0),
+                };
+
+                Ok((member, weight, score))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        weighted.sort_by(
+            |(member, weight, score), (other, other_weight, other_score)| {
+                let ordering = if self.alpha {
+                    weight.cmp(other_weight)
+                } else {
+                    score.cmp(other_score)
+                }
+                .then_with(|| member.cmp(other));
+
+                match self.order {
+                    SortOrder::Ascending => ordering,
+                    SortOrder::Descending => ordering.reverse(),
+                }
+            },
+        );
+
+        Ok(weighted.into_iter().map(|(member, ..)| member).collect())
+    }
+}
+
+/// The value a `BY` or `GET` pattern points to for a member. The first `*` is replaced
+/// by the member to get the key, which has to hold a string, or a hash when the pattern
+/// ends with `->field`.
+fn lookup(items: &Database, pattern: &[u8], member: &Bytes) -> Option<Bytes> {
+    if pattern == b"#" {
+        return Some(member.clone());
+    }
+
+    let star = pattern.iter().position(|byte| *byte == b'*')?;
+    let arrow = pattern[star..]
+        .windows(2)
+        .position(|window| window == b"->")
+        .map(|arrow| star + arrow)
+        .filter(|arrow| arrow + 2 < pattern.len());
+    let (key_pattern, field) = match arrow {
+        Some(arrow) => (&pattern[..arrow], Some(&pattern[arrow + 2..])),
+        None => (pattern, None),
+    };
+
+    let mut key = Vec::with_capacity(key_pattern.len() + member.len());
+    key.extend_from_slice(&key_pattern[..star]);
+    key.extend_from_slice(member);
+    key.extend_from_slice(&key_pattern[star + 1..]);
+    match (items.get(&key[..])?, field) {
+        (value, _) if value.is_expired() => None,
+        (StoreValue::String { value, .. }, None) => Some(value.clone()),
+        (StoreValue::Hash(fields), Some(field)) => fields.get(field).cloned(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+
+    use crate::redis::{
+        resp::command::ListEnd,
+        store::{
+            geo::SortOrder,
+            list::List,
+            set::Set,
+            sorted_set::{Score, SortedSet},
+            Database, EncodingConfig, StoreValue,
+        },
+    };
+
+    use super::{Sort, SortError};
+
+    fn string(value: &'static str) -> StoreValue {
+        StoreValue::String {
+            value: Bytes::from_static(value.as_bytes()),
+            expiration: None,
+        }
+    }
+
+    fn sort(items: &Database, options: Sort) -> Result<Vec<Option<Bytes>>, SortError> {
+        options.sort(items, b"key")
+    }
+
+    fn values(values: &[Option<&'static str>]) -> Result<Vec<Option<Bytes>>, SortError> {
+        Ok(values
+            .iter()
+            .map(|value| value.map(|value| Bytes::from_static(value.as_bytes())))
+            .collect())
+    }
+
+    fn options() -> Sort {
+        Sort {
+            by: None,
+            limit: None,
+            get: vec![],
+            order: SortOrder::Ascending,
+            alpha: false,
+            store: None,
+        }
+    }
+
+    #[test]
+    fn sorts_members_by_number_or_by_string() {
+        let mut items = Database::default();
+        let members = ["10", "9", "-1.5", "100"];
+        items.insert(
+            Bytes::from_static(b"key"),
+            StoreValue::Set(Set::from_members(
+                members.map(Bytes::from),
+                &EncodingConfig::default(),
+            )),
+        );
+
+        assert_eq!(
+            sort(&items, options()),
+            values(&[Some("-1.5"), Some("9"), Some("10"), Some("100")])
+        );
+        let alpha = Sort {
+            alpha: true,
+            order: SortOrder::Descending,
+            limit: Some((1, 2)),
+            ..options()
+        };
+        assert_eq!(sort(&items, alpha), values(&[Some("100"), Some("10")]));
+        let past_the_end = Sort {
+            limit: Some((10, -1)),
+            ..options()
+        };
+        assert_eq!(sort(&items, past_the_end), Ok(vec![]));
+
+        items.insert(
+            Bytes::from_static(b"key"),
+            StoreValue::Set(Set::from_members(
+                [Bytes::from_static(b"a")],
+                &EncodingConfig::default(),
+            )),
+        );
+        assert_eq!(sort(&items, options()), Err(SortError::NotNumber));
+        items.insert(Bytes::from_static(b"key"), string("value"));
+        assert_eq!(sort(&items, options()), Err(SortError::WrongType));
+        assert_eq!(Sort::sort(&options(), &items, b"missing"), Ok(vec![]));
+    }
+
+    #[test]
+    fn sorts_by_and_gets_other_keys() {
+        let mut items = Database::default();
+        let mut sorted_set = SortedSet::default();
+        for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 3.0)] {
+            sorted_set.insert(Bytes::from(member), Score::new(score).unwrap());
+        }
+
+        items.insert(
+            Bytes::from_static(b"key"),
+            StoreValue::SortedSet(sorted_set),
+        );
+        items.insert(Bytes::from_static(b"weight_a"), string("3"));
+        items.insert(Bytes::from_static(b"weight_b"), string("1"));
+        items.insert(Bytes::from_static(b"name_a"), string("alice"));
+        items.insert(
+            Bytes::from_static(b"hash_b"),
+            StoreValue::Hash(HashMap::from([(
+                Bytes::from_static(b"field"),
+                Bytes::from_static(b"bob"),
+            )])),
+        );
+
+        // c has no weight, so it counts as 0
+        let by = Sort {
+            by: Some(Bytes::from_static(b"weight_*")),
+            get: ["#", "name_*", "hash_*->field"]
+                .into_iter()
+                .map(Bytes::from)
+                .collect(),
+            ..options()
+        };
+        assert_eq!(
+            sort(&items, by),
+            values(&[
+                Some("c"),
+                None,
+                None,
+                Some("b"),
+                None,
+                Some("bob"),
+                Some("a"),
+                Some("alice"),
+                None,
+            ])
+        );
+
+        // a pattern without `*` keeps the order of the sorted set or list
+        let unsorted = Sort {
+            by: Some(Bytes::from_static(b"nosort")),
+            order: SortOrder::Descending,
+            ..options()
+        };
+        assert_eq!(
+            sort(&items, unsorted.clone()),
+            values(&[Some("c"), Some("b"), Some("a")])
+        );
+
+        let mut list = List::default();
+        for element in ["b", "c", "a"] {
+            list.push(ListEnd::Right, Bytes::from(element), -2);
+        }
+        items.insert(Bytes::from_static(b"key"), StoreValue::List(list));
+        assert_eq!(
+            sort(&items, unsorted),
+            values(&[Some("a"), Some("c"), Some("b")])
+        );
+        let alpha = Sort {
+            alpha: true,
+            ..options()
+        };
+        assert_eq!(
+            sort(&items, alpha),
+            values(&[Some("a"), Some("b"), Some("c")])
+        );
+    }
+}
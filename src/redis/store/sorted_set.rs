@@ -0,0 +1,126 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+};
+
+use bytes::Bytes;
+
+use crate::redis::resp::command::MinOrMax;
+
+/// The score of a sorted set member. Scores are never NaN, so they are totally ordered.
+#[derive(Debug, Clone, Copy)]
+pub struct Score(f64);
+
+impl Score {
+    pub fn new(score: f64) -> Option<Self> {
+        (!score.is_nan()).then_some(Self(score))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        Self::new(std::str::from_utf8(bytes).ok()?.parse::<f64>().ok()?)
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Members ordered by score, and by member for equal scores.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, Score>,
+    ordered: BTreeSet<(Score, Bytes)>,
+}
+
+impl SortedSet {
+    /// Adds the member or updates its score, returning whether it was added.
+    pub fn insert(&mut self, member: Bytes, score: Score) -> bool {
+        let old_score = self.scores.insert(member.clone(), score);
+        if let Some(old_score) = old_score {
+            self.ordered.remove(&(old_score, member.clone()));
+        }
+
+        self.ordered.insert((score, member));
+        old_score.is_none()
+    }
+
+    /// Removes the member with the lowest or highest score.
+    pub fn pop(&mut self, end: MinOrMax) -> Option<(Bytes, Score)> {
+        let (score, member) = match end {
+            MinOrMax::Min => self.ordered.pop_first()?,
+            MinOrMax::Max => self.ordered.pop_last()?,
+        };
+
+        self.scores.remove(&member);
+        Some((member, score))
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<Score> {
+        self.scores.get(member).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Iterates over the members from the lowest score to the highest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Bytes, Score)> {
+        self.ordered.iter().map(|(score, member)| (member, *score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Score, SortedSet};
+
+    #[test]
+    fn orders_members_by_score_then_member() {
+        let mut sorted_set = SortedSet::default();
+        for (member, score) in [("b", "1"), ("a", "1"), ("c", "-inf"), ("d", "2.5")] {
+            let score = Score::parse(score.as_bytes()).unwrap();
+            assert!(sorted_set.insert(Bytes::from_static(member.as_bytes()), score));
+        }
+
+        assert!(!sorted_set.insert(Bytes::from_static(b"d"), Score::parse(b"0").unwrap()));
+        let members = sorted_set
+            .iter()
+            .map(|(member, score)| format!("{}:{score}", std::str::from_utf8(member).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(members, ["c:-inf", "d:0", "a:1", "b:1"]);
+        assert_eq!(sorted_set.len(), 4);
+        assert!(Score::parse(b"nan").is_none());
+    }
+}
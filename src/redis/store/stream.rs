@@ -0,0 +1,488 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    ops::Bound,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub struct StreamEntryId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamEntryId {
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let id = std::str::from_utf8(bytes).ok()?;
+        match id.split_once('-') {
+            Some((ms, seq)) => Some(Self::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(Self::new(id.parse().ok()?, 0)),
+        }
+    }
+}
+
+impl Display for StreamEntryId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum XAddEntryId {
+    Auto,
+    AutoSequence { ms: u64 },
+    Explicit(StreamEntryId),
+}
+
+impl XAddEntryId {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes == b"*" {
+            return Some(Self::Auto);
+        }
+
+        match std::str::from_utf8(bytes).ok()?.split_once('-') {
+            Some((ms, "*")) => Some(Self::AutoSequence {
+                ms: ms.parse().ok()?,
+            }),
+            _ => StreamEntryId::parse(bytes).map(Self::Explicit),
+        }
+    }
+}
+
+impl Display for XAddEntryId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            XAddEntryId::Auto => write!(f, "*"),
+            XAddEntryId::AutoSequence { ms } => write!(f, "{ms}-*"),
+            XAddEntryId::Explicit(id) => write!(f, "{id}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StreamTrimStrategy {
+    MaxLen(usize),
+    MinId(StreamEntryId),
+}
+
+/// Where `XREADGROUP` reads from a stream.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum XReadGroupId {
+    /// `>`, the entries never delivered to the group.
+    New,
+    /// The entries pending for the consumer, after the given ID.
+    Pending(StreamEntryId),
+}
+
+impl XReadGroupId {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes == b">" {
+            return Some(Self::New);
+        }
+
+        StreamEntryId::parse(bytes).map(Self::Pending)
+    }
+}
+
+impl Display for XReadGroupId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            XReadGroupId::New => write!(f, ">"),
+            XReadGroupId::Pending(id) => write!(f, "{id}"),
+        }
+    }
+}
+
+/// An entry delivered to a consumer of a group, which has not acknowledged it yet.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    pub delivery_time: SystemTime,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ConsumerGroup {
+    last_delivered_id: StreamEntryId,
+    /// The pending entries list, or PEL, of every consumer in the group.
+    pending: BTreeMap<StreamEntryId, PendingEntry>,
+}
+
+impl ConsumerGroup {
+    pub fn pending(&self) -> &BTreeMap<StreamEntryId, PendingEntry> {
+        &self.pending
+    }
+}
+
+/// An entry read by a consumer, without its fields when it was deleted after it was
+/// delivered.
+pub type DeliveredEntry = (StreamEntryId, Option<Vec<(Bytes, Bytes)>>);
+
+/// How many entries an approximate trim removes at most when it is not given a `LIMIT`,
+/// which is what Redis removes with its default of 100 entries per node.
+pub const DEFAULT_TRIM_LIMIT: usize = 100 * 100;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StreamTrim {
+    pub strategy: StreamTrimStrategy,
+    pub approximate: bool,
+    /// The `LIMIT` of an approximate trim, where 0 means no limit.
+    pub limit: Option<usize>,
+}
+
+impl StreamTrim {
+    /// The most entries removed by a single trim.
+    fn max_removed(&self) -> usize {
+        match (self.approximate, self.limit) {
+            (false, _) | (true, Some(0)) => usize::MAX,
+            (true, Some(limit)) => limit,
+            (true, None) => DEFAULT_TRIM_LIMIT,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Stream {
+    entries: BTreeMap<StreamEntryId, Vec<(Bytes, Bytes)>>,
+    last_id: StreamEntryId,
+    groups: HashMap<Bytes, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn last_id(&self) -> StreamEntryId {
+        self.last_id
+    }
+
+    pub fn first_entry(&self) -> Option<(&StreamEntryId, &Vec<(Bytes, Bytes)>)> {
+        self.entries.first_key_value()
+    }
+
+    pub fn last_entry(&self) -> Option<(&StreamEntryId, &Vec<(Bytes, Bytes)>)> {
+        self.entries.last_key_value()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&StreamEntryId, &Vec<(Bytes, Bytes)>)> {
+        self.entries.iter()
+    }
+
+    /// Restores the last generated ID of a loaded stream, which is ahead of its last
+    /// entry once that entry was removed.
+    pub fn set_last_id(&mut self, id: StreamEntryId) {
+        self.last_id = id;
+    }
+
+    /// Resolves the ID for a new entry, returning `None` when the resulting ID
+    /// would not be strictly greater than the last generated ID.
+    pub fn next_id(&self, entry_id: XAddEntryId) -> Option<StreamEntryId> {
+        let next_id = match entry_id {
+            XAddEntryId::Auto => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+
+                if now > self.last_id.ms {
+                    StreamEntryId::new(now, 0)
+                } else {
+                    StreamEntryId::new(self.last_id.ms, self.last_id.seq.checked_add(1)?)
+                }
+            }
+            XAddEntryId::AutoSequence { ms } if ms == self.last_id.ms => {
+                StreamEntryId::new(ms, self.last_id.seq.checked_add(1)?)
+            }
+            XAddEntryId::AutoSequence { ms } => StreamEntryId::new(ms, 0),
+            XAddEntryId::Explicit(id) => id,
+        };
+
+        (next_id > self.last_id).then_some(next_id)
+    }
+
+    pub fn add(&mut self, id: StreamEntryId, fields: Vec<(Bytes, Bytes)>) {
+        self.entries.insert(id, fields);
+        self.last_id = id;
+    }
+
+    /// Removes entries from the head of the stream according to the trim
+    /// strategy and returns the number of removed entries. Since the entries are not
+    /// grouped into nodes, approximate trims are exact but stop after their limit, so
+    /// that a large stream is trimmed in bounded batches.
+    pub fn trim(&mut self, trim: StreamTrim) -> usize {
+        let max_removed = trim.max_removed();
+        let mut removed = 0;
+        while let Some((&first_id, _)) = self.entries.first_key_value() {
+            if removed == max_removed {
+                break;
+            }
+
+            let should_remove = match trim.strategy {
+                StreamTrimStrategy::MaxLen(max_len) => self.entries.len() > max_len,
+                StreamTrimStrategy::MinId(min_id) => first_id < min_id,
+            };
+
+            if !should_remove {
+                break;
+            }
+
+            self.entries.pop_first();
+            removed += 1;
+        }
+
+        removed
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    /// Creates a consumer group that delivers the entries after `last_delivered_id`,
+    /// returning `false` when the group already exists.
+    pub fn create_group(&mut self, name: Bytes, last_delivered_id: StreamEntryId) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+
+        self.groups.insert(
+            name,
+            ConsumerGroup {
+                last_delivered_id,
+                pending: BTreeMap::new(),
+            },
+        );
+        true
+    }
+
+    /// Reads entries for a consumer of a group, or returns `None` when there is no such
+    /// group. New entries are delivered to the consumer and, unless `no_ack` is set, kept
+    /// pending until they are acknowledged. Reading pending entries delivers them again.
+    pub fn read_group(
+        &mut self,
+        group_name: &[u8],
+        consumer: &Bytes,
+        id: XReadGroupId,
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> Option<Vec<DeliveredEntry>> {
+        let group = self.groups.get_mut(group_name)?;
+        let count = count.unwrap_or(usize::MAX);
+        let now = SystemTime::now();
+        let delivered = match id {
+            XReadGroupId::New => {
+                let delivered = self
+                    .entries
+                    .range((Bound::Excluded(group.last_delivered_id), Bound::Unbounded))
+                    .take(count)
+                    .map(|(id, fields)| (*id, Some(fields.clone())))
+                    .collect::<Vec<_>>();
+
+                for (id, _) in &delivered {
+                    group.last_delivered_id = *id;
+                    if !no_ack {
+                        group.pending.insert(
+                            *id,
+                            PendingEntry {
+                                consumer: consumer.clone(),
+                                delivery_time: now,
+                                delivery_count: 1,
+                            },
+                        );
+                    }
+                }
+
+                delivered
+            }
+            XReadGroupId::Pending(after) => group
+                .pending
+                .range_mut((Bound::Excluded(after), Bound::Unbounded))
+                .filter(|(_, pending)| pending.consumer == consumer)
+                .take(count)
+                .map(|(id, pending)| {
+                    pending.delivery_time = now;
+                    pending.delivery_count += 1;
+                    (*id, self.entries.get(id).cloned())
+                })
+                .collect(),
+        };
+
+        Some(delivered)
+    }
+
+    /// Acknowledges entries delivered to a group, returning how many of them were
+    /// pending, or `None` when there is no such group.
+    pub fn ack(&mut self, group_name: &[u8], ids: &[StreamEntryId]) -> Option<usize> {
+        let group = self.groups.get_mut(group_name)?;
+        Some(
+            ids.iter()
+                .filter(|id| group.pending.remove(id).is_some())
+                .count(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Stream, StreamEntryId, StreamTrim, StreamTrimStrategy, XAddEntryId, XReadGroupId};
+
+    fn stream_with_ids(ids: &[(u64, u64)]) -> Stream {
+        let mut stream = Stream::default();
+        for &(ms, seq) in ids {
+            stream.add(StreamEntryId::new(ms, seq), vec![]);
+        }
+
+        stream
+    }
+
+    #[test]
+    fn orders_ids_numerically() {
+        let stream = stream_with_ids(&[(9, 0), (10, 0)]);
+        let ids = stream.entries.keys().copied().collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![StreamEntryId::new(9, 0), StreamEntryId::new(10, 0)]
+        );
+    }
+
+    #[test]
+    fn generates_sequence_numbers() {
+        let stream = stream_with_ids(&[(5, 3)]);
+        assert_eq!(
+            stream.next_id(XAddEntryId::AutoSequence { ms: 5 }),
+            Some(StreamEntryId::new(5, 4))
+        );
+        assert_eq!(
+            stream.next_id(XAddEntryId::AutoSequence { ms: 6 }),
+            Some(StreamEntryId::new(6, 0))
+        );
+        assert_eq!(stream.next_id(XAddEntryId::AutoSequence { ms: 4 }), None);
+        assert_eq!(
+            stream.next_id(XAddEntryId::Explicit(StreamEntryId::new(5, 3))),
+            None
+        );
+    }
+
+    #[test]
+    fn trims_by_max_len() {
+        let mut stream = stream_with_ids(&[(1, 0), (2, 0), (3, 0), (4, 0)]);
+        let removed = stream.trim(StreamTrim {
+            strategy: StreamTrimStrategy::MaxLen(2),
+            approximate: false,
+            limit: None,
+        });
+
+        assert_eq!(removed, 2);
+        let ids = stream.entries.keys().copied().collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![StreamEntryId::new(3, 0), StreamEntryId::new(4, 0)]
+        );
+    }
+
+    #[test]
+    fn trims_by_min_id() {
+        let mut stream = stream_with_ids(&[(1, 0), (2, 0), (2, 1), (3, 0)]);
+        let removed = stream.trim(StreamTrim {
+            strategy: StreamTrimStrategy::MinId(StreamEntryId::new(2, 1)),
+            approximate: true,
+            limit: None,
+        });
+
+        assert_eq!(removed, 2);
+        assert_eq!(
+            stream.entries.first_key_value().map(|(id, _)| *id),
+            Some(StreamEntryId::new(2, 1))
+        );
+    }
+
+    #[test]
+    fn trims_approximately_up_to_the_limit() {
+        let mut stream = stream_with_ids(&[(1, 0), (2, 0), (3, 0), (4, 0), (5, 0)]);
+        let trim = StreamTrim {
+            strategy: StreamTrimStrategy::MaxLen(1),
+            approximate: true,
+            limit: Some(3),
+        };
+
+        assert_eq!(stream.trim(trim), 3);
+        assert_eq!(stream.len(), 2);
+        assert_eq!(stream.trim(trim), 1);
+        assert_eq!(stream.len(), 1);
+
+        let mut stream = stream_with_ids(&[(1, 0), (2, 0), (3, 0)]);
+        let removed = stream.trim(StreamTrim {
+            limit: Some(0),
+            ..trim
+        });
+        assert_eq!(removed, 2);
+    }
+
+    #[test]
+    fn delivers_entries_to_consumers_until_they_are_acked() {
+        let mut stream = stream_with_ids(&[(1, 0), (2, 0), (3, 0)]);
+        assert!(stream.create_group(Bytes::from("group"), StreamEntryId::new(1, 0)));
+        assert!(!stream.create_group(Bytes::from("group"), StreamEntryId::default()));
+
+        let alice = Bytes::from("alice");
+        let bob = Bytes::from("bob");
+        let ids = |entries: Option<Vec<super::DeliveredEntry>>| {
+            entries
+                .unwrap()
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(stream.read_group(b"group", &alice, XReadGroupId::New, Some(1), false)),
+            [StreamEntryId::new(2, 0)]
+        );
+        assert_eq!(
+            ids(stream.read_group(b"group", &bob, XReadGroupId::New, None, false)),
+            [StreamEntryId::new(3, 0)]
+        );
+        assert!(ids(stream.read_group(b"group", &bob, XReadGroupId::New, None, false)).is_empty());
+
+        // each consumer only reads its own pending entries again
+        let pending = XReadGroupId::Pending(StreamEntryId::default());
+        assert_eq!(
+            ids(stream.read_group(b"group", &alice, pending, None, false)),
+            [StreamEntryId::new(2, 0)]
+        );
+        let group = stream.group(b"group").unwrap();
+        assert_eq!(group.pending()[&StreamEntryId::new(2, 0)].delivery_count, 2);
+        assert_eq!(group.pending()[&StreamEntryId::new(3, 0)].consumer, bob);
+
+        assert_eq!(
+            stream.ack(
+                b"group",
+                &[StreamEntryId::new(2, 0), StreamEntryId::new(2, 0)]
+            ),
+            Some(1)
+        );
+        assert!(ids(stream.read_group(b"group", &alice, pending, None, false)).is_empty());
+        assert_eq!(stream.ack(b"missing", &[StreamEntryId::new(3, 0)]), None);
+        assert!(stream
+            .read_group(b"missing", &alice, XReadGroupId::New, None, false)
+            .is_none());
+    }
+
+    #[test]
+    fn does_not_keep_entries_read_without_ack_pending() {
+        let mut stream = stream_with_ids(&[(1, 0)]);
+        stream.create_group(Bytes::from("group"), StreamEntryId::default());
+        let consumer = Bytes::from("consumer");
+        let delivered = stream.read_group(b"group", &consumer, XReadGroupId::New, None, true);
+
+        assert_eq!(delivered.unwrap().len(), 1);
+        assert!(stream.group(b"group").unwrap().pending().is_empty());
+    }
+}